@@ -0,0 +1,137 @@
+use crate::{
+    config::Conf,
+    days_off::{self, Absence, AbsenceKind, DateRange},
+};
+use augr_core::{store::patch::RemoveAbsence, Patch, Timesheet};
+use snafu::{ResultExt, Snafu};
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub enum Cmd {
+    /// Record a range of days, like `2019-07-01..2019-07-14`, as vacation,
+    /// sick, or holiday
+    #[structopt(no_version, name = "add")]
+    Add {
+        range: DateRange,
+
+        /// Why the days were missed: vacation, sick, or holiday
+        #[structopt(long = "kind", default_value = "vacation")]
+        kind: AbsenceKind,
+    },
+
+    /// Remove an absence. The range and kind have to be the ones it was added
+    /// with.
+    #[structopt(no_version, name = "remove")]
+    Remove {
+        range: DateRange,
+
+        #[structopt(long = "kind", default_value = "vacation")]
+        kind: AbsenceKind,
+    },
+
+    /// List the absences, optionally only those of one kind
+    #[structopt(no_version, name = "list")]
+    List {
+        #[structopt(long = "kind")]
+        kind: Option<AbsenceKind>,
+    },
+}
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("There is no {} absence for {}", kind, range))]
+    NoSuchAbsence { kind: AbsenceKind, range: DateRange },
+
+    #[snafu(display("{}", source))]
+    LoadAbsences { source: days_off::Error },
+}
+
+impl Cmd {
+    /// The patches that make the change, or none for `list`
+    pub fn exec(&self, timesheet: &Timesheet, conf: &Conf) -> Result<Vec<Patch>, Error> {
+        match self {
+            Cmd::Add { range, kind } => {
+                let mut patch = Patch::new();
+                patch.insert_add_absence(absence(*kind, *range).to_patch());
+                Ok(vec![patch])
+            }
+            Cmd::Remove { range, kind } => {
+                let removed = absence(*kind, *range).to_patch();
+                let mut patch = Patch::new();
+                for (added_by, absence) in timesheet.get_patched_timesheet().absences.iter() {
+                    if *absence == removed {
+                        patch.insert_remove_absence(RemoveAbsence {
+                            patch: *added_by,
+                            kind: absence.kind.clone(),
+                            start: absence.start,
+                            end: absence.end,
+                        });
+                    }
+                }
+                if patch.remove_absence.is_empty() {
+                    return NoSuchAbsence {
+                        kind: *kind,
+                        range: *range,
+                    }
+                    .fail();
+                }
+                Ok(vec![patch])
+            }
+            Cmd::List { kind } => {
+                let absences = days_off::absences(conf, timesheet).context(LoadAbsences {})?;
+                for absence in absences {
+                    if kind.is_none_or(|kind| kind == absence.kind) {
+                        println!("{} {}", absence.range(), absence.kind);
+                    }
+                }
+                Ok(Vec::new())
+            }
+        }
+    }
+}
+
+fn absence(kind: AbsenceKind, range: DateRange) -> Absence {
+    Absence {
+        kind,
+        start: range.start,
+        end: range.end,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::{self, Tracked};
+
+    #[test]
+    fn remove_the_absence_that_was_added() {
+        let cmd = |args: &[&str]| Cmd::from_iter_safe(args).unwrap();
+        let empty = Tracked::new(&[]);
+        let timesheet = empty.timesheet();
+        let conf: Conf = testing::conf("");
+        let add = cmd(&["absence", "add", "2019-07-01..2019-07-03", "--kind", "sick"])
+            .exec(&timesheet, &conf)
+            .unwrap();
+
+        let mut tracked = Tracked::new(&[&add[0]]);
+        let timesheet = tracked.timesheet();
+        let remove = cmd(&[
+            "absence",
+            "remove",
+            "2019-07-01..2019-07-03",
+            "--kind",
+            "sick",
+        ])
+        .exec(&timesheet, &conf)
+        .unwrap();
+        assert!(remove[0].parents().contains(&add[0].id));
+
+        // The kind has to match too
+        assert!(cmd(&["absence", "remove", "2019-07-01..2019-07-03"])
+            .exec(&timesheet, &conf)
+            .is_err());
+
+        tracked.apply(&remove[0]);
+        assert!(tracked.patched.absences.is_empty());
+    }
+}
@@ -0,0 +1,112 @@
+//! Short names for tags, set in the `[alias]` section of the config like
+//! `ws = "workshop"`. Tags are expanded as patches are added, so only the full
+//! name is ever recorded. Tags that only pick events, in filters and in tags to
+//! remove, are expanded as the arguments are read.
+
+use augr_core::{tag_expr::ParseError, Patch, Tag, TagExpr};
+use std::{cell::RefCell, collections::BTreeMap};
+
+thread_local! {
+    static ALIASES: RefCell<BTreeMap<Tag, Tag>> = const { RefCell::new(BTreeMap::new()) };
+}
+
+/// Expand `aliases` in the arguments read from now on
+pub fn set_aliases(aliases: BTreeMap<Tag, Tag>) {
+    ALIASES.with(|current| *current.borrow_mut() = aliases);
+}
+
+/// The tag that `text` stands for, for arguments that take a tag
+pub fn tag(text: &str) -> Tag {
+    ALIASES.with(|aliases| {
+        aliases
+            .borrow()
+            .get(text)
+            .cloned()
+            .unwrap_or_else(|| text.to_string())
+    })
+}
+
+/// Parse a tag expression, with the aliases in it expanded
+pub fn tag_expr(text: &str) -> Result<TagExpr, ParseError> {
+    let expr: TagExpr = text.parse()?;
+    Ok(expr.map_tags(&|t: Tag| tag(&t)))
+}
+
+/// Replace the aliases among the tags that `patches` add with the tags they
+/// stand for. Aliases aren't expanded again, so they can't loop.
+pub fn expand(aliases: &BTreeMap<Tag, Tag>, mut patches: Vec<Patch>) -> Vec<Patch> {
+    if aliases.is_empty() {
+        return patches;
+    }
+    let expand_tag = |tag: Tag| aliases.get(&tag).cloned().unwrap_or(tag);
+    for patch in patches.iter_mut() {
+        patch.add_tag = patch
+            .add_tag
+            .drain()
+            .map(|mut add| {
+                add.tag = expand_tag(add.tag);
+                add
+            })
+            .collect();
+        patch.create_event = patch
+            .create_event
+            .drain()
+            .map(|mut create| {
+                let mut tags: Vec<Tag> = Vec::with_capacity(create.tags.len());
+                for tag in create.tags.drain(..).map(expand_tag) {
+                    if !tags.contains(&tag) {
+                        tags.push(tag);
+                    }
+                }
+                create.tags = tags;
+                create
+            })
+            .collect();
+    }
+    patches
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use augr_core::store::patch::AddTag;
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn aliases_are_expanded_once() {
+        let aliases: BTreeMap<Tag, Tag> = vec![("ws", "workshop"), ("workshop", "ws")]
+            .into_iter()
+            .map(|(alias, tag)| (alias.into(), tag.into()))
+            .collect();
+        let time = Utc.ymd(2019, 7, 24).and_hms(9, 0, 0);
+        let mut patch =
+            Patch::new().create_event("a".into(), time, vec!["ws".into(), "work".into()]);
+        patch.insert_add_tag(AddTag {
+            parents: Default::default(),
+            event: "b".into(),
+            tag: "lunch".into(),
+        });
+
+        let patches = expand(&aliases, vec![patch]);
+        let create = patches[0].create_event.iter().next().unwrap();
+        assert_eq!(create.tags, vec![String::from("workshop"), "work".into()]);
+        let add = patches[0].add_tag.iter().next().unwrap();
+        assert_eq!(add.tag, "lunch");
+    }
+
+    #[test]
+    fn aliases_in_arguments() {
+        set_aliases(
+            vec![(String::from("ws"), String::from("workshop"))]
+                .into_iter()
+                .collect(),
+        );
+        assert_eq!(tag("ws"), "workshop");
+        assert_eq!(tag("work"), "work");
+        assert_eq!(
+            tag_expr("ws and not work").unwrap().to_string(),
+            "workshop and not work"
+        );
+        set_aliases(BTreeMap::new());
+    }
+}
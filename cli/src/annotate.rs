@@ -0,0 +1,42 @@
+use augr_core::{store::patch::SetNote, EventRef, Patch, Timesheet};
+use snafu::Snafu;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct Cmd {
+    /// The id of the event to annotate, a unique prefix of it, or
+    /// `@` for the current event and `@-1`, `@-2`, ... for earlier ones today
+    pub event: EventRef,
+
+    /// The note to attach to the event. Replaces any existing note; an empty
+    /// note removes it.
+    note: String,
+}
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Unknown event reference: {}", event_ref))]
+    UnknownEventRef { event_ref: EventRef },
+}
+
+impl Cmd {
+    pub fn exec(&self, timesheet: &Timesheet) -> Result<Vec<Patch>, Error> {
+        let event = timesheet
+            .get_patched_timesheet()
+            .events
+            .get(&self.event)
+            .filter(|event| !event.is_deleted())
+            .ok_or(Error::UnknownEventRef {
+                event_ref: self.event.clone(),
+            })?;
+
+        let mut patch = Patch::new();
+        patch.insert_set_note(SetNote {
+            parents: event.latest_patches(),
+            replaces: event.note_patches(),
+            event: self.event.clone(),
+            note: self.note.trim().to_string(),
+        });
+        Ok(vec![patch])
+    }
+}
@@ -0,0 +1,180 @@
+use crate::{
+    config::Conf,
+    days_off::{self, DaysOff},
+    format_duration,
+    target::{self, Target},
+    time_input::parse_default_local,
+};
+use augr_core::{
+    aggregate::{self, Period},
+    timesheet::Segment,
+    Tag, Timesheet,
+};
+use chrono::{DateTime, Local, NaiveDate, Weekday};
+use snafu::{ensure, ResultExt, Snafu};
+use std::{collections::BTreeMap, fmt::Write};
+use structopt::StructOpt;
+
+/// The number of columns in a full progress bar
+const BAR_WIDTH: i64 = 20;
+
+#[derive(StructOpt, Debug)]
+pub struct Cmd {
+    /// Any day in the periods to show, like "7 days ago". Defaults to today.
+    #[structopt(parse(try_from_os_str = parse_default_local))]
+    day: Option<DateTime<Local>>,
+
+    /// Leave weekends, or the days not in `weekdays` of `[working_hours]`,
+    /// out of the targets
+    #[structopt(long = "workdays")]
+    workdays: bool,
+}
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("{}", source))]
+    LoadDaysOff { source: days_off::Error },
+
+    #[snafu(display(
+        "Add targets like `work = \"40h/week\"` to the [target] table of the config"
+    ))]
+    NoTargets,
+}
+
+impl Cmd {
+    pub fn exec(&self, timesheet: &Timesheet, conf: &Conf) -> Result<(), Error> {
+        ensure!(!conf.targets.is_empty(), NoTargets);
+        let days_off = DaysOff::load(conf, timesheet)
+            .context(LoadDaysOff {})?
+            .with_workdays(conf.workdays(self.workdays));
+        let day = self
+            .day
+            .unwrap_or_else(|| timesheet.clock().now().with_timezone(&Local));
+        print!(
+            "{}",
+            render(
+                timesheet,
+                &conf.targets,
+                &days_off,
+                conf.week_starts_on,
+                day.naive_local().date()
+            )
+        );
+        Ok(())
+    }
+}
+
+/// A progress bar for each target, over the period of the target that `date`
+/// is in, with weeks starting on `week_start`. Days off in the period make
+/// the target smaller.
+pub fn render(
+    timesheet: &Timesheet,
+    targets: &BTreeMap<Tag, Target>,
+    days_off: &DaysOff,
+    week_start: Weekday,
+    date: NaiveDate,
+) -> String {
+    let segments = timesheet.segments();
+    let now = timesheet.clock().now();
+    let width = targets.keys().map(String::len).max().unwrap_or(0);
+    let mut out = String::new();
+    for (tag, target) in targets {
+        let period = match target.period {
+            target::Period::Day => Period::Day,
+            target::Period::Week => Period::Week(week_start),
+            target::Period::Month => Period::Month,
+        };
+        let first = period.start_of(date);
+        let next = period.next(first);
+        let tagged: Vec<Segment> = segments
+            .iter()
+            .filter(|s| s.tags.contains(tag))
+            .cloned()
+            .collect();
+        let tracked = aggregate::by_tag(
+            &tagged,
+            aggregate::midnight(first, &Local),
+            aggregate::midnight(next, &Local).min(now),
+        )
+        .total;
+        let days = (next - first).num_days();
+        let goal = if days_off.working_days(first, next.pred()) == days {
+            target.duration
+        } else {
+            days_off.goal(target, first, next.pred())
+        };
+
+        let filled = match goal.num_seconds() {
+            0 => BAR_WIDTH,
+            goal => (tracked.num_seconds() * BAR_WIDTH / goal).min(BAR_WIDTH),
+        };
+        let percent = tracked.num_seconds() * 100 / goal.num_seconds().max(1);
+        let remaining = if tracked > goal {
+            format!("{} over", format_duration(tracked - goal))
+        } else {
+            format!("{} left", format_duration(goal - tracked))
+        };
+        writeln!(
+            out,
+            "{: <width$} {}{} {} of {} {} ({}%), {}",
+            tag,
+            "█".repeat(filled as usize),
+            "░".repeat((BAR_WIDTH - filled) as usize),
+            format_duration(tracked),
+            format_duration(goal),
+            period_name(period, first),
+            percent,
+            remaining,
+            width = width
+        )
+        .unwrap();
+    }
+    out
+}
+
+fn period_name(period: Period, first: NaiveDate) -> String {
+    match period {
+        Period::Day => first.format("on %a %m-%d").to_string(),
+        Period::Week(_) => first.format("in the week of %m-%d").to_string(),
+        Period::Month => first.format("in %B").to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::Tracked;
+    use augr_core::Patch;
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn budget_progress() {
+        let time = |d, h| Local.ymd(2019, 7, d).and_hms(h, 0, 0).with_timezone(&Utc);
+        let create = Patch::new()
+            .create_event("a".into(), time(22, 9), vec!["work".into()])
+            .create_event("b".into(), time(22, 19), vec!["side-project".into()])
+            .create_event("c".into(), time(22, 21), vec![])
+            .create_event("d".into(), time(24, 9), vec!["work".into()])
+            .create_event("e".into(), time(24, 19), vec![]);
+        let tracked = Tracked::new(&[&create]).at(time(24, 20));
+        let timesheet = tracked.timesheet();
+
+        let mut targets = BTreeMap::new();
+        targets.insert("work".into(), Target::parse("40h/week").unwrap());
+        targets.insert("side-project".into(), Target::parse("1h/day").unwrap());
+        let budget = render(
+            &timesheet,
+            &targets,
+            &DaysOff::default(),
+            Weekday::Mon,
+            NaiveDate::from_ymd(2019, 7, 24),
+        );
+        assert_eq!(
+            budget.lines().collect::<Vec<_>>(),
+            vec![
+                "side-project ░░░░░░░░░░░░░░░░░░░░ 0m of 1h 0m on Wed 07-24 (0%), 1h 0m left",
+                "work         ██████████░░░░░░░░░░ 20h 0m of 40h 0m in the week of 07-22 (50%), 20h 0m left",
+            ]
+        );
+    }
+}
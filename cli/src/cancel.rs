@@ -0,0 +1,70 @@
+use crate::{delete, format_duration};
+use augr_core::{Patch, Timesheet};
+use chrono::Local;
+use snafu::{ResultExt, Snafu};
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct Cmd {}
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("There is no ongoing event to cancel"))]
+    NothingToCancel,
+
+    #[snafu(display("{}", source))]
+    Delete { source: delete::Error },
+}
+
+impl Cmd {
+    /// Delete the ongoing event, so that the event before it continues in its
+    /// place
+    pub fn exec(&self, timesheet: &Timesheet) -> Result<Vec<Patch>, Error> {
+        let current = timesheet
+            .current_event(timesheet.clock().now())
+            .ok_or(Error::NothingToCancel)?;
+        let patch = delete::delete_event(timesheet, &current.event_ref).context(Delete {})?;
+
+        let tags: Vec<&str> = current.tags.iter().map(|s| &**s).collect();
+        eprintln!(
+            "Cancelled {} started at {} ({} ago)",
+            if tags.is_empty() {
+                String::from("stop")
+            } else {
+                tags.join(" ")
+            },
+            current.start_time.with_timezone(&Local).format("%H:%M"),
+            format_duration(current.elapsed)
+        );
+        Ok(vec![patch])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::Tracked;
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn cancel_deletes_the_ongoing_event() {
+        let time = |h| Utc.ymd(2019, 7, 24).and_hms(h, 0, 0);
+        let create = Patch::new()
+            .create_event("a".into(), time(9), vec!["work".into()])
+            .create_event("b".into(), time(12), vec!["lunch".into()]);
+        let tracked = Tracked::new(&[&create]).at(time(13));
+        let timesheet = tracked.timesheet();
+
+        let patches = Cmd {}.exec(&timesheet).unwrap();
+        let deleted = patches[0].delete_event.iter().next().unwrap();
+        assert_eq!(deleted.event, "b");
+
+        let cancelled = Tracked::new(&[&create, &patches[0]]).at(time(13));
+        let timesheet = cancelled.timesheet();
+        assert_eq!(timesheet.current_event(time(13)).unwrap().event_ref, "a");
+
+        let early = Tracked::new(&[&create]).at(time(8));
+        let timesheet = early.timesheet();
+        assert!(Cmd {}.exec(&timesheet).is_err());
+    }
+}
@@ -1,14 +1,137 @@
-use augr_core::{Tag, Timesheet};
-use chrono::{offset::TimeZone, Local, NaiveDate, Utc};
-use std::collections::BTreeSet;
+use crate::{
+    config::{self, Conf},
+    days_off::{self, DaysOff},
+    export::json,
+    format_duration, suggest,
+    svg::{self, Grid},
+    target::Target,
+    time_input::{RangeError, RangeGuard},
+};
+use augr_core::{aggregate, timesheet::Segment, Tag, TagExpr, Timesheet};
+use chrono::{offset::TimeZone, Date, DateTime, Duration, Local, NaiveDate, Utc};
+use clap::arg_enum;
+use console::{style, Color, Term};
+use serde::{de, Deserialize, Deserializer, Serialize};
+use snafu::{ResultExt, Snafu};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+    path::PathBuf,
+    str::FromStr,
+};
 use structopt::StructOpt;
 
+/// The colours given to groups that don't pick one, in order
+const FALLBACK_COLORS: [Color; 6] = [
+    Color::Blue,
+    Color::Green,
+    Color::Yellow,
+    Color::Magenta,
+    Color::Cyan,
+    Color::Red,
+];
+
+/// The symbols given to groups that don't pick one, in order. `░` and `▒` are
+/// left out since they mark the comparison period and ungrouped time.
+const FALLBACK_SYMBOLS: [char; 6] = ['█', '▓', '●', '■', '◆', '▲'];
+
+/// The symbol for charted time that isn't in any of the groups
+const UNGROUPED_SYMBOL: char = '▒';
+
+arg_enum! {
+    /// Ways of writing a chart
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    enum Format {
+        Text,
+        Json,
+    }
+}
+
+/// Events to colour the same way in the chart, like
+///
+/// ```toml
+/// [[chart_group]]
+/// filter = "work or meeting"
+/// color = "blue"
+/// ```
+///
+/// Each cell takes the colour and symbol of the group with the most time in
+/// it. Groups without a colour or symbol get ones that aren't taken.
+#[derive(Deserialize, Debug)]
+pub struct ChartGroup {
+    /// What to call the group. Defaults to the filter.
+    #[serde(default)]
+    pub name: Option<String>,
+
+    #[serde(deserialize_with = "config::tag_expr")]
+    pub filter: TagExpr,
+
+    /// A colour name like "red" or "cyan", or a number from 0 to 255
+    #[serde(default, deserialize_with = "color")]
+    pub color: Option<Color>,
+
+    /// The character to fill the group's cells with, like "●"
+    #[serde(default)]
+    pub symbol: Option<char>,
+}
+
+impl ChartGroup {
+    pub fn name(&self) -> String {
+        self.name.clone().unwrap_or_else(|| self.filter.to_string())
+    }
+}
+
+impl FromStr for ChartGroup {
+    type Err = String;
+
+    /// Parse a group from `--group`, like "exercise:gym or run"
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut parts = text.splitn(2, ':');
+        let name = parts.next().unwrap_or("").trim();
+        let filter = parts.next().ok_or_else(|| {
+            format!(
+                "expected a name and a tag expression, like \"exercise:gym or run\", not \"{}\"",
+                text
+            )
+        })?;
+        if name.is_empty() {
+            return Err(format!(
+                "the group \"{}\" needs a name before the \":\"",
+                text
+            ));
+        }
+        Ok(Self {
+            name: Some(name.to_string()),
+            filter: crate::alias::tag_expr(filter).map_err(|e| format!("{}", e))?,
+            color: None,
+            symbol: None,
+        })
+    }
+}
+
 #[derive(StructOpt, Debug)]
 #[structopt(name = "chart")]
 pub struct Cmd {
-    /// A list of tags to filter against
+    /// Only chart events with all of these tags. A shorthand for
+    /// `--filter "tag and other-tag"`.
+    #[structopt(parse(from_str = crate::alias::tag))]
     tags: Vec<String>,
 
+    /// Only chart events matching a tag expression, like "coding or review"
+    #[structopt(long = "filter", parse(try_from_str = crate::alias::tag_expr))]
+    filter: Option<TagExpr>,
+
+    /// Chart the events matching a tag expression as a group with its own
+    /// symbol and colour, like "exercise:gym or run". Can be given more than
+    /// once, and replaces the `[[chart_group]]` tables of the config.
+    #[structopt(long = "group", number_of_values = 1)]
+    groups: Vec<ChartGroup>,
+
+    /// Leave out events with any of these tags, like "sleep,off", even if
+    /// they match the other filters
+    #[structopt(long = "exclude", use_delimiter = true, parse(from_str = crate::alias::tag))]
+    exclude: Vec<Tag>,
+
     /// The date to start charting from. Defaults to 7 days ago.
     #[structopt(long = "start")]
     start: Option<NaiveDate>,
@@ -16,52 +139,885 @@ pub struct Cmd {
     /// The date to stop charting at. Defaults to today.
     #[structopt(long = "end")]
     end: Option<NaiveDate>,
+
+    /// Show the same chart from an earlier period, like "2 weeks ago", as
+    /// dimmed cells behind the current one
+    #[structopt(long = "compare-with", parse(try_from_str = parse_offset))]
+    compare_with: Option<Duration>,
+
+    /// Leave weekends, or the days not in `weekdays` of `[working_hours]`,
+    /// out of the target
+    #[structopt(long = "workdays")]
+    workdays: bool,
+
+    /// Print the time charted on each day at the end of its row, and the
+    /// time for the whole chart beneath it
+    #[structopt(long = "totals")]
+    totals: bool,
+
+    /// Put the hours of the day down the side and the days across the top,
+    /// fitting as many days side by side as the terminal has room for
+    #[structopt(long = "vertical")]
+    vertical: bool,
+
+    /// "text", or "json" for which sections of each day were charted
+    #[structopt(
+        long = "format",
+        default_value = "text",
+        possible_values = &Format::variants(),
+        case_insensitive = true
+    )]
+    format: Format,
+
+    /// Save the chart as an SVG image instead of printing it
+    #[structopt(long = "output", short = "o")]
+    output: Option<PathBuf>,
+
+    #[structopt(flatten)]
+    guard: RangeGuard,
+}
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("{}", source))]
+    LoadDaysOff { source: days_off::Error },
+
+    #[snafu(display("{}", source))]
+    LongRange { source: RangeError },
+
+    #[snafu(display("{}", source))]
+    SaveImage { source: svg::Error },
+
+    #[snafu(display(
+        "Charts are labelled by hour, so day_start has to be on the hour, not {}",
+        day_start
+    ))]
+    UnevenDayStart { day_start: String },
 }
 
+/// The number of cells that each day is divided into
+const SECTIONS_PER_DAY: u32 = 24 * 3;
+
 impl Cmd {
-    pub fn exec(&self, timesheet: &Timesheet) {
-        let tags: BTreeSet<Tag> = self.tags.iter().cloned().map(Tag::from).collect();
+    pub fn exec(&self, timesheet: &Timesheet, conf: &Conf) -> Result<(), Error> {
+        let expr = self.expr();
+        suggest::warn_unknown_tags(timesheet, &BTreeSet::new(), expr.as_ref());
+        let matches = |event_tags: &BTreeSet<Tag>| {
+            !event_tags.is_empty() && expr.as_ref().is_none_or(|e| e.matches(event_tags))
+        };
+        let groups = if self.groups.is_empty() {
+            &conf.chart_groups
+        } else {
+            &self.groups
+        };
 
-        let now = chrono::Local::now();
+        let now = timesheet.clock().now().with_timezone(&Local);
+        let offset = conf.day_offset();
         let end_date = match self.end {
             Some(naive_date) => Local.from_local_date(&naive_date).unwrap(),
-            None => chrono::Local::today(),
+            None => (now - offset).date(),
         };
         let start_date = match self.start {
             Some(naive_date) => Local.from_local_date(&naive_date).unwrap(),
             None => end_date - chrono::Duration::days(6),
         };
+        self.guard
+            .check(start_date.naive_local(), end_date.naive_local())
+            .context(LongRange {})?;
+        let day_start_label = format!("{:02}:{:02}", offset.num_hours(), offset.num_minutes() % 60);
 
+        if self.format == Format::Json {
+            let segments = timesheet.segments();
+            let mut days = Vec::new();
+            let mut cur_date = start_date;
+            while cur_date <= end_date {
+                let day_start = day_start(cur_date, offset);
+                let day_end = day_start_after(cur_date, offset).min(now);
+                days.push(DayJson {
+                    date: cur_date.format("%Y-%m-%d").to_string(),
+                    seconds: matched_duration(timesheet, &matches, day_start, day_end)
+                        .num_seconds(),
+                    sections: occupancy(timesheet, &matches, cur_date, offset, now),
+                    groups: Some(dominant_groups(
+                        &segments, &matches, groups, cur_date, offset,
+                    ))
+                    .filter(|_| !groups.is_empty()),
+                });
+                cur_date = cur_date + Duration::days(1);
+            }
+            let output = ChartJson {
+                filter: self.expr().map(|expr| expr.to_string()),
+                day_start: day_start_label,
+                section_minutes: 20,
+                groups: groups
+                    .iter()
+                    .map(|group| GroupJson {
+                        name: group.name(),
+                        filter: group.filter.to_string(),
+                    })
+                    .collect(),
+                days,
+            };
+            print!("{}", json::to_string(&output));
+            return Ok(());
+        }
+        if offset.num_minutes() % 60 != 0 {
+            return UnevenDayStart {
+                day_start: day_start_label,
+            }
+            .fail();
+        }
+
+        if !self.tags.is_empty() || self.filter.is_some() {
+            let range_start = day_start(start_date, offset);
+            let range_end = day_start_after(end_date, offset).min(now);
+            let tracked = matched_duration(timesheet, &matches, range_start, range_end);
+            let days_off = DaysOff::load(conf, timesheet)
+                .context(LoadDaysOff {})?
+                .with_workdays(conf.workdays(self.workdays));
+            let target = self.target(&conf.targets);
+            let name = match &self.filter {
+                Some(filter) => self
+                    .tags
+                    .iter()
+                    .cloned()
+                    .chain(Some(format!("[{}]", filter)))
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                None => self.tags.join(" "),
+            };
+            let goal =
+                target.map(|t| days_off.goal(t, start_date.naive_local(), end_date.naive_local()));
+            print_target_header(&name, tracked, goal);
+        }
+
+        if let Some(path) = &self.output {
+            let mut dates = Vec::new();
+            let mut cells = Vec::new();
+            let mut cur_date = start_date;
+            while cur_date <= end_date {
+                let current = occupancy(timesheet, &matches, cur_date, offset, now);
+                let compared = self
+                    .compare_with
+                    .map(|earlier| occupancy(timesheet, &matches, cur_date - earlier, offset, now));
+                let row = (0..SECTIONS_PER_DAY as usize)
+                    .map(|section| {
+                        let in_comparison = compared.as_ref().is_some_and(|c| c[section]);
+                        Some(match (current[section], in_comparison) {
+                            (true, _) => 1.0,
+                            (false, true) => 0.3,
+                            (false, false) => 0.0,
+                        })
+                    })
+                    .collect();
+                dates.push(cur_date.format("%a %m-%d").to_string());
+                cells.push(row);
+                cur_date = cur_date + chrono::Duration::days(1);
+            }
+            let grid = Grid {
+                title: format!(
+                    "{} to {}",
+                    start_date.format("%Y-%m-%d"),
+                    end_date.format("%Y-%m-%d")
+                ),
+                row_labels: dates,
+                column_labels: (0..24)
+                    .step_by(3)
+                    .map(|hour| {
+                        (
+                            hour * 3,
+                            format!("{:02}", (hour as i64 + offset.num_hours()) % 24),
+                        )
+                    })
+                    .collect(),
+                cells,
+                cell_width: 6,
+                cell_height: 16,
+            };
+            return grid.save(path).context(SaveImage {});
+        }
+
+        let colors = pick(groups.iter().map(|g| g.color), &FALLBACK_COLORS);
+        let symbols = pick(groups.iter().map(|g| g.symbol), &FALLBACK_SYMBOLS);
+        let segments = timesheet.segments();
+        let mut days = Vec::new();
         let mut cur_date = start_date;
+        while cur_date <= end_date {
+            let current = occupancy(timesheet, &matches, cur_date, offset, now);
+            let compared = self
+                .compare_with
+                .map(|earlier| occupancy(timesheet, &matches, cur_date - earlier, offset, now));
+
+            let dominant = if groups.is_empty() {
+                Vec::new()
+            } else {
+                dominant_groups(&segments, &matches, groups, cur_date, offset)
+            };
+
+            let cells = (0..SECTIONS_PER_DAY as usize)
+                .map(|section| {
+                    let in_comparison = compared.as_ref().map(|c| c[section]).unwrap_or(false);
+                    let group = dominant.get(section).copied().flatten();
+                    if let (true, Some(group)) = (current[section], group) {
+                        style(symbols[group]).fg(colors[group]).to_string()
+                    } else if current[section] && !groups.is_empty() {
+                        UNGROUPED_SYMBOL.to_string()
+                    } else if current[section] {
+                        String::from("█")
+                    } else if in_comparison {
+                        String::from("░")
+                    } else {
+                        String::from(" ")
+                    }
+                })
+                .collect();
+            let tracked = if self.totals {
+                let day_start = day_start(cur_date, offset);
+                let day_end = day_start_after(cur_date, offset).min(now);
+                Some(matched_duration(timesheet, &matches, day_start, day_end))
+            } else {
+                None
+            };
+            let since_start = now - day_start(cur_date, offset);
+            let now_section = since_start.num_minutes().div_euclid(20);
+            days.push(ChartDay {
+                date: cur_date,
+                cells,
+                tracked,
+                now: Some(now_section as usize)
+                    .filter(|_| (0..i64::from(SECTIONS_PER_DAY)).contains(&now_section)),
+            });
+            cur_date = cur_date + chrono::Duration::days(1);
+        }
+
+        if self.vertical {
+            // `COLUMNS` covers output that goes to a pipe instead of a terminal
+            let width = Term::stdout()
+                .size_checked()
+                .map(|(_, columns)| columns as usize)
+                .or_else(|| std::env::var("COLUMNS").ok()?.parse().ok())
+                .unwrap_or(80);
+            print!("{}", render_vertical(&days, offset.num_hours(), width));
+        } else {
+            print!("{}", render_horizontal(&days, offset.num_hours()));
+        }
+
+        if !groups.is_empty() {
+            println!();
+            print!("{}", legend(groups, &symbols, &colors));
+        }
+        if let Some(offset) = self.compare_with {
+            println!("█ this period  ░ {} days earlier", offset.num_days());
+        }
+        Ok(())
+    }
+}
 
-        print!("Day ");
+/// The chart as it's written in JSON
+#[derive(Serialize, Debug)]
+struct ChartJson {
+    /// What events had to match to be charted, or `None` for every event
+    filter: Option<String>,
+    /// The time of day that days start at, like "04:00"
+    day_start: String,
+    section_minutes: u32,
+    groups: Vec<GroupJson>,
+    days: Vec<DayJson>,
+}
+
+#[derive(Serialize, Debug)]
+struct GroupJson {
+    name: String,
+    filter: String,
+}
+
+#[derive(Serialize, Debug)]
+struct DayJson {
+    date: String,
+    seconds: i64,
+    /// Whether each section of the day was charted
+    sections: Vec<bool>,
+    /// The index of the group with the most time in each section
+    #[serde(skip_serializing_if = "Option::is_none")]
+    groups: Option<Vec<Option<usize>>>,
+}
+
+/// The cells of a day in the chart, and the time charted on it if the totals
+/// are shown
+struct ChartDay {
+    date: Date<Local>,
+    cells: Vec<String>,
+    tracked: Option<Duration>,
+    /// The section that the current time is in, if it's on this day
+    now: Option<usize>,
+}
+
+impl ChartDay {
+    /// The cells, with `marker` in place of the one for the current time
+    fn cells_with_marker(&self, marker: &str) -> Vec<String> {
+        let mut cells = self.cells.clone();
+        if let Some(now) = self.now {
+            cells[now] = style(marker).red().bold().to_string();
+        }
+        cells
+    }
+}
+
+/// A row for each day, with the hours of the day, starting `hour_offset`
+/// after midnight, going across
+fn render_horizontal(days: &[ChartDay], hour_offset: i64) -> String {
+    let totals = days.iter().any(|day| day.tracked.is_some());
+    let mut out = String::from("Day       ");
+    for hour in 0..24 {
+        write!(out, "{: <3}", (hour + hour_offset) % 24).unwrap();
+    }
+    if totals {
+        out.push_str(" Total");
+    }
+    out.push('\n');
+
+    for day in days {
+        write!(
+            out,
+            "{} {}",
+            day.date.format("%a %m-%d"),
+            day.cells_with_marker("|").concat()
+        )
+        .unwrap();
+        if let Some(tracked) = day.tracked {
+            write!(out, " {}", format_duration(tracked)).unwrap();
+        }
+        out.push('\n');
+    }
+    if totals {
+        // Lined up with the totals of the days, after the day names and cells
+        writeln!(
+            out,
+            "{: <width$}{}",
+            "Total",
+            format_duration(total(days)),
+            width = 11 + SECTIONS_PER_DAY as usize
+        )
+        .unwrap();
+    }
+    out
+}
+
+/// A column for each day, with the hours of the day going down, in blocks of
+/// as many days as fit in `width`. Days get columns wide enough for their
+/// dates and totals if they all fit, and a character per section otherwise.
+fn render_vertical(days: &[ChartDay], hour_offset: i64, width: usize) -> String {
+    const HOUR_WIDTH: usize = 3;
+    const WIDE: usize = 9;
+    let totals = days.iter().any(|day| day.tracked.is_some());
+    let wide = HOUR_WIDTH + days.len() * (WIDE + 1) <= width;
+    let column = if wide { WIDE } else { 3 };
+    let per_block = (width.saturating_sub(HOUR_WIDTH) / (column + 1)).max(1);
+
+    let mut out = String::new();
+    for (index, block) in days.chunks(per_block).enumerate() {
+        if index > 0 {
+            out.push('\n');
+        }
+        let headers: &[&str] = if wide { &["%a %m-%d"] } else { &["%a", "%d"] };
+        for header in headers {
+            let mut line = " ".repeat(HOUR_WIDTH);
+            for day in block {
+                write!(
+                    line,
+                    "{: <column$} ",
+                    day.date.format(header),
+                    column = column
+                )
+                .unwrap();
+            }
+            writeln!(out, "{}", line.trim_end()).unwrap();
+        }
         for hour in 0..24 {
-            print!("{: <3}", hour);
+            let mut line = format!("{: <3}", (hour as i64 + hour_offset) % 24);
+            for day in block {
+                let cells = day.cells_with_marker("─");
+                for cell in &cells[hour * 3..hour * 3 + 3] {
+                    line.push_str(&cell.repeat(column / 3));
+                }
+                line.push(' ');
+            }
+            writeln!(out, "{}", line.trim_end()).unwrap();
         }
-        println!();
+        if totals && wide {
+            let mut line = " ".repeat(HOUR_WIDTH);
+            for day in block {
+                let tracked = day.tracked.unwrap_or_else(Duration::zero);
+                write!(
+                    line,
+                    "{: <column$} ",
+                    format_duration(tracked),
+                    column = column
+                )
+                .unwrap();
+            }
+            writeln!(out, "{}", line.trim_end()).unwrap();
+        }
+    }
+    if totals {
+        writeln!(out, "Total {}", format_duration(total(days))).unwrap();
+    }
+    out
+}
 
-        while cur_date <= end_date {
-            print!("{} ", cur_date.format("%a"));
-            for section in 0..(24 * 3) {
-                let hour = section / 3;
-                let minutes = (section % 3) * 20;
-                let cur_datetime = cur_date.and_hms(hour, minutes, 0);
-                let cur_tags = timesheet.tags_at_time(&cur_datetime.with_timezone(&Utc));
-                let matches = cur_tags
-                    .map(|x| tags.is_subset(&x) && !x.is_empty())
-                    .unwrap_or(false);
-
-                // Avoid highlighting the entire day
-                let in_past = cur_datetime <= now;
-
-                if matches && in_past {
-                    print!("█");
-                } else {
-                    print!(" ");
+fn total(days: &[ChartDay]) -> Duration {
+    days.iter()
+        .filter_map(|day| day.tracked)
+        .fold(Duration::zero(), |acc, tracked| acc + tracked)
+}
+
+/// When `date` starts, `day_offset` after its midnight by the clock on the
+/// wall, so that days on which daylight saving changes still start on time
+fn day_start(date: Date<Local>, day_offset: Duration) -> DateTime<Local> {
+    let naive = date.naive_local().and_hms(0, 0, 0) + day_offset;
+    Local
+        .from_local_datetime(&naive)
+        .earliest()
+        // The start of the day was skipped by a daylight saving change
+        .unwrap_or_else(|| {
+            (aggregate::midnight(date.naive_local(), &Local) + day_offset).with_timezone(&Local)
+        })
+}
+
+/// When the day after `date` starts, which is when `date` ends
+fn day_start_after(date: Date<Local>, day_offset: Duration) -> DateTime<Local> {
+    day_start(date + Duration::days(1), day_offset)
+}
+
+/// Find which sections of `date` were spent on events for which `matches` is
+/// true. The day starts `day_offset` after midnight.
+fn occupancy(
+    timesheet: &Timesheet,
+    matches: &dyn Fn(&BTreeSet<Tag>) -> bool,
+    date: Date<Local>,
+    day_offset: Duration,
+    now: DateTime<Local>,
+) -> Vec<bool> {
+    (0..SECTIONS_PER_DAY)
+        .map(|section| {
+            let cur_datetime =
+                day_start(date, day_offset) + Duration::minutes(i64::from(section) * 20);
+            let cur_tags = timesheet.tags_at_time(&cur_datetime.with_timezone(&Utc));
+
+            // Avoid highlighting the entire day
+            let in_past = cur_datetime <= now;
+
+            in_past && cur_tags.map(|x| matches(&x)).unwrap_or(false)
+        })
+        .collect()
+}
+
+impl Cmd {
+    /// The target of the first tag that every charted event has, whether it
+    /// is one of the positional tags or comes from the filter
+    fn target<'t>(&self, targets: &'t BTreeMap<Tag, Target>) -> Option<&'t Target> {
+        let expr = self.expr()?;
+        let required = expr.required_tags();
+        self.tags
+            .iter()
+            .chain(required.iter().copied())
+            .find_map(|tag| targets.get(tag))
+    }
+
+    /// The expression that events have to match to go in the chart: all of
+    /// the positional tags, the filter, one of the groups, and none of the
+    /// excluded tags. `None` if every event goes in.
+    fn expr(&self) -> Option<TagExpr> {
+        let groups = self.groups.iter().map(|group| group.filter.clone()).fold(
+            None,
+            |acc: Option<TagExpr>, filter| match acc {
+                None => Some(filter),
+                Some(acc) => Some(acc.or(filter)),
+            },
+        );
+        let excluded = self
+            .exclude
+            .iter()
+            .map(|tag| TagExpr::Not(Box::new(TagExpr::tag(tag.clone()))));
+        TagExpr::all(self.tags.iter().cloned())
+            .into_iter()
+            .chain(self.filter.clone())
+            .chain(groups)
+            .chain(excluded)
+            .fold(None, |acc, expr| match acc {
+                None => Some(expr),
+                Some(acc) => Some(acc.and(expr)),
+            })
+    }
+}
+
+/// A line for each group with its symbol, its name, and the tag expression
+/// it matches, if that isn't its name, and a last line for the time in none
+/// of them
+fn legend(groups: &[ChartGroup], symbols: &[char], colors: &[Color]) -> String {
+    let width = groups.iter().map(|g| g.name().len()).max().unwrap_or(0);
+    let mut out = String::new();
+    for (index, group) in groups.iter().enumerate() {
+        let name = group.name();
+        let filter = group.filter.to_string();
+        let line = if name == filter {
+            name
+        } else {
+            format!("{: <width$}  {}", name, filter, width = width)
+        };
+        writeln!(out, "{} {}", style(symbols[index]).fg(colors[index]), line).unwrap();
+    }
+    writeln!(out, "{} other", UNGROUPED_SYMBOL).unwrap();
+    out
+}
+
+/// For each section of `date`, the index of the group with the most time in
+/// it, among the events for which `matches` is true. Events go in the first
+/// group that matches them.
+fn dominant_groups(
+    segments: &[Segment],
+    matches: &dyn Fn(&BTreeSet<Tag>) -> bool,
+    groups: &[ChartGroup],
+    date: Date<Local>,
+    day_offset: Duration,
+) -> Vec<Option<usize>> {
+    let day_end = day_start_after(date, day_offset).with_timezone(&Utc);
+    let day_start = day_start(date, day_offset).with_timezone(&Utc);
+    let segments: Vec<(&Segment, usize)> = segments
+        .iter()
+        .filter(|s| s.start_time < day_end && s.end_time > day_start)
+        .filter(|s| matches(&s.tags))
+        .filter_map(|s| {
+            let group = groups.iter().position(|g| g.filter.matches(&s.tags))?;
+            Some((s, group))
+        })
+        .collect();
+    (0..SECTIONS_PER_DAY)
+        .map(|section| {
+            let start = day_start + Duration::minutes(i64::from(section) * 20);
+            let end = start + Duration::minutes(20);
+            let mut times = vec![Duration::zero(); groups.len()];
+            for (segment, group) in segments.iter() {
+                let overlap = segment.end_time.min(end) - segment.start_time.max(start);
+                if overlap > Duration::zero() {
+                    times[*group] = times[*group] + overlap;
                 }
             }
-            println!();
-            cur_date = cur_date + chrono::Duration::days(1);
+            // Ties go to the group listed first
+            let (group, time) = times
+                .iter()
+                .enumerate()
+                .max_by_key(|(group, time)| (**time, std::cmp::Reverse(*group)))?;
+            Some(group).filter(|_| *time > Duration::zero())
+        })
+        .collect()
+}
+
+/// The colour or symbol of each group, with the groups that didn't pick one
+/// given the first of `fallbacks` that no other group has
+fn pick<T: Copy + PartialEq>(chosen: impl Iterator<Item = Option<T>>, fallbacks: &[T]) -> Vec<T> {
+    let chosen: Vec<Option<T>> = chosen.collect();
+    let mut taken: Vec<T> = chosen.iter().flatten().copied().collect();
+    chosen
+        .into_iter()
+        .enumerate()
+        .map(|(index, choice)| {
+            choice.unwrap_or_else(|| {
+                let fallback = fallbacks
+                    .iter()
+                    .copied()
+                    .find(|fallback| !taken.contains(fallback))
+                    .unwrap_or(fallbacks[index % fallbacks.len()]);
+                taken.push(fallback);
+                fallback
+            })
+        })
+        .collect()
+}
+
+fn color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Color>, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    let color = match text.as_str() {
+        "black" => Color::Black,
+        "red" => Color::Red,
+        "green" => Color::Green,
+        "yellow" => Color::Yellow,
+        "blue" => Color::Blue,
+        "magenta" => Color::Magenta,
+        "cyan" => Color::Cyan,
+        "white" => Color::White,
+        _ => text.parse().map(Color::Color256).map_err(|_| {
+            de::Error::custom(format!(
+                "unknown colour \"{}\"; expected a name like \"blue\" or a number from 0 to 255",
+                text
+            ))
+        })?,
+    };
+    Ok(Some(color))
+}
+
+/// Parse how far back to look for a comparison, like "2 weeks ago" or "7days".
+/// Only whole days are used.
+fn parse_offset(text: &str) -> Result<Duration, String> {
+    let text = text.trim();
+    let text = text.strip_suffix("ago").unwrap_or(text).trim();
+    let duration = ::parse_duration::parse(text)
+        .ok()
+        .and_then(|d| Duration::from_std(d).ok())
+        .ok_or_else(|| format!("invalid duration \"{}\"", text))?;
+    match duration.num_days() {
+        0 => Err(String::from("comparisons must be at least one day apart")),
+        days => Ok(Duration::days(days)),
+    }
+}
+
+/// Sum the time between `start` and `end` spent on events for which `matches` is true
+fn matched_duration(
+    timesheet: &Timesheet,
+    matches: &dyn Fn(&BTreeSet<Tag>) -> bool,
+    start: DateTime<Local>,
+    end: DateTime<Local>,
+) -> Duration {
+    let start = start.with_timezone(&Utc);
+    let end = end.with_timezone(&Utc);
+    timesheet
+        .segments()
+        .into_iter()
+        .filter(|s| matches(&s.tags))
+        .map(|s| s.end_time.min(end) - s.start_time.max(start))
+        .filter(|d| *d > Duration::zero())
+        .fold(Duration::zero(), |acc, d| acc + d)
+}
+
+fn print_target_header(name: &str, tracked: Duration, target: Option<Duration>) {
+    match target {
+        Some(target) if target > Duration::zero() => println!(
+            "{}: {} tracked, {}% of {} target",
+            name,
+            format_duration(tracked),
+            tracked.num_minutes() * 100 / target.num_minutes().max(1),
+            format_duration(target)
+        ),
+        _ => println!("{}: {} tracked", name, format_duration(tracked)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn offset_with_ago() {
+        assert_eq!(parse_offset("2 weeks ago"), Ok(Duration::days(14)));
+        assert_eq!(parse_offset("3days"), Ok(Duration::days(3)));
+    }
+
+    #[test]
+    fn days_start_at_day_start_on_the_wall_clock() {
+        let date = Local.ymd(2020, 3, 8);
+        let start = day_start(date, Duration::hours(4));
+        assert_eq!(start.naive_local(), date.naive_local().and_hms(4, 0, 0));
+        assert_eq!(
+            day_start_after(date, Duration::hours(4)).naive_local(),
+            date.succ().naive_local().and_hms(4, 0, 0)
+        );
+    }
+
+    #[test]
+    fn targets_are_found_through_the_filter() {
+        let mut targets = BTreeMap::new();
+        targets.insert(String::from("work"), Target::parse("40h/week").unwrap());
+        let target = |args: &[&str]| Cmd::from_iter_safe(args).unwrap().target(&targets).cloned();
+        assert_eq!(target(&["chart", "work"]), targets.get("work").cloned());
+        assert_eq!(
+            target(&["chart", "--filter", "work and not meeting"]),
+            targets.get("work").cloned()
+        );
+        assert_eq!(target(&["chart", "--filter", "work or home"]), None);
+    }
+
+    #[test]
+    fn offset_too_small() {
+        assert!(parse_offset("5 hours ago").is_err());
+    }
+
+    #[test]
+    fn exclude_wins_over_filters() {
+        let cmd = Cmd::from_iter_safe(&[
+            "chart",
+            "--filter",
+            "home or away",
+            "--exclude",
+            "sleep,off",
+        ])
+        .unwrap();
+        let expr = cmd.expr().unwrap();
+        let set = |tags: &[&str]| tags.iter().map(|t| t.to_string()).collect::<BTreeSet<_>>();
+        assert!(expr.matches(&set(&["home"])));
+        assert!(!expr.matches(&set(&["home", "sleep"])));
+        assert!(!expr.matches(&set(&["away", "off"])));
+        assert!(!expr.matches(&set(&["work"])));
+    }
+
+    #[test]
+    fn positional_tags_are_a_shorthand() {
+        let expr = |args: &[&str]| Cmd::from_iter_safe(args).unwrap().expr();
+        assert_eq!(expr(&["chart"]), None);
+        assert_eq!(
+            expr(&["chart", "work", "coding"]),
+            expr(&["chart", "--filter", "work and coding"])
+        );
+        assert_eq!(
+            expr(&["chart", "augr", "--filter", "coding or review"])
+                .unwrap()
+                .to_string(),
+            "augr and (coding or review)"
+        );
+        assert_eq!(
+            expr(&[
+                "chart",
+                "--group",
+                "a:work",
+                "--group",
+                "b:gym",
+                "--exclude",
+                "off"
+            ])
+            .unwrap()
+            .to_string(),
+            "(work or gym) and not off"
+        );
+    }
+
+    #[test]
+    fn marker_at_current_time() {
+        console::set_colors_enabled(false);
+        let day = ChartDay {
+            date: Local.ymd(2019, 7, 22),
+            cells: vec![String::from("█"); SECTIONS_PER_DAY as usize],
+            tracked: None,
+            now: Some(2),
+        };
+        let chart = render_horizontal(&[day], 0);
+        assert!(chart
+            .lines()
+            .nth(1)
+            .unwrap()
+            .starts_with("Mon 07-22 ██|███"));
+    }
+
+    #[test]
+    fn vertical_chart_fits_width() {
+        let day = |d, tracked| {
+            let mut cells = vec![String::from(" "); SECTIONS_PER_DAY as usize];
+            cells[27] = String::from("█");
+            ChartDay {
+                date: Local.ymd(2019, 7, d),
+                cells,
+                tracked,
+                now: None,
+            }
+        };
+        let days = vec![
+            day(22, Some(Duration::minutes(20))),
+            day(23, Some(Duration::zero())),
+        ];
+        let wide = render_vertical(&days, 0, 80);
+        let lines: Vec<&str> = wide.lines().collect();
+        assert_eq!(lines[0], "   Mon 07-22 Tue 07-23");
+        assert_eq!(lines[10], "9  ███       ███");
+        assert_eq!(lines[25], "   20m       0m");
+        assert_eq!(lines[26], "Total 20m");
+
+        let narrow = render_vertical(&days, 0, 10);
+        let lines: Vec<&str> = narrow.lines().collect();
+        assert_eq!(lines[0], "   Mon");
+        assert_eq!(lines[1], "   22");
+        assert_eq!(lines[11], "9  █");
+        assert_eq!(lines[26], "");
+        assert_eq!(lines[27], "   Tue");
+        assert_eq!(lines.last(), Some(&"Total 20m"));
+    }
+
+    #[test]
+    fn legend_maps_symbols_to_filters() {
+        console::set_colors_enabled(false);
+        let groups = vec![
+            "exercise:gym or run".parse::<ChartGroup>().unwrap(),
+            "work:work".parse().unwrap(),
+        ];
+        assert_eq!(
+            legend(&groups, &['█', '●'], &[Color::Blue, Color::Green]),
+            "█ exercise  gym or run\n● work\n▒ other\n"
+        );
+    }
+
+    #[test]
+    fn group_from_args() {
+        let group: ChartGroup = "exercise:gym or run".parse().unwrap();
+        assert_eq!(group.name.as_deref(), Some("exercise"));
+        assert_eq!(group.filter, "gym or run".parse().unwrap());
+        assert!("gym".parse::<ChartGroup>().is_err());
+        assert!(":gym".parse::<ChartGroup>().is_err());
+
+        let symbols = pick(vec![None, Some('█'), None].into_iter(), &FALLBACK_SYMBOLS);
+        assert_eq!(symbols, vec!['▓', '█', '●']);
+    }
+
+    #[test]
+    fn cells_take_the_dominant_group() {
+        #[derive(Deserialize)]
+        struct Groups {
+            chart_group: Vec<ChartGroup>,
         }
+        let groups = toml::de::from_str::<Groups>(
+            r#"
+            [[chart_group]]
+            filter = "meeting"
+            [[chart_group]]
+            filter = "work"
+            color = "blue"
+            [[chart_group]]
+            filter = "gym"
+            color = "208"
+            "#,
+        )
+        .unwrap()
+        .chart_group;
+        assert_eq!(
+            pick(groups.iter().map(|g| g.color), &FALLBACK_COLORS),
+            vec![Color::Green, Color::Blue, Color::Color256(208)]
+        );
+
+        let time = |h, m| Local.ymd(2019, 7, 24).and_hms(h, m, 0).with_timezone(&Utc);
+        let segment = |start, end, tags: &[&str]| Segment {
+            event_ref: "a".into(),
+            start_time: start,
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            duration: end - start,
+            end_time: end,
+        };
+        let segments = vec![
+            segment(time(0, 0), time(0, 5), &["work", "meeting"]),
+            segment(time(0, 5), time(0, 30), &["work"]),
+            segment(time(0, 30), time(0, 40), &["lunch"]),
+        ];
+        let dominant = dominant_groups(
+            &segments,
+            &|_| true,
+            &groups,
+            Local.ymd(2019, 7, 24),
+            Duration::zero(),
+        );
+        assert_eq!(&dominant[..3], &[Some(1), Some(1), None]);
+
+        let only_meetings = dominant_groups(
+            &segments,
+            &|tags| tags.contains("meeting"),
+            &groups,
+            Local.ymd(2019, 7, 24),
+            Duration::zero(),
+        );
+        assert_eq!(&only_meetings[..2], &[Some(0), None]);
     }
 }
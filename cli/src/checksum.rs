@@ -0,0 +1,376 @@
+use crate::time_input::{parse_default_local, RangeError, RangeGuard};
+use augr_core::{
+    repository::event::Resolution,
+    store::{meta::DayChecksums, SyncFolderStore},
+    Clock, PatchRef, Repository, Timesheet,
+};
+use chrono::{DateTime, Duration, Local, NaiveDate, Utc};
+use sha2::{Digest, Sha256};
+use snafu::{ResultExt, Snafu};
+use std::collections::{BTreeMap, BTreeSet};
+use structopt::StructOpt;
+
+/// How many days back the checksums saved in the meta file go
+const RECENT_DAYS: i64 = 60;
+
+#[derive(StructOpt, Debug)]
+pub struct Cmd {
+    /// The first day to show checksums for. Defaults to a week ago.
+    #[structopt(long = "start", parse(try_from_os_str = parse_default_local))]
+    start: Option<DateTime<Local>>,
+
+    /// The last day to show checksums for. Defaults to today.
+    #[structopt(long = "end", parse(try_from_os_str = parse_default_local))]
+    end: Option<DateTime<Local>>,
+
+    #[structopt(flatten)]
+    guard: RangeGuard,
+}
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("{}", source))]
+    LongRange { source: RangeError },
+}
+
+/// The number of events and the checksum of a day
+struct Day {
+    events: usize,
+    checksum: String,
+}
+
+impl Cmd {
+    pub fn exec(
+        &self,
+        repo: &Repository<SyncFolderStore>,
+        timesheet: &Timesheet,
+    ) -> Result<(), Error> {
+        let now = timesheet.clock().now();
+        let start = self
+            .start
+            .map(|start| start.with_timezone(&Utc))
+            .unwrap_or_else(|| now - Duration::days(7))
+            .naive_utc()
+            .date();
+        let end = self.end.map_or(now, |end| end.with_timezone(&Utc));
+        let end = end.naive_utc().date();
+        self.guard.check(start, end).context(LongRange {})?;
+
+        let days = days(timesheet, start, end);
+        println!("Date (UTC)  Events Checksum");
+        println!("――――――――――  ―――――― ――――――――――――――――");
+        for (date, day) in days.iter() {
+            println!("{}  {: >6} {}", date, day.events, day.checksum);
+        }
+
+        let ours = DayChecksums {
+            patches: patches_digest(repo.loaded_patches()),
+            since: start,
+            days: days.into_iter().map(|(d, day)| (d, day.checksum)).collect(),
+        };
+        let our_patches: BTreeSet<PatchRef> = repo.loaded_patches().copied().collect();
+        for other in other_checksums(repo) {
+            let differing: Vec<String> = disagreements(&ours, &other.checksums)
+                .into_iter()
+                .filter(|date| *date <= end)
+                .map(|date| date.to_string())
+                .collect();
+            if differing.is_empty() {
+                println!("{} agrees on every day it has checked", other.name);
+            } else {
+                println!("{} disagrees about {}", other.name, differing.join(", "));
+            }
+            for line in patch_differences(&our_patches, &other.patches) {
+                println!("  {}", line);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Checksums of the last few days, to save in the meta file. They take a while
+/// to work out, so they are only made by `augr checksum`, after a sync brings
+/// in new patches, and after this device adds patches.
+pub fn recent<'a>(
+    patches: impl Iterator<Item = &'a PatchRef>,
+    timesheet: &Timesheet,
+) -> DayChecksums {
+    let today = timesheet.clock().now().naive_utc().date();
+    let since = today - Duration::days(RECENT_DAYS);
+    DayChecksums {
+        patches: patches_digest(patches),
+        since,
+        days: days(timesheet, since, today)
+            .into_iter()
+            .map(|(date, day)| (date, day.checksum))
+            .collect(),
+    }
+}
+
+/// The checksums of the patches that `repo` has loaded now, for saving along
+/// with them once this device has added its own
+pub fn current(
+    repo: &Repository<SyncFolderStore>,
+    resolution: Resolution,
+    clock: &dyn Clock,
+) -> DayChecksums {
+    let (timesheet, _errors) = repo.timesheet().flatten_lossy(resolution);
+    recent(repo.loaded_patches(), &timesheet.with_clock(clock))
+}
+
+/// Print a warning for each device that sees different events on some day,
+/// along with the patches that one of the devices hasn't loaded
+pub fn warn_disagreements(repo: &Repository<SyncFolderStore>, ours: &DayChecksums) {
+    let our_patches: BTreeSet<PatchRef> = repo.loaded_patches().copied().collect();
+    for (other, differing) in disagreeing(repo, ours) {
+        let dates: Vec<String> = differing.iter().map(|d| d.to_string()).collect();
+        eprintln!(
+            "warning: {} sees different events on {}; see `augr checksum`",
+            other.name,
+            dates.join(", ")
+        );
+        for line in patch_differences(&our_patches, &other.patches) {
+            eprintln!("  {}", line);
+        }
+    }
+}
+
+/// The other devices that disagree with `ours` about some days, and the days
+fn disagreeing(
+    repo: &Repository<SyncFolderStore>,
+    ours: &DayChecksums,
+) -> Vec<(Other, Vec<NaiveDate>)> {
+    other_checksums(repo)
+        .into_iter()
+        .map(|other| {
+            let differing = disagreements(ours, &other.checksums);
+            (other, differing)
+        })
+        .filter(|(_, differing)| !differing.is_empty())
+        .collect()
+}
+
+/// The checksums that another device saved, and the patches it had loaded
+/// when it saved them
+struct Other {
+    name: String,
+    checksums: DayChecksums,
+    patches: BTreeSet<PatchRef>,
+}
+
+/// The saved checksums of every other device. Checksums that were made before
+/// the device last added patches don't describe its events any more, so they
+/// are left out.
+fn other_checksums(repo: &Repository<SyncFolderStore>) -> Vec<Other> {
+    let metas = match repo.store().get_other_device_metas() {
+        Ok(metas) => metas,
+        Err(_) => return Vec::new(),
+    };
+    metas
+        .filter_map(Result::ok)
+        .filter_map(|(device_id, meta)| {
+            let checksums = meta.day_checksums()?.clone();
+            if checksums.patches != patches_digest(meta.patches()) {
+                return None;
+            }
+            let name = repo
+                .meta()
+                .device_name(&device_id)
+                .map(String::from)
+                .unwrap_or(device_id);
+            Some(Other {
+                name,
+                checksums,
+                patches: meta.patches().copied().collect(),
+            })
+        })
+        .collect()
+}
+
+/// The days that both checksums cover, but disagree about
+fn disagreements(ours: &DayChecksums, theirs: &DayChecksums) -> Vec<NaiveDate> {
+    let since = ours.since.max(theirs.since);
+    let mut dates: Vec<NaiveDate> = ours
+        .days
+        .keys()
+        .chain(theirs.days.keys())
+        .filter(|date| **date >= since)
+        .filter(|date| ours.days.get(date) != theirs.days.get(date))
+        .copied()
+        .collect();
+    dates.sort();
+    dates.dedup();
+    dates
+}
+
+/// The events that start on each UTC day from `start` to `end`. Days without
+/// events are left out.
+fn days(timesheet: &Timesheet, start: NaiveDate, end: NaiveDate) -> BTreeMap<NaiveDate, Day> {
+    let mut canonical: BTreeMap<NaiveDate, (usize, String)> = BTreeMap::new();
+    for segment in timesheet.segments() {
+        let date = segment.start_time.naive_utc().date();
+        if date < start || date > end {
+            continue;
+        }
+        let tags: Vec<&str> = segment.tags.iter().map(String::as_str).collect();
+        let (events, text) = canonical.entry(date).or_default();
+        *events += 1;
+        // End times depend on the clock, so they are left out
+        text.push_str(&format!(
+            "{}\t{}\t{}\n",
+            segment.event_ref,
+            segment.start_time.to_rfc3339(),
+            tags.join(",")
+        ));
+    }
+    canonical
+        .into_iter()
+        .map(|(date, (events, text))| {
+            let checksum = hex::encode(&Sha256::digest(text.as_bytes())[..8]);
+            (date, Day { events, checksum })
+        })
+        .collect()
+}
+
+/// Lines naming the patches that only one of the devices has loaded, which
+/// explain why they see different events
+fn patch_differences(ours: &BTreeSet<PatchRef>, theirs: &BTreeSet<PatchRef>) -> Vec<String> {
+    let list = |patches: Vec<&PatchRef>| -> String {
+        let patches: Vec<String> = patches.iter().map(ToString::to_string).collect();
+        patches.join(", ")
+    };
+    let missing: Vec<&PatchRef> = ours.difference(theirs).collect();
+    let extra: Vec<&PatchRef> = theirs.difference(ours).collect();
+    let mut lines = Vec::new();
+    if !missing.is_empty() {
+        lines.push(format!("it hasn't loaded {}", list(missing)));
+    }
+    if !extra.is_empty() {
+        lines.push(format!("this device hasn't loaded {}", list(extra)));
+    }
+    lines
+}
+
+fn patches_digest<'a>(patches: impl Iterator<Item = &'a PatchRef>) -> String {
+    let mut patches: Vec<&PatchRef> = patches.collect();
+    patches.sort();
+    let mut hasher = Sha256::new();
+    for patch in patches {
+        hasher.update(patch.to_string().as_bytes());
+    }
+    hex::encode(&hasher.finalize()[..8])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::Tracked;
+    use augr_core::{FixedClock, Patch, Tag};
+    use chrono::TimeZone;
+    use std::{fs, process};
+
+    #[test]
+    fn devices_agree_after_adding_their_own_patches() {
+        let root = std::env::temp_dir().join(format!("augr-checksums-{}", process::id()));
+        let time = |h| Utc.ymd(2019, 7, 24).and_hms(h, 0, 0);
+        // Run a command on `device` the way main does, and return the devices
+        // it warns about. `refresh` is false for versions of augr that didn't
+        // make checksums after adding patches.
+        let run = |device: &str, hour, tags: Option<&[&str]>, refresh: bool| {
+            let clock = FixedClock(time(hour));
+            let store = SyncFolderStore::new(root.clone(), device.into()).should_init(true);
+            let mut repo = Repository::from_store(store)
+                .unwrap()
+                .with_clock(Box::new(clock));
+            repo.save_meta().unwrap();
+            let before = repo.loaded_patches().count();
+            repo.try_sync_data().unwrap();
+            let mut checksums = None;
+            let mut warned = Vec::new();
+            if repo.loaded_patches().count() > before {
+                let ours = current(&repo, Resolution::Manual, &clock);
+                warned = disagreeing(&repo, &ours)
+                    .into_iter()
+                    .map(|(other, _)| other.name)
+                    .collect();
+                checksums = Some(ours);
+            }
+            if let Some(tags) = tags {
+                let tags: Vec<Tag> = tags.iter().map(|t| t.to_string()).collect();
+                let event = augr_core::id::new_id().to_string();
+                repo.begin().unwrap();
+                repo.add_patch(Patch::new().create_event(event, time(hour), tags))
+                    .unwrap();
+                repo.commit().unwrap();
+                if refresh {
+                    checksums = Some(current(&repo, Resolution::Manual, &clock));
+                }
+            }
+            if let Some(checksums) = checksums {
+                repo.meta_mut().set_day_checksums(checksums);
+            }
+            repo.save_meta().unwrap();
+            warned
+        };
+
+        for refresh in [true, false].iter().copied() {
+            assert!(run("a", 9, Some(&["work"]), refresh).is_empty());
+            assert!(run("b", 10, None, refresh).is_empty());
+            assert!(run("b", 11, Some(&["meeting"]), refresh).is_empty());
+            assert!(run("a", 12, None, refresh).is_empty());
+            fs::remove_dir_all(&root).unwrap();
+        }
+    }
+
+    #[test]
+    fn checksums_cover_events_by_day() {
+        let time = |d, h| Utc.ymd(2019, 7, d).and_hms(h, 0, 0);
+        let create = Patch::new()
+            .create_event("a".into(), time(1, 9), vec!["work".into()])
+            .create_event("b".into(), time(1, 12), vec!["lunch".into()])
+            .create_event("c".into(), time(3, 9), vec!["work".into()]);
+        let mut tracked = Tracked::new(&[&create]);
+        let before = days(
+            &tracked.timesheet(),
+            time(1, 0).naive_utc().date(),
+            time(3, 0).naive_utc().date(),
+        );
+        assert_eq!(before.len(), 2);
+        assert_eq!(before[&time(1, 0).naive_utc().date()].events, 2);
+
+        let tag = Patch::new().add_tag(*create.patch_ref(), "b".into(), "break".into());
+        tracked.apply(&tag);
+        let after = days(
+            &tracked.timesheet(),
+            time(1, 0).naive_utc().date(),
+            time(3, 0).naive_utc().date(),
+        );
+        let checksums = |days: &BTreeMap<NaiveDate, Day>| DayChecksums {
+            patches: String::new(),
+            since: time(1, 0).naive_utc().date(),
+            days: days
+                .iter()
+                .map(|(d, day)| (*d, day.checksum.clone()))
+                .collect(),
+        };
+        assert_eq!(
+            disagreements(&checksums(&before), &checksums(&after)),
+            vec![time(1, 0).naive_utc().date()]
+        );
+    }
+
+    #[test]
+    fn differences_name_the_missing_patches() {
+        let patches: Vec<PatchRef> = (0..3).map(|_| *Patch::new().patch_ref()).collect();
+        let ours: BTreeSet<PatchRef> = vec![patches[0], patches[1]].into_iter().collect();
+        let theirs: BTreeSet<PatchRef> = vec![patches[0], patches[2]].into_iter().collect();
+        assert_eq!(
+            patch_differences(&ours, &theirs),
+            vec![
+                format!("it hasn't loaded {}", patches[1]),
+                format!("this device hasn't loaded {}", patches[2]),
+            ]
+        );
+        assert!(patch_differences(&ours, &ours).is_empty());
+    }
+}
@@ -0,0 +1,102 @@
+use crate::device;
+use augr_core::{store::SyncFolderStore, Repository};
+use clap::{App, Shell};
+use snafu::{ResultExt, Snafu};
+use std::{collections::BTreeSet, io};
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct Cmd {
+    /// The shell to generate completions for. Only bash completes tags,
+    /// events, and devices; other shells complete commands and flags.
+    #[structopt(possible_values = &Shell::variants(), case_insensitive = true)]
+    shell: Shell,
+}
+
+impl Cmd {
+    pub fn exec(&self, mut app: App) {
+        app.gen_completions_to("augr", self.shell, &mut io::stdout());
+        if let Shell::Bash = self.shell {
+            print!("{}", BASH_DYNAMIC);
+        }
+    }
+}
+
+/// Wraps the generated bash completion function, asking `augr complete` for
+/// the values of arguments that depend on the timesheet.
+const BASH_DYNAMIC: &str = r#"
+_augr_dynamic() {
+    local cur="${COMP_WORDS[COMP_CWORD]}"
+    local kind=""
+    case "${COMP_WORDS[1]}" in
+        start|summary|chart)
+            kind=tags
+            ;;
+        tag)
+            if [[ ${COMP_CWORD} -eq 2 ]]; then kind=events; else kind=tags; fi
+            ;;
+        annotate|set-start|delete|edit|history)
+            if [[ ${COMP_CWORD} -eq 2 ]]; then kind=events; fi
+            ;;
+        device)
+            if [[ ${COMP_CWORD} -eq 3 ]]; then kind=devices; fi
+            ;;
+    esac
+    if [[ -n "${kind}" && "${COMP_WORDS[COMP_CWORD-1]}" != -* && "${cur}" != --* ]]; then
+        # Keep the + or - of `augr tag` changes in front of the tag
+        local prefix=""
+        if [[ "${kind}" == tags && "${cur}" == [+-]* ]]; then
+            prefix="${cur:0:1}"
+            cur="${cur:1}"
+        fi
+        COMPREPLY=( $(compgen -P "${prefix}" -W "$(augr complete ${kind} 2>/dev/null)" -- "${cur}") )
+        return 0
+    fi
+    _augr "$@"
+}
+
+complete -F _augr_dynamic -o bashdefault -o default augr
+"#;
+
+#[derive(StructOpt, Debug)]
+pub struct CompleteCmd {
+    /// What to list: tags, events, or devices
+    #[structopt(possible_values = &["tags", "events", "devices"])]
+    kind: String,
+}
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("{}", source))]
+    Devices { source: device::Error },
+}
+
+impl CompleteCmd {
+    pub fn exec(&self, repo: &Repository<SyncFolderStore>) -> Result<(), Error> {
+        let events = repo
+            .timesheet()
+            .events
+            .iter()
+            .filter(|(_, event)| !event.is_deleted());
+        match self.kind.as_str() {
+            "tags" => {
+                let tags: BTreeSet<_> = events
+                    .flat_map(|(_, event)| event.tags())
+                    .map(|(_, tag)| tag)
+                    .collect();
+                tags.iter().for_each(|tag| println!("{}", tag));
+            }
+            "events" => events.for_each(|(event_ref, _)| println!("{}", event_ref)),
+            "devices" => {
+                for info in device::devices(repo).context(Devices {})? {
+                    if let Some(name) = repo.meta().device_name(&info.id) {
+                        println!("{}", name);
+                    }
+                    println!("{}", info.id);
+                }
+            }
+            _ => unreachable!("clap checks the possible values"),
+        }
+        Ok(())
+    }
+}
@@ -1,15 +1,402 @@
-use serde::Deserialize;
+use crate::{
+    chart::ChartGroup,
+    invoice::Rate,
+    rules::{self, Rule},
+    target::Target,
+};
+use augr_core::{
+    aggregate::Period,
+    duration::{Clock, DecimalHours, HoursMinutes, Rounding},
+    repository::event::Resolution,
+    DurationFormat, EndPolicy, Tag, TagExpr, Zone,
+};
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveTime, Weekday};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use snafu::{ResultExt, Snafu};
 use std::{
+    collections::BTreeMap,
     fs::read_to_string,
     io,
     path::{Path, PathBuf},
+    str::FromStr,
 };
 
 #[derive(Deserialize)]
 pub struct Conf {
     pub sync_folder: PathBuf,
     pub device_id: String,
+
+    /// How much time should be spent on each tag, e.g. `work = "40h/week"`
+    #[serde(default, rename = "target")]
+    pub targets: BTreeMap<Tag, Target>,
+
+    /// The local time that days start at, like "04:00" to count time tracked
+    /// after midnight toward the day before. Used by summary, chart, report,
+    /// and stats wherever time is split up by day. Defaults to midnight.
+    #[serde(default, deserialize_with = "time_of_day")]
+    pub day_start: Option<NaiveTime>,
+
+    /// The day that weeks start on, like "sun". Used by week, month, report,
+    /// summary, heatmap, and budget. Defaults to Monday.
+    #[serde(default = "default_week_start", deserialize_with = "weekday")]
+    pub week_starts_on: Weekday,
+
+    /// The IANA timezone to show and read times in, like "Europe/Berlin" or
+    /// "UTC", unless `--tz` or `--utc` is given. Defaults to the system's.
+    /// Zones other than UTC need the system's timezone database, so they
+    /// only work on Unix-like systems.
+    #[serde(default)]
+    pub timezone: Option<String>,
+
+    /// How durations are written everywhere, unless `--duration-format` is
+    /// given
+    #[serde(default)]
+    pub duration_format: DurationFormatConf,
+
+    /// When events end: "next-event" (the default), "end-of-day", or a
+    /// maximum duration like "8h"
+    #[serde(default)]
+    pub end_policy: EndPolicyConf,
+
+    /// Round the start times entered in `start` and `set-start` to the nearest
+    /// multiple of this duration, like "5m". The exact time is kept in the
+    /// patch's metadata.
+    #[serde(default, deserialize_with = "optional_duration")]
+    pub snap_starts: Option<Duration>,
+
+    /// How to pick a start time for events that were moved on two devices at
+    /// once: "manual" (the default), "earliest-wins", or "latest-patch-wins"
+    #[serde(default, deserialize_with = "resolution")]
+    pub conflict_resolution: Resolution,
+
+    /// A file listing holidays, one `YYYY-MM-DD` date per line. Holidays and
+    /// vacation don't count towards targets.
+    #[serde(default)]
+    pub holidays: Option<PathBuf>,
+
+    /// A secret used to sign exports, so that `augr verify-export` can later
+    /// check them against the tracked time
+    #[serde(default)]
+    pub export_key: Option<String>,
+
+    /// Show how each command would change the events and ask before saving,
+    /// like `--interactive`
+    #[serde(default)]
+    pub confirm_changes: bool,
+
+    /// Warn about devices that haven't added a patch in this many days.
+    /// Defaults to 14.
+    #[serde(default = "default_stale_device_days")]
+    pub stale_device_days: i64,
+
+    /// Warn when the sync folder holds more patch files than this. Defaults
+    /// to 5000.
+    #[serde(default = "default_max_patch_files")]
+    pub max_patch_files: usize,
+
+    /// Warn when the patches take up more than this many megabytes on disk.
+    /// Defaults to 50.
+    #[serde(default = "default_max_store_megabytes")]
+    pub max_store_megabytes: u64,
+
+    /// Warn when a single source, like a hook or script, adds more than this
+    /// many patches in an hour. Defaults to 120.
+    #[serde(default = "default_max_patches_per_hour")]
+    pub max_patches_per_hour: usize,
+
+    /// Colours for the events matching tag expressions in `augr chart`
+    #[serde(default, rename = "chart_group")]
+    pub chart_groups: Vec<ChartGroup>,
+
+    /// Hourly rates for `augr invoice`, matched in order
+    #[serde(default, rename = "rate")]
+    pub rates: Vec<Rate>,
+
+    /// Rules that add tags to events as they are created or changed
+    #[serde(default, rename = "rule")]
+    pub rules: Vec<Rule>,
+
+    /// Named lists of tags for `augr start --template`, like
+    /// `meeting = ["work", "meeting", "no-code"]`
+    #[serde(default, rename = "template")]
+    pub templates: BTreeMap<String, Vec<Tag>>,
+
+    /// Short names for tags, like `ws = "workshop"`. The full name is recorded
+    /// whenever an alias is typed.
+    #[serde(default, rename = "alias")]
+    pub aliases: BTreeMap<Tag, Tag>,
+
+    /// The rounding that `augr report` uses when `--round` isn't given, like
+    /// "15m up per-event"
+    #[serde(default, deserialize_with = "rounding")]
+    pub rounding: Option<Rounding>,
+
+    /// When work is expected to be tracked, for `augr report --untracked`.
+    /// Defaults to 09:00-17:00, Monday to Friday.
+    #[serde(default)]
+    pub working_hours: WorkingHours,
+
+    /// Always leave the days that aren't in `working_hours.weekdays` out of
+    /// averages and targets, like `--workdays`
+    #[serde(default)]
+    pub workdays_only: bool,
+
+    /// Contract hours and carry-over rules for `augr overtime`
+    #[serde(default)]
+    pub overtime: Option<OvertimeConf>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct WorkingHours {
+    /// The local times that work starts and ends, like "09:00-17:00"
+    #[serde(default = "default_hours", deserialize_with = "time_range")]
+    pub hours: (NaiveTime, NaiveTime),
+
+    /// The days of the week that are worked, like ["mon", "tue"]
+    #[serde(default = "default_weekdays", deserialize_with = "rules::weekdays")]
+    pub weekdays: Vec<Weekday>,
+}
+
+impl Conf {
+    /// How long after midnight days start
+    pub fn day_offset(&self) -> Duration {
+        self.day_start
+            .map_or_else(Duration::zero, |time| time - NaiveTime::from_hms(0, 0, 0))
+    }
+
+    /// `period`, with weeks starting on `week_starts_on`
+    pub fn calendar(&self, period: Period) -> Period {
+        period.with_week_start(self.week_starts_on)
+    }
+
+    /// Weeks starting on `week_starts_on`
+    pub fn week(&self) -> Period {
+        Period::Week(self.week_starts_on)
+    }
+
+    /// The days of the week that count toward averages and targets, if
+    /// `--workdays` was given or `workdays_only` is set
+    pub fn workdays(&self, flag: bool) -> Option<Vec<Weekday>> {
+        if flag || self.workdays_only {
+            Some(self.working_hours.weekdays.clone())
+        } else {
+            None
+        }
+    }
+
+    /// The start of the day that `now` is in, following `day_start`
+    pub fn start_of_day(&self, now: DateTime<Local>) -> DateTime<Local> {
+        let offset = self.day_offset();
+        (now - offset).date().and_hms(0, 0, 0) + offset
+    }
+}
+
+impl Default for WorkingHours {
+    fn default() -> Self {
+        Self {
+            hours: default_hours(),
+            weekdays: default_weekdays(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct OvertimeConf {
+    /// The hours that should be worked, like "40h/week"
+    pub contract: Target,
+
+    /// Only count events with one of these tags. Defaults to all events.
+    #[serde(default)]
+    pub tags: Vec<Tag>,
+
+    /// The most overtime that can be banked, like "40h"
+    #[serde(default, deserialize_with = "optional_duration")]
+    pub cap: Option<Duration>,
+
+    /// Drop overtime that hasn't been used up after this many weeks
+    #[serde(default)]
+    pub expire_after_weeks: Option<usize>,
+
+    /// The date to start counting from, like "2019-07-01"
+    #[serde(default)]
+    pub start: Option<NaiveDate>,
+}
+
+fn default_hours() -> (NaiveTime, NaiveTime) {
+    (NaiveTime::from_hms(9, 0, 0), NaiveTime::from_hms(17, 0, 0))
+}
+
+fn default_weekdays() -> Vec<Weekday> {
+    vec![
+        Weekday::Mon,
+        Weekday::Tue,
+        Weekday::Wed,
+        Weekday::Thu,
+        Weekday::Fri,
+    ]
+}
+
+fn default_week_start() -> Weekday {
+    Weekday::Mon
+}
+
+fn default_stale_device_days() -> i64 {
+    14
+}
+
+fn default_max_patch_files() -> usize {
+    5000
+}
+
+fn default_max_store_megabytes() -> u64 {
+    50
+}
+
+fn default_max_patches_per_hour() -> usize {
+    120
+}
+
+fn parse_duration(text: &str) -> Option<Duration> {
+    ::parse_duration::parse(text)
+        .ok()
+        .and_then(|d| Duration::from_std(d).ok())
+}
+
+fn optional_duration<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Duration>, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    parse_duration(&text)
+        .map(Some)
+        .ok_or_else(|| de::Error::custom(format!("invalid duration \"{}\"", text)))
+}
+
+fn weekday<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Weekday, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    text.parse()
+        .map_err(|_| de::Error::custom(format!("invalid weekday \"{}\"", text)))
+}
+
+fn time_of_day<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<NaiveTime>, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    NaiveTime::parse_from_str(text.trim(), "%H:%M")
+        .map(Some)
+        .map_err(|_| de::Error::custom(format!("invalid time \"{}\"; expected HH:MM", text)))
+}
+
+fn time_range<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<(NaiveTime, NaiveTime), D::Error> {
+    let text = String::deserialize(deserializer)?;
+    rules::parse_time_range(&text)
+        .ok_or_else(|| de::Error::custom(format!("invalid time range \"{}\"", text)))
+}
+
+pub(crate) fn tag_expr<'de, D: Deserializer<'de>>(deserializer: D) -> Result<TagExpr, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    text.parse().map_err(de::Error::custom)
+}
+
+fn rounding<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Rounding>, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    text.parse().map(Some).map_err(de::Error::custom)
+}
+
+fn resolution<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Resolution, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    text.parse().map_err(de::Error::custom)
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum EndPolicyConf {
+    #[default]
+    NextEvent,
+    EndOfDay,
+    MaxDuration(Duration),
+}
+
+impl EndPolicyConf {
+    /// The policy, with days measured in the local time zone, starting
+    /// `day_offset` after midnight
+    pub fn to_end_policy(self, day_offset: Duration) -> EndPolicy {
+        match self {
+            EndPolicyConf::NextEvent => EndPolicy::NextEvent,
+            EndPolicyConf::EndOfDay => EndPolicy::EndOfDay {
+                zone: Zone::Local,
+                day_start: day_offset,
+            },
+            EndPolicyConf::MaxDuration(max) => EndPolicy::MaxDuration(max),
+        }
+    }
+}
+
+/// Written the way it's configured, with durations in seconds
+impl Serialize for EndPolicyConf {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            EndPolicyConf::NextEvent => serializer.serialize_str("next-event"),
+            EndPolicyConf::EndOfDay => serializer.serialize_str("end-of-day"),
+            EndPolicyConf::MaxDuration(max) => {
+                serializer.serialize_str(&format!("{}s", max.num_seconds()))
+            }
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for EndPolicyConf {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        match text.as_str() {
+            "next-event" => Ok(EndPolicyConf::NextEvent),
+            "end-of-day" => Ok(EndPolicyConf::EndOfDay),
+            _ => parse_duration(&text)
+                .map(EndPolicyConf::MaxDuration)
+                .ok_or_else(|| de::Error::custom(format!("invalid end policy \"{}\"", text))),
+        }
+    }
+}
+
+/// How durations are written: "hours-minutes" like `7h 45m` (the default),
+/// "decimal" like `7.75h`, or "clock" like `07:45`
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum DurationFormatConf {
+    #[default]
+    HoursMinutes,
+    Decimal,
+    Clock,
+}
+
+impl DurationFormatConf {
+    pub fn to_duration_format(self) -> Box<dyn DurationFormat> {
+        match self {
+            DurationFormatConf::HoursMinutes => Box::new(HoursMinutes),
+            DurationFormatConf::Decimal => Box::new(DecimalHours::default()),
+            DurationFormatConf::Clock => Box::new(Clock),
+        }
+    }
+}
+
+impl FromStr for DurationFormatConf {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hours-minutes" => Ok(DurationFormatConf::HoursMinutes),
+            "decimal" => Ok(DurationFormatConf::Decimal),
+            "clock" => Ok(DurationFormatConf::Clock),
+            _ => Err(format!(
+                "unknown duration format \"{}\"; expected hours-minutes, decimal, or clock",
+                s
+            )),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for DurationFormatConf {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        text.parse().map_err(de::Error::custom)
+    }
 }
 
 #[derive(Debug, Snafu)]
@@ -24,6 +411,37 @@ pub enum Error {
     },
 }
 
+/// Where systems usually keep their timezone database
+const ZONEINFO_DIRS: &[&str] = &[
+    "/usr/share/zoneinfo",
+    "/usr/lib/zoneinfo",
+    "/usr/share/lib/zoneinfo",
+    "/etc/zoneinfo",
+];
+
+/// The folders that timezones are looked up in: `TZDIR` if it's set, or else
+/// the usual places
+pub fn timezone_database() -> Vec<PathBuf> {
+    match std::env::var_os("TZDIR") {
+        Some(dir) => vec![PathBuf::from(dir)],
+        None => ZONEINFO_DIRS.iter().map(PathBuf::from).collect(),
+    }
+}
+
+/// Whether `name` is a timezone in the system's timezone database, like
+/// "Europe/Berlin"
+pub fn is_timezone(name: &str) -> bool {
+    if name == "UTC" {
+        return true;
+    }
+    if name.is_empty() || name.starts_with('/') || name.split('/').any(|part| part == "..") {
+        return false;
+    }
+    timezone_database()
+        .iter()
+        .any(|database| database.join(name).is_file())
+}
+
 pub fn load_config(path: &Path) -> Result<Conf, Error> {
     let conf_str = read_to_string(path).context(ReadConfiguration { path })?;
 
@@ -0,0 +1,410 @@
+use crate::{config::Conf, target::Target};
+use augr_core::{aggregate::Period, store::patch::AddAbsence, Patch, Timesheet};
+use chrono::{Datelike, Duration, NaiveDate, Weekday};
+use serde::{Deserialize, Serialize};
+use snafu::{ResultExt, Snafu};
+use std::{
+    collections::BTreeMap,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// Days that no time is expected to be tracked on: holidays from the
+/// `holidays` file in the config, and absences added with `augr absence add`.
+#[derive(Default, Debug)]
+pub struct DaysOff {
+    days: BTreeMap<NaiveDate, AbsenceKind>,
+    /// The days of the week that are worked, if the rest are left out of
+    /// targets
+    workdays: Option<Vec<Weekday>>,
+}
+
+/// Why someone wasn't working on a day
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AbsenceKind {
+    Vacation,
+    Sick,
+    Holiday,
+}
+
+/// An inclusive range of dates, written like `2019-07-01..2019-07-14`
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Deserialize)]
+pub struct DateRange {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+}
+
+/// An all-day marker for a range of days someone was absent
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Deserialize)]
+pub struct Absence {
+    pub kind: AbsenceKind,
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+}
+
+/// The folders in the sync folder that absences were kept in, a file for each
+/// device, before they were stored in patches. Vacation came first, and then
+/// absences of every kind.
+const LEGACY_FOLDERS: [&str; 2] = ["vacation", "absences"];
+
+/// A file in one of the `LEGACY_FOLDERS`
+#[derive(Default, Debug, Deserialize)]
+struct LegacyFile {
+    #[serde(default)]
+    vacation: Vec<DateRange>,
+    #[serde(default)]
+    absence: Vec<Absence>,
+}
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Unable to read {}: {}", path.display(), source))]
+    ReadFile { source: io::Error, path: PathBuf },
+
+    #[snafu(display("Invalid date \"{}\" in holidays file {}", text, path.display()))]
+    InvalidHoliday { text: String, path: PathBuf },
+
+    #[snafu(display("Invalid absence file {}: {}", path.display(), source))]
+    InvalidAbsences {
+        source: toml::de::Error,
+        path: PathBuf,
+    },
+
+    #[snafu(display("Unable to remove {}: {}", path.display(), source))]
+    RemoveFile { source: io::Error, path: PathBuf },
+}
+
+impl DaysOff {
+    /// The holidays in the config and the absences in `timesheet`. Devices
+    /// that haven't moved their absences into patches yet still have them in
+    /// the old files, so those are read too.
+    pub fn load(conf: &Conf, timesheet: &Timesheet) -> Result<Self, Error> {
+        let mut days_off = Self::default();
+        if let Some(path) = &conf.holidays {
+            for date in read_holidays(path)? {
+                days_off.days.insert(date, AbsenceKind::Holiday);
+            }
+        }
+
+        for absence in absences(conf, timesheet)? {
+            for date in absence.range().days() {
+                days_off.days.insert(date, absence.kind);
+            }
+        }
+        Ok(days_off)
+    }
+
+    /// Leave the days of the week that aren't `workdays` out of targets
+    pub fn with_workdays(self, workdays: Option<Vec<Weekday>>) -> Self {
+        Self { workdays, ..self }
+    }
+
+    pub fn contains(&self, date: NaiveDate) -> bool {
+        self.days.contains_key(&date)
+    }
+
+    /// Why `date` is a day off, if it is one
+    pub fn kind(&self, date: NaiveDate) -> Option<AbsenceKind> {
+        self.days.get(&date).cloned()
+    }
+
+    /// The days off from `start` to `end`, inclusive
+    pub fn between(
+        &self,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> impl Iterator<Item = (NaiveDate, AbsenceKind)> + '_ {
+        self.days
+            .range(start..=end.max(start))
+            .map(|(date, kind)| (*date, *kind))
+    }
+
+    /// The number of days from `start` to `end`, inclusive, that are not days
+    /// off, or outside the workdays if there are any
+    pub fn working_days(&self, start: NaiveDate, end: NaiveDate) -> i64 {
+        DateRange { start, end }
+            .days()
+            .filter(|date| !self.contains(*date) && self.is_workday(*date))
+            .count() as i64
+    }
+
+    /// Whether `date` is one of the workdays, or any day if there are none
+    pub fn is_workday(&self, date: NaiveDate) -> bool {
+        self.workdays
+            .as_ref()
+            .is_none_or(|w| w.contains(&date.weekday()))
+    }
+
+    /// How much of `target` should be tracked from `start` to `end`,
+    /// inclusive. Each period's target is spread over its workdays, and days
+    /// off get none of it.
+    pub fn goal(&self, target: &Target, start: NaiveDate, end: NaiveDate) -> Duration {
+        DateRange { start, end }
+            .days()
+            .filter(|date| !self.contains(*date))
+            .fold(Duration::zero(), |goal, date| {
+                goal + target.for_date(date, |day| self.is_workday(day))
+            })
+    }
+}
+
+impl AbsenceKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            AbsenceKind::Vacation => "vacation",
+            AbsenceKind::Sick => "sick",
+            AbsenceKind::Holiday => "holiday",
+        }
+    }
+}
+
+impl FromStr for AbsenceKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "vacation" => Ok(AbsenceKind::Vacation),
+            "sick" => Ok(AbsenceKind::Sick),
+            "holiday" => Ok(AbsenceKind::Holiday),
+            _ => Err(format!(
+                "unknown absence \"{}\"; expected vacation, sick, or holiday",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for AbsenceKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl Absence {
+    pub fn range(self) -> DateRange {
+        DateRange {
+            start: self.start,
+            end: self.end,
+        }
+    }
+
+    /// The absence that `absence` adds, if its kind is known
+    pub fn from_patch(absence: &AddAbsence) -> Option<Self> {
+        Some(Self {
+            kind: absence.kind.parse().ok()?,
+            start: absence.start,
+            end: absence.end,
+        })
+    }
+
+    pub fn to_patch(self) -> AddAbsence {
+        AddAbsence {
+            kind: self.kind.name().into(),
+            start: self.start,
+            end: self.end,
+        }
+    }
+}
+
+impl DateRange {
+    pub fn days(self) -> impl Iterator<Item = NaiveDate> {
+        let num_days = (self.end - self.start).num_days().max(-1) + 1;
+        (0..num_days).map(move |offset| self.start + Duration::days(offset))
+    }
+}
+
+impl FromStr for DateRange {
+    type Err = String;
+
+    /// Either side of the range can be a whole month, like "2019-01"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |text: &str| {
+            let text = text.trim();
+            if let Ok(date) = NaiveDate::parse_from_str(text, "%Y-%m-%d") {
+                return Ok((date, date));
+            }
+            NaiveDate::parse_from_str(&format!("{}-01", text), "%Y-%m-%d")
+                .map(|first| (first, Period::Month.next(first).pred()))
+                .map_err(|_| format!("invalid date \"{}\"; expected YYYY-MM-DD or YYYY-MM", text))
+        };
+        let mut parts = s.splitn(2, "..");
+        let (start, first_end) = parse(parts.next().unwrap_or(""))?;
+        let end = match parts.next() {
+            Some(end) => parse(end)?.1,
+            None => first_end,
+        };
+        if end < start {
+            return Err(format!("range {} ends before it starts", s));
+        }
+        Ok(Self { start, end })
+    }
+}
+
+impl fmt::Display for DateRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.start == self.end {
+            write!(f, "{}", self.start)
+        } else {
+            write!(f, "{}..{}", self.start, self.end)
+        }
+    }
+}
+
+/// Read a holidays file, which has a date at the start of each line. Anything
+/// after the date, like the name of the holiday, is ignored, as are blank
+/// lines and lines starting with `#`.
+fn read_holidays(path: &Path) -> Result<Vec<NaiveDate>, Error> {
+    let text = fs::read_to_string(path).context(ReadFile { path })?;
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let date = line.split_whitespace().next().unwrap_or("");
+            NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|_| Error::InvalidHoliday {
+                text: date.into(),
+                path: path.into(),
+            })
+        })
+        .collect()
+}
+
+/// The absences in `timesheet`, and those in the old files of devices that
+/// haven't moved them into patches yet, in order
+pub fn absences(conf: &Conf, timesheet: &Timesheet) -> Result<Vec<Absence>, Error> {
+    let mut absences: Vec<Absence> = timesheet
+        .get_patched_timesheet()
+        .absences
+        .iter()
+        // Kinds added by a newer version of augr are left out
+        .filter_map(|(_patch, absence)| Absence::from_patch(absence))
+        .collect();
+    for folder in LEGACY_FOLDERS.iter() {
+        let dir = conf.sync_folder.join(folder);
+        if !dir.exists() {
+            continue;
+        }
+        for entry in fs::read_dir(&dir).context(ReadFile { path: &dir })? {
+            let path = entry.context(ReadFile { path: &dir })?.path();
+            absences.extend(read_legacy(&path)?);
+        }
+    }
+    absences.sort_by_key(|a| (a.start, a.end, a.kind));
+    absences.dedup();
+    Ok(absences)
+}
+
+/// The absences in a file in one of the `LEGACY_FOLDERS`
+fn read_legacy(path: &Path) -> Result<Vec<Absence>, Error> {
+    let text = fs::read_to_string(path).context(ReadFile { path })?;
+    let file: LegacyFile = toml::de::from_str(&text).context(InvalidAbsences { path })?;
+    let vacation = file.vacation.into_iter().map(|range| Absence {
+        kind: AbsenceKind::Vacation,
+        start: range.start,
+        end: range.end,
+    });
+    Ok(vacation.chain(file.absence).collect())
+}
+
+/// The files that this device kept its absences in before they were stored
+/// in patches, if it has any
+fn legacy_files(conf: &Conf) -> Vec<PathBuf> {
+    LEGACY_FOLDERS
+        .iter()
+        .map(|folder| {
+            conf.sync_folder
+                .join(folder)
+                .join(format!("{}.toml", conf.device_id))
+        })
+        .filter(|path| path.exists())
+        .collect()
+}
+
+/// A patch that adds the absences in this device's old absence files, if
+/// there are any. Once it's saved, `remove_legacy_files` deletes the files.
+pub fn legacy_patch(conf: &Conf) -> Result<Option<Patch>, Error> {
+    let mut patch = Patch::new();
+    for path in legacy_files(conf) {
+        for absence in read_legacy(&path)? {
+            patch.insert_add_absence(absence.to_patch());
+        }
+    }
+    Ok(Some(patch).filter(|patch| !patch.add_absence.is_empty()))
+}
+
+pub fn remove_legacy_files(conf: &Conf) -> Result<(), Error> {
+    for path in legacy_files(conf) {
+        fs::remove_file(&path).context(RemoveFile { path: &path })?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_date_range() {
+        assert_eq!(
+            "2019-07-01..2019-07-14".parse(),
+            Ok(DateRange {
+                start: NaiveDate::from_ymd(2019, 7, 1),
+                end: NaiveDate::from_ymd(2019, 7, 14),
+            })
+        );
+        assert_eq!(
+            "2019-07-01".parse(),
+            Ok(DateRange {
+                start: NaiveDate::from_ymd(2019, 7, 1),
+                end: NaiveDate::from_ymd(2019, 7, 1),
+            })
+        );
+        assert_eq!(
+            "2019-01..2019-02".parse(),
+            Ok(DateRange {
+                start: NaiveDate::from_ymd(2019, 1, 1),
+                end: NaiveDate::from_ymd(2019, 2, 28),
+            })
+        );
+        assert!("2019-07-14..2019-07-01".parse::<DateRange>().is_err());
+    }
+
+    #[test]
+    fn working_days_skip_days_off() {
+        let mut days_off = DaysOff::default();
+        days_off
+            .days
+            .insert(NaiveDate::from_ymd(2019, 7, 4), AbsenceKind::Sick);
+        assert_eq!(
+            days_off.working_days(
+                NaiveDate::from_ymd(2019, 7, 1),
+                NaiveDate::from_ymd(2019, 7, 7)
+            ),
+            6
+        );
+    }
+
+    #[test]
+    fn goal_is_spread_over_workdays() {
+        let mut days_off = DaysOff::default().with_workdays(Some(vec![
+            Weekday::Mon,
+            Weekday::Tue,
+            Weekday::Wed,
+            Weekday::Thu,
+            Weekday::Fri,
+        ]));
+        days_off
+            .days
+            .insert(NaiveDate::from_ymd(2019, 7, 4), AbsenceKind::Sick);
+        let target = Target::parse("35h/week").unwrap();
+        assert_eq!(
+            days_off.goal(
+                &target,
+                NaiveDate::from_ymd(2019, 7, 1),
+                NaiveDate::from_ymd(2019, 7, 7)
+            ),
+            Duration::hours(28)
+        );
+    }
+}
@@ -0,0 +1,400 @@
+use augr_core::{
+    redact::Redactor,
+    store::{SyncFolderStore, SyncFolderStoreError},
+    Repository, Store, TagExpr,
+};
+use chrono::{DateTime, Local, Utc};
+use snafu::{ResultExt, Snafu};
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+use structopt::StructOpt;
+use toml::Value;
+
+#[derive(StructOpt, Debug)]
+pub enum Cmd {
+    /// Package an anonymized copy of the data, the config, and a trace of
+    /// this run into an archive that can be attached to a bug report
+    #[structopt(no_version, name = "bundle")]
+    Bundle {
+        /// Where to write the archive. Defaults to `augr-debug-<time>.tar` in
+        /// the current directory.
+        #[structopt(long = "output", short = "o")]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Could not read the config file: {}", source))]
+    ReadConfig { source: io::Error },
+
+    #[snafu(display("Could not parse the config file: {}", source))]
+    ParseConfig { source: toml::de::Error },
+
+    #[snafu(display("Could not read the sync folder: {}", source))]
+    ReadStore { source: SyncFolderStoreError },
+
+    #[snafu(display("Could not serialize {}: {}", file, source))]
+    Serialize {
+        file: String,
+        source: toml::ser::Error,
+    },
+
+    #[snafu(display("Could not write {}: {}", path.display(), source))]
+    WriteBundle { path: PathBuf, source: io::Error },
+}
+
+/// Collects the trace of a run in memory, so that it can be put in a bundle
+#[derive(Clone, Default)]
+pub struct TraceBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl Write for TraceBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl TraceBuffer {
+    fn contents(&self) -> Vec<u8> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+/// How a config value is put in the bundle
+enum Field {
+    /// The value says nothing about the user and is kept as it is
+    Keep,
+    /// A path or the user's own words, replaced with "redacted"
+    Text,
+    /// A list of tags
+    Tags,
+    /// A tag expression like "work and not meeting"
+    TagExpr,
+    /// A table with a tag for each key
+    TagKeys,
+    /// A table mapping tags to tags
+    TagPairs,
+    /// A table of tag lists, under names the user chose
+    NamedTags,
+    /// A table with these fields
+    Table(&'static [(&'static str, Field)]),
+    /// An array of tables with these fields
+    Tables(&'static [(&'static str, Field)]),
+}
+
+/// The config keys that go in the bundle. Anything else, like `export_key` or
+/// keys this version doesn't know about, is left out.
+const CONFIG_FIELDS: &[(&str, Field)] = &[
+    ("sync_folder", Field::Text),
+    ("device_id", Field::Keep),
+    ("target", Field::TagKeys),
+    ("day_start", Field::Keep),
+    ("week_starts_on", Field::Keep),
+    ("timezone", Field::Keep),
+    ("duration_format", Field::Keep),
+    ("end_policy", Field::Keep),
+    ("snap_starts", Field::Keep),
+    ("conflict_resolution", Field::Keep),
+    ("holidays", Field::Text),
+    ("confirm_changes", Field::Keep),
+    ("stale_device_days", Field::Keep),
+    ("max_patch_files", Field::Keep),
+    ("max_store_megabytes", Field::Keep),
+    ("max_patches_per_hour", Field::Keep),
+    (
+        "chart_group",
+        Field::Tables(&[
+            ("name", Field::Text),
+            ("filter", Field::TagExpr),
+            ("color", Field::Keep),
+            ("symbol", Field::Keep),
+        ]),
+    ),
+    (
+        "rate",
+        Field::Tables(&[
+            ("name", Field::Text),
+            ("filter", Field::TagExpr),
+            ("hourly", Field::Keep),
+        ]),
+    ),
+    (
+        "rule",
+        Field::Tables(&[
+            ("name", Field::Text),
+            ("tags", Field::Tags),
+            ("weekdays", Field::Keep),
+            ("between", Field::Keep),
+            ("note", Field::Text),
+            ("add", Field::Tags),
+        ]),
+    ),
+    ("template", Field::NamedTags),
+    ("alias", Field::TagPairs),
+    ("rounding", Field::Keep),
+    ("working_hours", Field::Keep),
+    ("workdays_only", Field::Keep),
+    (
+        "overtime",
+        Field::Table(&[
+            ("contract", Field::Keep),
+            ("tags", Field::Tags),
+            ("cap", Field::Keep),
+            ("expire_after_weeks", Field::Keep),
+            ("start", Field::Keep),
+        ]),
+    ),
+];
+
+impl Cmd {
+    pub fn exec(
+        &self,
+        repo: &Repository<SyncFolderStore>,
+        conf_file: &Path,
+        trace: &TraceBuffer,
+        now: DateTime<Utc>,
+    ) -> Result<(), Error> {
+        let Cmd::Bundle { output } = self;
+        let path = output.clone().unwrap_or_else(|| {
+            let time = now.with_timezone(&Local).format("%Y%m%d-%H%M%S");
+            PathBuf::from(format!("augr-debug-{}.tar", time))
+        });
+
+        let mut redactor = Redactor::new();
+        let mut files = Vec::new();
+
+        files.push((String::from("version.txt"), version_info(repo).into_bytes()));
+
+        let conf_str = fs::read_to_string(conf_file).context(ReadConfig {})?;
+        let mut conf: Value = toml::de::from_str(&conf_str).context(ParseConfig {})?;
+        redact_config(&mut conf, &mut redactor);
+        let conf_str = toml::ser::to_string(&conf).context(Serialize {
+            file: "config.toml",
+        })?;
+        files.push((String::from("config.toml"), conf_str.into_bytes()));
+
+        for patch_ref in repo.loaded_patches() {
+            let patch = repo.store().get_patch(patch_ref).context(ReadStore {})?;
+            let file = format!("patches/{}.toml", patch_ref);
+            let contents = toml::ser::to_string(&redactor.patch(&patch))
+                .context(Serialize { file: file.clone() })?;
+            files.push((file, contents.into_bytes()));
+        }
+
+        files.push((String::from("trace.log"), trace.contents()));
+
+        write_archive(&path, &files, now).context(WriteBundle { path: path.clone() })?;
+        println!("Wrote {}", path.display());
+        println!("Tags have been replaced and notes removed; check the archive before sharing it.");
+        Ok(())
+    }
+}
+
+fn version_info(repo: &Repository<SyncFolderStore>) -> String {
+    let meta = repo.meta();
+    format!(
+        "augr {}\nos {} {}\nstore format version {}\npatches loaded {}\ndevices {}\n",
+        env!("CARGO_PKG_VERSION"),
+        std::env::consts::OS,
+        std::env::consts::ARCH,
+        meta.format_version(),
+        repo.loaded_patches().count(),
+        meta.devices().count(),
+    )
+}
+
+/// Keep only the config keys that are known to be safe to share, and replace
+/// tags with the same placeholders the patches use
+fn redact_config(conf: &mut Value, redactor: &mut Redactor) {
+    redact_value(conf, &Field::Table(CONFIG_FIELDS), redactor);
+}
+
+fn redact_value(value: &mut Value, field: &Field, redactor: &mut Redactor) {
+    match (field, value) {
+        (Field::Keep, _) => {}
+        (Field::Text, value) => *value = Value::String(String::from("redacted")),
+        (Field::Tags, Value::Array(tags)) => {
+            for tag in tags.iter_mut() {
+                *tag = match tag {
+                    Value::String(name) => Value::String(redactor.tag(name)),
+                    _ => Value::String(String::from("redacted")),
+                };
+            }
+        }
+        (Field::TagExpr, Value::String(text)) => {
+            *text = match text.parse::<TagExpr>() {
+                Ok(expr) => {
+                    let placeholders: BTreeMap<String, String> = expr
+                        .tags()
+                        .into_iter()
+                        .map(|tag| (tag.clone(), redactor.tag(tag)))
+                        .collect();
+                    expr.map_tags(&|tag| placeholders[&tag].clone()).to_string()
+                }
+                Err(_) => String::from("redacted"),
+            }
+        }
+        (Field::TagKeys, Value::Table(table)) => {
+            *table = table
+                .iter()
+                .map(|(tag, value)| (redactor.tag(tag), value.clone()))
+                .collect();
+        }
+        (Field::TagPairs, Value::Table(table)) => {
+            *table = table
+                .iter()
+                .map(|(alias, tag)| {
+                    let tag = match tag {
+                        Value::String(name) => Value::String(redactor.tag(name)),
+                        _ => Value::String(String::from("redacted")),
+                    };
+                    (redactor.tag(alias), tag)
+                })
+                .collect();
+        }
+        (Field::NamedTags, Value::Table(table)) => {
+            *table = table
+                .values()
+                .enumerate()
+                .map(|(i, tags)| {
+                    let mut tags = tags.clone();
+                    redact_value(&mut tags, &Field::Tags, redactor);
+                    (format!("name-{}", i + 1), tags)
+                })
+                .collect();
+        }
+        (Field::Table(fields), Value::Table(table)) => {
+            let keys: Vec<String> = table.keys().cloned().collect();
+            for key in keys {
+                match fields.iter().find(|(name, _)| *name == key) {
+                    Some((_, field)) => redact_value(table.get_mut(&key).unwrap(), field, redactor),
+                    None => {
+                        table.remove(&key);
+                    }
+                }
+            }
+        }
+        (Field::Tables(fields), Value::Array(tables)) => {
+            for table in tables.iter_mut() {
+                redact_value(table, &Field::Table(fields), redactor);
+            }
+        }
+        // A value of the wrong type can't be told apart from the user's own
+        // words
+        (_, value) => *value = Value::String(String::from("redacted")),
+    }
+}
+
+fn write_archive(path: &Path, files: &[(String, Vec<u8>)], now: DateTime<Utc>) -> io::Result<()> {
+    let mut archive = tar::Builder::new(File::create(path)?);
+    for (name, contents) in files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(now.timestamp() as u64);
+        header.set_cksum();
+        archive.append_data(&mut header, format!("augr-debug/{}", name), &contents[..])?;
+    }
+    archive.into_inner()?.flush()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn redact_config_secrets_and_tags() {
+        let mut conf: Value = toml::de::from_str(
+            r#"
+            sync_folder = "/home/someone/sync"
+            device_id = "laptop"
+            api_token = "abc123"
+
+            [target]
+            client-x = "8h"
+
+            [overtime]
+            contract = "40h"
+            tags = ["client-x"]
+            "#,
+        )
+        .unwrap();
+        redact_config(&mut conf, &mut Redactor::new());
+
+        let redacted = toml::ser::to_string(&conf).unwrap();
+        assert!(!redacted.contains("/home/someone"));
+        assert!(!redacted.contains("abc123"));
+        assert!(!redacted.contains("client-x"));
+        assert_eq!(conf["target"]["tag-1"].as_str(), Some("8h"));
+        assert_eq!(conf["overtime"]["tags"][0].as_str(), Some("tag-1"));
+        assert_eq!(conf["device_id"].as_str(), Some("laptop"));
+    }
+
+    #[test]
+    fn redact_config_tag_tables() {
+        let mut conf: Value = toml::de::from_str(
+            r#"
+            sync_folder = "/home/someone/sync"
+            device_id = "laptop"
+            export_key = "hunter2"
+            week_starts_on = "monday"
+
+            [template]
+            acme-standup = ["client-x", "standup"]
+
+            [alias]
+            cx = "client-x"
+
+            [[rate]]
+            name = "Client X consulting"
+            filter = "client-x and not internal"
+            hourly = 120.0
+
+            [[rule]]
+            name = "Client X standups"
+            tags = ["client-x"]
+            note = "client-x sync"
+            add = ["standup"]
+
+            [[chart_group]]
+            name = "Client X"
+            filter = "client-x or standup"
+            symbol = "x"
+            "#,
+        )
+        .unwrap();
+        redact_config(&mut conf, &mut Redactor::new());
+
+        let redacted = toml::ser::to_string(&conf).unwrap();
+        for secret in &[
+            "client-x", "Client X", "standup", "internal", "acme", "hunter2",
+        ] {
+            assert!(!redacted.contains(secret), "{} in {}", secret, redacted);
+        }
+        assert_eq!(conf.get("export_key"), None);
+        assert_eq!(conf["week_starts_on"].as_str(), Some("monday"));
+        assert_eq!(conf["template"]["name-1"][1].as_str(), Some("tag-3"));
+        assert_eq!(conf["alias"]["tag-2"].as_str(), Some("tag-1"));
+        assert_eq!(
+            conf["rate"][0]["filter"].as_str(),
+            Some("tag-1 and not tag-4")
+        );
+        assert_eq!(conf["rate"][0]["hourly"].as_float(), Some(120.0));
+        assert_eq!(conf["rule"][0]["tags"][0].as_str(), Some("tag-1"));
+        assert_eq!(conf["rule"][0]["add"][0].as_str(), Some("tag-3"));
+        assert_eq!(
+            conf["chart_group"][0]["filter"].as_str(),
+            Some("tag-1 or tag-3")
+        );
+        assert_eq!(conf["chart_group"][0]["symbol"].as_str(), Some("x"));
+    }
+}
@@ -0,0 +1,112 @@
+use crate::prompt;
+use augr_core::{store::patch::DeleteEvent, EventRef, Patch, Timesheet};
+use chrono::Local;
+use snafu::{ResultExt, Snafu};
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct Cmd {
+    /// The id of the event to delete, a unique prefix of it, or
+    /// `@` for the current event and `@-1`, `@-2`, ... for earlier ones today
+    pub event: EventRef,
+
+    /// Don't ask for confirmation
+    #[structopt(short = "y", long = "yes")]
+    yes: bool,
+}
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Unknown event reference: {}", event_ref))]
+    UnknownEventRef { event_ref: EventRef },
+
+    #[snafu(display(
+        "Not deleting {} without confirmation; pass --yes to delete it",
+        event_ref
+    ))]
+    NotConfirmed { event_ref: EventRef },
+
+    #[snafu(display("Could not read confirmation: {}", source))]
+    ReadConfirmation { source: std::io::Error },
+}
+
+impl Cmd {
+    pub fn exec(&self, timesheet: &Timesheet) -> Result<Vec<Patch>, Error> {
+        let patch = delete_event(timesheet, &self.event)?;
+        if !self.yes {
+            let question = format!("Delete {}?", describe_event(timesheet, &self.event));
+            if prompt::confirm(&question).context(ReadConfirmation {})? != Some(true) {
+                return Err(Error::NotConfirmed {
+                    event_ref: self.event.clone(),
+                });
+            }
+        }
+        Ok(vec![patch])
+    }
+}
+
+/// Build a patch that deletes `event_ref`
+pub fn delete_event(timesheet: &Timesheet, event_ref: &EventRef) -> Result<Patch, Error> {
+    let event = timesheet
+        .get_patched_timesheet()
+        .events
+        .get(event_ref)
+        .filter(|event| !event.is_deleted())
+        .ok_or(Error::UnknownEventRef {
+            event_ref: event_ref.clone(),
+        })?;
+    let mut patch = Patch::new();
+    patch.insert_delete_event(DeleteEvent {
+        parents: event.latest_patches(),
+        event: event_ref.clone(),
+    });
+    Ok(patch)
+}
+
+/// Describe an event by its tags and start times, like "work coding (started
+/// 2019-07-16 09:00)"
+fn describe_event(timesheet: &Timesheet, event_ref: &EventRef) -> String {
+    let event = &timesheet.get_patched_timesheet().events[event_ref];
+    let tags: Vec<String> = event.tags().into_iter().map(|(_patch, tag)| tag).collect();
+    let starts: Vec<String> = event
+        .starts()
+        .into_iter()
+        .map(|(_patch, start)| {
+            start
+                .with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M")
+                .to_string()
+        })
+        .collect();
+    let name = if tags.is_empty() {
+        String::from("stop")
+    } else {
+        tags.join(" ")
+    };
+    format!("{} (started {})", name, starts.join(", "))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::Tracked;
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn deleted_events_cannot_be_deleted_again() {
+        let create =
+            Patch::new().create_event("a".into(), Utc.ymd(2019, 7, 24).and_hms(9, 0, 0), vec![]);
+        let mut tracked = Tracked::new(&[&create]);
+        let timesheet = tracked.timesheet();
+
+        let delete = delete_event(&timesheet, &"a".into()).unwrap();
+        let deleted = delete.delete_event.iter().next().unwrap();
+        assert_eq!(deleted.event, "a");
+        assert!(deleted.parents.contains(create.patch_ref()));
+        assert!(delete_event(&timesheet, &"b".into()).is_err());
+
+        tracked.apply(&delete);
+        let timesheet = tracked.timesheet();
+        assert!(delete_event(&timesheet, &"a".into()).is_err());
+    }
+}
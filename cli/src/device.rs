@@ -0,0 +1,252 @@
+use augr_core::{
+    store::{SyncFolderStore, SyncFolderStoreError},
+    Repository,
+};
+use chrono::{DateTime, Duration, Utc};
+use snafu::{ResultExt, Snafu};
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub enum Cmd {
+    /// List the devices that share the sync folder
+    #[structopt(no_version, name = "list")]
+    List,
+
+    /// Give a device a human readable name, like "laptop"
+    #[structopt(no_version, name = "rename")]
+    Rename {
+        /// The id or current name of the device
+        device: String,
+
+        /// The new name
+        name: String,
+    },
+
+    /// Mark a device as no longer used
+    #[structopt(no_version, name = "retire")]
+    Retire {
+        /// The id or name of the device
+        device: String,
+    },
+}
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Could not read the devices in the sync folder: {}", source))]
+    ReadDevices { source: SyncFolderStoreError },
+
+    #[snafu(display("Unknown device \"{}\"", device))]
+    UnknownDevice { device: String },
+}
+
+/// What is known about one device
+pub struct DeviceInfo {
+    pub id: String,
+    /// The patches that the device's meta file lists but that couldn't be loaded
+    pub missing_patches: usize,
+    /// When the device last added a patch, if its meta file records it
+    pub last_patch_added: Option<DateTime<Utc>>,
+}
+
+/// A device that is in use but hasn't added any patches for a while
+pub struct StaleDevice {
+    /// The name of the device, or its id if it doesn't have a name
+    pub name: String,
+    pub last_patch_added: DateTime<Utc>,
+}
+
+impl Cmd {
+    /// Changes are made to the repository's meta, which the caller saves. A
+    /// dry run only says what would change.
+    pub fn exec(&self, repo: &mut Repository<SyncFolderStore>, dry_run: bool) -> Result<(), Error> {
+        match self {
+            Cmd::List => list(repo),
+            Cmd::Rename { device, name } if dry_run => {
+                let id = find_device(repo, device)?;
+                println!(
+                    "Would rename device {} to \"{}\" (dry run, nothing was saved)",
+                    id, name
+                );
+                Ok(())
+            }
+            Cmd::Retire { device } if dry_run => {
+                let id = find_device(repo, device)?;
+                println!("Would retire device {} (dry run, nothing was saved)", id);
+                Ok(())
+            }
+            Cmd::Rename { device, name } => {
+                let id = find_device(repo, device)?;
+                let now = repo.clock().now();
+                repo.meta_mut().set_device_name(id, name.as_str(), now);
+                Ok(())
+            }
+            Cmd::Retire { device } => {
+                let id = find_device(repo, device)?;
+                repo.meta_mut().retire_device(id);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Every known device, starting with this one
+pub fn devices(repo: &Repository<SyncFolderStore>) -> Result<Vec<DeviceInfo>, Error> {
+    let mut devices = vec![DeviceInfo {
+        id: repo.store().device_id().to_string(),
+        missing_patches: 0,
+        last_patch_added: repo.meta().last_patch_added(),
+    }];
+    let metas = repo
+        .store()
+        .get_other_device_metas()
+        .context(ReadDevices {})?;
+    for result in metas {
+        let (id, meta) = result.context(ReadDevices {})?;
+        let missing_patches = meta.patches().filter(|p| !repo.is_loaded(p)).count();
+        devices.push(DeviceInfo {
+            id,
+            missing_patches,
+            last_patch_added: meta.last_patch_added(),
+        });
+    }
+    // Devices that only appear in the registry, like ones that have been
+    // removed from the sync folder
+    for (id, _device) in repo.meta().devices() {
+        if !devices.iter().any(|d| &d.id == id) {
+            devices.push(DeviceInfo {
+                id: id.clone(),
+                missing_patches: 0,
+                last_patch_added: None,
+            });
+        }
+    }
+    devices[1..].sort_by(|a, b| a.id.cmp(&b.id));
+    Ok(devices)
+}
+
+/// Other devices that haven't added a patch in `days` days. Retired devices,
+/// and devices whose meta file doesn't say when they last added a patch, are
+/// never stale.
+pub fn stale_devices(
+    repo: &Repository<SyncFolderStore>,
+    now: DateTime<Utc>,
+    days: i64,
+) -> Result<Vec<StaleDevice>, Error> {
+    let meta = repo.meta();
+    Ok(devices(repo)?
+        .into_iter()
+        .skip(1)
+        .filter(|device| !meta.is_retired(&device.id))
+        .filter_map(|device| {
+            let last_patch_added = device.last_patch_added?;
+            if now.signed_duration_since(last_patch_added) < Duration::days(days) {
+                return None;
+            }
+            Some(StaleDevice {
+                name: display_name(repo, &device.id),
+                last_patch_added,
+            })
+        })
+        .collect())
+}
+
+/// The name of a device, or its id if it doesn't have one
+pub fn display_name(repo: &Repository<SyncFolderStore>, device_id: &str) -> String {
+    repo.meta()
+        .device_name(device_id)
+        .unwrap_or(device_id)
+        .to_string()
+}
+
+impl StaleDevice {
+    pub fn warning(&self, now: DateTime<Utc>) -> String {
+        format!(
+            "device {} hasn't added a patch in {} days",
+            self.name,
+            now.signed_duration_since(self.last_patch_added).num_days()
+        )
+    }
+}
+
+/// Find the id of a device from its id or its name
+fn find_device(repo: &Repository<SyncFolderStore>, device: &str) -> Result<String, Error> {
+    let meta = repo.meta();
+    devices(repo)?
+        .into_iter()
+        .map(|d| d.id)
+        .find(|id| id == device || meta.device_name(id) == Some(device))
+        .ok_or_else(|| Error::UnknownDevice {
+            device: device.to_string(),
+        })
+}
+
+fn list(repo: &Repository<SyncFolderStore>) -> Result<(), Error> {
+    let meta = repo.meta();
+    for (i, device) in devices(repo)?.iter().enumerate() {
+        let mut notes = Vec::new();
+        if i == 0 {
+            notes.push(String::from("this device"));
+        }
+        if meta.is_retired(&device.id) {
+            notes.push(String::from("retired"));
+        }
+        if device.missing_patches > 0 {
+            let noun = if device.missing_patches == 1 {
+                "patch"
+            } else {
+                "patches"
+            };
+            notes.push(format!("{} {} not loaded", device.missing_patches, noun));
+        }
+        let name = meta.device_name(&device.id).unwrap_or("-");
+        if notes.is_empty() {
+            println!("{: <12} {}", name, device.id);
+        } else {
+            println!("{: <12} {} ({})", name, device.id, notes.join(", "));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use augr_core::FixedClock;
+    use chrono::TimeZone;
+    use std::{fs, process};
+
+    #[test]
+    fn newest_rename_wins_after_sync() {
+        let root = std::env::temp_dir().join(format!("augr-devices-{}", process::id()));
+        let time = |h| Utc.ymd(2019, 7, 24).and_hms(h, 0, 0);
+        let open = |device: &str, hour| {
+            let store = SyncFolderStore::new(root.clone(), device.into()).should_init(true);
+            let mut repo = Repository::from_store(store)
+                .unwrap()
+                .with_clock(Box::new(FixedClock(time(hour))));
+            repo.save_meta().unwrap();
+            repo
+        };
+        let rename = |repo: &mut Repository<SyncFolderStore>, device: &str, name: &str| {
+            Cmd::Rename {
+                device: device.into(),
+                name: name.into(),
+            }
+            .exec(repo, false)
+            .unwrap();
+            repo.save_meta().unwrap();
+        };
+
+        let mut phone = open("phone", 9);
+        rename(&mut phone, "phone", "pocket");
+        let mut laptop = open("laptop", 10);
+        laptop.try_sync_data().unwrap();
+        rename(&mut laptop, "pocket", "android");
+        let mut phone = open("phone", 11);
+        phone.try_sync_data().unwrap();
+
+        assert_eq!(find_device(&phone, "android").unwrap(), "phone");
+        assert!(find_device(&phone, "pocket").is_err());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,83 @@
+use crate::{days_off::DateRange, report, suggest};
+use augr_core::{aggregate, TagExpr, Timesheet};
+use chrono::{Local, NaiveDate};
+use std::collections::BTreeSet;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct Cmd {
+    /// The days to compare against, like "2019-01" or "2019-07-01..2019-07-07"
+    before: DateRange,
+
+    /// The days to compare, in the same form
+    after: DateRange,
+
+    /// Only count events matching a tag expression, like "work and not meeting"
+    #[structopt(long = "filter", parse(try_from_str = crate::alias::tag_expr))]
+    filter: Option<TagExpr>,
+}
+
+impl Cmd {
+    pub fn exec(&self, timesheet: &Timesheet) {
+        suggest::warn_unknown_tags(timesheet, &BTreeSet::new(), self.filter.as_ref());
+        print!("{}", self.render(timesheet));
+    }
+
+    /// Compare the two ranges, each from the start of its first day to the
+    /// end of its last
+    fn render(&self, timesheet: &Timesheet) -> String {
+        let midnight = |date: NaiveDate| aggregate::midnight(date, &Local);
+        let range = |r: DateRange| (midnight(r.start), midnight(r.end.succ()));
+        report::render_comparison(
+            &timesheet.segments(),
+            range(self.before),
+            range(self.after),
+            self.filter.as_ref(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::Tracked;
+    use augr_core::Patch;
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn ranges_cover_whole_days() {
+        let time = |d, h| Local.ymd(2019, 7, d).and_hms(h, 0, 0).with_timezone(&Utc);
+        let create = Patch::new()
+            .create_event("a".into(), time(1, 9), vec!["work".into()])
+            .create_event("b".into(), time(1, 12), vec![])
+            .create_event("c".into(), time(8, 9), vec!["work".into()])
+            .create_event("d".into(), time(8, 14), vec!["meeting".into()])
+            .create_event("e".into(), time(8, 23), vec![]);
+        let tracked = Tracked::new(&[&create]);
+        let timesheet = tracked.timesheet();
+
+        let diff = |args: &[&str]| {
+            let cmd = Cmd::from_iter_safe(args).unwrap();
+            cmd.render(&timesheet)
+                .lines()
+                .skip(2)
+                .map(String::from)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            diff(&["diff", "2019-07-01", "2019-07-08"]),
+            vec![
+                "meeting 0m     9h 0m  +9h 0m  new",
+                "work    3h 0m  5h 0m  +2h 0m  +67%",
+                "Total   3h 0m  14h 0m +11h 0m +367%",
+            ]
+        );
+        assert_eq!(
+            diff(&["diff", "2019-07-01", "2019-07-08", "--filter", "work"]),
+            vec![
+                "work  3h 0m  5h 0m +2h 0m +67%",
+                "Total 3h 0m  5h 0m +2h 0m +67%",
+            ]
+        );
+    }
+}
@@ -0,0 +1,212 @@
+use crate::{config::Conf, device};
+use augr_core::{
+    repository::timesheet::PatchedTimesheet,
+    store::{StoreStats, SyncFolderStore, SyncFolderStoreError},
+    Repository,
+};
+use chrono::{DateTime, Duration, Utc};
+use snafu::{ResultExt, Snafu};
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct Cmd {}
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("{}", source))]
+    Devices { source: device::Error },
+
+    #[snafu(display("Could not measure the sync folder: {}", source))]
+    Stats { source: SyncFolderStoreError },
+}
+
+impl Cmd {
+    /// Print a line for each check of the sync folder
+    pub fn exec(
+        &self,
+        repo: &Repository<SyncFolderStore>,
+        conf: &Conf,
+        now: DateTime<Utc>,
+    ) -> Result<(), Error> {
+        let report = |ok: bool, message: String| {
+            println!("{} {}", if ok { "ok     " } else { "warning" }, message);
+        };
+
+        let meta = repo.meta();
+        report(
+            meta.is_supported(),
+            format!("store format version {}", meta.format_version()),
+        );
+
+        let devices = device::devices(repo).context(Devices {})?;
+        for info in devices.iter().filter(|d| d.missing_patches > 0) {
+            report(
+                false,
+                format!(
+                    "device {} lists {} patches that could not be loaded",
+                    device::display_name(repo, &info.id),
+                    info.missing_patches
+                ),
+            );
+        }
+
+        let stale = device::stale_devices(repo, now, conf.stale_device_days).context(Devices {})?;
+        if stale.is_empty() {
+            report(
+                true,
+                format!(
+                    "every device has added a patch in the last {} days",
+                    conf.stale_device_days
+                ),
+            );
+        }
+        for device in stale {
+            report(false, device.warning(now));
+        }
+
+        let stats = repo.store().stats().context(Stats {})?;
+        let warnings = size_warnings(&stats, conf);
+        if warnings.is_empty() {
+            report(
+                true,
+                format!(
+                    "{} patches taking up {}",
+                    stats.patch_files,
+                    format_bytes(stats.patch_disk_bytes)
+                ),
+            );
+        }
+        for warning in warnings {
+            report(false, warning);
+        }
+
+        let warnings = rate_warnings(repo.timesheet(), now, conf);
+        if warnings.is_empty() {
+            report(
+                true,
+                format!(
+                    "no source added more than {} patches in the last hour",
+                    conf.max_patches_per_hour
+                ),
+            );
+        }
+        for warning in warnings {
+            report(false, warning);
+        }
+
+        let conflicts = repo.timesheet().conflicts();
+        if conflicts.is_empty() {
+            report(true, String::from("no conflicts"));
+        }
+        for conflict in conflicts {
+            report(false, format!("conflict: {}", conflict));
+        }
+
+        Ok(())
+    }
+}
+
+/// Warnings for a sync folder that has grown past the limits in the config,
+/// ending with an estimate of how much packing the patches would save
+pub fn size_warnings(stats: &StoreStats, conf: &Conf) -> Vec<String> {
+    let mut warnings = Vec::new();
+    if stats.patch_files > conf.max_patch_files {
+        warnings.push(format!(
+            "the sync folder has {} patch files, more than the limit of {}",
+            stats.patch_files, conf.max_patch_files
+        ));
+    }
+    let max_bytes = conf.max_store_megabytes * 1_000_000;
+    if stats.patch_disk_bytes > max_bytes {
+        warnings.push(format!(
+            "the patches take up {}, more than the limit of {}",
+            format_bytes(stats.patch_disk_bytes),
+            format_bytes(max_bytes)
+        ));
+    }
+    if !warnings.is_empty() {
+        warnings.push(format!(
+            "packing the patches into a single file would save about {}",
+            format_bytes(stats.pack_savings())
+        ));
+    }
+    warnings
+}
+
+/// Warnings for sources that added more patches in the last hour than the
+/// config allows, which usually means a script is stuck in a loop
+pub fn rate_warnings(timesheet: &PatchedTimesheet, now: DateTime<Utc>, conf: &Conf) -> Vec<String> {
+    timesheet
+        .patch_rates(now - Duration::hours(1))
+        .into_iter()
+        .filter(|(_, count)| *count > conf.max_patches_per_hour)
+        .map(|(source, count)| {
+            format!(
+                "{} added {} patches in the last hour, more than the limit of {}",
+                source, count, conf.max_patches_per_hour
+            )
+        })
+        .collect()
+}
+
+fn format_bytes(bytes: u64) -> String {
+    if bytes < 1_000_000 {
+        format!("{:.1} kB", bytes as f64 / 1_000.0)
+    } else {
+        format!("{:.1} MB", bytes as f64 / 1_000_000.0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn warn_about_many_patches() {
+        let conf = testing::conf("max_patch_files = 2");
+        let small = StoreStats {
+            patch_files: 2,
+            patch_bytes: 2000,
+            patch_disk_bytes: 8192,
+        };
+        assert!(size_warnings(&small, &conf).is_empty());
+
+        let large = StoreStats {
+            patch_files: 3,
+            patch_bytes: 3000,
+            patch_disk_bytes: 12288,
+        };
+        assert_eq!(
+            size_warnings(&large, &conf),
+            vec![
+                "the sync folder has 3 patch files, more than the limit of 2",
+                "packing the patches into a single file would save about 8.2 kB",
+            ]
+        );
+    }
+
+    #[test]
+    fn warn_about_runaway_sources() {
+        use augr_core::{
+            repository::{CREATED_METADATA, SOURCE_METADATA},
+            Patch,
+        };
+        use chrono::TimeZone;
+
+        let conf = testing::conf("max_patches_per_hour = 2");
+        let now = Utc.ymd(2019, 7, 24).and_hms(14, 0, 0);
+        let mut timesheet = PatchedTimesheet::new();
+        for (i, source) in ["hook", "hook", "hook", "cli"].iter().enumerate() {
+            let patch = Patch::new()
+                .create_event(format!("e{}", i), now, vec![])
+                .with_metadata(CREATED_METADATA, now.to_rfc3339())
+                .with_metadata(SOURCE_METADATA, *source);
+            timesheet.apply_patch(&patch).unwrap();
+        }
+        assert_eq!(
+            rate_warnings(&timesheet, now, &conf),
+            vec!["hook added 3 patches in the last hour, more than the limit of 2"]
+        );
+    }
+}
@@ -0,0 +1,62 @@
+use crate::{days_off::DateRange, history::describe};
+use augr_core::{
+    repository::timesheet::{Error as TimesheetError, PatchedTimesheet},
+    store::patch::AddAbsence,
+    Patch,
+};
+use snafu::{ResultExt, Snafu};
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Could not serialize patch: {}", source))]
+    Serialize { source: toml::ser::Error },
+
+    #[snafu(display(
+        "The patch would not apply: {}",
+        errors.iter().map(ToString::to_string).collect::<Vec<_>>().join("; ")
+    ))]
+    WouldNotApply { errors: Vec<TimesheetError> },
+}
+
+/// Print the patches that a command would add, followed by the changes they
+/// would make to each event and to the absences, without adding them
+pub fn show(timesheet: &PatchedTimesheet, patches: &[Patch]) -> Result<(), Error> {
+    let mut after = timesheet.clone();
+    for patch in patches {
+        print!("{}", toml::ser::to_string(patch).context(Serialize {})?);
+        println!();
+        after
+            .apply_patch(patch)
+            .map_err(|errors| Error::WouldNotApply { errors })?;
+    }
+
+    println!("Changes (dry run, nothing was saved):");
+    for (event_ref, event) in after.events.iter() {
+        for operation in event
+            .history()
+            .iter()
+            .filter(|o| patches.iter().any(|p| o.patch == *p.patch_ref()))
+        {
+            println!("  {} {}", event_ref, describe(&operation.kind));
+        }
+    }
+    let absence_range = |absence: &AddAbsence| DateRange {
+        start: absence.start,
+        end: absence.end,
+    };
+    for (_, absence) in after.absences.difference(&timesheet.absences) {
+        println!(
+            "  added {} absence {}",
+            absence.kind,
+            absence_range(absence)
+        );
+    }
+    for (_, absence) in timesheet.absences.difference(&after.absences) {
+        println!(
+            "  removed {} absence {}",
+            absence.kind,
+            absence_range(absence)
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,322 @@
+use crate::{config::Conf, set_start};
+use augr_core::{
+    repository::event::PatchedEvent,
+    store::patch::{AddTag, RemoveTag, SetNote},
+    EventRef, Patch, Tag, Timesheet,
+};
+use chrono::{DateTime, FixedOffset, Local, Utc};
+use serde::{Deserialize, Serialize};
+use snafu::{OptionExt, ResultExt, Snafu};
+use std::{
+    collections::BTreeSet,
+    env, fs, io,
+    path::{Path, PathBuf},
+    process,
+};
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct Cmd {
+    /// The id of the event to modify, a unique prefix of it, or
+    /// `@` for the current event and `@-1`, `@-2`, ... for earlier ones today
+    pub event: EventRef,
+
+    /// When the event actually started, like "9:15" or "yesterday 9am"
+    #[structopt(long = "start", parse(try_from_os_str = crate::time_input::parse_default_local))]
+    start: Option<DateTime<Local>>,
+
+    /// Open the event's start time, tags and note as TOML in `$VISUAL` or
+    /// `$EDITOR`, and save whatever was changed
+    #[structopt(long = "editor", conflicts_with = "start")]
+    editor: bool,
+}
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Nothing to change; pass --start to move the event, or --editor"))]
+    NothingToEdit,
+
+    #[snafu(display("{}", source))]
+    SetStart { source: set_start::Error },
+
+    #[snafu(display("Unknown event reference: {}", event_ref))]
+    UnknownEventRef { event_ref: EventRef },
+
+    #[snafu(display("Event {} has more than one start time; resolve it first", event_ref))]
+    ConflictingStarts { event_ref: EventRef },
+
+    #[snafu(display("Could not write {}: {}", path.display(), source))]
+    WriteEventFile { path: PathBuf, source: io::Error },
+
+    #[snafu(display("Could not run editor \"{}\": {}", editor, source))]
+    RunEditor { editor: String, source: io::Error },
+
+    #[snafu(display("Editor \"{}\" exited with an error; nothing was changed", editor))]
+    EditorFailed { editor: String },
+
+    #[snafu(display("Could not read {}: {}", path.display(), source))]
+    ReadEventFile { path: PathBuf, source: io::Error },
+
+    #[snafu(display("Invalid event: {}", source))]
+    ParseEventFile { source: toml::de::Error },
+
+    #[snafu(display("Could not serialize event: {}", source))]
+    SerializeEvent { source: toml::ser::Error },
+}
+
+/// The parts of an event that can be changed in an editor
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+pub struct EventDoc {
+    pub start: DateTime<FixedOffset>,
+    pub tags: Vec<Tag>,
+    #[serde(default)]
+    pub note: String,
+}
+
+impl Cmd {
+    pub fn exec(&self, timesheet: &Timesheet, conf: &Conf) -> Result<Vec<Patch>, Error> {
+        if self.editor {
+            return self.edit_in_editor(timesheet, conf);
+        }
+        let start = self.start.ok_or(Error::NothingToEdit)?;
+        let patch = set_start::set_start(timesheet, conf, &self.event, start.with_timezone(&Utc))
+            .context(SetStart {})?;
+        Ok(vec![patch])
+    }
+
+    fn edit_in_editor(&self, timesheet: &Timesheet, conf: &Conf) -> Result<Vec<Patch>, Error> {
+        let event = timesheet
+            .get_patched_timesheet()
+            .events
+            .get(&self.event)
+            .filter(|event| !event.is_deleted())
+            .context(UnknownEventRef {
+                event_ref: self.event.clone(),
+            })?;
+        let current = event_doc(event).context(ConflictingStarts {
+            event_ref: self.event.clone(),
+        })?;
+
+        let contents = toml::ser::to_string(&current).context(SerializeEvent {})?;
+        let path = write_temp_file(&format!("augr-edit-{}", self.event), "toml", &contents)
+            .context(WriteEventFile {
+                path: env::temp_dir(),
+            })?;
+        let edited = run_editor(&path);
+        let _ = fs::remove_file(&path);
+        let edited: EventDoc = toml::de::from_str(&edited?).context(ParseEventFile {})?;
+
+        match changes(timesheet, conf, &self.event, event, &current, &edited)? {
+            Some(patch) => Ok(vec![patch]),
+            None => {
+                println!("Nothing was changed");
+                Ok(vec![])
+            }
+        }
+    }
+}
+
+/// The current state of `event`, or `None` if it has conflicting start times
+pub fn event_doc(event: &PatchedEvent) -> Option<EventDoc> {
+    let starts = event.starts();
+    if starts.len() != 1 {
+        return None;
+    }
+    let (_, start) = starts.into_iter().next().unwrap();
+    Some(EventDoc {
+        start: start.with_timezone(&Local).into(),
+        tags: event.tags().into_iter().map(|(_, tag)| tag).collect(),
+        note: event.note().unwrap_or_default(),
+    })
+}
+
+/// Write `contents` to a new file in the temporary folder, named after `name`
+/// and the process, that only the user can read. The file has to be new, so
+/// that a file or link that someone else put there first isn't written
+/// through; if the name is taken, a number is added to it.
+pub fn write_temp_file(name: &str, extension: &str, contents: &str) -> io::Result<PathBuf> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    for attempt in 0.. {
+        let path = env::temp_dir().join(format!(
+            "{}-{}-{}.{}",
+            name,
+            process::id(),
+            attempt,
+            extension
+        ));
+        match options.open(&path) {
+            Ok(mut file) => {
+                io::Write::write_all(&mut file, contents.as_bytes())?;
+                return Ok(path);
+            }
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    unreachable!("there is always another attempt")
+}
+
+/// Open `path` in the user's editor and read it back once the editor exits
+pub fn run_editor(path: &Path) -> Result<String, Error> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| String::from("vi"));
+    // Editors are often configured with arguments, like `code --wait`
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or("vi");
+    let status = process::Command::new(program)
+        .args(words)
+        .arg(path)
+        .status()
+        .context(RunEditor {
+            editor: editor.clone(),
+        })?;
+    if !status.success() {
+        return Err(Error::EditorFailed { editor });
+    }
+    fs::read_to_string(path).context(ReadEventFile { path })
+}
+
+/// The smallest patch that turns `current` into `edited`, or `None` if they
+/// are the same
+pub fn changes(
+    timesheet: &Timesheet,
+    conf: &Conf,
+    event_ref: &EventRef,
+    event: &PatchedEvent,
+    current: &EventDoc,
+    edited: &EventDoc,
+) -> Result<Option<Patch>, Error> {
+    let parents = event.latest_patches();
+    let mut changed = current.start != edited.start;
+    let mut patch = if changed {
+        set_start::set_start(timesheet, conf, event_ref, edited.start.with_timezone(&Utc))
+            .context(SetStart {})?
+    } else {
+        Patch::new()
+    };
+
+    let current_tags: BTreeSet<&Tag> = current.tags.iter().collect();
+    let edited_tags: BTreeSet<&Tag> = edited.tags.iter().collect();
+    for tag in edited_tags.difference(&current_tags) {
+        changed = true;
+        patch.insert_add_tag(AddTag {
+            parents: parents.clone(),
+            event: event_ref.clone(),
+            tag: tag.to_string(),
+        });
+    }
+    for (patch_ref, tag) in event.tags() {
+        if !edited_tags.contains(&tag) {
+            changed = true;
+            patch.insert_remove_tag(RemoveTag {
+                parents: Some(parents.clone()),
+                patch: patch_ref,
+                event: event_ref.clone(),
+                tag,
+            });
+        }
+    }
+
+    let note = edited.note.trim();
+    if note != current.note {
+        changed = true;
+        patch.insert_set_note(SetNote {
+            parents: parents.clone(),
+            replaces: event.note_patches(),
+            event: event_ref.clone(),
+            note: note.to_string(),
+        });
+    }
+    Ok(Some(patch).filter(|_| changed))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::{self, Tracked};
+    use chrono::TimeZone;
+
+    #[test]
+    fn only_changed_fields_are_patched() {
+        let start = Utc.ymd(2019, 7, 24).and_hms(9, 0, 0);
+        let create =
+            Patch::new().create_event("a".into(), start, vec!["work".into(), "meeting".into()]);
+        let tracked = Tracked::new(&[&create]);
+        let timesheet = tracked.timesheet();
+        let conf: Conf = testing::conf("");
+        let event = &tracked.patched.events["a"];
+
+        let current = event_doc(event).unwrap();
+        let unchanged = toml::de::from_str(&toml::ser::to_string(&current).unwrap()).unwrap();
+        assert!(
+            changes(&timesheet, &conf, &"a".into(), event, &current, &unchanged)
+                .unwrap()
+                .is_none()
+        );
+
+        let edited = EventDoc {
+            tags: vec!["work".into(), "review".into()],
+            note: String::from("Went over the design\n"),
+            ..unchanged
+        };
+        let patch = changes(&timesheet, &conf, &"a".into(), event, &current, &edited)
+            .unwrap()
+            .unwrap();
+        assert!(patch.add_start.is_empty() && patch.remove_start.is_empty());
+        assert_eq!(patch.add_tag.iter().next().unwrap().tag, "review");
+        assert_eq!(patch.remove_tag.iter().next().unwrap().tag, "meeting");
+        assert_eq!(
+            patch.set_note.iter().next().unwrap().note,
+            "Went over the design"
+        );
+    }
+
+    #[test]
+    fn start_is_moved() {
+        let time = |h, m| Utc.ymd(2019, 7, 24).and_hms(h, m, 0);
+        let create = Patch::new().create_event("a".into(), time(9, 40), vec!["work".into()]);
+        let mut tracked = Tracked::new(&[&create]);
+        let timesheet = tracked.timesheet();
+        let conf: Conf = testing::conf("");
+        let edit = |start: Option<DateTime<Utc>>| Cmd {
+            event: "a".into(),
+            start: start.map(|start| start.with_timezone(&Local)),
+            editor: false,
+        };
+
+        let patches = edit(Some(time(9, 15))).exec(&timesheet, &conf).unwrap();
+        tracked.apply(&patches[0]);
+        let timesheet = tracked.timesheet();
+        assert_eq!(
+            timesheet.events().keys().copied().collect::<Vec<_>>(),
+            vec![time(9, 15)]
+        );
+
+        assert!(edit(None).exec(&timesheet, &conf).is_err());
+    }
+
+    #[test]
+    fn temp_files_are_new_and_private() {
+        let name = "augr-edit-test";
+        let taken = env::temp_dir().join(format!("{}-{}-0.txt", name, process::id()));
+        fs::write(&taken, "someone else's").unwrap();
+
+        let path = write_temp_file(name, "txt", "event").unwrap();
+        assert_ne!(path, taken);
+        assert_eq!(fs::read_to_string(&taken).unwrap(), "someone else's");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "event");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        fs::remove_file(&taken).unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,360 @@
+use crate::{
+    config::Conf,
+    delete,
+    edit::{self, EventDoc},
+    time_input::parse_default_local,
+};
+use augr_core::{EventRef, Patch, Tag, Timesheet};
+use chrono::{Date, DateTime, Duration, Local, NaiveTime, TimeZone, Utc};
+use snafu::{OptionExt, ResultExt, Snafu};
+use std::{collections::BTreeMap, env, fs, path::PathBuf};
+use structopt::StructOpt;
+
+const HELP: &str = "\
+# One event per line, as `HH:MM tags... #ref`. Change a time or the tags to
+# edit an event, delete a line to delete its event, and add a line without a
+# #ref to start a new event. Lines starting with # are ignored, and removing
+# every other line aborts the edit.
+";
+
+#[derive(StructOpt, Debug)]
+pub struct Cmd {
+    /// The day to edit, like "yesterday" or "2019-07-24". Defaults to today.
+    #[structopt(parse(try_from_os_str = parse_default_local))]
+    date: Option<DateTime<Local>>,
+}
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("{}", source))]
+    Edit { source: edit::Error },
+
+    #[snafu(display("Could not write {}: {}", path.display(), source))]
+    WriteDayFile {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[snafu(display("Line {} isn't `HH:MM tags... #ref`: {}", number, line))]
+    InvalidLine { number: usize, line: String },
+
+    #[snafu(display(
+        "Line {} refers to #{}, which isn't an event of this day",
+        number,
+        event
+    ))]
+    UnknownEvent { number: usize, event: String },
+
+    #[snafu(display("Event #{} is listed more than once", event))]
+    DuplicateEvent { event: String },
+
+    #[snafu(display(
+        "Line {} starts at {}, which the clocks skipped that day",
+        number,
+        time.format("%H:%M")
+    ))]
+    SkippedTime { number: usize, time: NaiveTime },
+
+    #[snafu(display("{}", source))]
+    Delete { source: delete::Error },
+
+    #[snafu(display("Aborting because every event was removed"))]
+    EmptyDay,
+}
+
+/// A line of the edited day
+#[derive(Debug, Eq, PartialEq)]
+struct Line {
+    /// Where the line is in the file, counting from 1
+    number: usize,
+    time: NaiveTime,
+    tags: Vec<Tag>,
+    /// The short reference of the event, unless it is a new one
+    event: Option<String>,
+}
+
+impl Cmd {
+    pub fn exec(&self, timesheet: &Timesheet, conf: &Conf) -> Result<Vec<Patch>, Error> {
+        let date = self
+            .date
+            .unwrap_or_else(|| timesheet.clock().now().with_timezone(&Local))
+            .date();
+        let (events, conflicting) = day_events(timesheet, date);
+        let text = render(date, &events, &conflicting);
+
+        let name = format!("augr-day-{}", date.format("%Y-%m-%d"));
+        let path = edit::write_temp_file(&name, "txt", &text).context(WriteDayFile {
+            path: env::temp_dir(),
+        })?;
+        let edited = edit::run_editor(&path);
+        let _ = fs::remove_file(&path);
+        let lines = parse(&edited.context(Edit {})?)?;
+
+        let patches = day_patches(timesheet, conf, date, &events, &lines)?;
+        if patches.is_empty() {
+            println!("Nothing was changed");
+        }
+        Ok(patches)
+    }
+}
+
+/// The short reference and current state of each event that starts on `date`,
+/// and the short references of the events that can't be edited because they
+/// have conflicting start times
+fn day_events(
+    timesheet: &Timesheet,
+    date: Date<Local>,
+) -> (Vec<(String, EventRef, EventDoc)>, Vec<String>) {
+    let patched = timesheet.get_patched_timesheet();
+    let short_refs = patched.short_refs();
+    let day_start = date.and_hms(0, 0, 0).with_timezone(&Utc);
+    let day_end = (date + Duration::days(1))
+        .and_hms(0, 0, 0)
+        .with_timezone(&Utc);
+    let mut events = Vec::new();
+    let mut conflicting = Vec::new();
+    for segment in timesheet
+        .segments()
+        .into_iter()
+        .filter(|s| s.start_time >= day_start && s.start_time < day_end)
+    {
+        let short_ref = short_refs[&segment.event_ref].clone();
+        match edit::event_doc(&patched.events[&segment.event_ref]) {
+            Some(doc) => events.push((short_ref, segment.event_ref, doc)),
+            None => conflicting.push(short_ref),
+        }
+    }
+    (events, conflicting)
+}
+
+fn render(
+    date: Date<Local>,
+    events: &[(String, EventRef, EventDoc)],
+    conflicting: &[String],
+) -> String {
+    let mut text = format!("# Events on {}\n{}", date.format("%Y-%m-%d"), HELP);
+    for (short_ref, _, doc) in events {
+        text.push_str(&format!(
+            "{} {} #{}\n",
+            doc.start.format("%H:%M"),
+            doc.tags.join(" "),
+            short_ref
+        ));
+    }
+    // Listed so that they don't go missing without a word, but as comments,
+    // since the editor can't show more than one start time
+    for short_ref in conflicting {
+        text.push_str(&format!(
+            "# #{} has conflicting start times and is left alone; settle them with `augr resolve`\n",
+            short_ref
+        ));
+    }
+    text
+}
+
+fn parse(text: &str) -> Result<Vec<Line>, Error> {
+    let mut lines = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let invalid = || Error::InvalidLine {
+            number: i + 1,
+            line: line.to_string(),
+        };
+        let mut words: Vec<&str> = trimmed.split_whitespace().collect();
+        let event = match words.last() {
+            Some(word) if word.starts_with('#') => Some(words.pop().unwrap()[1..].to_string()),
+            _ => None,
+        };
+        if words.is_empty() {
+            return Err(invalid());
+        }
+        let time = NaiveTime::parse_from_str(words[0], "%H:%M").map_err(|_| invalid())?;
+        lines.push(Line {
+            number: i + 1,
+            time,
+            tags: words[1..].iter().map(|t| t.to_string()).collect(),
+            event,
+        });
+    }
+    Ok(lines)
+}
+
+/// Patches that turn the day's `events` into the edited `lines`
+fn day_patches(
+    timesheet: &Timesheet,
+    conf: &Conf,
+    date: Date<Local>,
+    events: &[(String, EventRef, EventDoc)],
+    lines: &[Line],
+) -> Result<Vec<Patch>, Error> {
+    // Like an empty commit message, an emptied file is taken as a change of
+    // mind rather than a request to delete the whole day
+    if lines.is_empty() && !events.is_empty() {
+        return EmptyDay {}.fail();
+    }
+    let patched = timesheet.get_patched_timesheet();
+    let mut remaining: BTreeMap<&str, (&EventRef, &EventDoc)> = events
+        .iter()
+        .map(|(short_ref, event_ref, doc)| (short_ref.as_str(), (event_ref, doc)))
+        .collect();
+
+    let mut patches = Vec::new();
+    let mut created = Patch::new();
+    for line in lines {
+        let start = Local
+            .from_local_datetime(&date.naive_local().and_time(line.time))
+            .earliest()
+            .context(SkippedTime {
+                number: line.number,
+                time: line.time,
+            })?;
+        let short_ref = match &line.event {
+            Some(short_ref) => short_ref,
+            None => {
+                let event_ref = augr_core::id::new_id().to_string();
+                created =
+                    created.create_event(event_ref, start.with_timezone(&Utc), line.tags.clone());
+                continue;
+            }
+        };
+        let (event_ref, current) = match remaining.remove(short_ref.as_str()) {
+            Some(event) => event,
+            None if events.iter().any(|(s, _, _)| s == short_ref) => {
+                return DuplicateEvent {
+                    event: short_ref.clone(),
+                }
+                .fail()
+            }
+            None => {
+                return UnknownEvent {
+                    number: line.number,
+                    event: short_ref.clone(),
+                }
+                .fail()
+            }
+        };
+        // Times are shown to the minute, so an untouched time keeps its seconds
+        let start =
+            if current.start.format("%H:%M").to_string() == line.time.format("%H:%M").to_string() {
+                current.start
+            } else {
+                start.into()
+            };
+        let edited = EventDoc {
+            start,
+            tags: line.tags.clone(),
+            note: current.note.clone(),
+        };
+        let event = &patched.events[event_ref];
+        if let Some(patch) =
+            edit::changes(timesheet, conf, event_ref, event, current, &edited).context(Edit {})?
+        {
+            patches.push(patch);
+        }
+    }
+
+    for (event_ref, _) in remaining.values() {
+        patches.push(delete::delete_event(timesheet, event_ref).context(Delete {})?);
+    }
+    if !created.create_event.is_empty() {
+        patches.push(created);
+    }
+    Ok(patches)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::{self, Tracked};
+    use augr_core::repository::event::Resolution;
+
+    #[test]
+    fn edits_become_patches() {
+        let date = Local.ymd(2019, 7, 24);
+        let at = |h, m| date.and_hms(h, m, 0).with_timezone(&Utc);
+        let create = Patch::new()
+            .create_event("aaaaaaaa01".into(), at(9, 0), vec!["work".into()])
+            .create_event("bbbbbbbb01".into(), at(12, 0), vec!["lunch".into()])
+            .create_event("cccccccc01".into(), at(13, 0), vec!["work".into()]);
+        let tracked = Tracked::new(&[&create]);
+        let timesheet = tracked.timesheet();
+        let conf: Conf = testing::conf("");
+
+        let (events, conflicting) = day_events(&timesheet, date);
+        assert!(conflicting.is_empty());
+        let text = render(date, &events, &conflicting);
+        assert!(text.contains("09:00 work #aaaaaaaa\n"));
+        let unchanged = parse(&text).unwrap();
+        assert!(day_patches(&timesheet, &conf, date, &events, &unchanged)
+            .unwrap()
+            .is_empty());
+
+        let edited = parse(
+            "# a comment\n\
+             09:00 work review #aaaaaaaa\n\
+             12:30 lunch #bbbbbbbb\n\
+             15:00 meeting\n",
+        )
+        .unwrap();
+        let patches = day_patches(&timesheet, &conf, date, &events, &edited).unwrap();
+        let mut after = Tracked::new(&[&create]);
+        for patch in patches.iter() {
+            after.apply(patch);
+        }
+        let after = after.timesheet();
+        let tags: Vec<Vec<Tag>> = after
+            .segments()
+            .into_iter()
+            .map(|s| s.tags.into_iter().collect())
+            .collect();
+        assert_eq!(
+            tags,
+            vec![
+                vec![String::from("review"), "work".into()],
+                vec![String::from("lunch")],
+                vec![String::from("meeting")],
+            ]
+        );
+        assert_eq!(after.segments()[1].start_time, at(12, 30));
+
+        assert!(parse("lunch #bbbbbbbb").is_err());
+        let unknown = parse("10:00 work #dddddddd").unwrap();
+        assert!(day_patches(&timesheet, &conf, date, &events, &unknown).is_err());
+
+        let emptied = parse(
+            "# Events on 2019-07-24
+
+",
+        )
+        .unwrap();
+        match day_patches(&timesheet, &conf, date, &events, &emptied) {
+            Err(Error::EmptyDay) => {}
+            other => panic!("expected the edit to be aborted, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn conflicting_events_are_listed_but_left_alone() {
+        let date = Local.ymd(2019, 7, 24);
+        let at = |h, m| date.and_hms(h, m, 0).with_timezone(&Utc);
+        let create = Patch::new()
+            .create_event("aaaaaaaa01".into(), at(9, 0), vec!["work".into()])
+            .create_event("bbbbbbbb01".into(), at(12, 0), vec!["lunch".into()]);
+        let moved = Patch::new().add_start(*create.patch_ref(), "bbbbbbbb01".into(), at(12, 30));
+        let tracked = Tracked::new(&[&create, &moved]);
+        let timesheet = tracked.timesheet_with(Resolution::EarliestWins);
+        let conf: Conf = testing::conf("");
+
+        let (events, conflicting) = day_events(&timesheet, date);
+        assert_eq!(conflicting, vec![String::from("bbbbbbbb")]);
+        let text = render(date, &events, &conflicting);
+        assert!(text.contains("# #bbbbbbbb has conflicting start times"));
+        let unchanged = parse(&text).unwrap();
+        assert!(day_patches(&timesheet, &conf, date, &events, &unchanged)
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,500 @@
+use snafu::{ensure, Snafu};
+use structopt::StructOpt;
+
+/// A command line that shows off a feature of augr
+pub struct Example {
+    pub description: &'static str,
+    /// The arguments passed to `augr`
+    pub args: &'static [&'static str],
+}
+
+/// Curated examples for each command. Every example is run on a sample
+/// timesheet by `tests/examples.rs`, which checks that it still prints the
+/// output saved there, so they can't go out of date.
+pub const EXAMPLES: &[Example] = &[
+    Example {
+        description: "Start working on something now",
+        args: &["start", "work", "coding"],
+    },
+    Example {
+        description: "Record that you started something 15 minutes ago",
+        args: &["start", "--at", "15 minutes ago", "lunch"],
+    },
+    Example {
+        description: "Record a meeting that started yesterday morning",
+        args: &["start", "--at", "yesterday 9am", "meeting"],
+    },
+    Example {
+        description: "Start a Taskwarrior task, tagged with its project and tags",
+        args: &["start", "--task", "42"],
+    },
+    Example {
+        description: "Stop tracking, leaving the time until the next start untracked",
+        args: &["stop"],
+    },
+    Example {
+        description: "Throw away an event that was started by mistake",
+        args: &["cancel"],
+    },
+    Example {
+        description: "Show what is being tracked right now",
+        args: &["status"],
+    },
+    Example {
+        description: "Show everything tracked today",
+        args: &["summary"],
+    },
+    Example {
+        description: "Show this week's work that wasn't meetings",
+        args: &[
+            "summary",
+            "--start",
+            "7d",
+            "--filter",
+            "work and not meeting",
+        ],
+    },
+    Example {
+        description: "Chart the last week of work against the same days a week earlier",
+        args: &["chart", "work", "--compare-with", "7 days ago"],
+    },
+    Example {
+        description: "Get this week's time per tag as JSON for a script",
+        args: &[
+            "report",
+            "--start",
+            "7 days ago",
+            "--bucket",
+            "day",
+            "--format",
+            "json",
+        ],
+    },
+    Example {
+        description: "Chart the last week with the time tracked on each day",
+        args: &["chart", "--totals"],
+    },
+    Example {
+        description: "Chart work, personal time, and exercise side by side",
+        args: &[
+            "chart",
+            "--group",
+            "work:work or meeting",
+            "--group",
+            "personal:family or reading",
+            "--group",
+            "exercise:gym or run",
+        ],
+    },
+    Example {
+        description: "Copy today's events as a table for a GitHub or GitLab comment",
+        args: &["summary", "--format", "markdown"],
+    },
+    Example {
+        description: "Save this year's heatmap as an image for a wiki page",
+        args: &["heatmap", "work", "--output", "heatmap.svg"],
+    },
+    Example {
+        description: "Save the last week's report as a web page to send to a client",
+        args: &[
+            "report",
+            "--start",
+            "7 days ago",
+            "--format",
+            "html",
+            "--output",
+            "report.html",
+        ],
+    },
+    Example {
+        description: "Review time recorded abroad in the timezone it was tracked in",
+        args: &["--tz", "America/New_York", "summary"],
+    },
+    Example {
+        description: "Show this week's totals in decimal hours, for pasting into a timesheet",
+        args: &[
+            "--duration-format",
+            "decimal",
+            "summary",
+            "--group-by",
+            "tag",
+        ],
+    },
+    Example {
+        description: "Show how far along each target is this week or month",
+        args: &["budget"],
+    },
+    Example {
+        description: "Make an HTML invoice for the time tracked for acme in July",
+        args: &[
+            "invoice",
+            "--client",
+            "acme",
+            "--from",
+            "2019-07-01",
+            "--to",
+            "2019-08-01",
+            "--format",
+            "html",
+        ],
+    },
+    Example {
+        description: "Report today's time, with each event rounded up to a quarter hour",
+        args: &["report", "--round", "15m up per-event"],
+    },
+    Example {
+        description: "Show where the week went, as a share of the time for each tag",
+        args: &["summary", "--breakdown", "--start", "2019-07-22"],
+    },
+    Example {
+        description: "Show a table of the time per tag on each day of the week so far",
+        args: &["summary", "--matrix", "--start", "2019-07-22"],
+    },
+    Example {
+        description: "Show which hours of the day coding happens in",
+        args: &["histogram", "coding"],
+    },
+    Example {
+        description: "Compare the time per tag in January and February",
+        args: &["diff", "2019-01", "2019-02"],
+    },
+    Example {
+        description: "Compare the time per tag this week with the same part of last week",
+        args: &["report", "--compare", "week"],
+    },
+    Example {
+        description: "Show the working hours of the last week that nothing was tracked in",
+        args: &["report", "--untracked", "--start", "7 days ago"],
+    },
+    Example {
+        description: "Show statistics for the last 30 days, with the longest run of gym days",
+        args: &["stats", "--streak", "gym"],
+    },
+    Example {
+        description: "Show which days of 2019 had time spent exercising",
+        args: &["heatmap", "exercise", "--year", "2019"],
+    },
+    Example {
+        description: "Show yesterday as a timeline of bars",
+        args: &["timeline", "yesterday"],
+    },
+    Example {
+        description: "Show a calendar of the hours worked in July",
+        args: &["month", "2019-07-01", "--filter", "work"],
+    },
+    Example {
+        description: "Review last week, day by day",
+        args: &["week", "7 days ago"],
+    },
+    Example {
+        description: "Fill in a weekly timesheet: time per tag on each day, without breaks",
+        args: &[
+            "report",
+            "--start",
+            "2019-07-22",
+            "--end",
+            "2019-07-27",
+            "--bucket",
+            "day",
+            "--filter",
+            "not break",
+        ],
+    },
+    Example {
+        description: "Show how much was worked each week since July",
+        args: &[
+            "summary",
+            "work",
+            "--start",
+            "2019-07-01",
+            "--group-by",
+            "week",
+        ],
+    },
+    Example {
+        description: "Pick the tags to start with from the ones used before",
+        args: &["start", "-i"],
+    },
+    Example {
+        description: "Rename a tag on every event, past and present",
+        args: &["tag-rename", "ws", "workshop"],
+    },
+    Example {
+        description: "Start a meeting with the tags of the \"meeting\" template",
+        args: &["start", "--template", "meeting", "planning"],
+    },
+    Example {
+        description: "Record that the current event turned into a review at 15:00",
+        args: &["split", "@", "--at", "15:00", "work", "review"],
+    },
+    Example {
+        description: "Merge the current event into the one started just before it",
+        args: &["merge", "@-1", "@"],
+    },
+    Example {
+        description: "Fix a tag that was misspelled for months",
+        args: &[
+            "retag",
+            "--from",
+            "2019-04-01",
+            "--to",
+            "2019-07-01",
+            "--remove",
+            "wrok",
+            "--add",
+            "work",
+        ],
+    },
+    Example {
+        description: "Check that the other devices see the same events this week",
+        args: &["checksum"],
+    },
+    Example {
+        description: "Save a week's report, to check that a risky change didn't alter it",
+        args: &[
+            "report",
+            "--start",
+            "2019-07-15",
+            "--end",
+            "2019-07-22",
+            "--save-golden",
+            "week-29",
+        ],
+    },
+    Example {
+        description: "Check the saved report after the change",
+        args: &["report", "--check-golden", "week-29"],
+    },
+    Example {
+        description: "Show the weekly overtime balance",
+        args: &["overtime"],
+    },
+    Example {
+        description: "List every tag that has been used",
+        args: &["tags"],
+    },
+    Example {
+        description: "Add tags to an event, using a reference from `summary --refs`",
+        args: &["tag", "1b1b3b3a-39c4-4e3c-9a0f-6a3b3f1d1f2e", "review"],
+    },
+    Example {
+        description: "Swap a tag on an event",
+        args: &[
+            "tag",
+            "1b1b3b3a-39c4-4e3c-9a0f-6a3b3f1d1f2e",
+            "+review",
+            "-meeting",
+        ],
+    },
+    Example {
+        description: "Tag the event before the current one",
+        args: &["tag", "@-1", "+meeting"],
+    },
+    Example {
+        description: "Keep the events of a personal errand out of exports",
+        args: &["private", "on"],
+    },
+    Example {
+        description: "See which events the tagging rules in the config would change",
+        args: &["rules", "test"],
+    },
+    Example {
+        description: "Change the current event's start, tags and note in your editor",
+        args: &["edit", "@", "--editor"],
+    },
+    Example {
+        description: "Preview tagging this year's events with the rules from the config",
+        args: &["--dry-run", "rules", "apply", "--start", "2019-01-01"],
+    },
+    Example {
+        description: "Fix the times and tags of yesterday's events in your editor",
+        args: &["edit-day", "yesterday"],
+    },
+    Example {
+        description: "Write down what an event was about",
+        args: &[
+            "annotate",
+            "1b1b3b3a-39c4-4e3c-9a0f-6a3b3f1d1f2e",
+            "Planned the next release",
+        ],
+    },
+    Example {
+        description: "Show today's events along with their notes",
+        args: &["summary", "--notes"],
+    },
+    Example {
+        description: "Take back the last change made on this device",
+        args: &["undo"],
+    },
+    Example {
+        description: "Change when an event started",
+        args: &["set-start", "1b1b3b3a-39c4-4e3c-9a0f-6a3b3f1d1f2e", "09:30"],
+    },
+    Example {
+        description: "Fix an event that actually started at 9:15",
+        args: &[
+            "edit",
+            "1b1b3b3a-39c4-4e3c-9a0f-6a3b3f1d1f2e",
+            "--start",
+            "9:15",
+        ],
+    },
+    Example {
+        description: "Delete an event without being asked to confirm",
+        args: &["delete", "--yes", "1b1b3b3a-39c4-4e3c-9a0f-6a3b3f1d1f2e"],
+    },
+    Example {
+        description: "Show the changes made to an event",
+        args: &["history", "1b1b3b3a-39c4-4e3c-9a0f-6a3b3f1d1f2e"],
+    },
+    Example {
+        description: "Keep the earliest start of events moved on two devices at once",
+        args: &["resolve", "--strategy", "earliest-wins"],
+    },
+    Example {
+        description: "Record two weeks of vacation",
+        args: &["vacation", "add", "2019-07-01..2019-07-14"],
+    },
+    Example {
+        description: "Record a sick day",
+        args: &["absence", "add", "2019-07-23", "--kind", "sick"],
+    },
+    Example {
+        description: "Name a device so it is easier to recognize",
+        args: &[
+            "device",
+            "rename",
+            "7a826905-7a3e-430d-9d54-5af08ecb482c",
+            "laptop",
+        ],
+    },
+    Example {
+        description: "Mark a device that is no longer used",
+        args: &["device", "retire", "phone"],
+    },
+    Example {
+        description: "See what changed on other devices since you last looked",
+        args: &["whatsnew"],
+    },
+    Example {
+        description: "Check for devices that haven't synced lately, and other problems",
+        args: &["doctor"],
+    },
+    Example {
+        description: "Print today's events as a Markdown log with Obsidian links",
+        args: &["export", "markdown", "--obsidian"],
+    },
+    Example {
+        description: "Export last week and sign it, so the client can check it later",
+        args: &[
+            "export",
+            "markdown",
+            "--start",
+            "7 days ago",
+            "--signature",
+            "week.sig",
+        ],
+    },
+    Example {
+        description: "Check a signed export against the tracked time",
+        args: &["verify-export", "week.sig"],
+    },
+    Example {
+        description: "Update today's daily note in an Obsidian vault",
+        args: &["push", "obsidian", "--vault", "notes/daily"],
+    },
+    Example {
+        description: "Write the time spent on tasks back into Taskwarrior",
+        args: &["push", "taskwarrior"],
+    },
+    Example {
+        description: "Export whatever can still be read from a damaged sync folder",
+        args: &["--safe-mode", "export", "markdown"],
+    },
+    Example {
+        description: "Make an anonymized archive to attach to a bug report",
+        args: &["debug", "bundle", "--output", "augr-debug.tar"],
+    },
+    Example {
+        description: "Complete commands, tags, and events in bash",
+        args: &["completions", "bash"],
+    },
+    Example {
+        description: "Import data from augr 0.1",
+        args: &["import", "OriginalLineFormat", "old-sync-folder"],
+    },
+];
+
+#[derive(StructOpt, Debug)]
+pub struct Cmd {
+    /// Only show examples for this command
+    command: Option<String>,
+}
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("No examples for \"{}\"", command))]
+    NoExamples { command: String },
+}
+
+impl Cmd {
+    pub fn exec(&self) -> Result<(), Error> {
+        let examples: Vec<&Example> = EXAMPLES
+            .iter()
+            .filter(|e| self.command.as_ref().is_none_or(|c| e.args[0] == c))
+            .collect();
+        ensure!(
+            !examples.is_empty(),
+            NoExamples {
+                command: self.command.clone().unwrap_or_default(),
+            }
+        );
+
+        for (i, example) in examples.iter().enumerate() {
+            if i > 0 {
+                println!();
+            }
+            println!("# {}", example.description);
+            println!("augr {}", quote(example.args));
+        }
+        Ok(())
+    }
+}
+
+/// Join arguments so that they can be pasted into a shell
+fn quote(args: &[&str]) -> String {
+    args.iter()
+        .map(|arg| {
+            if arg.contains(' ') {
+                format!("\"{}\"", arg)
+            } else {
+                arg.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Opt;
+
+    #[test]
+    fn examples_parse() {
+        for example in EXAMPLES {
+            let args = std::iter::once("augr").chain(example.args.iter().cloned());
+            if let Err(e) = Opt::from_iter_safe(args) {
+                panic!("example `augr {}` is invalid: {}", quote(example.args), e);
+            }
+        }
+    }
+
+    #[test]
+    fn quote_args_with_spaces() {
+        assert_eq!(
+            quote(&["chart", "--compare-with", "7 days ago"]),
+            "chart --compare-with \"7 days ago\""
+        );
+    }
+}
@@ -0,0 +1,122 @@
+pub mod csv;
+pub mod json;
+pub mod markdown;
+
+use crate::{
+    config::Conf,
+    days_off::{self, DaysOff},
+    signature::Signature,
+    time_input::{parse_default_local, RangeError, RangeGuard},
+};
+use augr_core::Timesheet;
+use chrono::{DateTime, Duration, Local, Utc};
+use clap::arg_enum;
+use snafu::{OptionExt, ResultExt, Snafu};
+use std::{fs, io, path::PathBuf};
+use structopt::StructOpt;
+
+arg_enum! {
+    /// List of formats that can be exported
+    #[derive(Copy, Clone, Debug)]
+    enum Format {
+        Markdown,
+    }
+}
+
+#[derive(StructOpt, Debug)]
+pub struct ExportCmd {
+    /// The format to export to
+    #[structopt(possible_values = &Format::variants(), case_insensitive = true)]
+    format: Format,
+
+    /// The datetime at which to begin exporting events. Defaults to the start of today.
+    #[structopt(long = "start", parse(try_from_os_str = parse_default_local))]
+    start: Option<DateTime<Local>>,
+
+    /// The datetime at which to stop exporting events. Defaults to now.
+    #[structopt(long = "end", parse(try_from_os_str = parse_default_local))]
+    end: Option<DateTime<Local>>,
+
+    /// Write tags as Obsidian wiki-links, like `[[coding]]`
+    #[structopt(long = "obsidian")]
+    obsidian: bool,
+
+    /// Also export events that were started in private mode
+    #[structopt(long = "include-private")]
+    pub include_private: bool,
+
+    /// Sign the exported time with the `export_key` from the config and write
+    /// the signature to this file, for checking with `augr verify-export`
+    #[structopt(long = "signature")]
+    signature: Option<PathBuf>,
+
+    #[structopt(flatten)]
+    guard: RangeGuard,
+}
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("{}", source))]
+    LoadDaysOff { source: days_off::Error },
+
+    #[snafu(display("{}", source))]
+    LongRange { source: RangeError },
+
+    #[snafu(display("Set `export_key` in the config to sign exports"))]
+    NoExportKey,
+
+    #[snafu(display("Could not write signature to {}: {}", path.display(), source))]
+    WriteSignature { path: PathBuf, source: io::Error },
+}
+
+impl ExportCmd {
+    pub fn exec(&self, timesheet: &Timesheet, conf: &Conf) -> Result<(), Error> {
+        let days_off = DaysOff::load(conf, timesheet).context(LoadDaysOff {})?;
+        let now = timesheet.clock().now().with_timezone(&Local);
+        let start = self.start.unwrap_or_else(|| now.date().and_hms(0, 0, 0));
+        let end = self.end.unwrap_or(now);
+        self.guard
+            .check(start.naive_local().date(), end.naive_local().date())
+            .context(LongRange {})?;
+        let key = match &self.signature {
+            Some(_) => Some(conf.export_key.as_ref().context(NoExportKey {})?),
+            None => None,
+        };
+
+        match self.format {
+            Format::Markdown => {
+                let range = start.with_timezone(&Utc)..end.with_timezone(&Utc);
+                let mut date = start.date();
+                let mut days = Vec::new();
+                while date.and_hms(0, 0, 0) <= end {
+                    let absence = days_off.kind(date.naive_local());
+                    let day = markdown::render_day(
+                        timesheet,
+                        date,
+                        range.clone(),
+                        absence,
+                        self.obsidian,
+                    );
+                    if !day.is_empty() {
+                        days.push(day);
+                    }
+                    date = date + Duration::days(1);
+                }
+                print!("{}", days.join("\n"));
+            }
+        }
+
+        if let (Some(path), Some(key)) = (&self.signature, key) {
+            let signature = Signature::sign(
+                timesheet,
+                start.with_timezone(&Utc),
+                end.with_timezone(&Utc),
+                self.include_private,
+                key,
+            );
+            fs::write(path, format!("{}\n", signature))
+                .context(WriteSignature { path: path.clone() })?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,74 @@
+//! Rows of time as CSV, for importing into spreadsheets
+
+use chrono::{DateTime, Duration, Local, TimeZone};
+use std::fmt::Write;
+
+/// The first line of CSV written by `row`
+pub const HEADER: &str = "start,end,duration,tags\n";
+
+/// A line with the local `start` and `end` times, like "2019-07-22 09:00:00",
+/// the duration as hours, minutes, and seconds, like "1:30:00", which
+/// spreadsheets read as a duration, and the tags separated by spaces
+pub fn row<Tz: TimeZone, I, T>(
+    start: DateTime<Tz>,
+    end: DateTime<Tz>,
+    duration: Duration,
+    tags: I,
+) -> String
+where
+    I: IntoIterator<Item = T>,
+    T: AsRef<str>,
+{
+    let tags: Vec<T> = tags.into_iter().collect();
+    let tags: Vec<&str> = tags.iter().map(AsRef::as_ref).collect();
+    let seconds = duration.num_seconds();
+    let mut out = String::new();
+    writeln!(
+        out,
+        "{},{},{}:{:02}:{:02},{}",
+        time(start),
+        time(end),
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+        field(&tags.join(" "))
+    )
+    .unwrap();
+    out
+}
+
+fn time<Tz: TimeZone>(time: DateTime<Tz>) -> String {
+    time.with_timezone(&Local)
+        .format("%Y-%m-%d %H:%M:%S")
+        .to_string()
+}
+
+/// Quote a CSV field if it has a comma, quote, or line break in it
+pub fn field(text: &str) -> String {
+    if text.contains(&[',', '"', '\n'][..]) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::Utc;
+
+    #[test]
+    fn csv_rows() {
+        let start = Local.ymd(2019, 7, 22).and_hms(9, 0, 0).with_timezone(&Utc);
+        assert_eq!(
+            row(
+                start,
+                start + Duration::minutes(90),
+                Duration::minutes(90),
+                ["coding", "work"]
+            ),
+            "2019-07-22 09:00:00,2019-07-22 10:30:00,1:30:00,coding work\n"
+        );
+        assert_eq!(field("say \"hi\", then"), "\"say \"\"hi\"\", then\"");
+    }
+}
@@ -0,0 +1,61 @@
+//! Output for scripts and other tools, from `--format json`. The schema of
+//! each command is described under "JSON output" in GUIDE.md. Fields are only
+//! ever added to it; anything else bumps `VERSION`.
+
+use augr_core::{timesheet::Segment, Tag};
+use chrono::{DateTime, Local, SecondsFormat, TimeZone};
+use serde::Serialize;
+use std::collections::BTreeSet;
+
+/// The version of the schemas, which every object starts with
+pub const VERSION: u32 = 1;
+
+#[derive(Serialize)]
+struct Versioned<'a, T> {
+    version: u32,
+    #[serde(flatten)]
+    output: &'a T,
+}
+
+/// `output` as pretty-printed JSON with a `version` field, ending in a newline
+pub fn to_string<T: Serialize>(output: &T) -> String {
+    let versioned = Versioned {
+        version: VERSION,
+        output,
+    };
+    let mut text = serde_json::to_string_pretty(&versioned).expect("output is always valid JSON");
+    text.push('\n');
+    text
+}
+
+/// A time in RFC 3339, like "2019-07-22T09:00:00+02:00", in the local
+/// timezone
+pub fn time<Tz: TimeZone>(time: DateTime<Tz>) -> String {
+    time.with_timezone(&Local)
+        .to_rfc3339_opts(SecondsFormat::Secs, false)
+}
+
+/// An event, or the part of it in the range being shown
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Event {
+    #[serde(rename = "ref")]
+    pub event_ref: String,
+    pub start: String,
+    pub end: String,
+    pub seconds: i64,
+    pub tags: BTreeSet<Tag>,
+    pub note: Option<String>,
+}
+
+impl Event {
+    pub fn new(segment: &Segment, note: Option<String>) -> Self {
+        Self {
+            event_ref: segment.event_ref.clone(),
+            start: time(segment.start_time),
+            end: time(segment.end_time),
+            seconds: segment.duration.num_seconds(),
+            tags: segment.tags.clone(),
+            note,
+        }
+    }
+}
@@ -0,0 +1,159 @@
+use crate::{days_off::AbsenceKind, format_duration};
+use augr_core::Timesheet;
+use chrono::{Date, DateTime, Duration, Local, Utc};
+use std::{fmt::Write, ops::Range};
+
+/// Render the events that started on `date` and within `range` as a Markdown
+/// daily log, with a heading for the date and a bullet for each event. Returns
+/// an empty string if nothing was tracked and the day wasn't an absence.
+pub fn render_day(
+    timesheet: &Timesheet,
+    date: Date<Local>,
+    range: Range<DateTime<Utc>>,
+    absence: Option<AbsenceKind>,
+    obsidian: bool,
+) -> String {
+    let day = day_range(date);
+    let range = day.start.max(range.start)..day.end.min(range.end);
+    let events = render_range(timesheet, range, absence, obsidian);
+    if events.is_empty() {
+        return events;
+    }
+    format!("## {}\n\n{}", date.format("%Y-%m-%d"), events)
+}
+
+/// Render a bullet for each event that started on `date`, after a bullet for
+/// the absence if the day was one
+pub fn render_events(
+    timesheet: &Timesheet,
+    date: Date<Local>,
+    absence: Option<AbsenceKind>,
+    obsidian: bool,
+) -> String {
+    render_range(timesheet, day_range(date), absence, obsidian)
+}
+
+fn day_range(date: Date<Local>) -> Range<DateTime<Utc>> {
+    let day_start = date.and_hms(0, 0, 0).with_timezone(&Utc);
+    let day_end = (date + Duration::days(1))
+        .and_hms(0, 0, 0)
+        .with_timezone(&Utc);
+    day_start..day_end
+}
+
+/// Render a bullet for each event that started in `range`, with its note
+/// indented below it
+fn render_range(
+    timesheet: &Timesheet,
+    range: Range<DateTime<Utc>>,
+    absence: Option<AbsenceKind>,
+    obsidian: bool,
+) -> String {
+    let segments: Vec<_> = timesheet
+        .segments()
+        .into_iter()
+        .filter(|s| range.contains(&s.start_time))
+        .filter(|s| !s.tags.is_empty())
+        .collect();
+
+    let mut out = String::new();
+    if let Some(kind) = absence {
+        writeln!(out, "- Absent ({})", kind).unwrap();
+    }
+    for segment in segments {
+        let tags: Vec<String> = segment
+            .tags
+            .iter()
+            .map(|tag| {
+                if obsidian {
+                    format!("[[{}]]", tag)
+                } else {
+                    format!("`{}`", tag)
+                }
+            })
+            .collect();
+        writeln!(
+            out,
+            "- {}–{} ({}) {}",
+            segment.start_time.with_timezone(&Local).format("%H:%M"),
+            segment.end_time.with_timezone(&Local).format("%H:%M"),
+            format_duration(segment.duration),
+            tags.join(" ")
+        )
+        .unwrap();
+        if let Some(note) = timesheet.note(&segment.event_ref) {
+            for line in note.lines() {
+                writeln!(out, "  {}", line).unwrap();
+            }
+        }
+    }
+    out
+}
+
+/// A Markdown table with a row for each of `rows` under `headers`. Pipes in
+/// the cells are escaped, so that they don't split the cell.
+pub fn table(headers: &[&str], rows: &[Vec<String>]) -> String {
+    let line = |cells: Vec<String>| format!("| {} |\n", cells.join(" | "));
+    let mut out = line(headers.iter().map(|header| header.to_string()).collect());
+    out.push_str(&line(headers.iter().map(|_| String::from("---")).collect()));
+    for row in rows {
+        out.push_str(&line(
+            row.iter().map(|cell| cell.replace('|', "\\|")).collect(),
+        ));
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::Tracked;
+    use augr_core::Patch;
+    use chrono::TimeZone;
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn days_are_cut_to_the_range_and_keep_notes() {
+        let time = |h| Local.ymd(2019, 7, 1).and_hms(h, 0, 0).with_timezone(&Utc);
+        let first = Patch::new()
+            .create_event("a".into(), time(9), vec!["work".into()])
+            .create_event("b".into(), time(12), vec!["lunch".into()])
+            .create_event("c".into(), time(13), vec![]);
+        let note = Patch::new().set_note(
+            *first.patch_ref(),
+            BTreeSet::new(),
+            "a".into(),
+            String::from("Planning\nReviews"),
+        );
+        let tracked = Tracked::new(&[&first, &note]);
+        let timesheet = tracked.timesheet();
+        let date = Local.ymd(2019, 7, 1);
+
+        assert_eq!(
+            render_day(&timesheet, date, time(0)..time(23), None, false),
+            "## 2019-07-01\n\n\
+             - 09:00–12:00 (3h 0m) `work`\n  Planning\n  Reviews\n\
+             - 12:00–13:00 (1h 0m) `lunch`\n"
+        );
+        assert_eq!(
+            render_day(&timesheet, date, time(10)..time(23), None, true),
+            "## 2019-07-01\n\n- 12:00–13:00 (1h 0m) [[lunch]]\n"
+        );
+        assert_eq!(
+            render_day(&timesheet, date, time(0)..time(9), None, false),
+            ""
+        );
+    }
+
+    #[test]
+    fn markdown_table() {
+        let rows = vec![
+            vec![String::from("work"), String::from("3h 0m")],
+            vec![String::from("a|b"), String::from("1h 0m")],
+        ];
+        assert_eq!(
+            table(&["Tag", "Time"], &rows),
+            "| Tag | Time |\n| --- | --- |\n| work | 3h 0m |\n| a\\|b | 1h 0m |\n"
+        );
+    }
+}
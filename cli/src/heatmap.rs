@@ -0,0 +1,271 @@
+use crate::{
+    config::Conf,
+    format_duration, suggest,
+    svg::{self, Grid},
+};
+use augr_core::{
+    aggregate::{self, Period},
+    timesheet::Segment,
+    Tag, TagExpr, Timesheet,
+};
+use chrono::{Datelike, Duration, Local, NaiveDate, Weekday};
+use snafu::{ResultExt, Snafu};
+use std::{collections::BTreeSet, fmt::Write, path::PathBuf};
+use structopt::StructOpt;
+
+/// The cells for no time up to the most time tracked on a day
+const SHADES: [char; 5] = ['·', '░', '▒', '▓', '█'];
+
+#[derive(StructOpt, Debug)]
+pub struct Cmd {
+    /// Only count events with all of these tags
+    #[structopt(parse(from_str = crate::alias::tag))]
+    tags: Vec<String>,
+
+    /// Only count events matching a tag expression, like "coding or review"
+    #[structopt(long = "filter", parse(try_from_str = crate::alias::tag_expr))]
+    filter: Option<TagExpr>,
+
+    /// The year to show. Defaults to this year.
+    #[structopt(long = "year")]
+    year: Option<i32>,
+
+    /// Save the heatmap as an SVG image instead of printing it
+    #[structopt(long = "output", short = "o")]
+    output: Option<PathBuf>,
+}
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("{}", source))]
+    SaveImage { source: svg::Error },
+}
+
+impl Cmd {
+    pub fn exec(&self, timesheet: &Timesheet, conf: &Conf) -> Result<(), Error> {
+        let tags: BTreeSet<Tag> = self.tags.iter().cloned().collect();
+        suggest::warn_unknown_tags(timesheet, &tags, self.filter.as_ref());
+        let year = self
+            .year
+            .unwrap_or_else(|| timesheet.clock().now().with_timezone(&Local).year());
+        let segments: Vec<Segment> = timesheet
+            .segments()
+            .into_iter()
+            .filter(|s| s.tags.is_superset(&tags))
+            .filter(|s| self.filter.as_ref().is_none_or(|f| f.matches(&s.tags)))
+            .collect();
+        match &self.output {
+            Some(path) => grid(&segments, year, conf.week_starts_on)
+                .save(path)
+                .context(SaveImage {})?,
+            None => print!("{}", render(&segments, year, conf.week_starts_on)),
+        }
+        Ok(())
+    }
+}
+
+/// The days of `year`, with their total time, in columns of weeks starting
+/// on `week_start`. Days outside the year are `None`.
+struct Weeks {
+    weeks: Vec<NaiveDate>,
+    days: aggregate::Totals<NaiveDate>,
+    busiest: Duration,
+}
+
+impl Weeks {
+    fn new(segments: &[Segment], year: i32, week_start: Weekday) -> Self {
+        let first = NaiveDate::from_ymd(year, 1, 1);
+        let next_year = NaiveDate::from_ymd(year + 1, 1, 1);
+        let days = aggregate::by_period(
+            segments,
+            aggregate::midnight(first, &Local),
+            aggregate::midnight(next_year, &Local),
+            Period::Day,
+            &Local,
+        );
+        let busiest = days
+            .groups
+            .values()
+            .max()
+            .copied()
+            .unwrap_or_else(Duration::zero);
+        let mut week_first = Period::Week(week_start).start_of(first);
+        let mut weeks = Vec::new();
+        while week_first < next_year {
+            weeks.push(week_first);
+            week_first += Duration::weeks(1);
+        }
+        Self {
+            weeks,
+            days,
+            busiest,
+        }
+    }
+
+    /// Which of the `SHADES` each day of the week gets in every week, or
+    /// `None` for days in another year
+    fn shades(&self, year: i32) -> Vec<Vec<Option<usize>>> {
+        (0..7)
+            .map(|row| {
+                self.weeks
+                    .iter()
+                    .map(|week_first| {
+                        let date = *week_first + Duration::days(row);
+                        if date.year() != year {
+                            return None;
+                        }
+                        let tracked = self.days.groups.get(&date).copied();
+                        Some(shade(tracked.unwrap_or_else(Duration::zero), self.busiest))
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// The columns that months start in, with the months' names. Names
+    /// that would run into the one before are left out.
+    fn months(&self, year: i32) -> Vec<(usize, String)> {
+        let mut months = Vec::new();
+        let mut free = 0;
+        for (column, week_first) in self.weeks.iter().enumerate() {
+            let month_start = (0..7)
+                .map(|day| *week_first + Duration::days(day))
+                .find(|date| date.day() == 1 && date.year() == year);
+            if let Some(date) = month_start.filter(|_| column >= free) {
+                months.push((column, date.format("%b").to_string()));
+                free = column + 4;
+            }
+        }
+        months
+    }
+
+    /// The names of the days of the week, with every other one left out so
+    /// that they don't crowd each other
+    fn day_names(&self) -> Vec<String> {
+        (0..7)
+            .map(|row| {
+                if row % 2 == 0 {
+                    (self.weeks[0] + Duration::days(row))
+                        .format("%a")
+                        .to_string()
+                } else {
+                    String::new()
+                }
+            })
+            .collect()
+    }
+}
+
+/// The heatmap as an image, with the same cells as `render`
+fn grid(segments: &[Segment], year: i32, week_start: Weekday) -> Grid {
+    let weeks = Weeks::new(segments, year, week_start);
+    let levels = (SHADES.len() - 1) as f64;
+    Grid {
+        title: format!(
+            "{}: busiest day {}, total {}",
+            year,
+            format_duration(weeks.busiest),
+            format_duration(weeks.days.total)
+        ),
+        row_labels: weeks.day_names(),
+        column_labels: weeks.months(year),
+        cells: weeks
+            .shades(year)
+            .into_iter()
+            .map(|row| {
+                row.into_iter()
+                    .map(|shade| shade.map(|shade| shade as f64 / levels))
+                    .collect()
+            })
+            .collect(),
+        cell_width: 12,
+        cell_height: 12,
+    }
+}
+
+/// A grid like a contribution graph, with a column for each week of `year`
+/// and a row for each day of the week, starting on `week_start`. The more
+/// time was tracked on a day, the darker its cell.
+fn render(segments: &[Segment], year: i32, week_start: Weekday) -> String {
+    let weeks = Weeks::new(segments, year, week_start);
+
+    let mut out = String::new();
+    writeln!(out, "{}", year).unwrap();
+    // A month's name goes over the week that it starts in
+    let mut months = vec![' '; weeks.weeks.len() + 3];
+    for (column, name) in weeks.months(year) {
+        for (offset, c) in name.chars().enumerate() {
+            months[column + offset] = c;
+        }
+    }
+    let months: String = months.into_iter().collect();
+    writeln!(out, "    {}", months.trim_end()).unwrap();
+
+    for (name, shades) in weeks.day_names().iter().zip(weeks.shades(year)) {
+        let mut line = format!("{: <4}", name);
+        for shade in shades {
+            line.push(shade.map_or(' ', |shade| SHADES[shade]));
+        }
+        writeln!(out, "{}", line.trim_end()).unwrap();
+    }
+    writeln!(
+        out,
+        "Less {} More  (busiest day {}, total {})",
+        SHADES.iter().collect::<String>(),
+        format_duration(weeks.busiest),
+        format_duration(weeks.days.total)
+    )
+    .unwrap();
+    out
+}
+
+/// Which of the `SHADES` a day with `tracked` time gets, where the busiest
+/// day gets the darkest one
+fn shade(tracked: Duration, busiest: Duration) -> usize {
+    if tracked <= Duration::zero() || busiest <= Duration::zero() {
+        return 0;
+    }
+    let levels = (SHADES.len() - 1) as i64;
+    let level =
+        (tracked.num_seconds() * levels + busiest.num_seconds() - 1) / busiest.num_seconds();
+    level.clamp(1, levels) as usize
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn heatmap_shades_days() {
+        assert_eq!(shade(Duration::zero(), Duration::hours(8)), 0);
+        assert_eq!(shade(Duration::minutes(1), Duration::hours(8)), 1);
+        assert_eq!(shade(Duration::hours(4), Duration::hours(8)), 2);
+        assert_eq!(shade(Duration::hours(8), Duration::hours(8)), 4);
+
+        let time = |m, d, h| Local.ymd(2019, m, d).and_hms(h, 0, 0).with_timezone(&Utc);
+        let segment = |start, end| Segment {
+            event_ref: "a".into(),
+            start_time: start,
+            tags: vec![String::from("work")].into_iter().collect(),
+            duration: end - start,
+            end_time: end,
+        };
+        // 2019 starts on a Tuesday
+        let segments = vec![
+            segment(time(1, 1, 9), time(1, 1, 17)),
+            segment(time(1, 2, 9), time(1, 2, 11)),
+        ];
+        let heatmap = render(&segments, 2019, Weekday::Mon);
+        let lines: Vec<&str> = heatmap.lines().collect();
+        assert_eq!(lines[0], "2019");
+        assert!(lines[1].starts_with("    Jan Feb"));
+        assert!(lines[2].starts_with("Mon  ·"));
+        assert!(lines[3].starts_with("    █·"));
+        assert!(lines[4].starts_with("Wed ░·"));
+        assert_eq!(
+            lines[9],
+            "Less ·░▒▓█ More  (busiest day 8h 0m, total 10h 0m)"
+        );
+    }
+}
@@ -0,0 +1,166 @@
+use crate::{
+    format_duration, suggest,
+    time_input::{parse_default_local, RangeError, RangeGuard},
+};
+use augr_core::{aggregate, timesheet::Segment, Tag, TagExpr, Timesheet};
+use chrono::{DateTime, Duration, Local, Utc};
+use snafu::{ResultExt, Snafu};
+use std::{collections::BTreeSet, fmt::Write};
+use structopt::StructOpt;
+
+/// The number of columns that the bar for the busiest hour fills
+const WIDTH: i64 = 40;
+
+#[derive(StructOpt, Debug)]
+pub struct Cmd {
+    /// Only count events with all of these tags
+    #[structopt(parse(from_str = crate::alias::tag))]
+    tags: Vec<String>,
+
+    /// Only count events matching a tag expression, like "coding or review"
+    #[structopt(long = "filter", parse(try_from_str = crate::alias::tag_expr))]
+    filter: Option<TagExpr>,
+
+    /// Show a separate histogram for each tag
+    #[structopt(long = "per-tag")]
+    per_tag: bool,
+
+    /// The datetime at which to begin. Defaults to 30 days ago.
+    #[structopt(long = "start", parse(try_from_os_str = parse_default_local))]
+    start: Option<DateTime<Local>>,
+
+    /// The datetime at which to end. Defaults to now.
+    #[structopt(long = "end", parse(try_from_os_str = parse_default_local))]
+    end: Option<DateTime<Local>>,
+
+    #[structopt(flatten)]
+    guard: RangeGuard,
+}
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("{}", source))]
+    LongRange { source: RangeError },
+}
+
+impl Cmd {
+    pub fn exec(&self, timesheet: &Timesheet) -> Result<(), Error> {
+        let tags: BTreeSet<Tag> = self.tags.iter().cloned().collect();
+        suggest::warn_unknown_tags(timesheet, &tags, self.filter.as_ref());
+
+        let now = timesheet.clock().now().with_timezone(&Local);
+        let end = self.end.unwrap_or(now);
+        let start = self
+            .start
+            .unwrap_or_else(|| (end - Duration::days(30)).date().and_hms(0, 0, 0));
+        self.guard
+            .check(start.naive_local().date(), end.naive_local().date())
+            .context(LongRange {})?;
+
+        let segments: Vec<Segment> = timesheet
+            .segments()
+            .into_iter()
+            .filter(|s| s.tags.is_superset(&tags))
+            .filter(|s| self.filter.as_ref().is_none_or(|f| f.matches(&s.tags)))
+            .collect();
+        println!(
+            "Time of day {} to {}",
+            start.format("%Y-%m-%d %H:%M"),
+            end.format("%Y-%m-%d %H:%M")
+        );
+        let (start, end) = (start.with_timezone(&Utc), end.with_timezone(&Utc));
+        if !self.per_tag {
+            print!("{}", render(&segments, start, end));
+            return Ok(());
+        }
+        let all_tags: BTreeSet<&Tag> = segments.iter().flat_map(|s| s.tags.iter()).collect();
+        for tag in all_tags {
+            let tagged: Vec<Segment> = segments
+                .iter()
+                .filter(|s| s.tags.contains(tag))
+                .cloned()
+                .collect();
+            let histogram = render(&tagged, start, end);
+            if !histogram.is_empty() {
+                println!("\n{}", tag);
+                print!("{}", histogram);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A bar for each local hour of the day, from the first hour with time
+/// tracked to the last, sized relative to the busiest hour
+fn render(segments: &[Segment], start: DateTime<Utc>, end: DateTime<Utc>) -> String {
+    let hours = aggregate::by_hour(segments, start, end, &Local);
+    let busiest = hours
+        .groups
+        .values()
+        .max()
+        .copied()
+        .unwrap_or_else(Duration::zero);
+    let (first, last) = match (hours.groups.keys().next(), hours.groups.keys().last()) {
+        (Some(first), Some(last)) => (*first, *last),
+        _ => return String::new(),
+    };
+
+    let mut out = String::new();
+    for hour in first..=last {
+        let duration = hours
+            .groups
+            .get(&hour)
+            .copied()
+            .unwrap_or_else(Duration::zero);
+        let width = match busiest.num_seconds() {
+            0 => 0,
+            busiest => (duration.num_seconds() * WIDTH + busiest - 1) / busiest,
+        };
+        writeln!(
+            out,
+            "{:02} {: <width$} {}",
+            hour,
+            "█".repeat(width as usize),
+            format_duration(duration),
+            width = WIDTH as usize
+        )
+        .unwrap();
+    }
+    writeln!(out, "Total {}", format_duration(hours.total)).unwrap();
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn histogram_bars() {
+        let time = |d, h, m| Local.ymd(2019, 7, d).and_hms(h, m, 0).with_timezone(&Utc);
+        let segment = |start, end| Segment {
+            event_ref: "a".into(),
+            start_time: start,
+            tags: vec![String::from("work")].into_iter().collect(),
+            duration: end - start,
+            end_time: end,
+        };
+        let segments = vec![
+            segment(time(1, 9, 0), time(1, 10, 30)),
+            segment(time(2, 9, 0), time(2, 10, 0)),
+            segment(time(2, 12, 0), time(2, 12, 10)),
+        ];
+        let histogram = render(&segments, time(1, 0, 0), time(3, 0, 0));
+        let bar = |columns| format!("{: <40}", "█".repeat(columns));
+        assert_eq!(
+            histogram.lines().collect::<Vec<_>>(),
+            vec![
+                format!("09 {} 2h 0m", bar(40)),
+                format!("10 {} 30m", bar(10)),
+                format!("11 {} 0m", bar(0)),
+                format!("12 {} 10m", bar(4)),
+                String::from("Total 2h 40m"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,132 @@
+use augr_core::{
+    repository::{
+        event::{Operation, OperationKind},
+        Repository, CREATED_METADATA,
+    },
+    store::SyncFolderStore,
+    EventRef,
+};
+use chrono::{DateTime, Local};
+use snafu::Snafu;
+use std::collections::BTreeMap;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct Cmd {
+    /// The id of the event to show the history of, a unique prefix of it, or
+    /// `@` for the current event and `@-1`, `@-2`, ... for earlier ones today
+    pub event: EventRef,
+}
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Unknown event reference: {}", event_ref))]
+    UnknownEventRef { event_ref: EventRef },
+}
+
+impl Cmd {
+    pub fn exec(&self, repo: &Repository<SyncFolderStore>) -> Result<(), Error> {
+        let history = repo.history(&self.event).ok_or(Error::UnknownEventRef {
+            event_ref: self.event.clone(),
+        })?;
+        for operation in history {
+            println!(
+                "{}",
+                describe_operation(operation, repo.patch_metadata(&operation.patch))
+            );
+        }
+        Ok(())
+    }
+}
+
+/// A line with when the operation's patch was made, the patch, what it did,
+/// and the rest of the patch's metadata, like
+/// `2019-07-24 14:00 <patch> added tag work (source=cli)`
+fn describe_operation(
+    operation: &Operation,
+    metadata: Option<&BTreeMap<String, String>>,
+) -> String {
+    let empty = BTreeMap::new();
+    let metadata = metadata.unwrap_or(&empty);
+    let created = metadata
+        .get(CREATED_METADATA)
+        .and_then(|created| DateTime::parse_from_rfc3339(created).ok())
+        .map(|created| {
+            created
+                .with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M")
+                .to_string()
+        })
+        // Patches from before the time was recorded
+        .unwrap_or_else(|| String::from("unknown"));
+    let mut line = format!(
+        "{: <16} {} {}",
+        created,
+        operation.patch,
+        describe(&operation.kind)
+    );
+    let rest: Vec<String> = metadata
+        .iter()
+        .filter(|(key, _)| *key != CREATED_METADATA)
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect();
+    if !rest.is_empty() {
+        line.push_str(&format!(" ({})", rest.join(", ")));
+    }
+    line
+}
+
+pub fn describe(kind: &OperationKind) -> String {
+    let fmt_time = |t: &chrono::DateTime<chrono::Utc>| {
+        t.with_timezone(&Local).format("%Y-%m-%d %H:%M").to_string()
+    };
+    match kind {
+        OperationKind::Create { start, tags } => {
+            format!(
+                "created at {} with tags {}",
+                fmt_time(start),
+                tags.join(" ")
+            )
+        }
+        OperationKind::AddStart(time) => format!("added start {}", fmt_time(time)),
+        OperationKind::RemoveStart(time) => format!("removed start {}", fmt_time(time)),
+        OperationKind::AddTag(tag) => format!("added tag {}", tag),
+        OperationKind::RemoveTag(tag) => format!("removed tag {}", tag),
+        OperationKind::Delete => String::from("deleted event"),
+        OperationKind::Undelete => String::from("restored event"),
+        OperationKind::SetNote(note) if note.is_empty() => String::from("cleared note"),
+        OperationKind::SetNote(note) => format!("set note {:?}", note),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use augr_core::Patch;
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn operations_show_when_and_how_they_were_made() {
+        let created = Utc.ymd(2019, 7, 24).and_hms(14, 0, 0);
+        let patch = *Patch::new().patch_ref();
+        let operation = Operation {
+            patch,
+            kind: OperationKind::AddTag("work".into()),
+        };
+        let mut metadata = BTreeMap::new();
+        metadata.insert(CREATED_METADATA.to_string(), created.to_rfc3339());
+        metadata.insert("source".to_string(), "hook".to_string());
+        assert_eq!(
+            describe_operation(&operation, Some(&metadata)),
+            format!(
+                "{} {} added tag work (source=hook)",
+                created.with_timezone(&Local).format("%Y-%m-%d %H:%M"),
+                patch
+            )
+        );
+        assert_eq!(
+            describe_operation(&operation, None),
+            format!("unknown          {} added tag work", patch)
+        );
+    }
+}
@@ -21,6 +21,11 @@ pub struct ImportCmd {
 
     /// Path to data to import
     path: String,
+
+    /// When stopped with Ctrl-C, keep the patches imported so far instead of
+    /// none of them
+    #[structopt(long = "keep-partial")]
+    pub keep_partial: bool,
 }
 
 impl ImportCmd {
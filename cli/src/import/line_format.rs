@@ -41,7 +41,7 @@ pub fn import<P: AsRef<Path>>(sync_folder: P) -> Result<Vec<Patch>, Error> {
 
         let mut patch = Patch::new();
         for (start, tags) in timesheet {
-            let event = uuid::Uuid::new_v4().to_string();
+            let event = augr_core::id::new_id().to_string();
             patch.create_event.insert(CreateEvent {
                 event,
                 start,
@@ -0,0 +1,98 @@
+//! Ctrl-C handling. While a long operation is running, Ctrl-C cancels it
+//! between steps, so that no file is left half written. Anywhere else, like
+//! at a prompt, Ctrl-C exits right away.
+
+use augr_core::{progress::Stage, CancelToken, Progress};
+use indicatif::{ProgressBar, ProgressStyle};
+use std::{
+    cell::RefCell,
+    process,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+/// Operations with fewer steps than this finish too quickly for a progress
+/// bar to be worth drawing
+const MIN_STEPS: usize = 200;
+
+pub struct Interrupt {
+    token: CancelToken,
+    busy: Arc<AtomicBool>,
+}
+
+/// Draws a progress bar on stderr once an operation turns out to be long, and
+/// lets Ctrl-C cancel the operation while it exists
+pub struct Bar<'a> {
+    interrupt: &'a Interrupt,
+    bar: RefCell<Option<ProgressBar>>,
+}
+
+impl Interrupt {
+    /// Install the Ctrl-C handler. This can only be done once.
+    pub fn install() -> Self {
+        let token = CancelToken::new();
+        let busy = Arc::new(AtomicBool::new(false));
+        let (handler_token, handler_busy) = (token.clone(), busy.clone());
+        // If the handler can't be installed, Ctrl-C just exits like it used to
+        let _ = ctrlc::set_handler(move || {
+            // Pressing Ctrl-C again exits without waiting
+            if handler_busy.load(Ordering::SeqCst) && !handler_token.is_cancelled() {
+                eprintln!("\nStopping...");
+                handler_token.cancel();
+            } else {
+                process::exit(130);
+            }
+        });
+        Self { token, busy }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.token.is_cancelled()
+    }
+
+    /// Start a long operation
+    pub fn progress(&self) -> Bar<'_> {
+        self.busy.store(true, Ordering::SeqCst);
+        Bar {
+            interrupt: self,
+            bar: RefCell::new(None),
+        }
+    }
+}
+
+impl Bar<'_> {
+    pub fn set(&self, message: &str, done: usize, total: usize) {
+        if total < MIN_STEPS {
+            return;
+        }
+        let mut bar = self.bar.borrow_mut();
+        let bar = bar.get_or_insert_with(|| {
+            ProgressBar::new(total as u64)
+                .with_style(ProgressStyle::default_bar().template("{msg} [{bar:40}] {pos}/{len}"))
+        });
+        bar.set_message(message);
+        bar.set_length(total as u64);
+        bar.set_position(done as u64);
+    }
+}
+
+impl Progress for Bar<'_> {
+    fn update(&self, stage: Stage, done: usize, total: usize) {
+        self.set(&stage.to_string(), done, total);
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.interrupt.is_cancelled()
+    }
+}
+
+impl Drop for Bar<'_> {
+    fn drop(&mut self) {
+        if let Some(bar) = self.bar.borrow().as_ref() {
+            bar.finish_and_clear();
+        }
+        self.interrupt.busy.store(false, Ordering::SeqCst);
+    }
+}
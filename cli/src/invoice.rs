@@ -0,0 +1,465 @@
+use crate::{
+    config::{self, Conf},
+    escape_html,
+    export::csv,
+    format_duration,
+    signature::Signature,
+    time_input::{parse_default_local, RangeError, RangeGuard},
+};
+use augr_core::{
+    aggregate::{self, Period},
+    duration::{Rounding, RoundingPer},
+    Tag, TagExpr, Timesheet,
+};
+use chrono::{DateTime, Duration, Local, Utc};
+use clap::arg_enum;
+use serde::Deserialize;
+use snafu::{ensure, OptionExt, ResultExt, Snafu};
+use std::{
+    fmt::{self, Write},
+    fs, io,
+    path::PathBuf,
+};
+use structopt::StructOpt;
+
+/// An hourly rate for the events matching a tag expression, like
+///
+/// ```toml
+/// [[rate]]
+/// name = "Meetings"
+/// filter = "meeting or call"
+/// hourly = 80.0
+/// ```
+///
+/// Each event is billed at the first rate that matches it.
+#[derive(Deserialize, Debug)]
+pub struct Rate {
+    /// The description of the line on invoices. Defaults to the filter.
+    #[serde(default)]
+    pub name: Option<String>,
+
+    #[serde(deserialize_with = "config::tag_expr")]
+    pub filter: TagExpr,
+
+    pub hourly: f64,
+}
+
+arg_enum! {
+    /// Ways of writing an invoice
+    #[derive(Copy, Clone, Debug)]
+    enum Format {
+        Text,
+        Csv,
+        Html,
+    }
+}
+
+#[derive(StructOpt, Debug)]
+pub struct Cmd {
+    /// Only bill events with this tag
+    #[structopt(long = "client", parse(from_str = crate::alias::tag))]
+    client: Tag,
+
+    /// The datetime at which to begin. Defaults to the start of this month.
+    #[structopt(long = "start", alias = "from", parse(try_from_os_str = parse_default_local))]
+    start: Option<DateTime<Local>>,
+
+    /// The datetime at which to end. Defaults to now.
+    #[structopt(long = "end", alias = "to", parse(try_from_os_str = parse_default_local))]
+    end: Option<DateTime<Local>>,
+
+    #[structopt(
+        long = "format",
+        default_value = "text",
+        possible_values = &Format::variants(),
+        case_insensitive = true
+    )]
+    format: Format,
+
+    /// Sign the invoiced time with the `export_key` from the config and write
+    /// the signature to this file, for checking with `augr verify-export`
+    #[structopt(long = "signature")]
+    signature: Option<PathBuf>,
+
+    #[structopt(flatten)]
+    guard: RangeGuard,
+}
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("{}", source))]
+    LongRange { source: RangeError },
+
+    #[snafu(display("Add some [[rate]] tables to the config to make invoices"))]
+    NoRates,
+
+    #[snafu(display("Set `export_key` in the config to sign invoices"))]
+    NoExportKey,
+
+    #[snafu(display("Could not write signature to {}: {}", path.display(), source))]
+    WriteSignature { path: PathBuf, source: io::Error },
+}
+
+/// The time billed at each rate, in the order of the rates in the config
+#[derive(Debug, PartialEq)]
+struct Invoice {
+    lines: Vec<Line>,
+    /// Time for the client that no rate matched
+    unbilled: Duration,
+}
+
+#[derive(Debug, PartialEq)]
+struct Line {
+    item: String,
+    time: Duration,
+    hourly: f64,
+}
+
+impl Cmd {
+    pub fn exec(&self, timesheet: &Timesheet, conf: &Conf) -> Result<(), Error> {
+        ensure!(!conf.rates.is_empty(), NoRates);
+        let now = timesheet.clock().now().with_timezone(&Local);
+        let start = self.start.unwrap_or_else(|| {
+            let first = Period::Month.start_of(now.date().naive_local());
+            aggregate::midnight(first, &Local).with_timezone(&Local)
+        });
+        let end = self.end.unwrap_or(now);
+        self.guard
+            .check(start.naive_local().date(), end.naive_local().date())
+            .context(LongRange {})?;
+        let key = match &self.signature {
+            Some(_) => Some(conf.export_key.as_ref().context(NoExportKey {})?),
+            None => None,
+        };
+
+        let invoice = invoice(
+            timesheet,
+            start.with_timezone(&Utc),
+            end.with_timezone(&Utc),
+            &self.client,
+            &conf.rates,
+            conf.rounding.as_ref(),
+        );
+        if invoice.unbilled > Duration::zero() {
+            eprintln!(
+                "Warning: {} of time for {} didn't match any rate and was left out",
+                format_duration(invoice.unbilled),
+                self.client
+            );
+        }
+        let title = format!(
+            "Invoice for {}, {} to {}",
+            self.client,
+            start.format("%Y-%m-%d"),
+            end.format("%Y-%m-%d")
+        );
+        let text = match self.format {
+            Format::Text => render_text(&title, &invoice),
+            Format::Csv => render_csv(&invoice),
+            Format::Html => render_html(&title, &invoice),
+        };
+        print!("{}", text);
+
+        // Private events are never invoiced, so they aren't signed either
+        if let (Some(path), Some(key)) = (&self.signature, key) {
+            let signature = Signature::sign(
+                timesheet,
+                start.with_timezone(&Utc),
+                end.with_timezone(&Utc),
+                false,
+                key,
+            );
+            fs::write(path, format!("{}\n", signature))
+                .context(WriteSignature { path: path.clone() })?;
+        }
+        Ok(())
+    }
+}
+
+/// Bill the time tagged with `client` between `start` and `end` at the first
+/// of `rates` that matches each event, rounding the time if there is a
+/// `rounding`
+fn invoice(
+    timesheet: &Timesheet,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    client: &Tag,
+    rates: &[Rate],
+    rounding: Option<&Rounding>,
+) -> Invoice {
+    let mut times = vec![Duration::zero(); rates.len()];
+    let mut unbilled = Duration::zero();
+    for segment in timesheet.segments() {
+        if !segment.tags.contains(client) {
+            continue;
+        }
+        let mut time = segment.end_time.min(end) - segment.start_time.max(start);
+        if time <= Duration::zero() {
+            continue;
+        }
+        if let Some(rounding) = rounding.filter(|r| r.per == RoundingPer::Event) {
+            time = rounding.round(time);
+        }
+        match rates
+            .iter()
+            .position(|rate| rate.filter.matches(&segment.tags))
+        {
+            Some(index) => times[index] = times[index] + time,
+            None => unbilled = unbilled + time,
+        }
+    }
+
+    let lines = rates
+        .iter()
+        .zip(times)
+        .filter(|(_, time)| *time > Duration::zero())
+        .map(|(rate, time)| Line {
+            item: rate.name.clone().unwrap_or_else(|| rate.filter.to_string()),
+            time: match rounding.filter(|r| r.per == RoundingPer::Total) {
+                Some(rounding) => rounding.round(time),
+                None => time,
+            },
+            hourly: rate.hourly,
+        })
+        .collect();
+    Invoice { lines, unbilled }
+}
+
+impl Line {
+    fn hours(&self) -> f64 {
+        self.time.num_seconds() as f64 / 3600.0
+    }
+
+    /// What the line costs, rounded to whole cents
+    fn amount(&self) -> Cents {
+        Cents((self.hours() * self.hourly * 100.0).round() as i64)
+    }
+}
+
+impl Invoice {
+    fn total_hours(&self) -> f64 {
+        self.lines.iter().map(Line::hours).sum()
+    }
+
+    /// The sum of the rounded line amounts, so that the total is what the
+    /// lines add up to
+    fn total_amount(&self) -> Cents {
+        Cents(self.lines.iter().map(|line| line.amount().0).sum())
+    }
+}
+
+/// An amount of money in hundredths, shown like "12.50"
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+struct Cents(i64);
+
+impl fmt::Display for Cents {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let text = format!("{}{}.{:02}", sign, self.0.abs() / 100, self.0.abs() % 100);
+        f.pad(&text)
+    }
+}
+
+fn render_text(title: &str, invoice: &Invoice) -> String {
+    let width = invoice
+        .lines
+        .iter()
+        .map(|line| line.item.len())
+        .max()
+        .unwrap_or(0)
+        .max(5);
+    let mut out = String::new();
+    writeln!(out, "{}", title).unwrap();
+    writeln!(
+        out,
+        "{: <width$} {: >8} {: >8} {: >10}",
+        "Item",
+        "Hours",
+        "Rate",
+        "Amount",
+        width = width
+    )
+    .unwrap();
+    for line in invoice.lines.iter() {
+        writeln!(
+            out,
+            "{: <width$} {: >8.2} {: >8.2} {: >10}",
+            line.item,
+            line.hours(),
+            line.hourly,
+            line.amount(),
+            width = width
+        )
+        .unwrap();
+    }
+    writeln!(
+        out,
+        "{: <width$} {: >8.2} {: >8} {: >10}",
+        "Total",
+        invoice.total_hours(),
+        "",
+        invoice.total_amount(),
+        width = width
+    )
+    .unwrap();
+    out
+}
+
+fn render_csv(invoice: &Invoice) -> String {
+    let mut out = String::from("item,hours,rate,amount\n");
+    for line in invoice.lines.iter() {
+        writeln!(
+            out,
+            "{},{:.2},{:.2},{}",
+            csv::field(&line.item),
+            line.hours(),
+            line.hourly,
+            line.amount()
+        )
+        .unwrap();
+    }
+    writeln!(
+        out,
+        "Total,{:.2},,{}",
+        invoice.total_hours(),
+        invoice.total_amount()
+    )
+    .unwrap();
+    out
+}
+
+fn render_html(title: &str, invoice: &Invoice) -> String {
+    let mut out = String::new();
+    writeln!(out, "<!DOCTYPE html>").unwrap();
+    writeln!(out, "<html>").unwrap();
+    writeln!(
+        out,
+        "<head><meta charset=\"utf-8\"><title>{}</title></head>",
+        escape_html(title)
+    )
+    .unwrap();
+    writeln!(out, "<body>").unwrap();
+    writeln!(out, "<h1>{}</h1>", escape_html(title)).unwrap();
+    writeln!(out, "<table>").unwrap();
+    writeln!(
+        out,
+        "<tr><th>Item</th><th>Hours</th><th>Rate</th><th>Amount</th></tr>"
+    )
+    .unwrap();
+    for line in invoice.lines.iter() {
+        writeln!(
+            out,
+            "<tr><td>{}</td><td>{:.2}</td><td>{:.2}</td><td>{}</td></tr>",
+            escape_html(&line.item),
+            line.hours(),
+            line.hourly,
+            line.amount()
+        )
+        .unwrap();
+    }
+    writeln!(
+        out,
+        "<tr><th>Total</th><th>{:.2}</th><th></th><th>{}</th></tr>",
+        invoice.total_hours(),
+        invoice.total_amount()
+    )
+    .unwrap();
+    writeln!(out, "</table>").unwrap();
+    writeln!(out, "</body>").unwrap();
+    writeln!(out, "</html>").unwrap();
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::Tracked;
+    use augr_core::Patch;
+    use chrono::TimeZone;
+
+    #[test]
+    fn invoice_bills_first_matching_rate() {
+        let time = |h, m| Utc.ymd(2019, 7, 24).and_hms(h, m, 0);
+        let create = Patch::new()
+            .create_event("a".into(), time(9, 0), vec!["acme".into(), "coding".into()])
+            .create_event(
+                "b".into(),
+                time(11, 0),
+                vec!["acme".into(), "meeting".into()],
+            )
+            .create_event("c".into(), time(11, 50), vec!["acme".into()])
+            .create_event("d".into(), time(12, 0), vec!["coding".into()])
+            .create_event("e".into(), time(13, 0), vec![]);
+        let tracked = Tracked::new(&[&create]).at(time(20, 0));
+        let timesheet = tracked.timesheet();
+
+        #[derive(Deserialize)]
+        struct Rates {
+            rate: Vec<Rate>,
+        }
+        let rates = toml::de::from_str::<Rates>(
+            r#"
+            [[rate]]
+            name = "Meetings"
+            filter = "meeting"
+            hourly = 80.0
+            [[rate]]
+            filter = "coding or review"
+            hourly = 100.0
+            "#,
+        )
+        .unwrap()
+        .rate;
+        let rounding: Rounding = "15m up per-event".parse().unwrap();
+        let invoice = invoice(
+            &timesheet,
+            time(0, 0),
+            time(20, 0),
+            &"acme".into(),
+            &rates,
+            Some(&rounding),
+        );
+        assert_eq!(
+            invoice,
+            Invoice {
+                lines: vec![
+                    Line {
+                        item: "Meetings".into(),
+                        time: Duration::minutes(60),
+                        hourly: 80.0,
+                    },
+                    Line {
+                        item: "coding or review".into(),
+                        time: Duration::hours(2),
+                        hourly: 100.0,
+                    },
+                ],
+                unbilled: Duration::minutes(15),
+            }
+        );
+        assert_eq!(
+            render_csv(&invoice),
+            "item,hours,rate,amount\nMeetings,1.00,80.00,80.00\ncoding or review,2.00,100.00,200.00\nTotal,3.00,,280.00\n"
+        );
+    }
+
+    #[test]
+    fn total_is_the_sum_of_the_rounded_lines() {
+        let line = |item: &str| Line {
+            item: item.into(),
+            time: Duration::minutes(20),
+            hourly: 100.0,
+        };
+        let invoice = Invoice {
+            lines: vec![line("design"), line("review")],
+            unbilled: Duration::zero(),
+        };
+        assert_eq!(invoice.lines[0].amount(), Cents(3333));
+        assert_eq!(invoice.total_amount(), Cents(6666));
+        assert_eq!(
+            render_text("Acme", &invoice).lines().last(),
+            Some("Total      0.67               66.66")
+        );
+        assert_eq!(format!("{: >7}", Cents(-5)), "  -0.05");
+    }
+}
@@ -1,24 +1,79 @@
-#[cfg(feature = "flame_it")]
-#[macro_use]
-extern crate flamer;
-
+mod absence;
+mod alias;
+mod annotate;
+mod budget;
+mod cancel;
 mod chart;
+mod checksum;
+mod completions;
 mod config;
+mod days_off;
+mod debug;
+mod delete;
+mod device;
+mod diff;
+mod doctor;
+mod dry_run;
+mod edit;
+mod edit_day;
+mod examples;
+mod export;
+mod heatmap;
+mod histogram;
+mod history;
 mod import;
+mod interrupt;
+mod invoice;
+mod merge;
+mod month;
+mod overtime;
+mod picker;
+mod preview;
+mod private;
+mod prompt;
+mod push;
+mod report;
+mod resolve;
+mod retag;
+mod rules;
 mod set_start;
+mod signature;
+mod split;
 mod start;
+mod stats;
+mod status;
+mod stop;
+mod suggest;
 mod summary;
+mod svg;
 mod tag;
+mod tag_rename;
 mod tags;
+mod target;
+mod taskwarrior;
+#[cfg(test)]
+mod testing;
 mod time_input;
+mod timeline;
+mod undo;
+mod verify_export;
+mod week;
+mod whatsnew;
 
 use augr_core::{
-    repository::{timesheet::Error as Conflict, Error as RepositoryError, Repository},
+    repository::{
+        timesheet::{Error as Conflict, RefError},
+        Error as RepositoryError, Repository, PRIVATE_METADATA,
+    },
     store::{SyncFolderStore, SyncFolderStoreError},
+    Clock, DurationFormat, Patch,
 };
-use snafu::{ErrorCompat, ResultExt, Snafu};
-use std::path::PathBuf;
+use chrono::Local;
+use snafu::{ensure, ErrorCompat, ResultExt, Snafu};
+use std::{cell::RefCell, path::PathBuf, rc::Rc};
 use structopt::StructOpt;
+use tracing::info_span;
+use tracing_subscriber::fmt::format::FmtSpan;
 
 #[derive(StructOpt, Debug)]
 #[structopt(name = "augr", about, author)]
@@ -27,6 +82,39 @@ struct Opt {
     #[structopt(long = "config")]
     config: Option<PathBuf>,
 
+    /// Load whatever data can be read, skipping broken patches and events, so
+    /// that it can be viewed or exported. Commands that change data are
+    /// disabled.
+    #[structopt(long = "safe-mode")]
+    safe_mode: bool,
+
+    /// Print the patches that a command would add, and how they would change
+    /// the events, without saving anything
+    #[structopt(long = "dry-run")]
+    dry_run: bool,
+
+    /// Show how a command would change the events and ask before saving.
+    /// Can be turned on for every command with `confirm_changes` in the config.
+    #[structopt(long = "interactive", short = "I")]
+    interactive: bool,
+
+    /// How to write durations: "hours-minutes" like `7h 45m`, "decimal" like
+    /// `7.75h`, or "clock" like `07:45`. Overrides `duration_format` in the
+    /// config.
+    #[structopt(long = "duration-format")]
+    duration_format: Option<config::DurationFormatConf>,
+
+    /// Show and read times in this IANA timezone, like "Europe/Berlin",
+    /// instead of the system's. Overrides `timezone` in the config. The zone
+    /// is looked up in the system's timezone database (in `TZDIR`, if set),
+    /// so zones other than UTC only work on Unix-like systems.
+    #[structopt(long = "tz")]
+    tz: Option<String>,
+
+    /// Show and read times in UTC, like `--tz UTC`
+    #[structopt(long = "utc", conflicts_with = "tz")]
+    utc: bool,
+
     #[structopt(subcommand)]
     cmd: Option<Command>,
 }
@@ -37,6 +125,18 @@ enum Command {
     #[structopt(no_version, name = "start")]
     Start(start::StartCmd),
 
+    /// Stop tracking time; the time after this is left untracked
+    #[structopt(no_version, name = "stop")]
+    Stop(stop::Cmd),
+
+    /// Discard the event that is being tracked right now
+    #[structopt(no_version, name = "cancel")]
+    Cancel(cancel::Cmd),
+
+    /// Show what is being tracked right now
+    #[structopt(no_version, name = "status")]
+    Status(status::Cmd),
+
     /// Show a table tracked time; defaults to only showing time tracked today
     #[structopt(no_version, name = "summary")]
     Summary(summary::SummaryCmd),
@@ -45,18 +145,183 @@ enum Command {
     #[structopt(no_version, name = "chart")]
     Chart(chart::Cmd),
 
+    /// Show each day of a week with the time tracked and the top tags
+    #[structopt(no_version, name = "week")]
+    Week(week::Cmd),
+
+    /// Show a calendar of a month with the time tracked on each day
+    #[structopt(no_version, name = "month")]
+    Month(month::Cmd),
+
+    /// Show a day's events as bars along the hours, one row per event
+    #[structopt(no_version, name = "timeline")]
+    Timeline(timeline::Cmd),
+
+    /// Show a year as a grid of days, darker the more time was tracked
+    #[structopt(no_version, name = "heatmap")]
+    Heatmap(heatmap::Cmd),
+
+    /// Show how much time was tracked in each hour of the day
+    #[structopt(no_version, name = "histogram")]
+    Histogram(histogram::Cmd),
+
+    /// Show statistics like the average time per day, the longest event, and
+    /// the longest streak of days
+    #[structopt(no_version, name = "stats")]
+    Stats(stats::Cmd),
+
+    /// Compare the time spent on each tag in two ranges of days, like
+    /// "2019-01 2019-02"
+    #[structopt(no_version, name = "diff")]
+    Diff(diff::Cmd),
+
+    /// Show the progress toward each target in the config for the current
+    /// day, week, or month
+    #[structopt(no_version, name = "budget")]
+    Budget(budget::Cmd),
+
+    /// Bill the time tracked for a client at the hourly rates in the config
+    #[structopt(no_version, name = "invoice")]
+    Invoice(invoice::Cmd),
+
+    /// Show how much time was spent on each tag in a range, and save or check
+    /// golden copies of the report
+    #[structopt(no_version, name = "report")]
+    Report(report::Cmd),
+
+    /// Show how much overtime has been worked each week, and the running balance
+    #[structopt(no_version, name = "overtime")]
+    Overtime(overtime::Cmd),
+
+    /// Show a checksum of the events on each day, and whether the other
+    /// devices that loaded the same patches agree with it
+    #[structopt(no_version, name = "checksum")]
+    Checksum(checksum::Cmd),
+
     /// Get a list of all the different tags that have been used.
     #[structopt(no_version, name = "tags")]
     Tags(tags::TagsCmd),
 
-    /// Add tags to an existing event
-    #[structopt(no_version, name = "tag")]
+    /// Add or remove tags of an existing event, like `+review -meeting`
+    #[structopt(
+        no_version,
+        name = "tag",
+        setting = clap::AppSettings::AllowLeadingHyphen
+    )]
     Tag(tag::Cmd),
 
+    /// Rename a tag on every event that has it
+    #[structopt(no_version, name = "tag-rename")]
+    TagRename(tag_rename::Cmd),
+
+    /// Remove and add tags on every event in a range that matches a filter,
+    /// like fixing a misspelled tag
+    #[structopt(no_version, name = "retag")]
+    Retag(retag::Cmd),
+
+    /// Attach a free-text note to an event, or change its note
+    #[structopt(no_version, name = "annotate")]
+    Annotate(annotate::Cmd),
+
+    /// Combine two events, like one that was started by accident a minute
+    /// after the other. The earlier start is kept, with the tags of both.
+    #[structopt(no_version, name = "merge")]
+    Merge(merge::Cmd),
+
+    /// Start a new event in the middle of an existing one, so that the
+    /// existing one ends there
+    #[structopt(no_version, name = "split")]
+    Split(split::Cmd),
+
     /// Change when an event started
     #[structopt(no_version, name = "set-start")]
     SetStart(set_start::Cmd),
 
+    /// Delete an event
+    #[structopt(no_version, name = "delete")]
+    Delete(delete::Cmd),
+
+    /// Change an existing event
+    #[structopt(no_version, name = "edit")]
+    Edit(edit::Cmd),
+
+    /// Edit the times and tags of a day's events in `$VISUAL` or `$EDITOR`
+    #[structopt(no_version, name = "edit-day")]
+    EditDay(edit_day::Cmd),
+
+    /// Revert the most recent change made on this device, by adding a patch
+    /// that undoes it
+    #[structopt(no_version, name = "undo")]
+    Undo(undo::Cmd),
+
+    /// Show the changes that have been made to an event
+    #[structopt(no_version, name = "history")]
+    History(history::Cmd),
+
+    /// Check the tagging rules from the config
+    #[structopt(no_version, name = "rules")]
+    Rules(rules::Cmd),
+
+    /// Settle conflicting start times by recording a choice between them
+    #[structopt(no_version, name = "resolve")]
+    Resolve(resolve::Cmd),
+
+    /// Record days of vacation, sickness, or holidays, which don't count
+    /// towards targets. `vacation` is another name for it.
+    #[structopt(no_version, name = "absence", alias = "vacation")]
+    Absence(absence::Cmd),
+
+    /// Export tracked time to another format
+    #[structopt(no_version, name = "export")]
+    Export(export::ExportCmd),
+
+    /// Check that a signed export still matches the tracked time
+    #[structopt(no_version, name = "verify-export")]
+    VerifyExport(verify_export::Cmd),
+
+    /// Copy tracked time into another application
+    #[structopt(no_version, name = "push")]
+    Push(push::PushCmd),
+
+    /// Turn private mode on or off. While it is on, events started on this
+    /// device are left out of reports and exports.
+    #[structopt(no_version, name = "private")]
+    Private(private::Cmd),
+
+    /// Name and retire the devices that share the sync folder
+    #[structopt(no_version, name = "device")]
+    Device(device::Cmd),
+
+    /// List the changes that arrived from other devices since the last time
+    /// this was run
+    #[structopt(no_version, name = "whatsnew")]
+    Whatsnew(whatsnew::Cmd),
+
+    /// Check the sync folder for problems, like devices that haven't synced in
+    /// a while
+    #[structopt(no_version, name = "doctor")]
+    Doctor(doctor::Cmd),
+
+    /// Show examples of how to use augr, optionally for a single command
+    #[structopt(no_version, name = "examples")]
+    Examples(examples::Cmd),
+
+    /// Tools for reporting bugs
+    #[structopt(no_version, name = "debug")]
+    Debug(debug::Cmd),
+
+    /// Print a shell completion script, like `augr completions bash > /etc/bash_completion.d/augr`
+    #[structopt(no_version, name = "completions")]
+    Completions(completions::Cmd),
+
+    /// List tags, events, or devices for shell completions
+    #[structopt(
+        no_version,
+        name = "complete",
+        setting = clap::AppSettings::Hidden
+    )]
+    Complete(completions::CompleteCmd),
+
     /// Import data from version 0.1 of augr
     #[structopt(no_version, name = "import")]
     Import(import::ImportCmd),
@@ -83,6 +348,50 @@ pub enum Error {
         errors: Vec<RepositoryError<SyncFolderStoreError>>,
     },
 
+    #[snafu(display("{}", source))]
+    EventRef { source: RefError },
+
+    #[snafu(display("Error saving patches: {}", source))]
+    SavePatches {
+        source: RepositoryError<SyncFolderStoreError>,
+    },
+
+    #[snafu(display(
+        "Unknown timezone \"{}\"; expected a name like \"Europe/Berlin\" from the timezone database in {}. Set TZDIR if the database is somewhere else.",
+        name,
+        searched
+    ))]
+    UnknownTimezone { name: String, searched: String },
+
+    #[snafu(display(
+        "Timezone \"{}\" can't be used on this system; only UTC and the system's own timezone are supported",
+        name
+    ))]
+    UnsupportedTimezone { name: String },
+
+    #[snafu(display("This command changes data, so it can't be used with --safe-mode"))]
+    SafeMode,
+
+    #[snafu(display(
+        "The sync folder was written by a newer version of augr, so this version can only read it; upgrade augr to change it"
+    ))]
+    NewerFormat,
+
+    #[snafu(display("Unable to move absences into the timesheet: {}", source))]
+    MoveAbsences { source: days_off::Error },
+
+    #[snafu(display("{}", source))]
+    DryRun { source: dry_run::Error },
+
+    #[snafu(display("{}", source))]
+    Preview { source: preview::Error },
+
+    #[snafu(display("{}", source))]
+    RareTag { source: suggest::Error },
+
+    #[snafu(display("Stopped by Ctrl-C"))]
+    Cancelled,
+
     #[snafu(display("Error: {}", source))]
     GeneralError { source: Box<dyn std::error::Error> },
 }
@@ -95,16 +404,52 @@ fn main() {
             if let Some(backtrace) = ErrorCompat::backtrace(&e) {
                 eprintln!("{}", backtrace);
             }
+            std::process::exit(1);
         }
     }
 }
 
 fn run() -> Result<(), Error> {
-    let opt = Opt::from_args();
+    let mut opt = Opt::from_args();
+
+    // Report how long each step takes when AUGR_TRACE is set to a level like "debug"
+    let trace_level = std::env::var("AUGR_TRACE")
+        .ok()
+        .and_then(|level| level.parse::<tracing::Level>().ok());
+    let trace = debug::TraceBuffer::default();
+    if let Some(Command::Debug(_)) = &opt.cmd {
+        // Debug bundles include a trace of the run that made them
+        let trace = trace.clone();
+        tracing_subscriber::fmt()
+            .with_max_level(trace_level.unwrap_or(tracing::Level::DEBUG))
+            .with_span_events(FmtSpan::CLOSE)
+            .with_ansi(false)
+            .with_writer(move || trace.clone())
+            .init();
+    } else if let Some(level) = trace_level {
+        tracing_subscriber::fmt()
+            .with_max_level(level)
+            .with_span_events(FmtSpan::CLOSE)
+            .with_writer(std::io::stderr)
+            .init();
+    }
+
+    // Examples and completion scripts don't need a config or any data
+    if let Some(Command::Examples(subcmd)) = &opt.cmd {
+        return subcmd
+            .exec()
+            .map_err(|e| Box::new(e).into())
+            .context(GeneralError {});
+    }
+
+    if let Some(Command::Completions(subcmd)) = &opt.cmd {
+        subcmd.exec(Opt::clap());
+        return Ok(());
+    }
 
     // Load config
-    let conf_file = match opt.config {
-        Some(config_path) => config_path,
+    let conf_file = match &opt.config {
+        Some(config_path) => config_path.clone(),
         None => {
             let proj_dirs = directories::ProjectDirs::from("xyz", "geemili", "augr").unwrap();
             proj_dirs.config_dir().join("config.toml")
@@ -112,100 +457,697 @@ fn run() -> Result<(), Error> {
     };
     let conf = config::load_config(&conf_file).context(GetConfig {})?;
 
+    // Every time is shown and read through chrono's Local, which follows the
+    // TZ variable, so the zone is applied by setting TZ for this process. It
+    // has to happen here, before any other thread starts. This relies on the
+    // C library reading the timezone database, so other zones than UTC are
+    // refused where there isn't one. Times and tags on the command line were
+    // read before the zone and the aliases were known, so the arguments are
+    // read again.
+    let timezone = match (&opt.tz, opt.utc) {
+        (_, true) => Some("UTC".to_string()),
+        (Some(tz), false) => Some(tz.clone()),
+        (None, false) => conf.timezone.clone(),
+    };
+    let timezone_set = timezone.is_some();
+    if let Some(name) = timezone {
+        ensure!(cfg!(unix) || name == "UTC", UnsupportedTimezone { name });
+        ensure!(
+            config::is_timezone(&name),
+            UnknownTimezone {
+                name,
+                searched: config::timezone_database()
+                    .iter()
+                    .map(|dir| dir.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            }
+        );
+        std::env::set_var("TZ", &name);
+    }
+    alias::set_aliases(conf.aliases.clone());
+    if timezone_set || !conf.aliases.is_empty() {
+        opt = Opt::from_args();
+    }
+    set_duration_format(
+        opt.duration_format
+            .unwrap_or(conf.duration_format)
+            .to_duration_format(),
+    );
+
+    let cmd = opt.cmd.unwrap_or_default();
+    let safe_mode = opt.safe_mode;
+    if safe_mode && !cmd.is_read_only() {
+        return Err(Error::SafeMode);
+    }
+    let dry_run = opt.dry_run;
+    // Safe mode may have skipped patches, which saving would drop from the meta
+    // file, and a dry run mustn't change anything
+    let save = !safe_mode && !dry_run;
+    let patch_mode = if dry_run {
+        PatchMode::DryRun
+    } else if opt.interactive || conf.confirm_changes {
+        PatchMode::Confirm
+    } else {
+        PatchMode::Add
+    };
+
+    let interrupt = interrupt::Interrupt::install();
+
     // Load store for own data
-    #[cfg(feature = "flame_it")]
-    flame::start("load repository");
+    let span = info_span!("load repository").entered();
 
-    let store = SyncFolderStore::new(conf.sync_folder, conf.device_id).should_init(true);
-    let mut repo = Repository::from_store(store).unwrap();
+    let store = SyncFolderStore::new(conf.sync_folder.clone(), conf.device_id.clone())
+        .should_init(true)
+        .should_recover(save);
+    let progress = interrupt.progress();
+    let repo = if safe_mode {
+        let (repo, errors) = Repository::from_store_lenient_with_progress(store, &progress);
+        for error in errors {
+            eprintln!("warning: skipped: {}", error);
+        }
+        repo
+    } else {
+        match Repository::from_store_with_progress(store, &progress) {
+            Ok(repo) => repo,
+            Err(_) if interrupt.is_cancelled() => return Err(Error::Cancelled),
+            Err(errors) => return Err(Error::ReadRepository { errors }),
+        }
+    };
+    drop(progress);
+    if interrupt.is_cancelled() {
+        return Err(Error::Cancelled);
+    }
+    // Scripts and hooks can set AUGR_SOURCE, so that runaway ones can be spotted
+    let clock: Rc<dyn Clock> = time_input::clock().into();
+    let mut repo = repo
+        .with_source(std::env::var("AUGR_SOURCE").unwrap_or_else(|_| "cli".into()))
+        .with_clock(Box::new(Rc::clone(&clock)))
+        .should_record_arrivals(save);
 
-    #[cfg(feature = "flame_it")]
-    flame::end("load repository");
+    span.exit();
 
     // Synchronize data
-    #[cfg(feature = "flame_it")]
-    flame::start("synchronize data");
+    let span = info_span!("synchronize data").entered();
+
+    let progress = interrupt.progress();
+    let patches_before_sync = repo.loaded_patches().count();
+    let synced = repo.try_sync_data_with_progress(&progress);
+    drop(progress);
+    if interrupt.is_cancelled() {
+        return Err(Error::Cancelled);
+    }
+    match synced {
+        Ok(()) => {}
+        // Devices can still be listed when some of their patches are missing
+        Err(errors)
+            if safe_mode
+                || matches!(
+                    cmd,
+                    Command::Device(_) | Command::Doctor(_) | Command::Debug(_)
+                ) =>
+        {
+            for error in errors {
+                eprintln!("warning: {}", error);
+            }
+        }
+        Err(errors) => return Err(Error::SyncError { errors }),
+    }
+    // A newer version of augr may have written data that this one would lose
+    if !repo.is_writable() {
+        if !cmd.is_read_only() {
+            return Err(Error::NewerFormat);
+        }
+        eprintln!(
+            "warning: the sync folder was written by a newer version of augr, so nothing is saved"
+        );
+    }
+    let save = save && repo.is_writable();
+    if save {
+        repo.save_meta().unwrap();
+    }
 
-    repo.try_sync_data()
-        .map_err(|errors| Error::SyncError { errors })?;
-    repo.save_meta().unwrap();
+    // Setting AUGR_SEED to a number makes the ids of new patches and events
+    // the same whenever a command is run on the same data, which is useful
+    // for testing. The number of patches is mixed in, so that the next run
+    // doesn't make the same ids again.
+    if let Some(seed) = std::env::var("AUGR_SEED")
+        .ok()
+        .and_then(|seed| seed.parse().ok())
+    {
+        let known = repo.loaded_patches().count() as u64;
+        augr_core::id::set_generator(augr_core::id::SeededIds::with_known_patches(seed, known));
+    }
+
+    // Absences used to be kept in a file for each device instead of in
+    // patches, so that they didn't sync like the rest. This device's file is
+    // moved into a patch the first time it saves.
+    if save {
+        if let Some(patch) = days_off::legacy_patch(&conf).context(MoveAbsences {})? {
+            repo.begin().context(SavePatches {})?;
+            repo.add_patch(patch).context(SavePatches {})?;
+            repo.commit().context(SavePatches {})?;
+            days_off::remove_legacy_files(&conf).context(MoveAbsences {})?;
+        }
+    }
 
-    #[cfg(feature = "flame_it")]
-    flame::end("synchronize data");
+    span.exit();
+
+    // Resolving conflicts has to happen before the timesheet can be flattened
+    if let Command::Resolve(subcmd) = &cmd {
+        let patches = subcmd
+            .exec(repo.timesheet(), &conf)
+            .map_err(|e| Box::new(e).into())
+            .context(GeneralError {})?;
+        add_patches(&mut repo, patches, None, patch_mode, &interrupt)?;
+        if save {
+            repo.save_meta().unwrap();
+        }
+        return Ok(());
+    }
+
+    // Undoing a change can fix a conflict, so it doesn't need the timesheet
+    // to be flattened either
+    if let Command::Undo(subcmd) = &cmd {
+        let patches = subcmd
+            .exec(&repo, save)
+            .map_err(|e| Box::new(e).into())
+            .context(GeneralError {})?;
+        add_patches(&mut repo, patches, None, patch_mode, &interrupt)?;
+        if save {
+            repo.save_meta().unwrap();
+        }
+        return Ok(());
+    }
+
+    // Managing devices doesn't need a timesheet, and works even when there are
+    // conflicts
+    if let Command::Device(subcmd) = &cmd {
+        subcmd
+            .exec(&mut repo, dry_run)
+            .map_err(|e| Box::new(e).into())
+            .context(GeneralError {})?;
+        if save {
+            repo.save_meta().unwrap();
+        }
+        return Ok(());
+    }
+    if let Command::Private(subcmd) = &cmd {
+        subcmd.exec(&mut repo, dry_run);
+        if save {
+            repo.save_meta().unwrap();
+        }
+        return Ok(());
+    }
+    if let Command::Whatsnew(subcmd) = &cmd {
+        return subcmd
+            .exec(&mut repo, save)
+            .map_err(|e| Box::new(e).into())
+            .context(GeneralError {});
+    }
+    if let Command::Complete(subcmd) = &cmd {
+        return subcmd
+            .exec(&repo)
+            .map_err(|e| Box::new(e).into())
+            .context(GeneralError {});
+    }
+    if let Command::Debug(subcmd) = &cmd {
+        return subcmd
+            .exec(&repo, &conf_file, &trace, repo.clock().now())
+            .map_err(|e| Box::new(e).into())
+            .context(GeneralError {});
+    }
+    if let Command::Doctor(subcmd) = &cmd {
+        return subcmd
+            .exec(&repo, &conf, repo.clock().now())
+            .map_err(|e| Box::new(e).into())
+            .context(GeneralError {});
+    }
 
     // Convert abstract patch data structure into a more conventional format
-    #[cfg(feature = "flame_it")]
-    flame::start("flatten timesheet");
+    let span = info_span!("flatten timesheet").entered();
 
     let eventgraph = repo.timesheet();
-    let timesheet = eventgraph
-        .flatten()
-        .map_err(|conflicts| Error::MergeConflicts { conflicts })?;
+    let flattened = if safe_mode {
+        let (timesheet, errors) = eventgraph.flatten_lossy(conf.conflict_resolution);
+        for error in errors {
+            eprintln!("warning: skipped: {}", error);
+        }
+        Ok(timesheet)
+    } else {
+        eventgraph.flatten_with(conf.conflict_resolution)
+    };
+    let timesheet = match flattened {
+        Ok(timesheet) => timesheet
+            .with_end_policy(conf.end_policy.to_end_policy(conf.day_offset()))
+            .with_clock(&*clock),
+        Err(conflicts) => {
+            for conflict in eventgraph.conflicts() {
+                eprintln!("Conflict: {}", conflict);
+            }
+            return Err(Error::MergeConflicts { conflicts });
+        }
+    };
+
+    span.exit();
+
+    // Devices should see the same events, so tell the user when another
+    // device disagrees. Only new patches can change that, so the checksums
+    // are left alone otherwise.
+    let patches_before_command = repo.loaded_patches().count();
+    let synced_patches = repo.loaded_patches().count() > patches_before_sync;
+    let checksums = if synced_patches || matches!(cmd, Command::Checksum(_)) {
+        let checksums = checksum::recent(repo.loaded_patches(), &timesheet);
+        checksum::warn_disagreements(&repo, &checksums);
+        Some(checksums)
+    } else {
+        None
+    };
 
-    #[cfg(feature = "flame_it")]
-    flame::end("flatten timesheet");
+    // Events started in private mode are left out of reports and exports
+    let timesheet = if cmd.hides_private() {
+        timesheet.without_private()
+    } else {
+        timesheet
+    };
+
+    // Commands accept any unique prefix of an event reference, or a relative
+    // reference like `@-1`
+    let mut cmd = cmd;
+    for event_ref in cmd.event_refs_mut() {
+        *event_ref = timesheet
+            .resolve_event_ref(
+                event_ref,
+                *timesheet.clock().now().with_timezone(&Local).offset(),
+            )
+            .context(EventRef {})?;
+    }
 
     // Run command
-    #[cfg(feature = "flame_it")]
-    flame::start("command");
-    match opt.cmd.unwrap_or_default() {
+    let span = info_span!("command").entered();
+    match cmd {
         Command::Start(subcmd) => {
-            let patches = subcmd.exec(&timesheet);
-            for patch in patches {
-                println!("{}", patch.patch_ref());
-                repo.add_patch(patch).unwrap();
-            }
+            let patches = subcmd
+                .exec(&timesheet, &conf)
+                .map_err(|e| Box::new(e).into())
+                .context(GeneralError {})?;
+            let patches = if repo.meta().private_mode() {
+                patches
+                    .into_iter()
+                    .map(|patch| patch.with_metadata(PRIVATE_METADATA, "true"))
+                    .collect()
+            } else {
+                patches
+            };
+            add_patches(&mut repo, patches, Some(&conf), patch_mode, &interrupt)?;
+        }
+        Command::Stop(subcmd) => {
+            let patches = subcmd
+                .exec(&timesheet, &conf)
+                .map_err(|e| Box::new(e).into())
+                .context(GeneralError {})?;
+            add_patches(&mut repo, patches, Some(&conf), patch_mode, &interrupt)?;
+        }
+        Command::Cancel(subcmd) => {
+            let patches = subcmd
+                .exec(&timesheet)
+                .map_err(|e| Box::new(e).into())
+                .context(GeneralError {})?;
+            add_patches(&mut repo, patches, Some(&conf), patch_mode, &interrupt)?;
         }
         Command::Import(subcmd) => {
             let patches = subcmd.exec(&timesheet).context(ImportError {})?;
-            for patch in patches {
-                println!("{}", patch.patch_ref());
-                repo.add_patch(patch).unwrap();
+            add_patches_with(
+                &mut repo,
+                patches,
+                None,
+                patch_mode,
+                &interrupt,
+                subcmd.keep_partial,
+            )?;
+        }
+        Command::Resolve(_) => unreachable!("resolve is run before flattening"),
+        Command::Examples(_) => unreachable!("examples are shown before loading data"),
+        Command::Device(_) => unreachable!("devices are managed before flattening"),
+        Command::Private(_) => unreachable!("private mode is set before flattening"),
+        Command::Doctor(_) => unreachable!("doctor runs before flattening"),
+        Command::Whatsnew(_) => unreachable!("whatsnew runs before flattening"),
+        Command::Undo(_) => unreachable!("undo is run before flattening"),
+        Command::Debug(_) => unreachable!("debug bundles are made before flattening"),
+        Command::Completions(_) => unreachable!("completions are printed before loading data"),
+        Command::Complete(_) => unreachable!("completions are listed before flattening"),
+        Command::Status(subcmd) => subcmd
+            .exec(&timesheet, &repo, &conf)
+            .map_err(|e| Box::new(e).into())
+            .context(GeneralError {})?,
+        Command::Export(subcmd) => subcmd
+            .exec(&timesheet, &conf)
+            .map_err(|e| Box::new(e).into())
+            .context(GeneralError {})?,
+        Command::VerifyExport(subcmd) => subcmd
+            .exec(&timesheet, &conf)
+            .map_err(|e| Box::new(e).into())
+            .context(GeneralError {})?,
+        Command::Push(subcmd) => subcmd.exec(&timesheet, &conf).context(GeneralError {})?,
+        Command::Summary(subcmd) => subcmd
+            .exec(&timesheet, &conf)
+            .map_err(|e| Box::new(e).into())
+            .context(GeneralError {})?,
+        Command::Chart(subcmd) => subcmd
+            .exec(&timesheet, &conf)
+            .map_err(|e| Box::new(e).into())
+            .context(GeneralError {})?,
+        Command::Absence(subcmd) => {
+            let patches = subcmd
+                .exec(&timesheet, &conf)
+                .map_err(|e| Box::new(e).into())
+                .context(GeneralError {})?;
+            if !patches.is_empty() {
+                add_patches(&mut repo, patches, None, patch_mode, &interrupt)?;
             }
         }
-        Command::Summary(subcmd) => subcmd.exec(&timesheet),
-        Command::Chart(subcmd) => subcmd.exec(&timesheet),
+        Command::Report(subcmd) => subcmd
+            .exec(&timesheet, &conf)
+            .map_err(|e| Box::new(e).into())
+            .context(GeneralError {})?,
+        Command::Overtime(subcmd) => subcmd
+            .exec(&timesheet, &conf)
+            .map_err(|e| Box::new(e).into())
+            .context(GeneralError {})?,
         Command::Tags(subcmd) => subcmd.exec(&timesheet),
+        Command::Week(subcmd) => subcmd.exec(&timesheet, &conf),
+        Command::Month(subcmd) => subcmd.exec(&timesheet, &conf),
+        Command::Timeline(subcmd) => subcmd.exec(&timesheet),
+        Command::Heatmap(subcmd) => subcmd
+            .exec(&timesheet, &conf)
+            .map_err(|e| Box::new(e).into())
+            .context(GeneralError {})?,
+        Command::Diff(subcmd) => subcmd.exec(&timesheet),
+        Command::Budget(subcmd) => subcmd
+            .exec(&timesheet, &conf)
+            .map_err(|e| Box::new(e).into())
+            .context(GeneralError {})?,
+        Command::Invoice(subcmd) => subcmd
+            .exec(&timesheet, &conf)
+            .map_err(|e| Box::new(e).into())
+            .context(GeneralError {})?,
+        Command::Histogram(subcmd) => subcmd
+            .exec(&timesheet)
+            .map_err(|e| Box::new(e).into())
+            .context(GeneralError {})?,
+        Command::Stats(subcmd) => subcmd
+            .exec(&timesheet, &conf)
+            .map_err(|e| Box::new(e).into())
+            .context(GeneralError {})?,
+        Command::Checksum(subcmd) => subcmd
+            .exec(&repo, &timesheet)
+            .map_err(|e| Box::new(e).into())
+            .context(GeneralError {})?,
+        Command::Rules(subcmd) => {
+            let patches = subcmd
+                .exec(&timesheet, &conf.rules)
+                .map_err(|e| Box::new(e).into())
+                .context(GeneralError {})?;
+            // The rules have already run on these patches
+            add_patches(&mut repo, patches, None, patch_mode, &interrupt)?;
+        }
         Command::Tag(subcmd) => {
             let patches = subcmd
                 .exec(&timesheet)
                 .map_err(|e| Box::new(e).into())
                 .context(GeneralError {})?;
-            for patch in patches {
-                println!("{}", patch.patch_ref());
-                repo.add_patch(patch).unwrap();
-            }
+            add_patches(&mut repo, patches, Some(&conf), patch_mode, &interrupt)?;
         }
-        Command::SetStart(subcmd) => {
+        Command::Split(subcmd) => {
             let patches = subcmd
                 .exec(&timesheet)
                 .map_err(|e| Box::new(e).into())
                 .context(GeneralError {})?;
-            for patch in patches {
-                println!("{}", patch.patch_ref());
-                repo.add_patch(patch).unwrap();
-            }
+            add_patches(&mut repo, patches, Some(&conf), patch_mode, &interrupt)?;
+        }
+        Command::Merge(subcmd) => {
+            let patches = subcmd
+                .exec(&timesheet)
+                .map_err(|e| Box::new(e).into())
+                .context(GeneralError {})?;
+            add_patches(&mut repo, patches, Some(&conf), patch_mode, &interrupt)?;
+        }
+        Command::TagRename(subcmd) => {
+            let patches = subcmd
+                .exec(&timesheet)
+                .map_err(|e| Box::new(e).into())
+                .context(GeneralError {})?;
+            add_patches(&mut repo, patches, Some(&conf), patch_mode, &interrupt)?;
+        }
+        Command::Retag(subcmd) => {
+            let patches = subcmd
+                .exec(&timesheet)
+                .map_err(|e| Box::new(e).into())
+                .context(GeneralError {})?;
+            add_patches(&mut repo, patches, Some(&conf), patch_mode, &interrupt)?;
+        }
+        Command::Annotate(subcmd) => {
+            let patches = subcmd
+                .exec(&timesheet)
+                .map_err(|e| Box::new(e).into())
+                .context(GeneralError {})?;
+            add_patches(&mut repo, patches, Some(&conf), patch_mode, &interrupt)?;
+        }
+        Command::History(subcmd) => subcmd
+            .exec(&repo)
+            .map_err(|e| Box::new(e).into())
+            .context(GeneralError {})?,
+        Command::Delete(subcmd) => {
+            let patches = subcmd
+                .exec(&timesheet)
+                .map_err(|e| Box::new(e).into())
+                .context(GeneralError {})?;
+            add_patches(&mut repo, patches, Some(&conf), patch_mode, &interrupt)?;
+        }
+        Command::Edit(subcmd) => {
+            let patches = subcmd
+                .exec(&timesheet, &conf)
+                .map_err(|e| Box::new(e).into())
+                .context(GeneralError {})?;
+            add_patches(&mut repo, patches, Some(&conf), patch_mode, &interrupt)?;
+        }
+        Command::EditDay(subcmd) => {
+            let patches = subcmd
+                .exec(&timesheet, &conf)
+                .map_err(|e| Box::new(e).into())
+                .context(GeneralError {})?;
+            add_patches(&mut repo, patches, Some(&conf), patch_mode, &interrupt)?;
+        }
+        Command::SetStart(subcmd) => {
+            let patches = subcmd
+                .exec(&timesheet, &conf)
+                .map_err(|e| Box::new(e).into())
+                .context(GeneralError {})?;
+            add_patches(&mut repo, patches, Some(&conf), patch_mode, &interrupt)?;
         }
     };
-    #[cfg(feature = "flame_it")]
-    flame::end("command");
+    span.exit();
+
+    // Save which patches this device uses to disk, with checksums that match
+    // them
+    if save {
+        let checksums = if repo.loaded_patches().count() > patches_before_command {
+            Some(checksum::current(&repo, conf.conflict_resolution, &*clock))
+        } else {
+            checksums
+        };
+        if let Some(checksums) = checksums {
+            repo.meta_mut().set_day_checksums(checksums);
+        }
+        repo.save_meta().unwrap();
+    }
+    if interrupt.is_cancelled() {
+        return Err(Error::Cancelled);
+    }
+
+    Ok(())
+}
 
-    // Save which patches this device uses to disk
-    repo.save_meta().unwrap();
+/// What to do with the patches that a command makes
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum PatchMode {
+    Add,
+    /// Ask before adding them
+    Confirm,
+    /// Only show them
+    DryRun,
+}
 
-    #[cfg(feature = "flame_it")]
-    flame::dump_html(&mut std::fs::File::create("flame-graph.html").unwrap()).unwrap();
+/// Add the patches that a command made to the repository, after expanding the
+/// tag aliases and running the tagging rules from `conf` on them
+fn add_patches(
+    repo: &mut Repository<SyncFolderStore>,
+    patches: Vec<Patch>,
+    conf: Option<&config::Conf>,
+    mode: PatchMode,
+    interrupt: &interrupt::Interrupt,
+) -> Result<(), Error> {
+    add_patches_with(repo, patches, conf, mode, interrupt, false)
+}
 
+/// Like `add_patches`, but if `keep_partial` is set, stopping with Ctrl-C
+/// commits the patches that were added so far instead of throwing them away
+fn add_patches_with(
+    repo: &mut Repository<SyncFolderStore>,
+    patches: Vec<Patch>,
+    conf: Option<&config::Conf>,
+    mode: PatchMode,
+    interrupt: &interrupt::Interrupt,
+    keep_partial: bool,
+) -> Result<(), Error> {
+    let patches = match conf {
+        Some(conf) => {
+            let patches = alias::expand(&conf.aliases, patches);
+            suggest::check_rare_tags(repo.timesheet(), &patches, mode != PatchMode::DryRun)
+                .context(RareTag {})?;
+            rules::apply(&conf.rules, repo.timesheet(), patches)
+        }
+        None => patches,
+    };
+    match mode {
+        PatchMode::Add => {}
+        PatchMode::Confirm => {
+            preview::confirm(repo.timesheet(), &patches, repo.clock().now()).context(Preview {})?
+        }
+        PatchMode::DryRun => {
+            return dry_run::show(repo.timesheet(), &patches).context(DryRun {});
+        }
+    }
+    // The patches are written in one transaction, so that a crash can't leave
+    // the meta file pointing at missing patches. Imports can add a lot of
+    // them, so Ctrl-C stops between patches and throws the transaction away,
+    // unless the ones added so far should be kept.
+    let progress = interrupt.progress();
+    let total = patches.len();
+    repo.begin().context(SavePatches {})?;
+    for (done, patch) in patches.into_iter().enumerate() {
+        if interrupt.is_cancelled() {
+            if keep_partial {
+                eprintln!("Stopped after adding {} of {} patches", done, total);
+                break;
+            }
+            repo.rollback().context(SavePatches {})?;
+            return Err(Error::Cancelled);
+        }
+        progress.set("Adding patches", done, total);
+        println!("{}", patch.patch_ref());
+        repo.add_patch(patch).context(SavePatches {})?;
+    }
+    repo.commit().context(SavePatches {})?;
     Ok(())
 }
 
+thread_local! {
+    static DURATION_FORMAT: RefCell<Box<dyn DurationFormat>> =
+        RefCell::new(Box::new(augr_core::duration::HoursMinutes));
+}
+
+/// Write durations with `format` from now on
+fn set_duration_format(format: Box<dyn DurationFormat>) {
+    DURATION_FORMAT.with(|current| *current.borrow_mut() = format);
+}
+
 fn format_duration(duration: chrono::Duration) -> String {
-    let hours = duration.num_hours();
-    let mins = duration.num_minutes() - (hours * 60);
-    if hours < 1 {
-        format!("{}m", mins)
+    DURATION_FORMAT.with(|format| format.borrow().format(duration))
+}
+
+/// A duration with its sign, like "+1h 0m" or "-30m", for showing changes
+fn format_signed(duration: chrono::Duration) -> String {
+    if duration < chrono::Duration::zero() {
+        format!("-{}", format_duration(-duration))
     } else {
-        format!("{}h {}m", hours, mins)
+        format!("+{}", format_duration(duration))
+    }
+}
+
+/// `text` with the characters that mean something in HTML escaped
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+impl Command {
+    /// The event reference given to commands that change a single event
+    fn event_refs_mut(&mut self) -> Vec<&mut augr_core::EventRef> {
+        match self {
+            Command::Tag(subcmd) => vec![&mut subcmd.event],
+            Command::Annotate(subcmd) => vec![&mut subcmd.event],
+            Command::SetStart(subcmd) => vec![&mut subcmd.event],
+            Command::Delete(subcmd) => vec![&mut subcmd.event],
+            Command::Edit(subcmd) => vec![&mut subcmd.event],
+            Command::History(subcmd) => vec![&mut subcmd.event],
+            Command::Merge(subcmd) => vec![&mut subcmd.first, &mut subcmd.second],
+            Command::Split(subcmd) => vec![&mut subcmd.event],
+            _ => vec![],
+        }
+    }
+
+    /// Whether the command reports on the tracked time, so that events started
+    /// in private mode should be left out
+    fn hides_private(&self) -> bool {
+        match self {
+            Command::Export(subcmd) => !subcmd.include_private,
+            Command::Summary(subcmd) => !subcmd.include_private,
+            Command::Report(subcmd) => !subcmd.include_private,
+            Command::Push(subcmd) => !subcmd.include_private(),
+            _ => matches!(
+                self,
+                Command::Chart(_)
+                    | Command::Overtime(_)
+                    | Command::Week(_)
+                    | Command::Month(_)
+                    | Command::Timeline(_)
+                    | Command::Heatmap(_)
+                    | Command::Stats(_)
+                    | Command::Diff(_)
+                    | Command::Histogram(_)
+                    | Command::Invoice(_)
+                    | Command::Budget(_)
+                    | Command::Tags(_)
+            ),
+        }
+    }
+
+    /// Whether the command only reads data, so that it can run in safe mode
+    fn is_read_only(&self) -> bool {
+        matches!(
+            self,
+            Command::Status(_)
+                | Command::Summary(_)
+                | Command::Chart(_)
+                | Command::Overtime(_)
+                | Command::Report(_)
+                | Command::Week(_)
+                | Command::Month(_)
+                | Command::Timeline(_)
+                | Command::Heatmap(_)
+                | Command::Stats(_)
+                | Command::Diff(_)
+                | Command::Histogram(_)
+                | Command::Invoice(_)
+                | Command::Budget(_)
+                | Command::Tags(_)
+                | Command::Checksum(_)
+                | Command::Rules(rules::Cmd::Test)
+                | Command::History(_)
+                | Command::Export(_)
+                | Command::VerifyExport(_)
+                | Command::Doctor(_)
+                | Command::Debug(_)
+                | Command::Examples(_)
+                | Command::Completions(_)
+                | Command::Complete(_)
+                | Command::Device(device::Cmd::List)
+                | Command::Private(private::Cmd::Status)
+                | Command::Absence(absence::Cmd::List { .. })
+        )
     }
 }
 
@@ -0,0 +1,171 @@
+use crate::edit::{self, EventDoc};
+use augr_core::{
+    repository::event::PatchedEvent,
+    store::patch::{AddTag, DeleteEvent, SetNote},
+    EventRef, Patch, Timesheet,
+};
+use chrono::Utc;
+use snafu::{OptionExt, Snafu};
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct Cmd {
+    /// The id of one of the events to merge, a unique prefix of it, or
+    /// `@` for the current event and `@-1`, `@-2`, ... for earlier ones today
+    pub first: EventRef,
+
+    /// The other event to merge
+    pub second: EventRef,
+}
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Can't merge event {} with itself", event_ref))]
+    SameEvent { event_ref: EventRef },
+
+    #[snafu(display("Unknown event reference: {}", event_ref))]
+    UnknownEventRef { event_ref: EventRef },
+
+    #[snafu(display("Event {} has more than one start time; resolve it first", event_ref))]
+    ConflictingStarts { event_ref: EventRef },
+
+    #[snafu(display(
+        "Events {} and {} aren't next to each other; only an event and the one right after it can be merged",
+        first,
+        second
+    ))]
+    NotAdjacent { first: EventRef, second: EventRef },
+}
+
+impl Cmd {
+    pub fn exec(&self, timesheet: &Timesheet) -> Result<Vec<Patch>, Error> {
+        Ok(vec![merge(timesheet, &self.first, &self.second)?])
+    }
+}
+
+/// A patch that keeps whichever event started first, gives it the tags and
+/// note of the other one, and deletes the other one. The events must follow
+/// each other, so that the kept event can take over the other one's time.
+fn merge(timesheet: &Timesheet, first: &EventRef, second: &EventRef) -> Result<Patch, Error> {
+    if first == second {
+        return SameEvent {
+            event_ref: first.clone(),
+        }
+        .fail();
+    }
+    let (a, b) = (lookup(timesheet, first)?, lookup(timesheet, second)?);
+    let ((kept_ref, kept, kept_doc), (merged_ref, merged, merged_doc)) = if a.2.start <= b.2.start {
+        (a, b)
+    } else {
+        (b, a)
+    };
+    if timesheet
+        .events()
+        .range(kept_doc.start.with_timezone(&Utc)..merged_doc.start.with_timezone(&Utc))
+        .count()
+        > 1
+    {
+        return NotAdjacent {
+            first: kept_ref.clone(),
+            second: merged_ref.clone(),
+        }
+        .fail();
+    }
+
+    let mut patch = Patch::new();
+    let parents = kept.latest_patches();
+    for tag in merged_doc
+        .tags
+        .iter()
+        .filter(|tag| !kept_doc.tags.contains(tag))
+    {
+        patch.insert_add_tag(AddTag {
+            parents: parents.clone(),
+            event: kept_ref.clone(),
+            tag: tag.clone(),
+        });
+    }
+    if !merged_doc.note.is_empty() && merged_doc.note != kept_doc.note {
+        let note = if kept_doc.note.is_empty() {
+            merged_doc.note.clone()
+        } else {
+            format!("{}\n{}", kept_doc.note, merged_doc.note)
+        };
+        patch.insert_set_note(SetNote {
+            parents: parents.clone(),
+            replaces: kept.note_patches(),
+            event: kept_ref.clone(),
+            note,
+        });
+    }
+    patch.insert_delete_event(DeleteEvent {
+        parents: merged.latest_patches(),
+        event: merged_ref.clone(),
+    });
+    Ok(patch)
+}
+
+fn lookup<'a>(
+    timesheet: &'a Timesheet,
+    event_ref: &'a EventRef,
+) -> Result<(&'a EventRef, &'a PatchedEvent, EventDoc), Error> {
+    let event = timesheet
+        .get_patched_timesheet()
+        .events
+        .get(event_ref)
+        .filter(|event| !event.is_deleted())
+        .context(UnknownEventRef {
+            event_ref: event_ref.clone(),
+        })?;
+    let doc = edit::event_doc(event).context(ConflictingStarts {
+        event_ref: event_ref.clone(),
+    })?;
+    Ok((event_ref, event, doc))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::Tracked;
+    use augr_core::Tag;
+    use chrono::TimeZone;
+
+    #[test]
+    fn merge_keeps_earlier_start() {
+        let time = |h, m| Utc.ymd(2019, 7, 24).and_hms(h, m, 0);
+        let create = Patch::new()
+            .create_event("a".into(), time(9, 0), vec!["work".into()])
+            .create_event("b".into(), time(9, 1), vec!["work".into(), "coding".into()])
+            .create_event("c".into(), time(12, 0), vec!["lunch".into()]);
+        let tracked = Tracked::new(&[&create]);
+        let timesheet = tracked.timesheet();
+        assert!(merge(&timesheet, &"a".into(), &"a".into()).is_err());
+
+        let patch = merge(&timesheet, &"b".into(), &"a".into()).unwrap();
+        let merged = Tracked::new(&[&create, &patch]);
+        let segments = merged.timesheet().segments();
+        assert_eq!(segments.len(), 2);
+        assert_eq!(segments[0].event_ref, "a");
+        assert_eq!(segments[0].start_time, time(9, 0));
+        let tags: Vec<Tag> = segments[0].tags.iter().cloned().collect();
+        assert_eq!(tags, vec![String::from("coding"), "work".into()]);
+    }
+
+    #[test]
+    fn merge_rejects_events_with_others_between() {
+        let time = |h| Utc.ymd(2019, 7, 24).and_hms(h, 0, 0);
+        let create = Patch::new()
+            .create_event("a".into(), time(9), vec!["work".into()])
+            .create_event("b".into(), time(12), vec!["lunch".into()])
+            .create_event("c".into(), time(13), vec!["work".into()]);
+        let tracked = Tracked::new(&[&create]);
+        let timesheet = tracked.timesheet();
+        match merge(&timesheet, &"c".into(), &"a".into()) {
+            Err(Error::NotAdjacent { first, second }) => {
+                assert_eq!((first.as_str(), second.as_str()), ("a", "c"))
+            }
+            other => panic!("expected NotAdjacent, got {:?}", other),
+        }
+        assert!(merge(&timesheet, &"b".into(), &"c".into()).is_ok());
+    }
+}
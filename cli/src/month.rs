@@ -0,0 +1,141 @@
+use crate::{config::Conf, format_duration, suggest, time_input::parse_default_local};
+use augr_core::{
+    aggregate::{self, Period},
+    timesheet::Segment,
+    TagExpr, Timesheet,
+};
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, Weekday};
+use std::{collections::BTreeSet, fmt::Write};
+use structopt::StructOpt;
+
+/// The width of a day in the calendar, enough for "10h 30m"
+const CELL_WIDTH: usize = 8;
+
+#[derive(StructOpt, Debug)]
+pub struct Cmd {
+    /// Any day in the month to show, like "2019-07-01". Defaults to this month.
+    #[structopt(parse(try_from_os_str = parse_default_local))]
+    day: Option<DateTime<Local>>,
+
+    /// Only count events matching a tag expression, like "work and not meeting"
+    #[structopt(long = "filter", parse(try_from_str = crate::alias::tag_expr))]
+    filter: Option<TagExpr>,
+}
+
+impl Cmd {
+    pub fn exec(&self, timesheet: &Timesheet, conf: &Conf) {
+        suggest::warn_unknown_tags(timesheet, &BTreeSet::new(), self.filter.as_ref());
+        let day = self
+            .day
+            .unwrap_or_else(|| timesheet.clock().now().with_timezone(&Local));
+        let first = Period::Month.start_of(day.naive_local().date());
+        print!(
+            "{}",
+            render(timesheet, first, self.filter.as_ref(), conf.week_starts_on)
+        );
+    }
+}
+
+/// A calendar of the month starting on `first`, like `cal`, with the time
+/// tracked under each day. Each row is a week starting on `week_start`.
+fn render(
+    timesheet: &Timesheet,
+    first: NaiveDate,
+    filter: Option<&TagExpr>,
+    week_start: Weekday,
+) -> String {
+    let next = Period::Month.next(first);
+    let segments: Vec<Segment> = timesheet
+        .segments()
+        .into_iter()
+        .filter(|s| filter.is_none_or(|f| f.matches(&s.tags)))
+        .collect();
+    let totals = aggregate::by_period(
+        &segments,
+        aggregate::midnight(first, &Local),
+        aggregate::midnight(next, &Local),
+        Period::Day,
+        &Local,
+    );
+
+    let mut out = String::new();
+    writeln!(out, "{}", first.format("%B %Y")).unwrap();
+    let week = Period::Week(week_start);
+    let header: String = (0..7)
+        .map(|day| week.start_of(first) + Duration::days(day))
+        .map(|date| format!("{: <width$}", date.format("%a"), width = CELL_WIDTH))
+        .collect();
+    writeln!(out, "{}", header.trim_end()).unwrap();
+
+    // The first row may start in the month before
+    let mut week_first = week.start_of(first);
+    while week_first < next {
+        let mut days = String::new();
+        let mut durations = String::new();
+        for date in (0..7).map(|day| week_first + Duration::days(day)) {
+            let (day, duration) = if date < first || date >= next {
+                (String::new(), String::new())
+            } else {
+                let duration = match totals.groups.get(&date) {
+                    Some(duration) => format_duration(*duration),
+                    None => String::from("-"),
+                };
+                (date.day().to_string(), duration)
+            };
+            write!(days, "{: <width$}", day, width = CELL_WIDTH).unwrap();
+            write!(durations, "{: <width$}", duration, width = CELL_WIDTH).unwrap();
+        }
+        writeln!(out, "{}", days.trim_end()).unwrap();
+        writeln!(out, "{}", durations.trim_end()).unwrap();
+        week_first += Duration::weeks(1);
+    }
+    writeln!(out, "Total {}", format_duration(totals.total)).unwrap();
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::Tracked;
+    use augr_core::Patch;
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn month_calendar() {
+        let time = |d, h| Local.ymd(2019, 7, d).and_hms(h, 0, 0).with_timezone(&Utc);
+        let create = Patch::new()
+            .create_event("a".into(), time(2, 9), vec!["work".into()])
+            .create_event("b".into(), time(2, 12), vec!["lunch".into()])
+            .create_event("c".into(), time(2, 13), vec![])
+            .create_event("d".into(), time(31, 9), vec!["work".into()])
+            .create_event("e".into(), time(31, 10), vec![]);
+        let tracked = Tracked::new(&[&create]).at(time(31, 20));
+        let timesheet = tracked.timesheet();
+
+        let filter: TagExpr = "work".parse().unwrap();
+        let month = render(
+            &timesheet,
+            NaiveDate::from_ymd(2019, 7, 1),
+            Some(&filter),
+            Weekday::Mon,
+        );
+        assert_eq!(
+            month.lines().collect::<Vec<_>>(),
+            vec![
+                "July 2019",
+                "Mon     Tue     Wed     Thu     Fri     Sat     Sun",
+                "1       2       3       4       5       6       7",
+                "-       3h 0m   -       -       -       -       -",
+                "8       9       10      11      12      13      14",
+                "-       -       -       -       -       -       -",
+                "15      16      17      18      19      20      21",
+                "-       -       -       -       -       -       -",
+                "22      23      24      25      26      27      28",
+                "-       -       -       -       -       -       -",
+                "29      30      31",
+                "-       -       1h 0m",
+                "Total 4h 0m",
+            ]
+        );
+    }
+}
@@ -0,0 +1,316 @@
+use crate::{
+    config::{Conf, OvertimeConf},
+    days_off::{self, DaysOff},
+    format_duration, format_signed,
+    time_input::{RangeError, RangeGuard},
+};
+use augr_core::{
+    aggregate::{self, Period},
+    timesheet::Segment,
+    Tag, Timesheet,
+};
+use chrono::{Duration, Local, NaiveDate, TimeZone};
+use snafu::{OptionExt, ResultExt, Snafu};
+use std::collections::{BTreeMap, VecDeque};
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct Cmd {
+    /// The first week to include. Defaults to the `start` of the `[overtime]`
+    /// config, or the week of the first event. Weeks start on
+    /// `week_starts_on`.
+    #[structopt(long = "start")]
+    start: Option<NaiveDate>,
+
+    /// The last day to include. Defaults to today.
+    #[structopt(long = "end")]
+    end: Option<NaiveDate>,
+
+    #[structopt(flatten)]
+    guard: RangeGuard,
+}
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("No contract hours configured; add an [overtime] table with `contract = \"40h/week\"` to the config"))]
+    NoContract,
+
+    #[snafu(display("{}", source))]
+    LoadDaysOff { source: days_off::Error },
+
+    #[snafu(display("{}", source))]
+    LongRange { source: RangeError },
+}
+
+/// How much was worked in one week, and how much should have been
+#[derive(Clone, Debug, PartialEq)]
+struct Week {
+    start: NaiveDate,
+    worked: Duration,
+    expected: Duration,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct LedgerRow {
+    week: Week,
+    overtime: Duration,
+    balance: Duration,
+    /// Overtime that was dropped this week because it expired or went over the cap
+    forfeited: Duration,
+}
+
+impl Cmd {
+    pub fn exec(&self, timesheet: &Timesheet, conf: &Conf) -> Result<(), Error> {
+        let rules = conf.overtime.as_ref().context(NoContract {})?;
+        // Contract hours are spread over the workdays, with or without
+        // `workdays_only`
+        let days_off = DaysOff::load(conf, timesheet)
+            .context(LoadDaysOff {})?
+            .with_workdays(conf.workdays(true));
+
+        let today = timesheet
+            .clock()
+            .now()
+            .with_timezone(&Local)
+            .date()
+            .naive_local();
+        let end = self.end.unwrap_or(today).min(today);
+        let week = conf.week();
+        let first_event = timesheet
+            .segments()
+            .first()
+            .map(|s| s.start_time.with_timezone(&Local).date().naive_local());
+        let start = match self.start.or(rules.start).or(first_event) {
+            Some(start) => week.start_of(start),
+            None => return Ok(()),
+        };
+        // Only an explicit --start can be a typo; the configured start date and
+        // the first event are trusted
+        if let Some(start) = self.start {
+            self.guard.check(start, end).context(LongRange {})?;
+        }
+
+        let worked = worked_by_week(&timesheet.segments(), &rules.tags, week, start, end, &Local);
+
+        let mut weeks = Vec::new();
+        let mut first = start;
+        while first <= end {
+            let last_day = (first + Duration::days(6)).min(end);
+            weeks.push(Week {
+                start: first,
+                worked: worked.get(&first).cloned().unwrap_or_else(Duration::zero),
+                expected: days_off.goal(&rules.contract, first, last_day),
+            });
+            first = week.next(first);
+        }
+
+        println!("Week of    Worked   Expected Overtime Balance");
+        println!("―――――――――― ―――――――― ―――――――― ―――――――― ――――――――");
+        for row in ledger(&weeks, rules) {
+            print!(
+                "{} {: <8} {: <8} {: <8} {}",
+                row.week.start,
+                format_duration(row.week.worked),
+                format_duration(row.week.expected),
+                format_signed(row.overtime),
+                format_signed(row.balance),
+            );
+            if row.forfeited > Duration::zero() {
+                print!(" ({} forfeited)", format_duration(row.forfeited));
+            }
+            println!();
+        }
+        Ok(())
+    }
+}
+
+/// The time worked in each week from `start` up to the end of `end`, keyed by
+/// the first day of the week. Only segments with one of `tags`, or with any
+/// tag if `tags` is empty, count, and a segment that runs into the next week
+/// is split between the two.
+fn worked_by_week<Tz: TimeZone>(
+    segments: &[Segment],
+    tags: &[Tag],
+    week: Period,
+    start: NaiveDate,
+    end: NaiveDate,
+    timezone: &Tz,
+) -> BTreeMap<NaiveDate, Duration> {
+    let counted: Vec<Segment> = segments
+        .iter()
+        .filter(|segment| tags.is_empty() || tags.iter().any(|tag| segment.tags.contains(tag)))
+        .cloned()
+        .collect();
+    aggregate::by_period(
+        &counted,
+        aggregate::midnight(start, timezone),
+        aggregate::midnight(end.succ(), timezone),
+        week,
+        timezone,
+    )
+    .groups
+}
+
+/// Keep a running overtime balance. Overtime is banked per week, and
+/// shortfalls use up the oldest banked overtime first. Banked overtime is
+/// dropped once it is older than `expire_after_weeks`, and anything above
+/// `cap` is dropped straight away.
+fn ledger(weeks: &[Week], rules: &OvertimeConf) -> Vec<LedgerRow> {
+    let mut banked: VecDeque<(usize, Duration)> = VecDeque::new();
+    let mut debt = Duration::zero();
+    let mut rows = Vec::new();
+
+    for (index, week) in weeks.iter().enumerate() {
+        let overtime = week.worked - week.expected;
+        let mut forfeited = Duration::zero();
+
+        if overtime >= Duration::zero() {
+            let repaid = overtime.min(debt);
+            debt = debt - repaid;
+            if overtime > repaid {
+                banked.push_back((index, overtime - repaid));
+            }
+        } else {
+            let mut shortfall = -overtime;
+            while shortfall > Duration::zero() {
+                match banked.front_mut() {
+                    Some((_week, amount)) if *amount > shortfall => {
+                        *amount = *amount - shortfall;
+                        shortfall = Duration::zero();
+                    }
+                    Some((_week, amount)) => {
+                        shortfall = shortfall - *amount;
+                        banked.pop_front();
+                    }
+                    None => break,
+                }
+            }
+            debt = debt + shortfall;
+        }
+
+        if let Some(weeks) = rules.expire_after_weeks {
+            while let Some((earned, amount)) = banked.front() {
+                if index - earned < weeks {
+                    break;
+                }
+                forfeited = forfeited + *amount;
+                banked.pop_front();
+            }
+        }
+
+        if let Some(cap) = rules.cap {
+            let mut excess = total(&banked) - cap;
+            while excess > Duration::zero() {
+                let (_week, amount) = banked.back_mut().expect("banked overtime over cap");
+                let removed = excess.min(*amount);
+                *amount = *amount - removed;
+                excess = excess - removed;
+                forfeited = forfeited + removed;
+                if *amount == Duration::zero() {
+                    banked.pop_back();
+                }
+            }
+        }
+
+        rows.push(LedgerRow {
+            week: week.clone(),
+            overtime,
+            balance: total(&banked) - debt,
+            forfeited,
+        });
+    }
+    rows
+}
+
+fn total(banked: &VecDeque<(usize, Duration)>) -> Duration {
+    banked
+        .iter()
+        .fold(Duration::zero(), |sum, (_week, amount)| sum + *amount)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{target::Target, testing::Tracked};
+    use augr_core::Patch;
+    use chrono::{Utc, Weekday};
+
+    fn weeks(hours: &[(i64, i64)]) -> Vec<Week> {
+        hours
+            .iter()
+            .enumerate()
+            .map(|(i, (worked, expected))| Week {
+                start: NaiveDate::from_ymd(2019, 7, 1) + Duration::weeks(i as i64),
+                worked: Duration::hours(*worked),
+                expected: Duration::hours(*expected),
+            })
+            .collect()
+    }
+
+    fn rules(cap: Option<i64>, expire_after_weeks: Option<usize>) -> OvertimeConf {
+        OvertimeConf {
+            contract: Target::parse("40h/week").unwrap(),
+            tags: Vec::new(),
+            cap: cap.map(Duration::hours),
+            expire_after_weeks,
+            start: None,
+        }
+    }
+
+    fn balances(rows: Vec<LedgerRow>) -> Vec<i64> {
+        rows.iter().map(|row| row.balance.num_hours()).collect()
+    }
+
+    #[test]
+    fn worked_time_is_cut_to_the_weeks() {
+        let time = |d, h| Utc.ymd(2019, 7, d).and_hms(h, 0, 0);
+        let create = Patch::new()
+            .create_event("a".into(), time(7, 22), vec!["work".into()])
+            .create_event("b".into(), time(8, 2), vec![])
+            .create_event("c".into(), time(9, 20), vec!["lunch".into()])
+            .create_event("d".into(), time(10, 9), vec!["work".into()]);
+        let tracked = Tracked::new(&[&create]).at(time(12, 9));
+        let week = Period::Week(Weekday::Mon);
+        let day = |d| NaiveDate::from_ymd(2019, 7, d);
+
+        let segments = tracked.timesheet().segments();
+        let worked = worked_by_week(&segments, &["work".into()], week, day(8), day(10), &Utc);
+        // Only the part of the Sunday evening after midnight is in the week,
+        // and the last event is cut off at the end of the 10th
+        let expected: BTreeMap<NaiveDate, Duration> = vec![(day(8), Duration::hours(2 + 15))]
+            .into_iter()
+            .collect();
+        assert_eq!(worked, expected);
+
+        let worked = worked_by_week(&segments, &[], week, day(1), day(14), &Utc);
+        assert_eq!(worked[&day(1)], Duration::hours(2));
+        assert_eq!(worked[&day(8)], Duration::hours(2 + 13 + 48));
+    }
+
+    #[test]
+    fn running_balance() {
+        let rows = ledger(
+            &weeks(&[(42, 40), (38, 40), (35, 40), (45, 40)]),
+            &rules(None, None),
+        );
+        assert_eq!(balances(rows), vec![2, 0, -5, 0]);
+    }
+
+    #[test]
+    fn overtime_is_capped() {
+        let rows = ledger(&weeks(&[(45, 40), (45, 40)]), &rules(Some(8), None));
+        assert_eq!(rows[1].forfeited, Duration::hours(2));
+        assert_eq!(balances(rows), vec![5, 8]);
+    }
+
+    #[test]
+    fn overtime_expires() {
+        let rows = ledger(
+            &weeks(&[(45, 40), (42, 40), (40, 40), (39, 40)]),
+            &rules(None, Some(2)),
+        );
+        assert_eq!(rows[2].forfeited, Duration::hours(5));
+        assert_eq!(rows[3].forfeited, Duration::hours(1));
+        assert_eq!(balances(rows), vec![5, 7, 2, 0]);
+    }
+}
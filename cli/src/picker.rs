@@ -0,0 +1,172 @@
+//! A fuzzy finder over the tags that have been used before, for picking tags
+//! without remembering exactly how they were spelled
+
+use augr_core::Tag;
+use console::{Key, Term};
+use snafu::{ensure, OptionExt, ResultExt, Snafu};
+use std::{cmp::Reverse, collections::BTreeMap, io};
+
+/// How many matching tags are shown at once
+const SHOWN: usize = 10;
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Picking tags needs a terminal"))]
+    NotATerminal,
+
+    #[snafu(display("Could not use the terminal: {}", source))]
+    Terminal { source: io::Error },
+
+    #[snafu(display("No tags were picked"))]
+    Cancelled,
+}
+
+/// How well `query` matches `tag`, if every character of the query appears in
+/// the tag in order. Characters that follow each other in the tag, and a match
+/// at the start of the tag, score higher.
+fn score(query: &str, tag: &str) -> Option<i64> {
+    let mut score = 0;
+    let mut last_match: Option<usize> = None;
+    let mut tag_chars = tag.chars().map(|c| c.to_ascii_lowercase()).enumerate();
+    for wanted in query.chars().map(|c| c.to_ascii_lowercase()) {
+        let (index, _) = tag_chars.by_ref().find(|(_, c)| *c == wanted)?;
+        score += match last_match {
+            Some(last) if last + 1 == index => 5,
+            None if index == 0 => 10,
+            _ => 1,
+        };
+        last_match = Some(index);
+    }
+    // Prefer shorter tags when the match is equally good
+    Some(score * 100 - tag.len() as i64)
+}
+
+/// The tags matching `query`, best match and then most used first
+fn matches<'a>(query: &str, tag_counts: &'a BTreeMap<Tag, usize>) -> Vec<&'a Tag> {
+    let mut matching: Vec<_> = tag_counts
+        .iter()
+        .filter_map(|(tag, count)| score(query, tag).map(|score| (score, *count, tag)))
+        .collect();
+    matching.sort_by_key(|(score, count, _)| (Reverse(*score), Reverse(*count)));
+    matching.into_iter().map(|(_, _, tag)| tag).collect()
+}
+
+/// The tags that Enter starts: the picked ones, or else the highlighted tag, or
+/// else the typed text. `None` when there is nothing at all, because starting
+/// an event without tags would stop tracking instead.
+fn confirm(picked: Vec<Tag>, highlighted: Option<&Tag>, query: String) -> Option<Vec<Tag>> {
+    if !picked.is_empty() {
+        return Some(picked);
+    }
+    match highlighted {
+        Some(tag) => Some(vec![tag.clone()]),
+        None if !query.is_empty() => Some(vec![query]),
+        None => None,
+    }
+}
+
+/// Let the user type to filter the tags in `tag_counts`, toggle them with Tab
+/// or Space, and confirm with Enter. Enter without a selection picks the
+/// highlighted tag, or the typed text when nothing matches it, and cancels
+/// when there is neither.
+pub fn pick_tags(tag_counts: &BTreeMap<Tag, usize>) -> Result<Vec<Tag>, Error> {
+    let term = Term::stderr();
+    ensure!(term.is_term(), NotATerminal);
+
+    let mut query = String::new();
+    let mut picked: Vec<Tag> = Vec::new();
+    let mut cursor = 0;
+    let mut drawn = 0;
+    loop {
+        let matching = matches(&query, tag_counts);
+        cursor = cursor.min(matching.len().saturating_sub(1));
+        term.clear_last_lines(drawn).context(Terminal {})?;
+        let mut lines = vec![format!(
+            "Tags: {}  (Tab to pick, Enter to start, Esc to cancel)",
+            picked.join(" ")
+        )];
+        lines.push(format!("> {}", query));
+        for (index, tag) in matching.iter().enumerate().take(SHOWN) {
+            lines.push(format!(
+                "{} [{}] {} ({})",
+                if index == cursor { ">" } else { " " },
+                if picked.contains(tag) { "x" } else { " " },
+                tag,
+                tag_counts[*tag]
+            ));
+        }
+        for line in lines.iter() {
+            term.write_line(line).context(Terminal {})?;
+        }
+        drawn = lines.len();
+
+        match term.read_key().context(Terminal {})? {
+            Key::Char(c) if c != ' ' => {
+                query.push(c);
+                cursor = 0;
+            }
+            Key::Backspace => {
+                query.pop();
+                cursor = 0;
+            }
+            Key::ArrowUp => cursor = cursor.saturating_sub(1),
+            Key::ArrowDown => cursor = (cursor + 1).min(SHOWN - 1),
+            Key::Tab | Key::Char(' ') => {
+                if let Some(tag) = matching.get(cursor) {
+                    match picked.iter().position(|p| p == *tag) {
+                        Some(index) => {
+                            picked.remove(index);
+                        }
+                        None => picked.push((*tag).clone()),
+                    }
+                }
+            }
+            Key::Enter => {
+                term.clear_last_lines(drawn).context(Terminal {})?;
+                return confirm(picked, matching.get(cursor).copied(), query).context(Cancelled);
+            }
+            Key::Escape | Key::CtrlC => {
+                term.clear_last_lines(drawn).context(Terminal {})?;
+                return Cancelled.fail();
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fuzzy_matches() {
+        let counts: BTreeMap<Tag, usize> = vec![("meeting", 3), ("email", 8), ("emacs", 1)]
+            .into_iter()
+            .map(|(tag, count)| (tag.into(), count))
+            .collect();
+        let tags = |query| -> Vec<&str> {
+            matches(query, &counts)
+                .into_iter()
+                .map(|t| t.as_str())
+                .collect()
+        };
+        assert_eq!(tags("em"), vec!["email", "emacs"]);
+        assert_eq!(tags("mtg"), vec!["meeting"]);
+        assert_eq!(tags("ema"), vec!["email", "emacs"]);
+        assert_eq!(tags(""), vec!["email", "emacs", "meeting"]);
+        assert!(tags("xyz").is_empty());
+    }
+
+    #[test]
+    fn enter_needs_a_tag() {
+        let email = String::from("email");
+        let tags = |tags: &[&str]| Some(tags.iter().map(|t| t.to_string()).collect::<Vec<_>>());
+        assert_eq!(
+            confirm(vec!["work".into()], Some(&email), "em".into()),
+            tags(&["work"])
+        );
+        assert_eq!(confirm(vec![], Some(&email), "em".into()), tags(&["email"]));
+        assert_eq!(confirm(vec![], None, "gym".into()), tags(&["gym"]));
+        assert_eq!(confirm(vec![], None, String::new()), None);
+    }
+}
@@ -0,0 +1,209 @@
+use crate::prompt;
+use augr_core::{
+    repository::{
+        diff::{diff, metadata, EventDiff, NoteChange},
+        timesheet::PatchedTimesheet,
+    },
+    Patch,
+};
+use chrono::{Date, DateTime, Local, Utc};
+use snafu::{ResultExt, Snafu};
+use std::io;
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Could not read confirmation: {}", source))]
+    ReadConfirmation { source: io::Error },
+
+    #[snafu(display("Nothing was saved"))]
+    NotConfirmed,
+}
+
+/// Show what `patches` would change and ask whether to save them
+pub fn confirm(
+    timesheet: &PatchedTimesheet,
+    patches: &[Patch],
+    now: DateTime<Utc>,
+) -> Result<(), Error> {
+    let mut after = timesheet.clone();
+    for patch in patches {
+        // Patches that don't apply are rejected when they are added
+        if after.apply_patch(patch).is_err() {
+            return Ok(());
+        }
+    }
+
+    let changes = describe_changes(timesheet, &after, now);
+    if changes.is_empty() {
+        return Ok(());
+    }
+    for change in changes {
+        eprintln!("{}", change);
+    }
+    match prompt::confirm("Save these changes?").context(ReadConfirmation {})? {
+        Some(true) => Ok(()),
+        _ => Err(Error::NotConfirmed),
+    }
+}
+
+/// One line for each event that differs between the timesheets, like
+/// `event 1b1b3b3a: +tag meeting, start 09:00→09:15, note "standup"`
+pub fn describe_changes(
+    old: &PatchedTimesheet,
+    new: &PatchedTimesheet,
+    now: DateTime<Utc>,
+) -> Vec<String> {
+    let today = now.with_timezone(&Local).date();
+    let short_refs = new.short_refs();
+    diff(old, new)
+        .into_iter()
+        .map(|event_diff| {
+            let event = &short_refs[event_diff.event()];
+            let changes = match event_diff {
+                EventDiff::Added { event } => {
+                    let event = &new.events[&event];
+                    let mut changes = vec![String::from("new")];
+                    changes.extend(
+                        event
+                            .starts()
+                            .into_iter()
+                            .map(|(_, start)| format!("start {}", fmt_time(today, start))),
+                    );
+                    changes.extend(
+                        event
+                            .tags()
+                            .into_iter()
+                            .map(|(_, tag)| format!("+tag {}", tag)),
+                    );
+                    changes.extend(event.note().map(|note| format!("note {:?}", note)));
+                    changes.extend(
+                        metadata(new, event)
+                            .into_iter()
+                            .map(|(key, value)| format!("{}={}", key, value)),
+                    );
+                    changes
+                }
+                EventDiff::Removed { .. } => vec![String::from("deleted")],
+                EventDiff::Changed {
+                    starts_added,
+                    starts_removed,
+                    tags_added,
+                    tags_removed,
+                    note,
+                    metadata,
+                    ..
+                } => {
+                    let mut changes = Vec::new();
+                    changes.extend(tags_added.iter().map(|tag| format!("+tag {}", tag)));
+                    changes.extend(tags_removed.iter().map(|tag| format!("-tag {}", tag)));
+                    if starts_added.len() == 1 && starts_removed.len() == 1 {
+                        changes.push(format!(
+                            "start {}→{}",
+                            fmt_time(today, *starts_removed.iter().next().unwrap()),
+                            fmt_time(today, *starts_added.iter().next().unwrap())
+                        ));
+                    } else {
+                        changes.extend(
+                            starts_removed
+                                .iter()
+                                .map(|start| format!("-start {}", fmt_time(today, *start))),
+                        );
+                        changes.extend(
+                            starts_added
+                                .iter()
+                                .map(|start| format!("+start {}", fmt_time(today, *start))),
+                        );
+                    }
+                    match note {
+                        Some(NoteChange {
+                            new: Some(note), ..
+                        }) => changes.push(format!("note {:?}", note)),
+                        Some(NoteChange { new: None, .. }) => {
+                            changes.push(String::from("note removed"))
+                        }
+                        None => {}
+                    }
+                    changes.extend(metadata.into_iter().map(|(key, value)| match value {
+                        Some(value) => format!("{}={}", key, value),
+                        None => format!("-{}", key),
+                    }));
+                    changes
+                }
+            };
+            format!("event {}: {}", event, changes.join(", "))
+        })
+        .collect()
+}
+
+/// Times today only show the time of day
+fn fmt_time(today: Date<Local>, time: DateTime<Utc>) -> String {
+    let time = time.with_timezone(&Local);
+    if time.date() == today {
+        time.format("%H:%M").to_string()
+    } else {
+        time.format("%Y-%m-%d %H:%M").to_string()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::Tracked;
+    use chrono::TimeZone;
+
+    #[test]
+    fn describe_tag_and_start_changes() {
+        let start = Utc.ymd(2019, 7, 24).and_hms(9, 0, 0);
+        let create = Patch::new().create_event("abcdef0123".into(), start, vec!["work".into()]);
+        let old = Tracked::new(&[&create]).patched;
+
+        let parent = *create.patch_ref();
+        let change = Patch::new()
+            .add_tag(parent, "abcdef0123".into(), "meeting".into())
+            .remove_start(parent, "abcdef0123".into(), start)
+            .add_start(
+                parent,
+                "abcdef0123".into(),
+                Utc.ymd(2019, 7, 24).and_hms(9, 15, 0),
+            );
+        let mut new = old.clone();
+        new.apply_patch(&change).unwrap();
+
+        let now = Utc.ymd(2019, 7, 24).and_hms(12, 0, 0);
+        let fmt = |h, m| {
+            fmt_time(
+                now.with_timezone(&Local).date(),
+                Utc.ymd(2019, 7, 24).and_hms(h, m, 0),
+            )
+        };
+        assert_eq!(
+            describe_changes(&old, &new, now),
+            vec![format!(
+                "event abcdef01: +tag meeting, start {}→{}",
+                fmt(9, 0),
+                fmt(9, 15)
+            )]
+        );
+    }
+
+    #[test]
+    fn describe_note_changes() {
+        let start = Utc.ymd(2019, 7, 24).and_hms(9, 0, 0);
+        let create = Patch::new().create_event("abcdef0123".into(), start, vec!["work".into()]);
+        let old = Tracked::new(&[&create]).patched;
+
+        let annotate = Patch::new().set_note(
+            *create.patch_ref(),
+            Default::default(),
+            "abcdef0123".into(),
+            "standup".into(),
+        );
+        let mut new = old.clone();
+        new.apply_patch(&annotate).unwrap();
+
+        assert_eq!(
+            describe_changes(&old, &new, start),
+            vec![String::from("event abcdef01: note \"standup\"")]
+        );
+    }
+}
@@ -0,0 +1,50 @@
+use augr_core::{store::SyncFolderStore, Repository};
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug, PartialEq)]
+pub enum Cmd {
+    /// Mark every event started on this device as private, until private mode
+    /// is turned off again
+    #[structopt(no_version, name = "on")]
+    On,
+
+    /// Stop marking new events as private
+    #[structopt(no_version, name = "off")]
+    Off,
+
+    /// Show whether private mode is on
+    #[structopt(no_version, name = "status")]
+    Status,
+}
+
+impl Cmd {
+    /// Private mode is stored in the repository's meta, which the caller saves.
+    /// A dry run only says what would change.
+    pub fn exec(&self, repo: &mut Repository<SyncFolderStore>, dry_run: bool) {
+        match self {
+            Cmd::On | Cmd::Off if dry_run => {
+                let state = if *self == Cmd::On { "on" } else { "off" };
+                println!(
+                    "Would turn private mode {} (dry run, nothing was saved)",
+                    state
+                );
+            }
+            Cmd::On => {
+                repo.meta_mut().set_private_mode(true);
+                println!("Private mode is on; new events won't be reported or exported");
+            }
+            Cmd::Off => {
+                repo.meta_mut().set_private_mode(false);
+                println!("Private mode is off");
+            }
+            Cmd::Status => {
+                let state = if repo.meta().private_mode() {
+                    "on"
+                } else {
+                    "off"
+                };
+                println!("Private mode is {}", state);
+            }
+        }
+    }
+}
@@ -0,0 +1,17 @@
+use std::io::{self, BufRead, IsTerminal, Write};
+
+/// Ask the user a yes or no question. Returns `None` when stdin isn't a
+/// terminal, so that there is nobody to ask.
+pub fn confirm(question: &str) -> io::Result<Option<bool>> {
+    if !io::stdin().is_terminal() {
+        return Ok(None);
+    }
+    eprint!("{} [y/N] ", question);
+    io::stderr().flush()?;
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    let answer = answer.trim();
+    Ok(Some(
+        answer.eq_ignore_ascii_case("y") || answer.eq_ignore_ascii_case("yes"),
+    ))
+}
@@ -0,0 +1,40 @@
+mod obsidian;
+mod taskwarrior;
+
+use crate::config::Conf;
+use augr_core::Timesheet;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub enum PushCmd {
+    /// Keep an augr section up to date in each daily note of an Obsidian vault
+    #[structopt(no_version, name = "obsidian")]
+    Obsidian(obsidian::Cmd),
+
+    /// Write the total time spent on each task started with `start --task`
+    /// back into Taskwarrior
+    #[structopt(no_version, name = "taskwarrior")]
+    Taskwarrior(taskwarrior::Cmd),
+}
+
+impl PushCmd {
+    pub fn exec(
+        &self,
+        timesheet: &Timesheet,
+        conf: &Conf,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        match self {
+            PushCmd::Obsidian(cmd) => cmd.exec(timesheet, conf).map_err(Box::new)?,
+            PushCmd::Taskwarrior(cmd) => cmd.exec(timesheet).map_err(Box::new)?,
+        }
+        Ok(())
+    }
+
+    /// Whether events started in private mode should be pushed
+    pub fn include_private(&self) -> bool {
+        match self {
+            PushCmd::Obsidian(cmd) => cmd.include_private,
+            PushCmd::Taskwarrior(_) => true,
+        }
+    }
+}
@@ -0,0 +1,185 @@
+use crate::{
+    config::Conf,
+    days_off::{self, DaysOff},
+    export::markdown::render_events,
+    time_input::{parse_default_local, RangeError, RangeGuard},
+};
+use augr_core::Timesheet;
+use chrono::{DateTime, Duration, Local};
+use snafu::{OptionExt, ResultExt, Snafu};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+use structopt::StructOpt;
+
+const START_MARKER: &str = "<!-- augr:start -->";
+const END_MARKER: &str = "<!-- augr:end -->";
+
+#[derive(StructOpt, Debug)]
+pub struct Cmd {
+    /// The folder containing the daily notes, named like `2019-08-30.md`
+    #[structopt(long = "vault", parse(from_os_str))]
+    vault: PathBuf,
+
+    /// The first day to update. Defaults to today.
+    #[structopt(long = "start", parse(try_from_os_str = parse_default_local))]
+    start: Option<DateTime<Local>>,
+
+    /// The last day to update. Defaults to today.
+    #[structopt(long = "end", parse(try_from_os_str = parse_default_local))]
+    end: Option<DateTime<Local>>,
+
+    /// Also write events that were started in private mode
+    #[structopt(long = "include-private")]
+    pub include_private: bool,
+
+    #[structopt(flatten)]
+    guard: RangeGuard,
+}
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Could not read note {}: {}", path.display(), source))]
+    ReadNote {
+        source: std::io::Error,
+        path: PathBuf,
+    },
+
+    #[snafu(display("{}", source))]
+    LoadDaysOff { source: days_off::Error },
+
+    #[snafu(display("{}", source))]
+    LongRange { source: RangeError },
+
+    #[snafu(display("Could not write note {}: {}", path.display(), source))]
+    WriteNote {
+        source: std::io::Error,
+        path: PathBuf,
+    },
+
+    #[snafu(display(
+        "Note {} has an augr marker without its partner; fix or remove `{}` and `{}`",
+        path.display(),
+        START_MARKER,
+        END_MARKER
+    ))]
+    UnmatchedMarker { path: PathBuf },
+}
+
+impl Cmd {
+    pub fn exec(&self, timesheet: &Timesheet, conf: &Conf) -> Result<(), Error> {
+        let days_off = DaysOff::load(conf, timesheet).context(LoadDaysOff {})?;
+        let now = timesheet.clock().now().with_timezone(&Local);
+        let start = self.start.unwrap_or_else(|| now.date().and_hms(0, 0, 0));
+        let end = self.end.unwrap_or(now);
+        self.guard
+            .check(start.naive_local().date(), end.naive_local().date())
+            .context(LongRange {})?;
+
+        let mut date = start.date();
+        while date <= end.date() {
+            let path = self.vault.join(format!("{}.md", date.format("%Y-%m-%d")));
+            let absence = days_off.kind(date.naive_local());
+            let events = render_events(timesheet, date, absence, true);
+            if update_note(&path, &events)? {
+                println!("Updated {}", path.display());
+            }
+            date = date + Duration::days(1);
+        }
+        Ok(())
+    }
+}
+
+/// Write `events` into the augr section of the note at `path`. Notes that
+/// don't exist are only created if there is something to put in them.
+/// Returns whether the note was changed.
+fn update_note(path: &Path, events: &str) -> Result<bool, Error> {
+    let existing = if path.exists() {
+        fs::read_to_string(path).context(ReadNote { path })?
+    } else if events.is_empty() {
+        return Ok(false);
+    } else {
+        String::new()
+    };
+
+    let updated = replace_section(&existing, events).context(UnmatchedMarker { path })?;
+    if updated == existing {
+        return Ok(false);
+    }
+    // Write a new file and move it over the note, so that the note is never
+    // half written
+    let temp_path = path.with_extension("tmp");
+    fs::write(&temp_path, updated).context(WriteNote { path: &temp_path })?;
+    fs::rename(&temp_path, path).context(WriteNote { path })?;
+    Ok(true)
+}
+
+/// Replace the text between the augr markers with `events`, appending a new
+/// section to the end of the note if it doesn't have one yet. Returns `None`
+/// if only one of the markers is there, or the end comes before the start, as
+/// it isn't clear which part of the note is augr's.
+fn replace_section(note: &str, events: &str) -> Option<String> {
+    let section = format!("{}\n## augr\n\n{}{}\n", START_MARKER, events, END_MARKER);
+
+    match (note.find(START_MARKER), note.find(END_MARKER)) {
+        (Some(start), Some(end)) if start < end => {
+            let mut end = end + END_MARKER.len();
+            if note[end..].starts_with('\n') {
+                end += 1;
+            }
+            Some(format!("{}{}{}", &note[..start], section, &note[end..]))
+        }
+        (None, None) if note.is_empty() => Some(section),
+        (None, None) if note.ends_with('\n') => Some(format!("{}\n{}", note, section)),
+        (None, None) => Some(format!("{}\n\n{}", note, section)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn appends_section_to_note() {
+        let note = replace_section("# Friday\n", "- 09:00–10:00 (1h 0m) [[work]]\n").unwrap();
+        assert_eq!(
+            note,
+            "# Friday\n\n<!-- augr:start -->\n## augr\n\n- 09:00–10:00 (1h 0m) [[work]]\n<!-- augr:end -->\n"
+        );
+    }
+
+    #[test]
+    fn replaces_existing_section() {
+        let first = replace_section("# Friday\n", "- old\n").unwrap();
+        let note = format!("{}\nSome more notes\n", first);
+        let updated = replace_section(&note, "- new\n").unwrap();
+        assert_eq!(
+            updated,
+            "# Friday\n\n<!-- augr:start -->\n## augr\n\n- new\n<!-- augr:end -->\n\nSome more notes\n"
+        );
+        assert_eq!(replace_section(&updated, "- new\n").unwrap(), updated);
+    }
+
+    #[test]
+    fn unmatched_markers_leave_note_alone() {
+        let root = std::env::temp_dir().join(format!("augr-obsidian-{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        let notes = [
+            format!("# Friday\n{}\n- mine\n", START_MARKER),
+            format!("# Friday\n- mine\n{}\n", END_MARKER),
+            format!("# Friday\n{}\n- mine\n{}\n", END_MARKER, START_MARKER),
+        ];
+        for note in notes.iter() {
+            let path = root.join("2019-08-30.md");
+            fs::write(&path, note).unwrap();
+            match update_note(&path, "- new\n") {
+                Err(Error::UnmatchedMarker { .. }) => {}
+                other => panic!("expected an unmatched marker, got {:?}", other),
+            }
+            assert_eq!(&fs::read_to_string(&path).unwrap(), note);
+        }
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,51 @@
+use crate::taskwarrior::{self, TASK_METADATA};
+use augr_core::Timesheet;
+use chrono::Duration;
+use std::collections::BTreeMap;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct Cmd {
+    /// The user defined attribute to store the total time in. It should be
+    /// declared in your taskrc with `uda.<name>.type=duration`.
+    #[structopt(long = "uda", default_value = "augrtime")]
+    uda: String,
+}
+
+impl Cmd {
+    pub fn exec(&self, timesheet: &Timesheet) -> Result<(), taskwarrior::Error> {
+        let patched = timesheet.get_patched_timesheet();
+        let mut totals: BTreeMap<&str, Duration> = BTreeMap::new();
+        for segment in timesheet.segments() {
+            if let Some(uuid) = patched.event_metadata(&segment.event_ref, TASK_METADATA) {
+                let total = totals.entry(uuid).or_insert_with(Duration::zero);
+                *total = *total + segment.duration;
+            }
+        }
+
+        for (uuid, total) in totals {
+            let value = iso8601_duration(total);
+            taskwarrior::set_attribute(uuid, &self.uda, &value)?;
+            println!("{} {}:{}", uuid, self.uda, value);
+        }
+        Ok(())
+    }
+}
+
+/// Format a duration the way Taskwarrior expects, like `PT1H30M`
+fn iso8601_duration(duration: Duration) -> String {
+    let hours = duration.num_hours();
+    let mins = duration.num_minutes() - hours * 60;
+    format!("PT{}H{}M", hours, mins)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn format_iso8601_duration() {
+        assert_eq!(iso8601_duration(Duration::minutes(90)), "PT1H30M");
+        assert_eq!(iso8601_duration(Duration::minutes(5)), "PT0H5M");
+    }
+}
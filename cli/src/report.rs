@@ -0,0 +1,1674 @@
+use crate::{
+    config::{Conf, EndPolicyConf},
+    days_off::{self, DaysOff},
+    escape_html,
+    export::{csv, json, markdown},
+    format_duration, format_signed,
+    time_input::{parse_default_local, RangeError, RangeGuard},
+};
+use augr_core::{
+    aggregate::{self, Period, Totals},
+    duration::Rounding,
+    tag_expr::ParseError,
+    timesheet::Segment,
+    Tag, TagExpr, Timesheet,
+};
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, NaiveTime, TimeZone, Utc, Weekday};
+use clap::arg_enum;
+use serde::{Deserialize, Serialize};
+use snafu::{ResultExt, Snafu};
+use std::{collections::BTreeSet, fmt::Write, fs, io, path::PathBuf};
+use structopt::StructOpt;
+
+/// The number of pixels in the bar of the tag with the most time, in HTML
+/// reports
+const HTML_BAR_WIDTH: i64 = 300;
+
+arg_enum! {
+    /// Ways of writing a report
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    pub enum Format {
+        Text,
+        Html,
+        Markdown,
+        Json,
+        Csv,
+    }
+}
+
+#[derive(StructOpt, Debug)]
+pub struct Cmd {
+    /// The datetime at which to begin the report. Defaults to the start of today.
+    #[structopt(long = "start", parse(try_from_os_str = parse_default_local))]
+    start: Option<DateTime<Local>>,
+
+    /// The datetime at which to end the report. Defaults to now.
+    #[structopt(long = "end", parse(try_from_os_str = parse_default_local))]
+    end: Option<DateTime<Local>>,
+
+    /// Split the report into columns for each "day", "week", or "month"
+    #[structopt(long = "bucket")]
+    bucket: Option<Period>,
+
+    /// Only count events matching a tag expression, like "work and not meeting"
+    #[structopt(long = "filter", parse(try_from_str = crate::alias::tag_expr))]
+    filter: Option<TagExpr>,
+
+    /// Round to a billing increment, like "15m", "15m up", or "6m nearest
+    /// per-event". Rounding is to the nearest increment and per total by
+    /// default. Overrides `rounding` in the config; "0m" turns it off.
+    #[structopt(long = "round")]
+    round: Option<Rounding>,
+
+    /// Show the working hours of each day that no event covered, instead of
+    /// the time per tag. Working hours are set in the `[working_hours]` table
+    /// of the config.
+    #[structopt(
+        long = "untracked",
+        conflicts_with_all = &["bucket", "save-golden", "check-golden"]
+    )]
+    untracked: bool,
+
+    /// Show each tag next to the same range one "day", "week", or "month"
+    /// earlier, with the change. The report starts at the beginning of the
+    /// current period unless `--start` is given.
+    #[structopt(
+        long = "compare",
+        conflicts_with_all = &["bucket", "untracked", "save-golden", "check-golden"]
+    )]
+    compare: Option<Period>,
+
+    /// Save the report as a golden copy under this name, so that it can be
+    /// checked with `--check-golden` after risky changes like imports
+    #[structopt(long = "save-golden", conflicts_with = "check-golden")]
+    save_golden: Option<String>,
+
+    /// Compare the report for the range of a saved golden copy against it
+    #[structopt(long = "check-golden")]
+    check_golden: Option<String>,
+
+    /// "text", "html" for a standalone page with the table and a bar chart
+    /// to send to someone who doesn't use augr, "markdown" for a table to
+    /// paste into issues and notes, "json" for scripts, or "csv" for a row
+    /// per tag and bucket to import into spreadsheets
+    #[structopt(
+        long = "format",
+        default_value = "text",
+        possible_values = &Format::variants(),
+        case_insensitive = true
+    )]
+    format: Format,
+
+    /// Write the report to this file instead of printing it
+    #[structopt(long = "output", short = "o")]
+    output: Option<PathBuf>,
+
+    /// Also count events that were started in private mode
+    #[structopt(long = "include-private")]
+    pub include_private: bool,
+
+    /// Leave out events that start on weekends, or on the days not in
+    /// `weekdays` of `[working_hours]`. `--untracked` only looks at those
+    /// days either way.
+    #[structopt(long = "workdays")]
+    workdays: bool,
+
+    #[structopt(flatten)]
+    guard: RangeGuard,
+}
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("{}", source))]
+    LongRange { source: RangeError },
+
+    #[snafu(display(
+        "The report ends at {}, which hasn't happened yet, so it could still change; pass an earlier --end",
+        end.with_timezone(&Local).format("%Y-%m-%d %H:%M")
+    ))]
+    UnfinishedRange { end: DateTime<Utc> },
+
+    #[snafu(display("Could not load days off: {}", source))]
+    LoadDaysOff { source: days_off::Error },
+
+    #[snafu(display("Could not write golden report {}: {}", path.display(), source))]
+    WriteGolden { path: PathBuf, source: io::Error },
+
+    #[snafu(display("Could not read golden report {}: {}", path.display(), source))]
+    ReadGolden { path: PathBuf, source: io::Error },
+
+    #[snafu(display("Invalid golden report {}: {}", path.display(), source))]
+    ParseGolden {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+
+    #[snafu(display("Invalid filter in golden report {}: {}", path.display(), source))]
+    GoldenFilter { path: PathBuf, source: ParseError },
+
+    #[snafu(display("Invalid rounding in golden report {}: {}", path.display(), message))]
+    GoldenRounding { path: PathBuf, message: String },
+
+    #[snafu(display("Could not serialize golden report: {}", source))]
+    SerializeGolden { source: toml::ser::Error },
+
+    #[snafu(display("The report no longer matches golden report \"{}\"", name))]
+    GoldenMismatch { name: String },
+
+    #[snafu(display(
+        "Invalid golden report name \"{}\"; names can't contain path separators or \"..\"",
+        name
+    ))]
+    GoldenName { name: String },
+
+    #[snafu(display(
+        "Only the time per tag can be written as {}; leave out --untracked, --compare, and --check-golden",
+        format.to_string().to_lowercase()
+    ))]
+    UnsupportedFormat { format: Format },
+
+    #[snafu(display("Could not write report to {}: {}", path.display(), source))]
+    WriteOutput { path: PathBuf, source: io::Error },
+}
+
+/// The time per tag in a report, before it's written out in some format
+#[derive(Debug)]
+struct Report {
+    title: String,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    bucket: Option<Period>,
+    /// The first day of each bucket
+    buckets: Vec<NaiveDate>,
+    day_offset: Duration,
+    /// The bucket of each column, or nothing if there's only the total
+    headers: Vec<String>,
+    /// A row of durations for each tag, ending with the total of the row,
+    /// followed by a row of totals
+    rows: Vec<(String, Vec<Duration>)>,
+}
+
+/// A saved report, along with the range and options it was made with. Times
+/// are in UTC and durations in seconds, so that checking it doesn't depend on
+/// the time zone or on how reports are printed.
+#[derive(Serialize, Deserialize, Debug)]
+struct Golden {
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    bucket: Option<Period>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    filter: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    rounding: Option<String>,
+    /// How long after midnight days start, in minutes
+    #[serde(default)]
+    day_offset: i64,
+    /// The day weeks started on, since buckets are saved by name
+    week_starts_on: Weekday,
+    /// When events ended, since that changes how long they were
+    end_policy: EndPolicyConf,
+    /// The days of the week that were counted, if not all of them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    workdays: Option<Vec<Weekday>>,
+    /// When each bucket starts
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    buckets: Vec<DateTime<Utc>>,
+    rows: Vec<GoldenRow>,
+}
+
+/// The seconds spent on a tag in each bucket, followed by the row's total
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct GoldenRow {
+    tag: String,
+    seconds: Vec<i64>,
+}
+
+impl Golden {
+    fn new(
+        report: &Report,
+        bucket: Option<Period>,
+        filter: Option<&TagExpr>,
+        rounding: Option<&Rounding>,
+        workdays: Option<Vec<Weekday>>,
+        week_starts_on: Weekday,
+        end_policy: EndPolicyConf,
+    ) -> Self {
+        Self {
+            start: report.start,
+            end: report.end,
+            bucket,
+            filter: filter.map(TagExpr::to_string),
+            rounding: rounding.map(Rounding::to_string),
+            day_offset: report.day_offset.num_minutes(),
+            workdays,
+            week_starts_on,
+            end_policy,
+            buckets: report
+                .buckets
+                .iter()
+                .map(|date| aggregate::midnight(*date, &Local) + report.day_offset)
+                .collect(),
+            rows: report
+                .rows
+                .iter()
+                .map(|(tag, durations)| GoldenRow {
+                    tag: tag.clone(),
+                    seconds: durations.iter().map(Duration::num_seconds).collect(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Cmd {
+    pub fn exec(&self, timesheet: &Timesheet, conf: &Conf) -> Result<(), Error> {
+        if self.format != Format::Text
+            && (self.untracked || self.compare.is_some() || self.check_golden.is_some())
+        {
+            return UnsupportedFormat {
+                format: self.format,
+            }
+            .fail();
+        }
+        if let Some(name) = &self.check_golden {
+            let differences = check_golden(timesheet, conf, name)?;
+            if differences.is_empty() {
+                return self.write(format!("The report matches golden report \"{}\"\n", name));
+            }
+            self.write(
+                differences
+                    .iter()
+                    .map(|line| format!("{}\n", line))
+                    .collect(),
+            )?;
+            return GoldenMismatch { name }.fail();
+        }
+
+        let now = timesheet.clock().now().with_timezone(&Local);
+        let offset = conf.day_offset();
+        let start = self.start.unwrap_or_else(|| {
+            let today = (now - offset).date().naive_local();
+            let first = self
+                .compare
+                .map_or(today, |period| conf.calendar(period).start_of(today));
+            aggregate::midnight(first, &Local).with_timezone(&Local) + offset
+        });
+        let end = self.end.unwrap_or(now);
+        self.guard
+            .check(start.naive_local().date(), end.naive_local().date())
+            .context(LongRange {})?;
+        let (start, end) = (start.with_timezone(&Utc), end.with_timezone(&Utc));
+        if self.untracked {
+            // Working hours only apply on workdays, with or without --workdays
+            let days_off = DaysOff::load(conf, timesheet)
+                .context(LoadDaysOff {})?
+                .with_workdays(conf.workdays(true));
+            let end = end.min(timesheet.clock().now());
+            return self.write(render_untracked(
+                &timesheet.segments(),
+                start,
+                end,
+                self.filter.as_ref(),
+                conf.working_hours.hours,
+                &days_off,
+            ));
+        }
+        let workdays = conf.workdays(self.workdays);
+        let segments = on_workdays(timesheet.segments(), workdays.as_deref(), offset);
+        if let Some(period) = self.compare {
+            let previous = (shift_back(start, period), shift_back(end, period));
+            return self.write(render_comparison(
+                &segments,
+                previous,
+                (start, end),
+                self.filter.as_ref(),
+            ));
+        }
+        let rounding = self
+            .round
+            .or(conf.rounding)
+            .filter(|r| r.increment > Duration::zero());
+        let table = report(
+            &segments,
+            start,
+            end,
+            self.bucket.map(|bucket| conf.calendar(bucket)),
+            self.filter.as_ref(),
+            rounding.as_ref(),
+            offset,
+        );
+        let written = match self.format {
+            Format::Text => table.to_text(),
+            Format::Html => table.to_html(),
+            Format::Markdown => table.to_markdown(),
+            Format::Json => table.to_json(),
+            Format::Csv => table.to_csv(),
+        };
+        self.write(written)?;
+
+        if let Some(name) = &self.save_golden {
+            if end >= timesheet.clock().now() {
+                return UnfinishedRange { end }.fail();
+            }
+            let path = golden_path(conf, name)?;
+            let golden = Golden::new(
+                &table,
+                self.bucket,
+                self.filter.as_ref(),
+                rounding.as_ref(),
+                workdays,
+                conf.week_starts_on,
+                conf.end_policy,
+            );
+            let contents = toml::ser::to_string(&golden).context(SerializeGolden {})?;
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir).context(WriteGolden { path: path.clone() })?;
+            }
+            fs::write(&path, contents).context(WriteGolden { path: path.clone() })?;
+            println!("Saved golden report \"{}\"", name);
+        }
+        Ok(())
+    }
+
+    /// Writes `written` to the `--output` file, or prints it
+    fn write(&self, written: String) -> Result<(), Error> {
+        match &self.output {
+            Some(path) => fs::write(path, written).context(WriteOutput { path: path.clone() }),
+            None => {
+                print!("{}", written);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Where the golden report called `name` is saved. Names are kept to a single
+/// file in the goldens folder.
+fn golden_path(conf: &Conf, name: &str) -> Result<PathBuf, Error> {
+    if name.is_empty() || name.contains(&['/', '\\'][..]) || name.contains("..") {
+        return GoldenName { name }.fail();
+    }
+    Ok(conf
+        .sync_folder
+        .join("goldens")
+        .join(format!("{}.toml", name)))
+}
+
+/// The differences between the golden report called `name` and the report
+/// for its range now, as listed by `golden_differences`. The report is made
+/// with the golden's week start and end policy, so that changing them in the
+/// config doesn't count as a difference.
+fn check_golden(timesheet: &Timesheet, conf: &Conf, name: &str) -> Result<Vec<String>, Error> {
+    let path = golden_path(conf, name)?;
+    let contents = fs::read_to_string(&path).context(ReadGolden { path: path.clone() })?;
+    let golden: Golden =
+        toml::de::from_str(&contents).context(ParseGolden { path: path.clone() })?;
+    let filter: Option<TagExpr> = match &golden.filter {
+        Some(filter) => Some(
+            filter
+                .parse()
+                .context(GoldenFilter { path: path.clone() })?,
+        ),
+        None => None,
+    };
+    let rounding: Option<Rounding> = match &golden.rounding {
+        Some(rounding) => Some(
+            rounding
+                .parse()
+                .map_err(|message| Error::GoldenRounding { path, message })?,
+        ),
+        None => None,
+    };
+
+    let day_offset = Duration::minutes(golden.day_offset);
+    let timesheet = timesheet
+        .clone()
+        .with_end_policy(golden.end_policy.to_end_policy(day_offset));
+    let segments = on_workdays(timesheet.segments(), golden.workdays.as_deref(), day_offset);
+    let table = report(
+        &segments,
+        golden.start,
+        golden.end,
+        golden
+            .bucket
+            .map(|bucket| bucket.with_week_start(golden.week_starts_on)),
+        filter.as_ref(),
+        rounding.as_ref(),
+        day_offset,
+    );
+    let current = Golden::new(
+        &table,
+        golden.bucket,
+        filter.as_ref(),
+        rounding.as_ref(),
+        golden.workdays.clone(),
+        golden.week_starts_on,
+        golden.end_policy,
+    );
+    Ok(golden_differences(&golden, &current))
+}
+
+/// A line for each bucket start and row of `golden` that `current` doesn't
+/// have, starting with "-", and for each that `current` adds, starting with
+/// "+"
+fn golden_differences(golden: &Golden, current: &Golden) -> Vec<String> {
+    let mut lines = Vec::new();
+    if golden.buckets != current.buckets {
+        let starts = |buckets: &[DateTime<Utc>]| {
+            buckets
+                .iter()
+                .map(|start| start.to_rfc3339())
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        lines.push(format!("- buckets: {}", starts(&golden.buckets)));
+        lines.push(format!("+ buckets: {}", starts(&current.buckets)));
+    }
+    let row = |row: &GoldenRow| {
+        let durations: Vec<String> = row
+            .seconds
+            .iter()
+            .map(|seconds| format_duration(Duration::seconds(*seconds)))
+            .collect();
+        format!("{}: {}", row.tag, durations.join(", "))
+    };
+    for old in golden.rows.iter().filter(|r| !current.rows.contains(r)) {
+        lines.push(format!("- {}", row(old)));
+    }
+    for new in current.rows.iter().filter(|r| !golden.rows.contains(r)) {
+        lines.push(format!("+ {}", row(new)));
+    }
+    lines
+}
+
+/// `segments`, without those that start on a day that isn't one of
+/// `workdays`, if there are any. Days start `day_offset` after midnight.
+fn on_workdays(
+    segments: Vec<Segment>,
+    workdays: Option<&[Weekday]>,
+    day_offset: Duration,
+) -> Vec<Segment> {
+    match workdays {
+        Some(workdays) => segments
+            .into_iter()
+            .filter(|s| {
+                let day = (s.start_time.with_timezone(&Local) - day_offset).weekday();
+                workdays.contains(&day)
+            })
+            .collect(),
+        None => segments,
+    }
+}
+
+/// The time spent on each tag between `start` and `end`, followed by the
+/// total. Only the parts of events inside the range are counted, so the
+/// report for a range that has ended doesn't change unless the data does.
+/// With a bucket, there is a column for each period in the range.
+/// Rounding is done per bucket, and buckets start `day_offset` after midnight.
+fn report(
+    segments: &[Segment],
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    bucket: Option<Period>,
+    filter: Option<&TagExpr>,
+    rounding: Option<&Rounding>,
+    day_offset: Duration,
+) -> Report {
+    let segments: Vec<Segment> = segments
+        .iter()
+        .filter(|s| filter.is_none_or(|f| f.matches(&s.tags)))
+        .cloned()
+        .collect();
+
+    let mut title = format!(
+        "Report {} to {}",
+        start.with_timezone(&Local).format("%Y-%m-%d %H:%M"),
+        end.with_timezone(&Local).format("%Y-%m-%d %H:%M")
+    );
+    if let Some(bucket) = bucket {
+        write!(title, ", by {}", bucket).unwrap();
+    }
+    if let Some(filter) = filter {
+        write!(title, ", for \"{}\"", filter).unwrap();
+    }
+    if let Some(rounding) = rounding {
+        write!(title, ", rounded to {}", rounding).unwrap();
+    }
+
+    let (headers, rows) = match bucket {
+        None => {
+            let Totals {
+                groups: tag_totals,
+                total,
+            } = match rounding {
+                Some(rounding) => aggregate::by_tag_rounded(&segments, start, end, rounding),
+                None => aggregate::by_tag(&segments, start, end),
+            };
+            let rows = tag_totals
+                .into_iter()
+                .map(|(tag, duration)| (tag, vec![duration]))
+                .chain(Some((String::from("Total"), vec![total])))
+                .collect();
+            (Vec::new(), rows)
+        }
+        Some(bucket) => bucket_table(&segments, start, end, bucket, rounding, day_offset),
+    };
+    Report {
+        title,
+        start,
+        end,
+        bucket,
+        buckets: bucket.map_or_else(Vec::new, |bucket| {
+            bucket_starts(start, end, bucket, day_offset)
+        }),
+        day_offset,
+        headers,
+        rows,
+    }
+}
+
+impl Report {
+    fn to_text(&self) -> String {
+        let mut out = format!("{}\n", self.title);
+        write_table(&mut out, &self.headers, &self.rows);
+        out
+    }
+
+    /// The title in bold, above a table. The total row's label is in bold too.
+    fn to_markdown(&self) -> String {
+        let mut headers = vec!["Tag"];
+        if self.headers.is_empty() {
+            headers.push("Time");
+        } else {
+            headers.extend(self.headers.iter().map(String::as_str));
+        }
+        let rows: Vec<Vec<String>> = self
+            .rows
+            .iter()
+            .enumerate()
+            .map(|(index, (label, durations))| {
+                let label = if index + 1 == self.rows.len() {
+                    format!("**{}**", label)
+                } else {
+                    label.clone()
+                };
+                Some(label)
+                    .into_iter()
+                    .chain(
+                        durations
+                            .iter()
+                            .map(|duration| format_cell(*duration, &self.headers)),
+                    )
+                    .collect()
+            })
+            .collect();
+        format!("**{}**\n\n{}", self.title, markdown::table(&headers, &rows))
+    }
+
+    fn to_csv(&self) -> String {
+        buckets_csv(
+            &self.buckets,
+            self.bucket,
+            self.start,
+            self.end,
+            self.day_offset,
+            &self.rows,
+        )
+    }
+
+    fn to_json(&self) -> String {
+        #[derive(Serialize)]
+        struct ReportJson<'a> {
+            title: &'a str,
+            start: String,
+            end: String,
+            #[serde(flatten)]
+            table: BucketsJson,
+        }
+        json::to_string(&ReportJson {
+            title: &self.title,
+            start: json::time(self.start),
+            end: json::time(self.end),
+            table: BucketsJson::new(&self.buckets, &self.rows),
+        })
+    }
+
+    /// A standalone page with the table and a bar for each tag's total
+    fn to_html(&self) -> String {
+        let title = escape_html(&self.title);
+        let cell = |duration: &Duration| escape_html(&format_cell(*duration, &self.headers));
+        let mut out = String::new();
+        writeln!(out, "<!DOCTYPE html>").unwrap();
+        writeln!(out, "<html>").unwrap();
+        writeln!(out, "<head>").unwrap();
+        writeln!(out, "<meta charset=\"utf-8\">").unwrap();
+        writeln!(out, "<title>{}</title>", title).unwrap();
+        writeln!(
+            out,
+            "<style>\n\
+             body {{ font-family: sans-serif; margin: 2em; }}\n\
+             table {{ border-collapse: collapse; margin-bottom: 2em; }}\n\
+             th, td {{ padding: 0.25em 0.75em; text-align: left; border-bottom: 1px solid #ddd; }}\n\
+             tr.total td {{ font-weight: bold; }}\n\
+             .bar {{ background: #4a90d9; height: 1em; }}\n\
+             </style>"
+        )
+        .unwrap();
+        writeln!(out, "</head>").unwrap();
+        writeln!(out, "<body>").unwrap();
+        writeln!(out, "<h1>{}</h1>", title).unwrap();
+
+        writeln!(out, "<table>").unwrap();
+        if !self.headers.is_empty() {
+            let headers: String = self
+                .headers
+                .iter()
+                .map(|header| format!("<th>{}</th>", escape_html(header)))
+                .collect();
+            writeln!(out, "<tr><th></th>{}</tr>", headers).unwrap();
+        }
+        for (index, (label, durations)) in self.rows.iter().enumerate() {
+            let class = if index + 1 == self.rows.len() {
+                " class=\"total\""
+            } else {
+                ""
+            };
+            let cells: String = durations
+                .iter()
+                .map(|duration| format!("<td>{}</td>", cell(duration)))
+                .collect();
+            writeln!(
+                out,
+                "<tr{}><td>{}</td>{}</tr>",
+                class,
+                escape_html(label),
+                cells
+            )
+            .unwrap();
+        }
+        writeln!(out, "</table>").unwrap();
+
+        // The last row is the total, and the last column of each row is the
+        // row's total
+        let tags = &self.rows[..self.rows.len().saturating_sub(1)];
+        let total =
+            |durations: &Vec<Duration>| durations.last().copied().unwrap_or_else(Duration::zero);
+        let most = tags
+            .iter()
+            .map(|(_, durations)| total(durations).num_seconds())
+            .max()
+            .unwrap_or(0)
+            .max(1);
+        writeln!(out, "<table>").unwrap();
+        for (label, durations) in tags {
+            let duration = total(durations);
+            writeln!(
+                out,
+                "<tr><td>{}</td><td><div class=\"bar\" style=\"width: {}px\"></div></td><td>{}</td></tr>",
+                escape_html(label),
+                duration.num_seconds().max(0) * HTML_BAR_WIDTH / most,
+                escape_html(&format_duration(duration))
+            )
+            .unwrap();
+        }
+        writeln!(out, "</table>").unwrap();
+        writeln!(out, "</body>").unwrap();
+        writeln!(out, "</html>").unwrap();
+        out
+    }
+}
+
+/// A table of the time spent on each tag in each `bucket` between `start`
+/// and `end`, with a total for each row and column. Every period in the range
+/// gets a column, even if nothing was tracked in it. Periods start
+/// `day_offset` after midnight.
+pub(crate) fn write_buckets(
+    out: &mut String,
+    segments: &[Segment],
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    bucket: Period,
+    rounding: Option<&Rounding>,
+    day_offset: Duration,
+) {
+    let (headers, rows) = bucket_table(segments, start, end, bucket, rounding, day_offset);
+    write_table(out, &headers, &rows);
+}
+
+/// The headers and rows of the table that `write_buckets` writes
+pub(crate) fn bucket_table(
+    segments: &[Segment],
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    bucket: Period,
+    rounding: Option<&Rounding>,
+    day_offset: Duration,
+) -> (Vec<String>, Vec<(String, Vec<Duration>)>) {
+    let periods = bucket_starts(start, end, bucket, day_offset);
+    let segments = &aggregate::shift(segments, day_offset);
+    let (start, end) = (start - day_offset, end - day_offset);
+    let buckets = match rounding {
+        Some(rounding) => {
+            aggregate::by_period_and_tag_rounded(segments, start, end, bucket, &Local, rounding)
+        }
+        None => aggregate::by_period_and_tag(segments, start, end, bucket, &Local),
+    };
+    let empty = Totals::new();
+    let column = |date| buckets.get(date).unwrap_or(&empty);
+    let tags: BTreeSet<&Tag> = buckets
+        .values()
+        .flat_map(|totals| totals.groups.keys())
+        .collect();
+
+    let mut headers: Vec<String> = periods
+        .iter()
+        .map(|date| match bucket {
+            Period::Day => date.format("%a %m-%d").to_string(),
+            Period::Week(_) => date.format("W %m-%d").to_string(),
+            Period::Month => date.format("%Y-%m").to_string(),
+        })
+        .collect();
+    headers.push(String::from("Total"));
+    let row = |durations: Vec<Duration>| {
+        let total = durations.iter().fold(Duration::zero(), |sum, d| sum + *d);
+        durations.into_iter().chain(Some(total)).collect::<Vec<_>>()
+    };
+    let rows = tags
+        .iter()
+        .map(|tag| {
+            let durations = periods
+                .iter()
+                .map(|date| {
+                    column(date)
+                        .groups
+                        .get(*tag)
+                        .copied()
+                        .unwrap_or_else(Duration::zero)
+                })
+                .collect();
+            (tag.to_string(), row(durations))
+        })
+        .chain(Some((
+            String::from("Total"),
+            row(periods.iter().map(|date| column(date).total).collect()),
+        )))
+        .collect();
+    (headers, rows)
+}
+
+/// The first day of each of the columns of `bucket_table`
+pub(crate) fn bucket_starts(
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    bucket: Period,
+    day_offset: Duration,
+) -> Vec<NaiveDate> {
+    let local_date = |time: DateTime<Utc>| {
+        (time - day_offset)
+            .with_timezone(&Local)
+            .naive_local()
+            .date()
+    };
+    let last_day = local_date(end - Duration::nanoseconds(1));
+    let mut periods = Vec::new();
+    let mut period = bucket.start_of(local_date(start));
+    while period <= last_day {
+        periods.push(period);
+        period = bucket.next(period);
+    }
+    periods
+}
+
+/// The part of the range from `start` to `end` in the `period` starting on
+/// `first`, where days start `day_offset` after midnight
+pub(crate) fn bucket_range(
+    first: NaiveDate,
+    period: Period,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    day_offset: Duration,
+) -> (DateTime<Utc>, DateTime<Utc>) {
+    let bucket_start = |date| aggregate::midnight(date, &Local) + day_offset;
+    (
+        bucket_start(first).max(start),
+        bucket_start(period.next(first)).min(end),
+    )
+}
+
+/// A CSV row for the time of each tag in each bucket that has some, without
+/// the totals. `rows` are from `bucket_table`, or from a report without
+/// buckets if there's no `bucket`.
+pub(crate) fn buckets_csv(
+    buckets: &[NaiveDate],
+    bucket: Option<Period>,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    day_offset: Duration,
+    rows: &[(String, Vec<Duration>)],
+) -> String {
+    let ranges: Vec<(DateTime<Utc>, DateTime<Utc>)> = match bucket {
+        None => vec![(start, end)],
+        Some(bucket) => buckets
+            .iter()
+            .map(|first| bucket_range(*first, bucket, start, end, day_offset))
+            .collect(),
+    };
+    let mut out = String::from(csv::HEADER);
+    let (_, tags) = rows.split_last().expect("tables end with a row of totals");
+    for (index, (bucket_start, bucket_end)) in ranges.iter().enumerate() {
+        for (tag, durations) in tags {
+            if durations[index].num_seconds() > 0 {
+                out.push_str(&csv::row(
+                    *bucket_start,
+                    *bucket_end,
+                    durations[index],
+                    [tag],
+                ));
+            }
+        }
+    }
+    out
+}
+
+/// The time per tag in each bucket, as it's written in JSON
+#[derive(Serialize, Debug)]
+pub(crate) struct BucketsJson {
+    /// The first day of each bucket, or nothing if there's only the total
+    buckets: Vec<String>,
+    tags: Vec<TagJson>,
+    pub(crate) total_seconds: i64,
+    /// The total of each bucket
+    bucket_seconds: Vec<i64>,
+}
+
+#[derive(Serialize, Debug)]
+struct TagJson {
+    tag: Tag,
+    seconds: i64,
+    /// The time in each bucket
+    buckets: Vec<i64>,
+}
+
+impl BucketsJson {
+    /// The JSON for the `rows` of `bucket_table`, or of a report without
+    /// buckets if `buckets` is empty
+    pub(crate) fn new(buckets: &[NaiveDate], rows: &[(String, Vec<Duration>)]) -> Self {
+        let split = |durations: &[Duration]| {
+            let seconds: Vec<i64> = durations.iter().map(Duration::num_seconds).collect();
+            let (total, buckets) = seconds.split_last().expect("rows end with their total");
+            (*total, buckets.to_vec())
+        };
+        let (totals, tags) = rows.split_last().expect("tables end with a row of totals");
+        let tags = tags
+            .iter()
+            .map(|(tag, durations)| {
+                let (seconds, buckets) = split(durations);
+                TagJson {
+                    tag: tag.clone(),
+                    seconds,
+                    buckets,
+                }
+            })
+            .collect();
+        let (total_seconds, bucket_seconds) = split(&totals.1);
+        Self {
+            buckets: buckets
+                .iter()
+                .map(|date| date.format("%Y-%m-%d").to_string())
+                .collect(),
+            tags,
+            total_seconds,
+            bucket_seconds,
+        }
+    }
+}
+
+/// For each workday of `days_off` between `start` and `end`, the parts of the
+/// working `hours` that no event covered. Days off are listed but not counted.
+fn render_untracked(
+    segments: &[Segment],
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    filter: Option<&TagExpr>,
+    hours: (NaiveTime, NaiveTime),
+    days_off: &DaysOff,
+) -> String {
+    let segments: Vec<Segment> = segments
+        .iter()
+        .filter(|s| filter.is_none_or(|f| f.matches(&s.tags)))
+        .cloned()
+        .collect();
+    let (from, to) = hours;
+    let local_time = |date: NaiveDate, time| {
+        Local
+            .from_local_datetime(&date.and_time(time))
+            .earliest()
+            .map(|t| t.with_timezone(&Utc))
+    };
+
+    let mut out = String::new();
+    write!(
+        out,
+        "Untracked time {} to {}, within {}-{}",
+        start.with_timezone(&Local).format("%Y-%m-%d %H:%M"),
+        end.with_timezone(&Local).format("%Y-%m-%d %H:%M"),
+        from.format("%H:%M"),
+        to.format("%H:%M")
+    )
+    .unwrap();
+    if let Some(filter) = filter {
+        write!(out, ", for \"{}\"", filter).unwrap();
+    }
+    writeln!(out).unwrap();
+
+    let mut total = Duration::zero();
+    let mut date = start.with_timezone(&Local).naive_local().date();
+    while local_time(date, from).is_none_or(|time| time < end) {
+        let day = date;
+        date = date.succ();
+        if !days_off.is_workday(day) {
+            continue;
+        }
+        if let Some(kind) = days_off.kind(day) {
+            writeln!(out, "{} {}", day.format("%a %m-%d"), kind.name()).unwrap();
+            continue;
+        }
+        let (day_start, day_end) = match (local_time(day, from), local_time(day, to)) {
+            (Some(day_start), Some(day_end)) => (day_start.max(start), day_end.min(end)),
+            _ => continue,
+        };
+        if day_start >= day_end {
+            continue;
+        }
+        let gaps = aggregate::gaps(&segments, day_start, day_end);
+        let untracked = gaps
+            .iter()
+            .fold(Duration::zero(), |sum, (gap_start, gap_end)| {
+                sum + (*gap_end - *gap_start)
+            });
+        total = total + untracked;
+        let times: Vec<String> = gaps
+            .iter()
+            .map(|(gap_start, gap_end)| {
+                format!(
+                    "{}-{}",
+                    gap_start.with_timezone(&Local).format("%H:%M"),
+                    gap_end.with_timezone(&Local).format("%H:%M")
+                )
+            })
+            .collect();
+        let line = format!(
+            "{} {: <8} {}",
+            day.format("%a %m-%d"),
+            format_duration(untracked),
+            times.join(", ")
+        );
+        writeln!(out, "{}", line.trim_end()).unwrap();
+    }
+    writeln!(out, "Total     {}", format_duration(total)).unwrap();
+    out
+}
+
+/// The time per tag in the range `before` next to the range `after`, with
+/// how much it changed
+pub(crate) fn render_comparison(
+    segments: &[Segment],
+    before: (DateTime<Utc>, DateTime<Utc>),
+    after: (DateTime<Utc>, DateTime<Utc>),
+    filter: Option<&TagExpr>,
+) -> String {
+    let segments: Vec<Segment> = segments
+        .iter()
+        .filter(|s| filter.is_none_or(|f| f.matches(&s.tags)))
+        .cloned()
+        .collect();
+    let before_totals = aggregate::by_tag(&segments, before.0, before.1);
+    let after_totals = aggregate::by_tag(&segments, after.0, after.1);
+
+    let format_range = |(start, end): (DateTime<Utc>, DateTime<Utc>)| {
+        format!(
+            "{} to {}",
+            start.with_timezone(&Local).format("%Y-%m-%d %H:%M"),
+            end.with_timezone(&Local).format("%Y-%m-%d %H:%M")
+        )
+    };
+    let mut out = String::new();
+    write!(
+        out,
+        "Report {} compared to {}",
+        format_range(after),
+        format_range(before)
+    )
+    .unwrap();
+    if let Some(filter) = filter {
+        write!(out, ", for \"{}\"", filter).unwrap();
+    }
+    writeln!(out).unwrap();
+
+    let tags: BTreeSet<&Tag> = before_totals
+        .groups
+        .keys()
+        .chain(after_totals.groups.keys())
+        .collect();
+    let get = |totals: &Totals<Tag>, tag: &Tag| {
+        totals
+            .groups
+            .get(tag)
+            .copied()
+            .unwrap_or_else(Duration::zero)
+    };
+    let rows: Vec<(&str, Vec<String>)> = tags
+        .iter()
+        .map(|tag| {
+            let change = (get(&before_totals, tag), get(&after_totals, tag));
+            (tag.as_str(), change)
+        })
+        .chain(Some(("Total", (before_totals.total, after_totals.total))))
+        .map(|(label, (before, after))| {
+            let cells = vec![
+                format_duration(before),
+                format_duration(after),
+                format_signed(after - before),
+                format_percent_change(before, after),
+            ];
+            (label, cells)
+        })
+        .collect();
+    let headers: Vec<String> = ["Before", "After", "Change", "%"]
+        .iter()
+        .map(|header| header.to_string())
+        .collect();
+    write_rows(&mut out, &headers, &rows);
+    out
+}
+
+/// The same local time one `period` before `time`. Days missing from the
+/// month before, like the 31st, become its last day.
+fn shift_back(time: DateTime<Utc>, period: Period) -> DateTime<Utc> {
+    let local = time.with_timezone(&Local).naive_local();
+    let date = local.date();
+    let shifted = match period {
+        Period::Day => date.pred(),
+        Period::Week(_) => date - Duration::weeks(1),
+        Period::Month => {
+            let first = Period::Month.start_of(date);
+            let previous = Period::Month.start_of(first.pred());
+            let last = first.pred();
+            previous
+                .with_day(date.day().min(last.day()))
+                .unwrap_or(previous)
+        }
+    };
+    Local
+        .from_local_datetime(&shifted.and_time(local.time()))
+        .earliest()
+        .map_or(time - (date - shifted), |t| t.with_timezone(&Utc))
+}
+
+/// How much `after` differs from `before`, like "+25%", or "new" if there
+/// was nothing before
+fn format_percent_change(before: Duration, after: Duration) -> String {
+    if before == Duration::zero() {
+        return String::from(if after == Duration::zero() {
+            "0%"
+        } else {
+            "new"
+        });
+    }
+    let before = before.num_seconds() as f64;
+    let after = after.num_seconds() as f64;
+    format!("{:+.0}%", (after - before) * 100.0 / before)
+}
+
+/// Write `rows` of durations under `headers`, with the row labels in the
+/// first column
+fn write_table(out: &mut String, headers: &[String], rows: &[(String, Vec<Duration>)]) {
+    let rows: Vec<(&str, Vec<String>)> = rows
+        .iter()
+        .map(|(label, durations)| {
+            let cells = durations
+                .iter()
+                .map(|duration| format_cell(*duration, headers))
+                .collect();
+            (label.as_str(), cells)
+        })
+        .collect();
+    write_rows(out, headers, &rows);
+}
+
+/// A duration in a table. Empty cells of tables with buckets are shown as
+/// "-".
+pub(crate) fn format_cell(duration: Duration, headers: &[String]) -> String {
+    if duration == Duration::zero() && !headers.is_empty() {
+        String::from("-")
+    } else {
+        format_duration(duration)
+    }
+}
+
+/// Write `rows` of cells under `headers` with each column lined up
+fn write_rows(out: &mut String, headers: &[String], rows: &[(&str, Vec<String>)]) {
+    let label_width = rows.iter().map(|(label, _)| label.len()).max().unwrap_or(0);
+    let cells: Vec<&Vec<String>> = rows.iter().map(|(_, cells)| cells).collect();
+    let widths: Vec<usize> = (0..cells.first().map(|row| row.len()).unwrap_or(0))
+        .map(|column| {
+            cells
+                .iter()
+                .map(|row| row[column].len())
+                .chain(headers.get(column).map(String::len))
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+
+    if !headers.is_empty() {
+        let mut line = format!("{: <width$}", "", width = label_width);
+        for (header, width) in headers.iter().zip(widths.iter()) {
+            write!(line, " {: <width$}", header, width = width).unwrap();
+        }
+        writeln!(out, "{}", line.trim_end()).unwrap();
+    }
+    for ((label, _), row) in rows.iter().zip(cells.iter()) {
+        let mut line = format!("{: <width$}", label, width = label_width);
+        for (cell, width) in row.iter().zip(widths.iter()) {
+            write!(line, " {: <width$}", cell, width = width).unwrap();
+        }
+        writeln!(out, "{}", line.trim_end()).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::WorkingHours;
+    use crate::testing::{self, Tracked};
+    use augr_core::Patch;
+    use chrono::TimeZone;
+
+    /// The report as text, the way it's printed
+    fn render(
+        timesheet: &Timesheet,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        bucket: Option<Period>,
+        filter: Option<&TagExpr>,
+        rounding: Option<&Rounding>,
+        day_offset: Duration,
+    ) -> String {
+        report(
+            &timesheet.segments(),
+            start,
+            end,
+            bucket,
+            filter,
+            rounding,
+            day_offset,
+        )
+        .to_text()
+    }
+
+    #[test]
+    fn report_only_counts_time_in_range() {
+        let time = |h| Utc.ymd(2019, 7, 24).and_hms(h, 0, 0);
+        let create = Patch::new()
+            .create_event("a".into(), time(8), vec!["work".into()])
+            .create_event("b".into(), time(12), vec!["lunch".into(), "break".into()])
+            .create_event("c".into(), time(13), vec!["work".into()]);
+        let mut tracked = Tracked::new(&[&create]).at(time(20));
+        let timesheet = tracked.timesheet();
+
+        let report = render(
+            &timesheet,
+            time(9),
+            time(14),
+            None,
+            None,
+            None,
+            Duration::zero(),
+        );
+        let lines: Vec<&str> = report.lines().skip(1).collect();
+        assert_eq!(
+            lines,
+            vec!["break 1h 0m", "lunch 1h 0m", "work  4h 0m", "Total 5h 0m"]
+        );
+
+        // Events after the range don't change it
+        let later = Patch::new().create_event("d".into(), time(15), vec!["home".into()]);
+        tracked.apply(&later);
+        let timesheet = tracked.timesheet();
+        assert_eq!(
+            render(
+                &timesheet,
+                time(9),
+                time(14),
+                None,
+                None,
+                None,
+                Duration::zero()
+            ),
+            report
+        );
+    }
+
+    #[test]
+    fn report_rounds_to_increments() {
+        let time = |h, m| Utc.ymd(2019, 7, 24).and_hms(h, m, 0);
+        let create = Patch::new()
+            .create_event("a".into(), time(9, 0), vec!["work".into()])
+            .create_event("b".into(), time(9, 20), vec!["email".into()])
+            .create_event("c".into(), time(9, 25), vec!["work".into()])
+            .create_event("d".into(), time(9, 50), vec![]);
+        let tracked = Tracked::new(&[&create]).at(time(20, 0));
+        let timesheet = tracked.timesheet();
+
+        let report = |rounding: &str| {
+            let rounding: Rounding = rounding.parse().unwrap();
+            let report = render(
+                &timesheet,
+                time(9, 0),
+                time(10, 0),
+                None,
+                None,
+                Some(&rounding),
+                Duration::zero(),
+            );
+            report.lines().skip(1).map(String::from).collect::<Vec<_>>()
+        };
+        assert_eq!(report("15m"), vec!["email 0m", "work  45m", "Total 45m"]);
+        assert_eq!(
+            report("15m up per-event"),
+            vec!["email 15m", "work  1h 0m", "Total 1h 15m"]
+        );
+    }
+
+    #[test]
+    fn report_buckets_by_day() {
+        let time = |d, h| Local.ymd(2019, 7, d).and_hms(h, 0, 0).with_timezone(&Utc);
+        let create = Patch::new()
+            .create_event("a".into(), time(1, 9), vec!["work".into()])
+            .create_event("b".into(), time(1, 12), vec!["lunch".into()])
+            .create_event("c".into(), time(1, 13), vec![])
+            .create_event(
+                "d".into(),
+                time(3, 9),
+                vec!["work".into(), "meeting".into()],
+            )
+            .create_event("e".into(), time(3, 10), vec![]);
+        let tracked = Tracked::new(&[&create]).at(time(5, 0));
+        let timesheet = tracked.timesheet();
+
+        let filter: TagExpr = "not lunch".parse().unwrap();
+        let report = render(
+            &timesheet,
+            time(1, 0),
+            time(4, 0),
+            Some(Period::Day),
+            Some(&filter),
+            None,
+            Duration::zero(),
+        );
+        let lines: Vec<&str> = report.lines().skip(1).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "        Mon 07-01 Tue 07-02 Wed 07-03 Total",
+                "meeting -         -         1h 0m     1h 0m",
+                "work    3h 0m     -         1h 0m     4h 0m",
+                "Total   3h 0m     -         1h 0m     4h 0m",
+            ]
+        );
+    }
+
+    #[test]
+    fn report_days_start_after_midnight() {
+        let time = |d, h| Local.ymd(2019, 7, d).and_hms(h, 0, 0).with_timezone(&Utc);
+        let create = Patch::new()
+            .create_event("a".into(), time(1, 22), vec!["work".into()])
+            .create_event("b".into(), time(2, 2), vec![])
+            .create_event("c".into(), time(2, 9), vec!["work".into()])
+            .create_event("d".into(), time(2, 10), vec![]);
+        let tracked = Tracked::new(&[&create]).at(time(5, 0));
+        let timesheet = tracked.timesheet();
+
+        let report = render(
+            &timesheet,
+            time(1, 4),
+            time(3, 4),
+            Some(Period::Day),
+            None,
+            None,
+            Duration::hours(4),
+        );
+        assert_eq!(
+            report.lines().skip(1).collect::<Vec<_>>(),
+            vec![
+                "      Mon 07-01 Tue 07-02 Total",
+                "work  4h 0m     1h 0m     5h 0m",
+                "Total 4h 0m     1h 0m     5h 0m",
+            ]
+        );
+    }
+
+    #[test]
+    fn workdays_leave_weekends_out() {
+        // 2019-07-06 is a Saturday
+        let time = |d, h| Local.ymd(2019, 7, d).and_hms(h, 0, 0).with_timezone(&Utc);
+        let create = Patch::new()
+            .create_event("a".into(), time(5, 9), vec!["work".into()])
+            .create_event("b".into(), time(5, 11), vec![])
+            .create_event("c".into(), time(6, 9), vec!["garden".into()])
+            .create_event("d".into(), time(6, 12), vec![]);
+        let tracked = Tracked::new(&[&create]).at(time(8, 0));
+        let timesheet = tracked.timesheet();
+
+        let workdays = WorkingHours::default().weekdays;
+        let segments = on_workdays(timesheet.segments(), Some(&workdays), Duration::zero());
+        let table = report(
+            &segments,
+            time(5, 0),
+            time(7, 0),
+            None,
+            None,
+            None,
+            Duration::zero(),
+        );
+        assert_eq!(
+            table.to_text().lines().skip(1).collect::<Vec<_>>(),
+            vec!["work  2h 0m", "Total 2h 0m"]
+        );
+    }
+
+    #[test]
+    fn untracked_working_hours() {
+        let time = |d, h, m| Local.ymd(2019, 7, d).and_hms(h, m, 0).with_timezone(&Utc);
+        // 2019-07-05 is a Friday
+        let create = Patch::new()
+            .create_event("a".into(), time(5, 9, 30), vec!["work".into()])
+            .create_event("b".into(), time(5, 12, 0), vec![])
+            .create_event("c".into(), time(5, 13, 0), vec!["work".into()])
+            .create_event("d".into(), time(5, 16, 0), vec![])
+            .create_event("e".into(), time(6, 10, 0), vec!["garden".into()])
+            .create_event("f".into(), time(6, 11, 0), vec![])
+            .create_event("g".into(), time(8, 8, 0), vec!["work".into()]);
+        let tracked = Tracked::new(&[&create]).at(time(8, 14, 0));
+        let timesheet = tracked.timesheet();
+
+        let working_hours = WorkingHours::default();
+        let report = render_untracked(
+            &timesheet.segments(),
+            time(5, 0, 0),
+            time(8, 14, 0),
+            None,
+            working_hours.hours,
+            &DaysOff::default().with_workdays(Some(working_hours.weekdays)),
+        );
+        assert_eq!(
+            report.lines().skip(1).collect::<Vec<_>>(),
+            vec![
+                "Fri 07-05 2h 30m   09:00-09:30, 12:00-13:00, 16:00-17:00",
+                "Mon 07-08 0m",
+                "Total     2h 30m",
+            ]
+        );
+    }
+
+    #[test]
+    fn compare_with_previous_day() {
+        let time = |d, h| Utc.ymd(2019, 7, d).and_hms(h, 0, 0);
+        let create = Patch::new()
+            .create_event("a".into(), time(1, 9), vec!["work".into()])
+            .create_event("b".into(), time(1, 13), vec!["meeting".into()])
+            .create_event("c".into(), time(1, 14), vec![])
+            .create_event("d".into(), time(2, 9), vec!["work".into()])
+            .create_event("e".into(), time(2, 12), vec!["meeting".into()])
+            .create_event("f".into(), time(2, 14), vec!["lunch".into()])
+            .create_event("g".into(), time(2, 15), vec![]);
+        let tracked = Tracked::new(&[&create]).at(time(3, 0));
+        let timesheet = tracked.timesheet();
+
+        let local = |d, h| Local.ymd(2019, 7, d).and_hms(h, 0, 0).with_timezone(&Utc);
+        assert_eq!(shift_back(local(31, 9), Period::Day), local(30, 9));
+        assert_eq!(
+            shift_back(local(31, 9), Period::Week(Weekday::Mon)),
+            local(24, 9)
+        );
+        assert_eq!(
+            shift_back(local(31, 9), Period::Month),
+            Local.ymd(2019, 6, 30).and_hms(9, 0, 0).with_timezone(&Utc)
+        );
+
+        let report = render_comparison(
+            &timesheet.segments(),
+            (time(1, 0), time(2, 0)),
+            (time(2, 0), time(3, 0)),
+            None,
+        );
+        assert_eq!(
+            report.lines().skip(1).collect::<Vec<_>>(),
+            vec![
+                "        Before After Change %",
+                "lunch   0m     1h 0m +1h 0m new",
+                "meeting 1h 0m  2h 0m +1h 0m +100%",
+                "work    4h 0m  3h 0m -1h 0m -25%",
+                "Total   5h 0m  6h 0m +1h 0m +20%",
+            ]
+        );
+    }
+
+    #[test]
+    fn report_as_html() {
+        let time = |h| Utc.ymd(2019, 7, 24).and_hms(h, 0, 0);
+        let create = Patch::new()
+            .create_event("a".into(), time(9), vec!["work".into()])
+            .create_event("b".into(), time(12), vec!["<lunch>".into()])
+            .create_event("c".into(), time(13), vec![]);
+        let tracked = Tracked::new(&[&create]).at(time(20));
+        let timesheet = tracked.timesheet();
+
+        let html = report(
+            &timesheet.segments(),
+            time(9),
+            time(14),
+            None,
+            None,
+            None,
+            Duration::zero(),
+        )
+        .to_html();
+        let lines: Vec<&str> = html.lines().collect();
+        assert!(lines.contains(&"<tr><td>&lt;lunch&gt;</td><td>1h 0m</td></tr>"));
+        assert!(lines.contains(&"<tr class=\"total\"><td>Total</td><td>4h 0m</td></tr>"));
+        assert!(lines.contains(
+            &"<tr><td>work</td><td><div class=\"bar\" style=\"width: 300px\"></div></td><td>3h 0m</td></tr>"
+        ));
+        assert!(lines.contains(
+            &"<tr><td>&lt;lunch&gt;</td><td><div class=\"bar\" style=\"width: 100px\"></div></td><td>1h 0m</td></tr>"
+        ));
+    }
+
+    #[test]
+    fn report_as_markdown() {
+        let time = |h| Utc.ymd(2019, 7, 24).and_hms(h, 0, 0);
+        let create = Patch::new()
+            .create_event("a".into(), time(9), vec!["work".into()])
+            .create_event("b".into(), time(12), vec![]);
+        let tracked = Tracked::new(&[&create]).at(time(20));
+        let timesheet = tracked.timesheet();
+
+        let markdown = report(
+            &timesheet.segments(),
+            time(9),
+            time(14),
+            None,
+            None,
+            None,
+            Duration::zero(),
+        )
+        .to_markdown();
+        assert_eq!(
+            markdown.lines().skip(1).collect::<Vec<_>>(),
+            vec![
+                "",
+                "| Tag | Time |",
+                "| --- | --- |",
+                "| work | 3h 0m |",
+                "| **Total** | 3h 0m |",
+            ]
+        );
+    }
+
+    #[test]
+    fn report_as_json() {
+        let time = |d, h| Local.ymd(2019, 7, d).and_hms(h, 0, 0).with_timezone(&Utc);
+        let create = Patch::new()
+            .create_event("a".into(), time(1, 9), vec!["work".into()])
+            .create_event("b".into(), time(1, 12), vec![])
+            .create_event(
+                "c".into(),
+                time(2, 9),
+                vec!["work".into(), "meeting".into()],
+            )
+            .create_event("d".into(), time(2, 10), vec![]);
+        let tracked = Tracked::new(&[&create]).at(time(5, 0));
+        let timesheet = tracked.timesheet();
+
+        let json = report(
+            &timesheet.segments(),
+            time(1, 0),
+            time(3, 0),
+            Some(Period::Day),
+            None,
+            None,
+            Duration::zero(),
+        )
+        .to_json();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["version"], 1);
+        assert_eq!(value["start"], json::time(time(1, 0)));
+        assert_eq!(
+            value["buckets"],
+            serde_json::json!(["2019-07-01", "2019-07-02"])
+        );
+        assert_eq!(
+            value["tags"],
+            serde_json::json!([
+                { "tag": "meeting", "seconds": 3600, "buckets": [0, 3600] },
+                { "tag": "work", "seconds": 14400, "buckets": [10800, 3600] },
+            ])
+        );
+        assert_eq!(value["total_seconds"], 14400);
+        assert_eq!(value["bucket_seconds"], serde_json::json!([10800, 3600]));
+    }
+
+    #[test]
+    fn golden_names_stay_in_the_goldens_folder() {
+        let conf = testing::conf("");
+        assert_eq!(
+            golden_path(&conf, "2019-q3").unwrap(),
+            PathBuf::from("/tmp/goldens/2019-q3.toml")
+        );
+        for name in &["../config", "a/b", "a\\b", "..", ""] {
+            assert!(golden_path(&conf, name).is_err(), "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn goldens_are_checked_with_their_own_calendar_and_end_policy() {
+        let root = std::env::temp_dir().join(format!("augr-goldens-{}", std::process::id()));
+        let time = |d, h| Utc.ymd(2019, 7, d).and_hms(h, 0, 0);
+        let create = Patch::new()
+            .create_event("a".into(), time(23, 9), vec!["work".into()])
+            .create_event("b".into(), time(24, 9), vec!["work".into()]);
+        let tracked = Tracked::new(&[&create]).at(time(25, 9));
+
+        let mut saved_with = testing::conf("end_policy = \"1h\"\nweek_starts_on = \"Sun\"");
+        saved_with.sync_folder = root.clone();
+        let end_policy = saved_with.end_policy.to_end_policy(Duration::zero());
+        let table = report(
+            &tracked.timesheet().with_end_policy(end_policy).segments(),
+            time(21, 0),
+            time(28, 0),
+            Some(saved_with.week()),
+            None,
+            None,
+            Duration::zero(),
+        );
+        let golden = Golden::new(
+            &table,
+            Some(saved_with.week()),
+            None,
+            None,
+            None,
+            saved_with.week_starts_on,
+            saved_with.end_policy,
+        );
+        fs::create_dir_all(root.join("goldens")).unwrap();
+        fs::write(
+            golden_path(&saved_with, "july").unwrap(),
+            toml::ser::to_string(&golden).unwrap(),
+        )
+        .unwrap();
+
+        // Weeks now start on Monday and events run until the next one
+        let mut conf = testing::conf("");
+        conf.sync_folder = root.clone();
+        let differences = check_golden(&tracked.timesheet(), &conf, "july");
+        fs::remove_dir_all(&root).unwrap();
+        assert_eq!(differences.unwrap(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn golden_differences_compare_times() {
+        let time = |h| Utc.ymd(2019, 7, 24).and_hms(h, 0, 0);
+        let create = Patch::new()
+            .create_event("a".into(), time(8), vec!["work".into()])
+            .create_event("b".into(), time(12), vec!["lunch".into()]);
+        let mut tracked = Tracked::new(&[&create]).at(time(20));
+        let timesheet = tracked.timesheet();
+        let golden = |timesheet: &Timesheet| {
+            let table = report(
+                &timesheet.segments(),
+                time(9),
+                time(13),
+                None,
+                None,
+                None,
+                Duration::zero(),
+            );
+            Golden::new(
+                &table,
+                None,
+                None,
+                None,
+                None,
+                Weekday::Mon,
+                EndPolicyConf::NextEvent,
+            )
+        };
+        let saved = golden(&timesheet);
+        assert_eq!(
+            saved.rows[1],
+            GoldenRow {
+                tag: "work".into(),
+                seconds: vec![3 * 3600]
+            }
+        );
+        assert!(golden_differences(&saved, &golden(&timesheet)).is_empty());
+
+        let moved = Patch::new()
+            .add_start(*create.patch_ref(), "b".into(), time(11))
+            .remove_start(*create.patch_ref(), "b".into(), time(12));
+        tracked.apply(&moved);
+        let timesheet = tracked.timesheet();
+        assert_eq!(
+            golden_differences(&saved, &golden(&timesheet)),
+            vec![
+                "- lunch: 1h 0m",
+                "- work: 3h 0m",
+                "+ lunch: 2h 0m",
+                "+ work: 2h 0m"
+            ]
+        );
+    }
+}
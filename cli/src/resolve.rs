@@ -0,0 +1,39 @@
+use crate::config::Conf;
+use augr_core::{
+    repository::{event::Resolution, timesheet::PatchedTimesheet},
+    Patch,
+};
+use snafu::Snafu;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct Cmd {
+    /// How to pick between the start times of an event: "earliest-wins" or
+    /// "latest-patch-wins". Defaults to the `conflict_resolution` config option.
+    #[structopt(long = "strategy")]
+    strategy: Option<Resolution>,
+}
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display(
+        "No resolution strategy chosen; pass --strategy, or fix the events with set-start"
+    ))]
+    NoStrategy,
+}
+
+impl Cmd {
+    pub fn exec(&self, timesheet: &PatchedTimesheet, conf: &Conf) -> Result<Vec<Patch>, Error> {
+        let strategy = self.strategy.unwrap_or(conf.conflict_resolution);
+        if strategy == Resolution::Manual {
+            return Err(Error::NoStrategy);
+        }
+        match timesheet.resolution_patch(strategy) {
+            Some(patch) => Ok(vec![patch]),
+            None => {
+                println!("Nothing to resolve");
+                Ok(Vec::new())
+            }
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use crate::{
+    suggest,
+    time_input::{parse_default_local, RangeError, RangeGuard},
+};
+use augr_core::{
+    store::patch::{AddTag, RemoveTag},
+    Patch, Tag, TagExpr, Timesheet,
+};
+use chrono::{DateTime, Local, Utc};
+use snafu::{ResultExt, Snafu};
+use std::collections::BTreeSet;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct Cmd {
+    /// Only retag events matching a tag expression, like "work and not meeting".
+    /// Defaults to the events that have one of the tags to remove.
+    #[structopt(long = "filter", parse(try_from_str = crate::alias::tag_expr))]
+    filter: Option<TagExpr>,
+
+    /// The datetime at which to begin retagging events
+    #[structopt(long = "from", parse(try_from_os_str = parse_default_local))]
+    from: DateTime<Local>,
+
+    /// The datetime at which to stop retagging events. Defaults to now.
+    #[structopt(long = "to", parse(try_from_os_str = parse_default_local))]
+    to: Option<DateTime<Local>>,
+
+    /// A tag to remove from the matching events
+    #[structopt(long = "remove", number_of_values = 1, parse(from_str = crate::alias::tag))]
+    remove: Vec<Tag>,
+
+    /// A tag to add to the matching events
+    #[structopt(long = "add", number_of_values = 1)]
+    add: Vec<Tag>,
+
+    #[structopt(flatten)]
+    guard: RangeGuard,
+}
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Nothing to change; pass --add or --remove"))]
+    NothingToRetag,
+
+    #[snafu(display("Nothing to match; pass --filter or --remove"))]
+    NoFilter,
+
+    #[snafu(display("{}", source))]
+    LongRange { source: RangeError },
+}
+
+impl Cmd {
+    pub fn exec(&self, timesheet: &Timesheet) -> Result<Vec<Patch>, Error> {
+        if self.add.is_empty() && self.remove.is_empty() {
+            return NothingToRetag.fail();
+        }
+        if self.filter.is_none() && self.remove.is_empty() {
+            return NoFilter.fail();
+        }
+        let remove: BTreeSet<Tag> = self.remove.iter().cloned().collect();
+        suggest::warn_unknown_tags(timesheet, &remove, self.filter.as_ref());
+
+        let to = self
+            .to
+            .unwrap_or_else(|| timesheet.clock().now().with_timezone(&Local));
+        self.guard
+            .check(self.from.naive_local().date(), to.naive_local().date())
+            .context(LongRange {})?;
+
+        let patch = retag(
+            timesheet,
+            self.filter.as_ref(),
+            self.from.with_timezone(&Utc),
+            to.with_timezone(&Utc),
+            &remove,
+            &self.add,
+        );
+        let retagged = patch
+            .add_tag
+            .iter()
+            .map(|add| &add.event)
+            .chain(patch.remove_tag.iter().map(|remove| &remove.event))
+            .collect::<BTreeSet<_>>()
+            .len();
+        println!("Retagged {} events", retagged);
+        Ok(Some(patch).filter(|_| retagged > 0).into_iter().collect())
+    }
+}
+
+/// A patch removing `remove` from and adding `add` to the events that start
+/// between `start` and `end` and match `filter`. Without a filter, the events
+/// that have one of the tags in `remove` are retagged.
+fn retag(
+    timesheet: &Timesheet,
+    filter: Option<&TagExpr>,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    remove: &BTreeSet<Tag>,
+    add: &[Tag],
+) -> Patch {
+    let patched = timesheet.get_patched_timesheet();
+    let mut patch = Patch::new();
+    for segment in timesheet.segments() {
+        if segment.start_time < start || segment.start_time >= end {
+            continue;
+        }
+        let matches = match filter {
+            Some(filter) => filter.matches(&segment.tags),
+            None => !segment.tags.is_disjoint(remove),
+        };
+        if !matches {
+            continue;
+        }
+
+        let event = &patched.events[&segment.event_ref];
+        let parents = event.latest_patches();
+        for (patch_ref, tag) in event.tags() {
+            if remove.contains(&tag) {
+                patch.insert_remove_tag(RemoveTag {
+                    parents: Some(parents.clone()),
+                    patch: patch_ref,
+                    event: segment.event_ref.clone(),
+                    tag,
+                });
+            }
+        }
+        for tag in add.iter().filter(|tag| !segment.tags.contains(*tag)) {
+            patch.insert_add_tag(AddTag {
+                parents: parents.clone(),
+                event: segment.event_ref.clone(),
+                tag: tag.clone(),
+            });
+        }
+    }
+    patch
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::Tracked;
+    use chrono::TimeZone;
+
+    #[test]
+    fn retag_matching_events_in_range() {
+        let time = |d, h| Utc.ymd(2019, 7, d).and_hms(h, 0, 0);
+        let create = Patch::new()
+            .create_event("a".into(), time(1, 9), vec!["wrok".into()])
+            .create_event("b".into(), time(2, 9), vec!["wrok".into(), "work".into()])
+            .create_event("c".into(), time(3, 9), vec!["lunch".into()])
+            .create_event("d".into(), time(9, 9), vec!["wrok".into()]);
+        let tracked = Tracked::new(&[&create]);
+        let timesheet = tracked.timesheet();
+
+        let remove = vec![String::from("wrok")].into_iter().collect();
+        let patch = retag(
+            &timesheet,
+            None,
+            time(1, 0),
+            time(5, 0),
+            &remove,
+            &["work".into()],
+        );
+        let tags: Vec<Vec<Tag>> = Tracked::new(&[&create, &patch])
+            .timesheet()
+            .segments()
+            .into_iter()
+            .map(|s| s.tags.into_iter().collect())
+            .collect();
+        assert_eq!(
+            tags,
+            vec![
+                vec![String::from("work")],
+                vec![String::from("work")],
+                vec![String::from("lunch")],
+                vec![String::from("wrok")],
+            ]
+        );
+    }
+}
@@ -0,0 +1,479 @@
+use crate::time_input::{parse_default_local, RangeError, RangeGuard};
+use augr_core::{
+    repository::{event::PatchedEvent, timesheet::PatchedTimesheet},
+    store::patch::AddTag,
+    EventRef, Patch, PatchRef, Tag, Timesheet,
+};
+use chrono::{DateTime, Datelike, Local, NaiveTime, Utc, Weekday};
+use regex::Regex;
+use serde::{de, Deserialize, Deserializer};
+use std::collections::{BTreeMap, BTreeSet};
+use structopt::StructOpt;
+
+/// Adds tags to events that match some conditions, like
+///
+/// ```toml
+/// [[rule]]
+/// name = "morning"
+/// tags = ["work"]
+/// weekdays = ["mon", "tue", "wed", "thu", "fri"]
+/// between = "09:00-09:30"
+/// add = ["morning"]
+/// ```
+///
+/// Every condition that is given has to match.
+#[derive(Deserialize, Debug)]
+pub struct Rule {
+    /// Shown by `rules test`. Defaults to the tags the rule adds.
+    #[serde(default)]
+    pub name: Option<String>,
+
+    /// The event has all of these tags
+    #[serde(default)]
+    pub tags: Vec<Tag>,
+
+    /// The event starts on one of these days
+    #[serde(default, deserialize_with = "weekdays")]
+    pub weekdays: Vec<Weekday>,
+
+    /// The event starts in this range of local times, like "09:00-09:30". A
+    /// range like "22:00-02:00" crosses midnight.
+    #[serde(default, deserialize_with = "time_range")]
+    pub between: Option<(NaiveTime, NaiveTime)>,
+
+    /// The event's note matches this regular expression
+    #[serde(default, deserialize_with = "regex")]
+    pub note: Option<Regex>,
+
+    /// The tags to add
+    pub add: Vec<Tag>,
+}
+
+#[derive(StructOpt, Debug)]
+pub enum Cmd {
+    /// Show which tags the rules in the config would add to the events that
+    /// have already been tracked, without adding them
+    #[structopt(no_version, name = "test")]
+    Test,
+
+    /// Add the tags that the rules would add to the events in a range, in a
+    /// single patch. Combine with `--dry-run` or `--interactive` to check
+    /// the changes first.
+    #[structopt(no_version, name = "apply")]
+    Apply {
+        /// The datetime at which to begin applying the rules
+        #[structopt(long = "start", parse(try_from_os_str = parse_default_local))]
+        start: DateTime<Local>,
+
+        /// The datetime at which to stop applying the rules. Defaults to now.
+        #[structopt(long = "end", parse(try_from_os_str = parse_default_local))]
+        end: Option<DateTime<Local>>,
+
+        #[structopt(flatten)]
+        guard: RangeGuard,
+    },
+}
+
+impl Rule {
+    pub fn name(&self) -> String {
+        self.name.clone().unwrap_or_else(|| self.add.join(" "))
+    }
+
+    pub fn matches(&self, start: DateTime<Utc>, tags: &BTreeSet<Tag>, note: Option<&str>) -> bool {
+        let start = start.with_timezone(&Local);
+        self.tags.iter().all(|tag| tags.contains(tag))
+            && (self.weekdays.is_empty() || self.weekdays.contains(&start.weekday()))
+            && self
+                .between
+                .is_none_or(|range| in_range(range, start.time()))
+            && self
+                .note
+                .as_ref()
+                .is_none_or(|re| note.is_some_and(|note| re.is_match(note)))
+    }
+}
+
+/// Whether `time` is in `from..to`, wrapping past midnight when `to` comes
+/// before `from`
+fn in_range((from, to): (NaiveTime, NaiveTime), time: NaiveTime) -> bool {
+    if from <= to {
+        from <= time && time < to
+    } else {
+        from <= time || time < to
+    }
+}
+
+/// The tags that `rules` would add to `event`, along with the rule that adds
+/// each one. Events without a single start time are left alone.
+fn missing_tags<'r>(
+    rules: impl IntoIterator<Item = &'r Rule>,
+    event: &PatchedEvent,
+) -> BTreeMap<Tag, &'r Rule> {
+    let starts = event.starts();
+    if event.is_deleted() || starts.len() != 1 {
+        return BTreeMap::new();
+    }
+    let (_, start) = starts.into_iter().next().unwrap();
+    let tags: BTreeSet<Tag> = event.tags().into_iter().map(|(_, tag)| tag).collect();
+    let note = event.note();
+
+    let mut missing = BTreeMap::new();
+    for rule in rules
+        .into_iter()
+        .filter(|rule| rule.matches(start, &tags, note.as_deref()))
+    {
+        for tag in rule.add.iter().filter(|tag| !tags.contains(*tag)) {
+            missing.entry(tag.clone()).or_insert(rule);
+        }
+    }
+    missing
+}
+
+/// Run `rules` on the events that `patches` create. On other events, a rule
+/// only runs when the patches add one of its tags, or change the note it
+/// checks, so a rule's tag that was taken off by hand stays off. Tags for
+/// events that the patches create are added to the new events directly;
+/// other events get their tags in an extra patch. Tags that the patches
+/// remove from an event are not added back.
+pub fn apply(rules: &[Rule], timesheet: &PatchedTimesheet, mut patches: Vec<Patch>) -> Vec<Patch> {
+    if rules.is_empty() {
+        return patches;
+    }
+    let mut after = timesheet.clone();
+    for patch in patches.iter() {
+        if after.apply_patch(patch).is_err() {
+            // The patches are rejected when they are added
+            return patches;
+        }
+    }
+
+    let new_patches: BTreeSet<PatchRef> = patches.iter().map(|p| *p.patch_ref()).collect();
+    let added: BTreeSet<(EventRef, Tag)> = patches
+        .iter()
+        .flat_map(|p| p.add_tag.iter())
+        .map(|add| (add.event.clone(), add.tag.clone()))
+        .collect();
+    let removed: BTreeSet<(EventRef, Tag)> = patches
+        .iter()
+        .flat_map(|p| p.remove_tag.iter())
+        .map(|remove| (remove.event.clone(), remove.tag.clone()))
+        .collect();
+    let noted: BTreeSet<EventRef> = patches
+        .iter()
+        .flat_map(|p| p.set_note.iter())
+        .map(|set| set.event.clone())
+        .collect();
+    let mut extra = Patch::new();
+    for (event_ref, event) in after.events.iter() {
+        let created = event
+            .created_by()
+            .is_some_and(|created_by| new_patches.contains(&created_by));
+        let triggered = rules.iter().filter(|rule| {
+            created
+                || rule
+                    .tags
+                    .iter()
+                    .any(|tag| added.contains(&(event_ref.clone(), tag.clone())))
+                || (rule.note.is_some() && noted.contains(event_ref))
+        });
+        let tags: Vec<Tag> = missing_tags(triggered, event)
+            .into_keys()
+            .filter(|tag| !removed.contains(&(event_ref.clone(), tag.clone())))
+            .collect();
+        if tags.is_empty() {
+            continue;
+        }
+        match event.created_by().filter(|p| new_patches.contains(p)) {
+            Some(created_by) => {
+                add_to_created_event(&mut patches, created_by, event_ref, tags.into_iter())
+            }
+            None => {
+                for tag in tags {
+                    extra.insert_add_tag(AddTag {
+                        parents: event.latest_patches(),
+                        event: event_ref.clone(),
+                        tag,
+                    });
+                }
+            }
+        }
+    }
+    if !extra.add_tag.is_empty() {
+        patches.push(extra);
+    }
+    patches
+}
+
+fn add_to_created_event(
+    patches: &mut [Patch],
+    created_by: PatchRef,
+    event_ref: &EventRef,
+    tags: impl Iterator<Item = Tag>,
+) {
+    let patch = patches
+        .iter_mut()
+        .find(|p| *p.patch_ref() == created_by)
+        .expect("event was created by one of the patches");
+    let mut create = patch
+        .create_event
+        .iter()
+        .find(|c| c.event == *event_ref)
+        .cloned()
+        .expect("patch creates the event");
+    patch.create_event.remove(&create);
+    create.tags.extend(tags);
+    patch.insert_create_event(create);
+}
+
+impl Cmd {
+    pub fn exec(&self, timesheet: &Timesheet, rules: &[Rule]) -> Result<Vec<Patch>, RangeError> {
+        match self {
+            Cmd::Test => {
+                test(timesheet, rules);
+                Ok(vec![])
+            }
+            Cmd::Apply { start, end, guard } => {
+                let end = end.unwrap_or_else(|| timesheet.clock().now().with_timezone(&Local));
+                guard.check(start.naive_local().date(), end.naive_local().date())?;
+                let patch = backfill(
+                    rules,
+                    timesheet,
+                    start.with_timezone(&Utc),
+                    end.with_timezone(&Utc),
+                );
+                println!("The rules add tags to {} events", tagged_events(&patch));
+                Ok(Some(patch)
+                    .filter(|p| !p.add_tag.is_empty())
+                    .into_iter()
+                    .collect())
+            }
+        }
+    }
+}
+
+/// A patch adding the tags that `rules` are missing from the events that
+/// start between `start` and `end`
+fn backfill(
+    rules: &[Rule],
+    timesheet: &Timesheet,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+) -> Patch {
+    let patched = timesheet.get_patched_timesheet();
+    let mut patch = Patch::new();
+    for segment in timesheet.segments() {
+        if segment.start_time < start || segment.start_time >= end {
+            continue;
+        }
+        let event = &patched.events[&segment.event_ref];
+        for tag in missing_tags(rules, event).into_keys() {
+            patch.insert_add_tag(AddTag {
+                parents: event.latest_patches(),
+                event: segment.event_ref.clone(),
+                tag,
+            });
+        }
+    }
+    patch
+}
+
+fn tagged_events(patch: &Patch) -> usize {
+    patch
+        .add_tag
+        .iter()
+        .map(|add| &add.event)
+        .collect::<BTreeSet<_>>()
+        .len()
+}
+
+/// Print the tags that the rules would add to each event
+fn test(timesheet: &Timesheet, rules: &[Rule]) {
+    let patched = timesheet.get_patched_timesheet();
+    let short_refs = patched.short_refs();
+    let mut matched = 0;
+    for segment in timesheet.segments() {
+        let missing = missing_tags(rules, &patched.events[&segment.event_ref]);
+        if missing.is_empty() {
+            continue;
+        }
+        matched += 1;
+        let changes: Vec<String> = missing
+            .iter()
+            .map(|(tag, rule)| format!("+{} ({})", tag, rule.name()))
+            .collect();
+        println!(
+            "{} {} {}: {}",
+            short_refs[&segment.event_ref],
+            segment
+                .start_time
+                .with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M"),
+            segment.tags.iter().cloned().collect::<Vec<_>>().join(" "),
+            changes.join(", ")
+        );
+    }
+    println!("The rules would change {} events", matched);
+}
+
+pub(crate) fn weekdays<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<Weekday>, D::Error> {
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|day| {
+            day.parse()
+                .map_err(|_| de::Error::custom(format!("invalid weekday \"{}\"", day)))
+        })
+        .collect()
+}
+
+fn time_range<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<(NaiveTime, NaiveTime)>, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    parse_time_range(&text)
+        .map(Some)
+        .ok_or_else(|| de::Error::custom(format!("invalid time range \"{}\"", text)))
+}
+
+/// Parse a range of local times, like "09:00-17:00"
+pub(crate) fn parse_time_range(text: &str) -> Option<(NaiveTime, NaiveTime)> {
+    let mut parts = text.splitn(2, '-');
+    let mut time = || {
+        parts
+            .next()
+            .and_then(|t| NaiveTime::parse_from_str(t.trim(), "%H:%M").ok())
+    };
+    Some((time()?, time()?))
+}
+
+fn regex<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Regex>, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    Regex::new(&text).map(Some).map_err(de::Error::custom)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::Tracked;
+    use chrono::TimeZone;
+
+    fn rule(toml_str: &str) -> Rule {
+        toml::de::from_str(toml_str).unwrap()
+    }
+
+    #[test]
+    fn rules_add_tags_to_new_and_changed_events() {
+        let rules = vec![
+            rule(
+                r#"tags = ["work"]
+                    add = ["billable"]"#,
+            ),
+            rule(
+                r#"note = "PROJ-\\d+"
+                    add = ["ticket"]"#,
+            ),
+        ];
+        let start = Local.ymd(2019, 7, 24).and_hms(9, 10, 0).with_timezone(&Utc);
+        let timesheet = PatchedTimesheet::new();
+
+        let create = Patch::new().create_event("a".into(), start, vec!["work".into()]);
+        let patches = apply(&rules, &timesheet, vec![create]);
+        assert_eq!(patches.len(), 1);
+        let created = patches[0].create_event.iter().next().unwrap();
+        assert_eq!(created.tags, vec![String::from("work"), "billable".into()]);
+
+        let mut timesheet = timesheet;
+        timesheet.apply_patch(&patches[0]).unwrap();
+        let note = Patch::new().set_note(
+            *patches[0].patch_ref(),
+            Default::default(),
+            "a".into(),
+            "Fixed PROJ-12".into(),
+        );
+        let patches = apply(&rules, &timesheet, vec![note]);
+        assert_eq!(patches.len(), 2);
+        assert_eq!(patches[1].add_tag.iter().next().unwrap().tag, "ticket");
+    }
+
+    #[test]
+    fn backfill_only_tags_events_in_range() {
+        let rules = vec![rule(
+            r#"tags = ["work"]
+               add = ["billable"]"#,
+        )];
+        let time = |d| Utc.ymd(2019, 7, d).and_hms(9, 0, 0);
+        let create = Patch::new()
+            .create_event("a".into(), time(1), vec!["work".into()])
+            .create_event("b".into(), time(10), vec!["work".into()])
+            .create_event("c".into(), time(11), vec!["lunch".into()]);
+        let tracked = Tracked::new(&[&create]);
+        let timesheet = tracked.timesheet();
+
+        let patch = backfill(&rules, &timesheet, time(5), time(20));
+        assert_eq!(tagged_events(&patch), 1);
+        let add = patch.add_tag.iter().next().unwrap();
+        assert_eq!((add.event.as_str(), add.tag.as_str()), ("b", "billable"));
+    }
+
+    #[test]
+    fn rules_check_days_and_times() {
+        let morning = rule(
+            r#"weekdays = ["mon", "tue", "wed", "thu", "fri"]
+               between = "09:00-09:30"
+               add = ["morning"]"#,
+        );
+        let tags = BTreeSet::new();
+        let at = |d, h, m| Local.ymd(2019, 7, d).and_hms(h, m, 0).with_timezone(&Utc);
+        // 2019-07-24 was a Wednesday
+        assert!(morning.matches(at(24, 9, 10), &tags, None));
+        assert!(!morning.matches(at(24, 9, 30), &tags, None));
+        assert!(!morning.matches(at(27, 9, 10), &tags, None));
+
+        let night = rule(
+            r#"between = "22:00-02:00"
+               add = ["night"]"#,
+        );
+        assert!(night.matches(at(24, 23, 0), &tags, None));
+        assert!(night.matches(at(24, 1, 30), &tags, None));
+        assert!(!night.matches(at(24, 2, 0), &tags, None));
+        assert!(!night.matches(at(24, 12, 0), &tags, None));
+    }
+
+    #[test]
+    fn rules_leave_removed_tags_off() {
+        let rules = vec![rule(
+            r#"tags = ["work"]
+               add = ["billable"]"#,
+        )];
+        let start = Utc.ymd(2019, 7, 24).and_hms(9, 0, 0);
+        let create =
+            Patch::new().create_event("a".into(), start, vec!["work".into(), "billable".into()]);
+        let mut timesheet = PatchedTimesheet::new();
+        timesheet.apply_patch(&create).unwrap();
+
+        let remove = Patch::new().remove_tag(*create.patch_ref(), "a".into(), "billable".into());
+        let patches = apply(&rules, &timesheet, vec![remove]);
+        assert_eq!(patches.len(), 1);
+        assert!(patches[0].add_tag.is_empty());
+
+        // Later changes that don't involve the rule's tags leave it off too
+        timesheet.apply_patch(&patches[0]).unwrap();
+        let note = Patch::new().set_note(
+            *patches[0].patch_ref(),
+            Default::default(),
+            "a".into(),
+            "Planning".into(),
+        );
+        let patches = apply(&rules, &timesheet, vec![note]);
+        assert_eq!(patches.len(), 1);
+        assert!(patches[0].add_tag.is_empty());
+
+        // Adding one of the rule's tags again runs it again
+        let retag = Patch::new().add_tag(*patches[0].patch_ref(), "a".into(), "work".into());
+        timesheet.apply_patch(&patches[0]).unwrap();
+        let patches = apply(&rules, &timesheet, vec![retag]);
+        assert_eq!(patches.len(), 2);
+        assert_eq!(patches[1].add_tag.iter().next().unwrap().tag, "billable");
+    }
+}
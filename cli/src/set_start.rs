@@ -1,3 +1,4 @@
+use crate::{config::Conf, time_input::snap};
 use augr_core::{
     store::patch::{AddStart, RemoveStart},
     EventRef, Patch, Timesheet,
@@ -8,10 +9,11 @@ use structopt::StructOpt;
 
 #[derive(StructOpt, Debug)]
 pub struct Cmd {
-    /// The id of the event to modify
-    event: EventRef,
+    /// The id of the event to modify, a unique prefix of it, or
+    /// `@` for the current event and `@-1`, `@-2`, ... for earlier ones today
+    pub event: EventRef,
 
-    /// The time when you started
+    /// The time when you started, like "09:30" or "yesterday 9am"
     #[structopt(parse(try_from_os_str = crate::time_input::parse_default_local))]
     time: DateTime<Local>,
 }
@@ -22,29 +24,51 @@ pub enum Error {
     UnknownEventRef { event_ref: EventRef },
 }
 impl Cmd {
-    pub fn exec(&self, timesheet: &Timesheet) -> Result<Vec<Patch>, Error> {
-        let event = timesheet
-            .get_patched_timesheet()
-            .events
-            .get(&self.event)
-            .ok_or(Error::UnknownEventRef {
-                event_ref: self.event.clone(),
-            })?;
-        let parent_patches = event.latest_patches();
-        let mut patch = Patch::new();
-        for (patch_ref, previous_start_time) in event.starts() {
-            patch.insert_remove_start(RemoveStart {
-                parents: Some(parent_patches.clone()),
-                event: self.event.clone(),
-                patch: patch_ref,
-                time: previous_start_time,
-            });
-        }
-        patch.insert_add_start(AddStart {
-            parents: parent_patches.clone(),
-            event: self.event.clone(),
-            time: self.time.with_timezone(&Utc),
+    pub fn exec(&self, timesheet: &Timesheet, conf: &Conf) -> Result<Vec<Patch>, Error> {
+        Ok(vec![set_start(
+            timesheet,
+            conf,
+            &self.event,
+            self.time.with_timezone(&Utc),
+        )?])
+    }
+}
+
+/// Build a patch that replaces every start time of `event_ref` with `time`
+pub fn set_start(
+    timesheet: &Timesheet,
+    conf: &Conf,
+    event_ref: &EventRef,
+    time: DateTime<Utc>,
+) -> Result<Patch, Error> {
+    let event = timesheet
+        .get_patched_timesheet()
+        .events
+        .get(event_ref)
+        .ok_or(Error::UnknownEventRef {
+            event_ref: event_ref.clone(),
+        })?;
+    let parent_patches = event.latest_patches();
+    let mut patch = Patch::new();
+    for (patch_ref, previous_start_time) in event.starts() {
+        patch.insert_remove_start(RemoveStart {
+            parents: Some(parent_patches.clone()),
+            event: event_ref.clone(),
+            patch: patch_ref,
+            time: previous_start_time,
         });
-        Ok(vec![patch])
     }
+    let snapped_time = match conf.snap_starts {
+        Some(interval) => {
+            patch = patch.with_metadata("exact-time", time.to_rfc3339());
+            snap(time, interval)
+        }
+        None => time,
+    };
+    patch.insert_add_start(AddStart {
+        parents: parent_patches,
+        event: event_ref.clone(),
+        time: snapped_time,
+    });
+    Ok(patch)
 }
@@ -0,0 +1,274 @@
+//! Signatures that tie an export to the tracked time it was made from, so that
+//! whoever receives the export can later check it against the store.
+
+use augr_core::Timesheet;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac, NewMac};
+use sha2::Sha256;
+use snafu::{OptionExt, ResultExt, Snafu};
+use std::{fmt, str::FromStr};
+
+const HEADER: &str = "augr-export-signature";
+const VERSION: &str = "v1";
+
+#[derive(Debug, Snafu)]
+pub enum ParseError {
+    #[snafu(display("Not an augr export signature"))]
+    NotASignature,
+
+    #[snafu(display("Unsupported signature version {}", version))]
+    UnsupportedVersion { version: String },
+
+    #[snafu(display("Invalid time in signature: {}", source))]
+    InvalidTime { source: chrono::ParseError },
+
+    #[snafu(display("Unknown signature scope {}", scope))]
+    UnknownScope { scope: String },
+}
+
+/// An HMAC of the segments between `start` and `end`
+#[derive(Debug, Eq, PartialEq)]
+pub struct Signature {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    /// Whether the signed time includes the events started in private mode
+    pub include_private: bool,
+    mac: String,
+}
+
+impl Signature {
+    /// Sign the segments between `start` and `end`. Time that hasn't
+    /// happened yet can't be signed, so the range stops at the current time.
+    /// `include_private` says whether `timesheet` shows private events, so
+    /// that `verify` can hide them again.
+    pub fn sign(
+        timesheet: &Timesheet,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        include_private: bool,
+        key: &str,
+    ) -> Self {
+        let end = end.min(timesheet.clock().now());
+        let mac = mac(timesheet, start, end, include_private, key);
+        Self {
+            start,
+            end,
+            include_private,
+            mac: hex::encode(mac.finalize().into_bytes()),
+        }
+    }
+
+    /// Whether the timesheet still has the same segments in the signed range.
+    /// `timesheet` should include private events; they are left out again
+    /// unless they were signed.
+    pub fn verify(&self, timesheet: &Timesheet, key: &str) -> bool {
+        let timesheet = if self.include_private {
+            timesheet.clone()
+        } else {
+            timesheet.clone().without_private()
+        };
+        match hex::decode(&self.mac) {
+            Ok(bytes) => mac(&timesheet, self.start, self.end, self.include_private, key)
+                .verify(&bytes)
+                .is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    fn scope(&self) -> &'static str {
+        if self.include_private {
+            "with-private"
+        } else {
+            "without-private"
+        }
+    }
+}
+
+fn mac(
+    timesheet: &Timesheet,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    include_private: bool,
+    key: &str,
+) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key.as_bytes()).expect("HMAC accepts any key");
+    let canonical = canonical_segments(timesheet, start, end, include_private);
+    mac.update(canonical.as_bytes());
+    mac
+}
+
+/// One line for each segment that starts in the range, with its end cut off
+/// at the end of the range, so that events added later don't change it. Tags
+/// and notes are written with their length in front, so that no tag or note
+/// can be made to read like another.
+fn canonical_segments(
+    timesheet: &Timesheet,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    include_private: bool,
+) -> String {
+    let mut canonical = format!(
+        "{} {}\n{}\n{}\n{}\n",
+        HEADER,
+        VERSION,
+        start.to_rfc3339(),
+        end.to_rfc3339(),
+        include_private
+    );
+    let field = |text: &str| format!("{}:{}", text.len(), text);
+    for segment in timesheet.segments() {
+        if segment.start_time < start || segment.start_time > end {
+            continue;
+        }
+        let tags: String = segment.tags.iter().map(|tag| field(tag)).collect();
+        let note = timesheet.note(&segment.event_ref).unwrap_or_default();
+        canonical.push_str(&format!(
+            "{}\t{}\t{}\t{}\t{}\n",
+            segment.start_time.to_rfc3339(),
+            segment.end_time.min(end).to_rfc3339(),
+            segment.tags.len(),
+            tags,
+            field(&note)
+        ));
+    }
+    canonical
+}
+
+impl fmt::Display for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} {} {} {}",
+            HEADER,
+            VERSION,
+            self.start.to_rfc3339(),
+            self.end.to_rfc3339(),
+            self.scope(),
+            self.mac
+        )
+    }
+}
+
+impl FromStr for Signature {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split_whitespace();
+        if parts.next() != Some(HEADER) {
+            return Err(ParseError::NotASignature);
+        }
+        let version = parts.next().context(NotASignature {})?;
+        if version != VERSION {
+            return Err(ParseError::UnsupportedVersion {
+                version: version.to_string(),
+            });
+        }
+        let mut time = || -> Result<DateTime<Utc>, ParseError> {
+            let text = parts.next().context(NotASignature {})?;
+            Ok(DateTime::parse_from_rfc3339(text)
+                .context(InvalidTime {})?
+                .with_timezone(&Utc))
+        };
+        let start = time()?;
+        let end = time()?;
+        let include_private = match parts.next().context(NotASignature {})? {
+            "with-private" => true,
+            "without-private" => false,
+            scope => {
+                return Err(ParseError::UnknownScope {
+                    scope: scope.to_string(),
+                })
+            }
+        };
+        let mac = parts.next().context(NotASignature {})?.to_string();
+        Ok(Self {
+            start,
+            end,
+            include_private,
+            mac,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::Tracked;
+    use augr_core::{repository::PRIVATE_METADATA, Patch};
+    use chrono::TimeZone;
+
+    #[test]
+    fn signature_detects_changes() {
+        let time = |h| Utc.ymd(2019, 7, 24).and_hms(h, 0, 0);
+        let create = Patch::new()
+            .create_event("a".into(), time(9), vec!["work".into()])
+            .create_event("b".into(), time(12), vec!["lunch".into()]);
+        let mut tracked = Tracked::new(&[&create]).at(time(18));
+
+        let signature = {
+            let timesheet = tracked.timesheet();
+            Signature::sign(&timesheet, time(8), time(13), false, "secret")
+        };
+        let parsed: Signature = signature.to_string().parse().unwrap();
+        assert_eq!(parsed, signature);
+
+        // Events after the signed range don't matter
+        let later = Patch::new().create_event("c".into(), time(14), vec!["work".into()]);
+        tracked.apply(&later);
+        let timesheet = tracked.timesheet();
+        assert!(parsed.verify(&timesheet, "secret"));
+        assert!(!parsed.verify(&timesheet, "another key"));
+
+        let retag = Patch::new().add_tag(*create.patch_ref(), "a".into(), "meeting".into());
+        tracked.apply(&retag);
+        let timesheet = tracked.timesheet();
+        assert!(!parsed.verify(&timesheet, "secret"));
+    }
+
+    #[test]
+    fn signature_covers_notes_and_tag_boundaries() {
+        let time = |h| Utc.ymd(2019, 7, 24).and_hms(h, 0, 0);
+        let sign = |patches: &[&Patch]| {
+            let tracked = Tracked::new(patches).at(time(18));
+            let timesheet = tracked.timesheet();
+            canonical_segments(&timesheet, time(8), time(13), false)
+        };
+
+        let split = Patch::new().create_event("a".into(), time(9), vec!["a,b".into(), "c".into()]);
+        let joined = Patch::new().create_event("a".into(), time(9), vec!["a".into(), "b,c".into()]);
+        assert_ne!(sign(&[&split]), sign(&[&joined]));
+
+        let note = Patch::new().set_note(
+            *split.patch_ref(),
+            Default::default(),
+            "a".into(),
+            "client call".into(),
+        );
+        assert_ne!(sign(&[&split]), sign(&[&split, &note]));
+    }
+
+    #[test]
+    fn private_signatures_verify_with_private_events() {
+        let time = |h| Utc.ymd(2019, 7, 24).and_hms(h, 0, 0);
+        let create = Patch::new().create_event("a".into(), time(9), vec!["work".into()]);
+        let private = Patch::new()
+            .create_event("b".into(), time(12), vec!["dentist".into()])
+            .with_metadata(PRIVATE_METADATA, "true");
+        let tracked = Tracked::new(&[&create, &private]).at(time(18));
+        let timesheet = tracked.timesheet();
+
+        let with_private = Signature::sign(&timesheet, time(8), time(13), true, "secret");
+        let without_private = Signature::sign(
+            &timesheet.clone().without_private(),
+            time(8),
+            time(13),
+            false,
+            "secret",
+        );
+        assert_ne!(with_private.mac, without_private.mac);
+        for signature in [with_private, without_private].iter() {
+            let parsed: Signature = signature.to_string().parse().unwrap();
+            assert!(parsed.verify(&timesheet, "secret"));
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use augr_core::{EventRef, Patch, Tag, Timesheet};
+use chrono::{DateTime, Local, Utc};
+use snafu::{OptionExt, Snafu};
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct Cmd {
+    /// The id of the event to split, a unique prefix of it, or
+    /// `@` for the current event and `@-1`, `@-2`, ... for earlier ones today
+    pub event: EventRef,
+
+    /// When the second part starts, like "10:30"
+    #[structopt(long = "at", parse(try_from_os_str = crate::time_input::parse_default_local))]
+    at: DateTime<Local>,
+
+    /// The tags of the second part. Defaults to the tags of the event.
+    tags: Vec<Tag>,
+}
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Unknown event reference: {}", event_ref))]
+    UnknownEventRef { event_ref: EventRef },
+
+    #[snafu(display(
+        "{} isn't during event {}, which runs from {} to {}",
+        at.with_timezone(&Local).format("%Y-%m-%d %H:%M"),
+        event_ref,
+        start.with_timezone(&Local).format("%Y-%m-%d %H:%M"),
+        end.with_timezone(&Local).format("%Y-%m-%d %H:%M")
+    ))]
+    OutsideEvent {
+        event_ref: EventRef,
+        at: DateTime<Utc>,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    },
+}
+
+impl Cmd {
+    pub fn exec(&self, timesheet: &Timesheet) -> Result<Vec<Patch>, Error> {
+        let patch = split(
+            timesheet,
+            &self.event,
+            self.at.with_timezone(&Utc),
+            &self.tags,
+        )?;
+        Ok(vec![patch])
+    }
+}
+
+/// A patch starting a new event at `at`, in the middle of `event_ref`, with
+/// `tags` or the tags of `event_ref` if there are none
+fn split(
+    timesheet: &Timesheet,
+    event_ref: &EventRef,
+    at: DateTime<Utc>,
+    tags: &[Tag],
+) -> Result<Patch, Error> {
+    let segment = timesheet
+        .segments()
+        .into_iter()
+        .find(|segment| &segment.event_ref == event_ref)
+        .context(UnknownEventRef {
+            event_ref: event_ref.clone(),
+        })?;
+    if at <= segment.start_time || at >= segment.end_time {
+        return OutsideEvent {
+            event_ref: event_ref.clone(),
+            at,
+            start: segment.start_time,
+            end: segment.end_time,
+        }
+        .fail();
+    }
+    let tags = if tags.is_empty() {
+        segment.tags.into_iter().collect()
+    } else {
+        tags.to_vec()
+    };
+    let new_ref = augr_core::id::new_id().to_string();
+    Ok(Patch::new().create_event(new_ref, at, tags))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::Tracked;
+    use chrono::TimeZone;
+
+    #[test]
+    fn split_starts_new_event_inside_old_one() {
+        let time = |h, m| Utc.ymd(2019, 7, 24).and_hms(h, m, 0);
+        let create = Patch::new()
+            .create_event("a".into(), time(9, 0), vec!["work".into()])
+            .create_event("b".into(), time(12, 0), vec!["lunch".into()]);
+        let tracked = Tracked::new(&[&create]).at(time(13, 0));
+        let timesheet = tracked.timesheet();
+
+        assert!(split(&timesheet, &"a".into(), time(12, 30), &[]).is_err());
+        assert!(split(&timesheet, &"a".into(), time(9, 0), &[]).is_err());
+
+        let same = split(&timesheet, &"a".into(), time(10, 30), &[]).unwrap();
+        let other = split(&timesheet, &"a".into(), time(11, 0), &["review".into()]).unwrap();
+        let after = Tracked::new(&[&create, &same, &other]);
+        let segments = after.timesheet().segments();
+        let summary: Vec<(DateTime<Utc>, Vec<Tag>)> = segments
+            .into_iter()
+            .map(|s| (s.start_time, s.tags.into_iter().collect()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (time(9, 0), vec![String::from("work")]),
+                (time(10, 30), vec![String::from("work")]),
+                (time(11, 0), vec![String::from("review")]),
+                (time(12, 0), vec![String::from("lunch")]),
+            ]
+        );
+    }
+}
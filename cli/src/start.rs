@@ -1,26 +1,162 @@
+use crate::{
+    config::Conf,
+    picker,
+    taskwarrior::{self, TASK_METADATA},
+    time_input::snap,
+};
 use augr_core::{Patch, Timesheet};
 use chrono::{DateTime, Local, Utc};
+use snafu::{ResultExt, Snafu};
 use structopt::StructOpt;
 
 #[derive(StructOpt, Debug)]
 pub struct StartCmd {
-    /// The time when you started
-    #[structopt(long = "time", parse(try_from_os_str = crate::time_input::parse_default_local))]
+    /// The time when you started, like "09:30", "yesterday 9am", or "10 minutes
+    /// ago"
+    #[structopt(long = "at", alias = "time", parse(try_from_os_str = crate::time_input::parse_default_local))]
     time: Option<DateTime<Local>>,
 
+    /// Start working on a Taskwarrior task. Its project and tags are added to
+    /// the event's tags.
+    #[structopt(long = "task")]
+    task: Option<String>,
+
+    /// Start with the tags of a template from the config file. Any tags given
+    /// as well are added to them.
+    #[structopt(long = "template")]
+    template: Option<String>,
+
+    /// Pick tags that have been used before from a list, by typing part of
+    /// their names. Any tags given as well are added to them.
+    #[structopt(long = "pick", short = "i")]
+    pick: bool,
+
     /// A list of tags showing what you are doing
     tags: Vec<String>,
 }
 
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("{}", source))]
+    Taskwarrior { source: taskwarrior::Error },
+
+    #[snafu(display(
+        "No template named \"{}\" in the [template] section of the config",
+        name
+    ))]
+    UnknownTemplate { name: String },
+
+    #[snafu(display("{}", source))]
+    Pick { source: picker::Error },
+}
+
 impl StartCmd {
-    pub fn exec(&self, _timesheet: &Timesheet) -> Vec<Patch> {
-        let event_ref = uuid::Uuid::new_v4().to_string();
+    pub fn exec(&self, timesheet: &Timesheet, conf: &Conf) -> Result<Vec<Patch>, Error> {
+        let event_ref = augr_core::id::new_id().to_string();
         let now = self
             .time
             .map(|dt| dt.with_timezone(&Utc))
-            .unwrap_or_else(Utc::now);
-        let tags = self.tags.to_vec();
+            .unwrap_or_else(|| timesheet.clock().now());
+        let mut tags = match &self.template {
+            Some(name) => conf
+                .templates
+                .get(name)
+                .cloned()
+                .ok_or_else(|| Error::UnknownTemplate { name: name.clone() })?,
+            None => Vec::new(),
+        };
+        if self.pick {
+            for tag in picker::pick_tags(&timesheet.tag_counts()).context(Pick {})? {
+                if !tags.contains(&tag) {
+                    tags.push(tag);
+                }
+            }
+        }
+        for tag in self.tags.iter() {
+            if !tags.contains(tag) {
+                tags.push(tag.clone());
+            }
+        }
+
+        let task = match &self.task {
+            Some(id) => Some(taskwarrior::get_task(id).context(Taskwarrior {})?),
+            None => None,
+        };
+        if let Some(task) = &task {
+            for tag in task.augr_tags() {
+                if !tags.contains(&tag) {
+                    tags.push(tag);
+                }
+            }
+        }
+
+        let mut patch = match conf.snap_starts {
+            Some(interval) => Patch::new()
+                .create_event(event_ref, snap(now, interval), tags)
+                .with_metadata("exact-time", now.to_rfc3339()),
+            None => Patch::new().create_event(event_ref, now, tags),
+        };
+        if let Some(task) = task {
+            patch = patch
+                .with_metadata(TASK_METADATA, task.uuid)
+                .with_metadata("taskwarrior-description", task.description);
+        }
+        Ok(vec![patch])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::{conf, Tracked};
+    use augr_core::repository::timesheet::PatchedTimesheet;
+    use chrono::TimeZone;
+
+    fn start(args: &[&str], conf: &Conf, now: DateTime<Utc>) -> PatchedTimesheet {
+        let cmd =
+            StartCmd::from_iter_safe(std::iter::once("start").chain(args.iter().cloned())).unwrap();
+        let empty = Tracked::new(&[]).at(now);
+        let patches = cmd.exec(&empty.timesheet(), conf).unwrap();
+        Tracked::new(&patches.iter().collect::<Vec<_>>()).patched
+    }
+
+    #[test]
+    fn start_now_with_tags() {
+        let now = Utc.ymd(2019, 7, 23).and_hms(9, 2, 0);
+        let patched = start(&["work", "coding"], &conf(""), now);
+        let timesheet = patched.flatten().unwrap();
+
+        let events = timesheet.events();
+        assert_eq!(events.len(), 1);
+        let tags: Vec<&str> = events[&now].iter().map(|t| t.as_str()).collect();
+        assert_eq!(tags, vec!["coding", "work"]);
+    }
+
+    #[test]
+    fn start_from_template() {
+        let now = Utc.ymd(2019, 7, 23).and_hms(9, 2, 0);
+        let conf = conf("[template]\nmeeting = [\"work\", \"meeting\"]");
+        let patched = start(&["--template", "meeting", "standup", "work"], &conf, now);
+        let timesheet = patched.flatten().unwrap();
+
+        let events = timesheet.events();
+        let tags: Vec<&str> = events[&now].iter().map(|t| t.as_str()).collect();
+        assert_eq!(tags, vec!["meeting", "standup", "work"]);
+    }
+
+    #[test]
+    fn start_snaps_to_interval() {
+        let now = Utc.ymd(2019, 7, 23).and_hms(9, 2, 0);
+        let patched = start(&["work"], &conf("snap_starts = \"5m\""), now);
+        let timesheet = patched.flatten().unwrap();
 
-        vec![Patch::new().create_event(event_ref, now, tags)]
+        let snapped = Utc.ymd(2019, 7, 23).and_hms(9, 0, 0);
+        assert!(timesheet.events().contains_key(&snapped));
+        let metadata: Vec<&String> = patched
+            .patch_metadata
+            .values()
+            .filter_map(|m| m.get("exact-time"))
+            .collect();
+        assert_eq!(metadata, vec![&now.to_rfc3339()]);
     }
 }
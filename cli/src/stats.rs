@@ -0,0 +1,221 @@
+use crate::{
+    config::Conf,
+    days_off::DaysOff,
+    export::json,
+    format_duration, suggest,
+    time_input::{parse_default_local, RangeError, RangeGuard},
+};
+use augr_core::{aggregate, stats, Tag, Timesheet};
+use chrono::{DateTime, Duration, Local, Utc};
+use clap::arg_enum;
+use serde::Serialize;
+use snafu::{ResultExt, Snafu};
+use std::collections::BTreeSet;
+use structopt::StructOpt;
+
+arg_enum! {
+    /// Ways of writing statistics
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    enum Format {
+        Text,
+        Json,
+    }
+}
+
+#[derive(StructOpt, Debug)]
+pub struct Cmd {
+    /// The datetime at which to begin. Defaults to 30 days ago.
+    #[structopt(long = "start", parse(try_from_os_str = parse_default_local))]
+    start: Option<DateTime<Local>>,
+
+    /// The datetime at which to end. Defaults to now.
+    #[structopt(long = "end", parse(try_from_os_str = parse_default_local))]
+    end: Option<DateTime<Local>>,
+
+    /// Find the longest streak of days with this tag, instead of days with
+    /// any time tracked
+    #[structopt(long = "streak", parse(from_str = crate::alias::tag))]
+    streak: Option<Tag>,
+
+    /// Leave weekends, or the days not in `weekdays` of `[working_hours]`,
+    /// out of the averages and streaks
+    #[structopt(long = "workdays")]
+    workdays: bool,
+
+    /// "text", or "json" for scripts
+    #[structopt(
+        long = "format",
+        default_value = "text",
+        possible_values = &Format::variants(),
+        case_insensitive = true
+    )]
+    format: Format,
+
+    #[structopt(flatten)]
+    guard: RangeGuard,
+}
+
+/// The statistics as they're written in JSON
+#[derive(Serialize, Debug)]
+struct StatsJson {
+    start: String,
+    end: String,
+    total_seconds: i64,
+    days_tracked: usize,
+    days_in_range: i64,
+    average_seconds_per_day: i64,
+    longest_event: Option<LongestEventJson>,
+    busiest_weekday: Option<WeekdayJson>,
+    longest_streak: Option<StreakJson>,
+    distinct_tags: usize,
+}
+
+#[derive(Serialize, Debug)]
+struct LongestEventJson {
+    #[serde(rename = "ref")]
+    event_ref: String,
+    start: String,
+    seconds: i64,
+    tags: BTreeSet<Tag>,
+}
+
+#[derive(Serialize, Debug)]
+struct WeekdayJson {
+    /// The first three letters of the day's name, like "Mon"
+    weekday: String,
+    seconds: i64,
+}
+
+#[derive(Serialize, Debug)]
+struct StreakJson {
+    first: String,
+    days: usize,
+}
+
+impl StatsJson {
+    /// The JSON for `stats`, which were worked out with times moved back by
+    /// `day_offset`
+    fn new(
+        stats: &stats::Stats,
+        start: DateTime<Local>,
+        end: DateTime<Local>,
+        days_in_range: i64,
+        day_offset: Duration,
+    ) -> Self {
+        Self {
+            start: json::time(start),
+            end: json::time(end),
+            total_seconds: stats.total.num_seconds(),
+            days_tracked: stats.days_tracked,
+            days_in_range,
+            average_seconds_per_day: stats.average_per_day.num_seconds(),
+            longest_event: stats.longest_event.as_ref().map(|event| LongestEventJson {
+                event_ref: event.event_ref.clone(),
+                start: json::time(event.start + day_offset),
+                seconds: event.duration.num_seconds(),
+                tags: event.tags.clone(),
+            }),
+            busiest_weekday: stats
+                .busiest_weekday
+                .map(|(weekday, duration)| WeekdayJson {
+                    weekday: format!("{:?}", weekday),
+                    seconds: duration.num_seconds(),
+                }),
+            longest_streak: stats.longest_streak.as_ref().map(|streak| StreakJson {
+                first: streak.first.format("%Y-%m-%d").to_string(),
+                days: streak.days,
+            }),
+            distinct_tags: stats.distinct_tags,
+        }
+    }
+}
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("{}", source))]
+    LongRange { source: RangeError },
+}
+
+impl Cmd {
+    pub fn exec(&self, timesheet: &Timesheet, conf: &Conf) -> Result<(), Error> {
+        let streak_tags: BTreeSet<Tag> = self.streak.iter().cloned().collect();
+        suggest::warn_unknown_tags(timesheet, &streak_tags, None);
+
+        let now = timesheet.clock().now().with_timezone(&Local);
+        let end = self.end.unwrap_or(now);
+        let start = self
+            .start
+            .unwrap_or_else(|| conf.start_of_day(end - Duration::days(30)));
+        self.guard
+            .check(start.naive_local().date(), end.naive_local().date())
+            .context(LongRange {})?;
+
+        // Days start `day_start` after midnight, so move everything back by
+        // that much and forward again when showing times
+        let offset = conf.day_offset();
+        let workdays = conf.workdays(self.workdays);
+        let stats = stats::stats(
+            &aggregate::shift(&timesheet.segments(), offset),
+            start.with_timezone(&Utc) - offset,
+            end.with_timezone(&Utc) - offset,
+            self.streak.as_ref(),
+            workdays.as_deref(),
+            &Local,
+        );
+        let days_in_range = DaysOff::default().with_workdays(workdays).working_days(
+            (start - offset).naive_local().date(),
+            (end - offset).naive_local().date(),
+        );
+        if self.format == Format::Json {
+            let output = StatsJson::new(&stats, start, end, days_in_range, offset);
+            print!("{}", json::to_string(&output));
+            return Ok(());
+        }
+        println!(
+            "Stats {} to {}",
+            start.format("%Y-%m-%d %H:%M"),
+            end.format("%Y-%m-%d %H:%M")
+        );
+        println!("Total tracked    {}", format_duration(stats.total));
+        println!(
+            "Days tracked     {} of {}",
+            stats.days_tracked, days_in_range
+        );
+        println!(
+            "Average per day  {}",
+            format_duration(stats.average_per_day)
+        );
+        if let Some(event) = &stats.longest_event {
+            println!(
+                "Longest event    {}, {} on {}",
+                format_duration(event.duration),
+                event.tags.iter().cloned().collect::<Vec<_>>().join(" "),
+                (event.start + offset)
+                    .with_timezone(&Local)
+                    .format("%Y-%m-%d %H:%M")
+            );
+        }
+        if let Some((weekday, duration)) = stats.busiest_weekday {
+            println!(
+                "Busiest weekday  {:?}, {}",
+                weekday,
+                format_duration(duration)
+            );
+        }
+        if let Some(streak) = &stats.longest_streak {
+            let of = match &self.streak {
+                Some(tag) => format!(" of {}", tag),
+                None => String::new(),
+            };
+            println!(
+                "Longest streak   {} day{}{}, from {}",
+                streak.days,
+                if streak.days == 1 { "" } else { "s" },
+                of,
+                streak.first.format("%Y-%m-%d")
+            );
+        }
+        println!("Distinct tags    {}", stats.distinct_tags);
+        Ok(())
+    }
+}
@@ -0,0 +1,65 @@
+use crate::{
+    config::Conf,
+    device,
+    doctor::{rate_warnings, size_warnings},
+    format_duration,
+};
+use augr_core::{
+    store::{SyncFolderStore, SyncFolderStoreError},
+    Repository, Timesheet,
+};
+use chrono::Local;
+use snafu::{ResultExt, Snafu};
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct Cmd {}
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("{}", source))]
+    Devices { source: device::Error },
+
+    #[snafu(display("Could not measure the sync folder: {}", source))]
+    Stats { source: SyncFolderStoreError },
+}
+
+impl Cmd {
+    pub fn exec(
+        &self,
+        timesheet: &Timesheet,
+        repo: &Repository<SyncFolderStore>,
+        conf: &Conf,
+    ) -> Result<(), Error> {
+        let now = timesheet.clock().now();
+        match timesheet.current_event(now) {
+            Some(ref current) if !current.tags.is_empty() => {
+                let tags: Vec<&str> = current.tags.iter().map(|s| &**s).collect();
+                println!(
+                    "Tracking {} for {} (since {})",
+                    tags.join(" "),
+                    format_duration(current.elapsed),
+                    current.start_time.with_timezone(&Local).format("%H:%M")
+                );
+            }
+            _ => println!("Not tracking anything"),
+        }
+        if repo.meta().private_mode() {
+            println!("Private mode is on");
+        }
+
+        for device in
+            device::stale_devices(repo, now, conf.stale_device_days).context(Devices {})?
+        {
+            eprintln!("warning: {}", device.warning(now));
+        }
+        let stats = repo.store().stats().context(Stats {})?;
+        for warning in size_warnings(&stats, conf) {
+            eprintln!("warning: {}", warning);
+        }
+        for warning in rate_warnings(repo.timesheet(), now, conf) {
+            eprintln!("warning: {}", warning);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,74 @@
+use crate::{config::Conf, time_input::snap};
+use augr_core::{Patch, Timesheet};
+use chrono::{DateTime, Local, Utc};
+use snafu::{ensure, Snafu};
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct Cmd {
+    /// The time when you stopped, like "17:30" or "10 minutes ago"
+    #[structopt(long = "at", parse(try_from_os_str = crate::time_input::parse_default_local))]
+    time: Option<DateTime<Local>>,
+}
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Nothing is being tracked at {}", time.with_timezone(&Local).format("%Y-%m-%d %H:%M")))]
+    NotTracking { time: DateTime<Utc> },
+}
+
+impl Cmd {
+    /// Stopping adds an event without any tags, which every command treats as
+    /// time that wasn't tracked.
+    pub fn exec(&self, timesheet: &Timesheet, conf: &Conf) -> Result<Vec<Patch>, Error> {
+        let now = self
+            .time
+            .map(|dt| dt.with_timezone(&Utc))
+            .unwrap_or_else(|| timesheet.clock().now());
+        let tracking = timesheet
+            .current_event(now)
+            .is_some_and(|current| !current.tags.is_empty());
+        ensure!(tracking, NotTracking { time: now });
+
+        let event_ref = augr_core::id::new_id().to_string();
+        let patch = match conf.snap_starts {
+            Some(interval) => Patch::new()
+                .create_event(event_ref, snap(now, interval), Vec::new())
+                .with_metadata("exact-time", now.to_rfc3339()),
+            None => Patch::new().create_event(event_ref, now, Vec::new()),
+        };
+        Ok(vec![patch])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::{self, Tracked};
+    use chrono::TimeZone;
+
+    #[test]
+    fn stop_adds_an_untagged_event() {
+        let time = |h, m| Utc.ymd(2019, 7, 24).and_hms(h, m, 0);
+        let create = Patch::new().create_event("a".into(), time(9, 0), vec!["work".into()]);
+        let tracked = Tracked::new(&[&create]).at(time(12, 7));
+        let timesheet = tracked.timesheet();
+        let stop = Cmd::from_iter_safe(&["stop"]).unwrap();
+
+        let patches = stop.exec(&timesheet, &testing::conf("")).unwrap();
+        let created = patches[0].create_event.iter().next().unwrap();
+        assert_eq!(created.start, time(12, 7));
+        assert!(created.tags.is_empty());
+
+        let patches = stop
+            .exec(&timesheet, &testing::conf("snap_starts = \"5m\""))
+            .unwrap();
+        let created = patches[0].create_event.iter().next().unwrap();
+        assert_eq!(created.start, time(12, 5));
+        assert_eq!(patches[0].metadata["exact-time"], time(12, 7).to_rfc3339());
+
+        let stopped = Tracked::new(&[&create, &patches[0]]).at(time(12, 7));
+        let timesheet = stopped.timesheet();
+        assert!(stop.exec(&timesheet, &testing::conf("")).is_err());
+    }
+}
@@ -0,0 +1,158 @@
+//! Suggestions for tags that were probably mistyped
+
+use crate::prompt;
+use augr_core::{repository::timesheet::PatchedTimesheet, Patch, Tag, TagExpr, Timesheet};
+use snafu::{ResultExt, Snafu};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io,
+};
+
+/// Tags used by this many events or fewer might be typos
+const RARE_USES: usize = 1;
+
+/// How many suggestions to show for a rare tag
+const MAX_SUGGESTIONS: usize = 3;
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Could not read confirmation: {}", source))]
+    ReadConfirmation { source: io::Error },
+
+    #[snafu(display("Nothing was saved"))]
+    NotConfirmed,
+}
+
+/// Find the known tag closest to `word`, if any is close enough to be a typo.
+/// Ties go to the tag used by the most events.
+pub fn closest_tag<'a>(word: &str, tag_counts: &'a BTreeMap<Tag, usize>) -> Option<&'a Tag> {
+    similar_tags(word, tag_counts).into_iter().next()
+}
+
+/// The known tags close enough to `word` to be what was meant, closest and
+/// most used first
+fn similar_tags<'a>(word: &str, tag_counts: &'a BTreeMap<Tag, usize>) -> Vec<&'a Tag> {
+    let max_distance = (word.chars().count() / 3).max(1);
+    let mut similar: Vec<_> = tag_counts
+        .iter()
+        .filter(|(tag, _)| tag.as_str() != word)
+        .map(|(tag, count)| (strsim::damerau_levenshtein(word, tag), *count, tag))
+        .filter(|(distance, _, _)| *distance <= max_distance)
+        .collect();
+    similar.sort_by_key(|(distance, count, _)| (*distance, std::cmp::Reverse(*count)));
+    similar.into_iter().map(|(_, _, tag)| tag).collect()
+}
+
+/// Warn about tags that `patches` add which have been used once or never, but
+/// look like a more common tag, and ask whether to record them anyway. This
+/// stops `emails` and `email` from quietly becoming two tags. Without a
+/// terminal to ask, or when `ask` is false, only the warning is printed.
+pub fn check_rare_tags(
+    timesheet: &PatchedTimesheet,
+    patches: &[Patch],
+    ask: bool,
+) -> Result<(), Error> {
+    let mut tag_counts: BTreeMap<Tag, usize> = BTreeMap::new();
+    for event in timesheet.events.values().filter(|e| !e.is_deleted()) {
+        for (_, tag) in event.tags() {
+            *tag_counts.entry(tag).or_insert(0) += 1;
+        }
+    }
+    let added: BTreeSet<&Tag> = patches
+        .iter()
+        .flat_map(|patch| {
+            let created = patch.create_event.iter().flat_map(|c| c.tags.iter());
+            created.chain(patch.add_tag.iter().map(|a| &a.tag))
+        })
+        .collect();
+
+    let mut warned = false;
+    for tag in added {
+        let uses = tag_counts.get(tag).copied().unwrap_or(0);
+        if uses > RARE_USES {
+            continue;
+        }
+        let suggestions: Vec<String> = similar_tags(tag, &tag_counts)
+            .into_iter()
+            .filter(|suggestion| tag_counts[*suggestion] > uses)
+            .take(MAX_SUGGESTIONS)
+            .map(|suggestion| format!("\"{}\" ({} events)", suggestion, tag_counts[suggestion]))
+            .collect();
+        if suggestions.is_empty() {
+            continue;
+        }
+        let used = match uses {
+            0 => String::from("has never been used"),
+            _ => String::from("has only been used once"),
+        };
+        eprintln!(
+            "warning: \"{}\" {}; did you mean {}?",
+            tag,
+            used,
+            suggestions.join(" or ")
+        );
+        warned = true;
+    }
+    if !warned || !ask {
+        return Ok(());
+    }
+    match prompt::confirm("Record it anyway?").context(ReadConfirmation {})? {
+        Some(false) => Err(Error::NotConfirmed),
+        _ => Ok(()),
+    }
+}
+
+/// Print a warning for each tag in `tags` or `filter` that no event uses,
+/// suggesting the tag that was most likely meant.
+pub fn warn_unknown_tags(timesheet: &Timesheet, tags: &BTreeSet<Tag>, filter: Option<&TagExpr>) {
+    let tag_counts = timesheet.tag_counts();
+    let wanted = tags
+        .iter()
+        .chain(filter.into_iter().flat_map(|f| f.tags()))
+        .collect::<BTreeSet<_>>();
+    for tag in wanted {
+        if tag_counts.contains_key(tag) {
+            continue;
+        }
+        match closest_tag(tag, &tag_counts) {
+            Some(suggestion) => eprintln!(
+                "warning: no tag \"{}\"; did you mean \"{}\"?",
+                tag, suggestion
+            ),
+            None => eprintln!("warning: no tag \"{}\"", tag),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn counts(tags: &[(&str, usize)]) -> BTreeMap<Tag, usize> {
+        tags.iter().map(|(t, c)| (t.to_string(), *c)).collect()
+    }
+
+    #[test]
+    fn suggest_typo() {
+        let counts = counts(&[("coding", 10), ("meeting", 3), ("lunch", 5)]);
+        assert_eq!(closest_tag("codng", &counts), Some(&"coding".to_string()));
+        assert_eq!(closest_tag("lnuch", &counts), Some(&"lunch".to_string()));
+        assert_eq!(closest_tag("gardening", &counts), None);
+    }
+
+    #[test]
+    fn similar_tags_skip_the_word_itself() {
+        let counts = counts(&[("email", 12), ("emails", 1), ("mail", 4), ("lunch", 5)]);
+        let similar: Vec<&str> = similar_tags("emails", &counts)
+            .into_iter()
+            .map(|t| t.as_str())
+            .collect();
+        assert_eq!(similar, vec!["email", "mail"]);
+    }
+
+    #[test]
+    fn prefer_common_tags() {
+        let counts = counts(&[("bug", 1), ("bog", 20)]);
+        assert_eq!(closest_tag("bag", &counts), Some(&"bog".to_string()));
+    }
+}
@@ -1,22 +1,88 @@
-use crate::{format_duration, time_input::parse_default_local};
-use augr_core::{Tag, Timesheet};
-use chrono::{DateTime, Local};
-use std::collections::BTreeSet;
+use crate::{
+    budget,
+    config::Conf,
+    days_off::{self, DaysOff},
+    export::{csv, json, markdown},
+    format_duration,
+    report::{self, BucketsJson},
+    suggest,
+    time_input::{parse_default_local, RangeError, RangeGuard},
+};
+use augr_core::{
+    aggregate::{self, Period},
+    timesheet::Segment,
+    Tag, TagExpr, Timesheet,
+};
+use chrono::{DateTime, Local, Utc};
+use clap::arg_enum;
+use serde::Serialize;
+use snafu::{ResultExt, Snafu};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+    str::FromStr,
+};
 use structopt::StructOpt;
 
+/// The number of columns in the bar of a tag with all of the time
+const BREAKDOWN_WIDTH: usize = 30;
+
+arg_enum! {
+    /// Ways of writing a summary
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    enum Format {
+        Text,
+        Markdown,
+        Json,
+        Csv,
+    }
+}
+
+// `arg_enum!` doesn't pass attributes on to the variants, so this can't be
+// derived. The default summary with no arguments is printed as text.
+#[allow(clippy::derivable_impls)]
+impl Default for Format {
+    fn default() -> Self {
+        Format::Text
+    }
+}
+
 #[derive(StructOpt, Default, Debug)]
 pub struct SummaryCmd {
     /// A list of tags to filter against
+    #[structopt(parse(from_str = crate::alias::tag))]
     tags: Vec<String>,
 
+    /// Only show events matching a tag expression, like "work and not meeting"
+    #[structopt(long = "filter", parse(try_from_str = crate::alias::tag_expr))]
+    filter: Option<TagExpr>,
+
     /// Show the time that each event ended
     #[structopt(long = "show-ends")]
     show_ends: bool,
 
-    /// Show event references as tags in output
+    /// Show the shortest unique prefix of each event's reference after its tags
     #[structopt(long = "refs")]
     show_refs: bool,
 
+    /// Show the note attached to each event below it
+    #[structopt(long = "notes")]
+    show_notes: bool,
+
+    /// Show the total time in each "day", "week", or "month" of the range, or
+    /// for each "tag", instead of listing the events
+    #[structopt(long = "group-by")]
+    group_by: Option<GroupBy>,
+
+    /// Show a table with a row for each tag and a column for each day, with
+    /// totals for both, like most timesheet systems ask for
+    #[structopt(long = "matrix", conflicts_with = "group-by")]
+    matrix: bool,
+
+    /// Show each tag's share of the tracked time as a bar, biggest first
+    #[structopt(long = "breakdown", conflicts_with_all = &["group-by", "matrix"])]
+    breakdown: bool,
+
     /// The datetime at which to begin showing events
     #[structopt(long = "start", parse(try_from_os_str = parse_default_local))]
     start: Option<DateTime<Local>>,
@@ -24,42 +90,267 @@ pub struct SummaryCmd {
     /// The datetime at which to stop showing events
     #[structopt(long = "end", parse(try_from_os_str = parse_default_local))]
     end: Option<DateTime<Local>>,
+
+    /// Leave weekends, or the days not in `weekdays` of `[working_hours]`,
+    /// out of the targets
+    #[structopt(long = "workdays")]
+    workdays: bool,
+
+    /// "text", "markdown" for tables that paste cleanly into issues and
+    /// notes, "json" for scripts, or "csv" for a row per event, group, or tag
+    /// and day to import into spreadsheets
+    #[structopt(
+        long = "format",
+        default_value = "text",
+        possible_values = &Format::variants(),
+        case_insensitive = true
+    )]
+    format: Format,
+
+    /// Also count events that were started in private mode
+    #[structopt(long = "include-private")]
+    pub include_private: bool,
+
+    #[structopt(flatten)]
+    guard: RangeGuard,
+}
+
+/// The summary as it's written in JSON. Only one of the lists is there, for
+/// the options that the summary was made with.
+#[derive(Serialize, Debug)]
+struct SummaryJson {
+    start: String,
+    end: String,
+    total_seconds: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    events: Option<Vec<json::Event>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    groups: Option<Vec<GroupJson>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    shares: Option<Vec<ShareJson>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    matrix: Option<BucketsJson>,
+}
+
+#[derive(Serialize, Debug)]
+struct GroupJson {
+    label: String,
+    seconds: i64,
+}
+
+#[derive(Serialize, Debug)]
+struct ShareJson {
+    tag: Tag,
+    share: f64,
+    seconds: i64,
+}
+
+impl SummaryJson {
+    fn new(start: DateTime<Local>, end: DateTime<Local>, total: chrono::Duration) -> Self {
+        Self {
+            start: json::time(start),
+            end: json::time(end),
+            total_seconds: total.num_seconds(),
+            events: None,
+            groups: None,
+            shares: None,
+            matrix: None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+enum GroupBy {
+    Period(Period),
+    Tag,
+}
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("{}", source))]
+    LoadDaysOff { source: days_off::Error },
+
+    #[snafu(display("{}", source))]
+    LongRange { source: RangeError },
 }
 
 impl SummaryCmd {
-    #[cfg_attr(feature = "flame_it", flame)]
-    pub fn exec(&self, timesheet: &Timesheet) {
+    pub fn exec(&self, timesheet: &Timesheet, conf: &Conf) -> Result<(), Error> {
+        let days_off = DaysOff::load(conf, timesheet)
+            .context(LoadDaysOff {})?
+            .with_workdays(conf.workdays(self.workdays));
         let tags: BTreeSet<Tag> = self.tags.iter().cloned().collect();
+        suggest::warn_unknown_tags(timesheet, &tags, self.filter.as_ref());
 
-        let start = self.start.unwrap_or_else(default_start);
-        let end = self.end.unwrap_or_else(default_end);
-        let segments = timesheet
+        let now = timesheet.clock().now().with_timezone(&Local);
+        let start = self.start.unwrap_or_else(|| conf.start_of_day(now));
+        let end = self.end.unwrap_or(now);
+        self.guard
+            .check(start.naive_local().date(), end.naive_local().date())
+            .context(LongRange {})?;
+        let matching: Vec<Segment> = timesheet
             .segments()
+            .into_iter()
+            .filter(|s| !s.tags.is_empty())
+            .filter(|s| s.tags.is_superset(&tags))
+            .filter(|s| self.filter.as_ref().is_none_or(|f| f.matches(&s.tags)))
+            .collect();
+        if let Some(group_by) = self.group_by {
+            let group_by = match group_by {
+                GroupBy::Period(period) => GroupBy::Period(conf.calendar(period)),
+                GroupBy::Tag => GroupBy::Tag,
+            };
+            let (rows, total) = groups(
+                &matching,
+                start.with_timezone(&Utc),
+                end.with_timezone(&Utc),
+                group_by,
+                conf.day_offset(),
+            );
+            match self.format {
+                Format::Text => print_groups(&rows, total),
+                Format::Csv => print!(
+                    "{}",
+                    groups_csv(
+                        &matching,
+                        start.with_timezone(&Utc),
+                        end.with_timezone(&Utc),
+                        group_by,
+                        conf.day_offset(),
+                    )
+                ),
+                Format::Json => {
+                    let groups = rows
+                        .into_iter()
+                        .map(|(label, duration)| GroupJson {
+                            label,
+                            seconds: duration.num_seconds(),
+                        })
+                        .collect();
+                    let output = SummaryJson {
+                        groups: Some(groups),
+                        ..SummaryJson::new(start, end, total)
+                    };
+                    print!("{}", json::to_string(&output));
+                }
+                Format::Markdown => {
+                    let rows: Vec<Vec<String>> = rows
+                        .into_iter()
+                        .map(|(label, duration)| vec![label, format_duration(duration)])
+                        .chain(Some(vec![
+                            String::from("**Total**"),
+                            format_duration(total),
+                        ]))
+                        .collect();
+                    print!("{}", markdown::table(&[group_by.name(), "Time"], &rows));
+                }
+            }
+            self.print_days_off(&days_off, start, end);
+            return Ok(());
+        }
+        if self.breakdown {
+            let (start, end) = (start.with_timezone(&Utc), end.with_timezone(&Utc));
+            print!(
+                "{}",
+                match self.format {
+                    Format::Text => breakdown(&matching, start, end),
+                    Format::Markdown => breakdown_markdown(&matching, start, end),
+                    Format::Json => breakdown_json(&matching, start, end),
+                    Format::Csv =>
+                        groups_csv(&matching, start, end, GroupBy::Tag, conf.day_offset()),
+                }
+            );
+            self.print_days_off(
+                &days_off,
+                start.with_timezone(&Local),
+                end.with_timezone(&Local),
+            );
+            return Ok(());
+        }
+        if self.matrix {
+            let (start, end) = (start.with_timezone(&Utc), end.with_timezone(&Utc));
+            print!("{}", self.matrix(&matching, start, end, conf.day_offset()));
+            self.print_days_off(
+                &days_off,
+                start.with_timezone(&Local),
+                end.with_timezone(&Local),
+            );
+            return Ok(());
+        }
+        let segments = matching
             .into_iter()
             .filter(|s| s.start_time.with_timezone(&Local) >= start)
-            .filter(|s| s.start_time.with_timezone(&Local) <= end)
-            .filter(|s| s.tags.is_superset(&tags));
+            .filter(|s| s.start_time.with_timezone(&Local) <= end);
+
+        if self.format == Format::Json {
+            let events: Vec<json::Event> = segments
+                .map(|segment| {
+                    let note = timesheet.note(&segment.event_ref);
+                    json::Event::new(&segment, note)
+                })
+                .collect();
+            let total = events
+                .iter()
+                .fold(chrono::Duration::zero(), |total, event| {
+                    total + chrono::Duration::seconds(event.seconds)
+                });
+            let output = SummaryJson {
+                events: Some(events),
+                ..SummaryJson::new(start, end, total)
+            };
+            print!("{}", json::to_string(&output));
+            return Ok(());
+        }
+
+        if self.format == Format::Csv {
+            print!("{}", csv::HEADER);
+            for segment in segments {
+                print!(
+                    "{}",
+                    csv::row(
+                        segment.start_time,
+                        segment.end_time,
+                        segment.duration,
+                        &segment.tags
+                    )
+                );
+            }
+            return Ok(());
+        }
+
+        let short_refs = if self.show_refs {
+            timesheet.get_patched_timesheet().short_refs()
+        } else {
+            BTreeMap::new()
+        };
 
         let mut total_duration = chrono::Duration::seconds(0);
+        let mut day_duration = chrono::Duration::seconds(0);
         let mut current_date = None;
+        // The rows of the Markdown table, which is printed at the end
+        let mut rows: Vec<Vec<String>> = Vec::new();
 
-        if !self.show_ends {
-            println!("Date  Start Duration Total     Tags");
-            println!(
-                "――――― ――――― ―――――――― ――――――――  ――――――――"
-            );
-        } else {
-            println!("Date  Start End   Duration Total     Tags");
-            println!(
-                "――――― ――――― ――――― ―――――――― ――――――――  ――――――――"
-            );
+        match (self.format, self.show_ends) {
+            (Format::Markdown, _) | (Format::Json, _) | (Format::Csv, _) => {}
+            (Format::Text, false) => {
+                println!("Date  Start Duration Total     Tags");
+                println!("――――― ――――― ―――――――― ――――――――  ――――――――");
+            }
+            (Format::Text, true) => {
+                println!("Date  Start End   Duration Total     Tags");
+                println!("――――― ――――― ――――― ―――――――― ――――――――  ――――――――");
+            }
         }
         for segment in segments {
             let seg_datetime = segment.start_time.with_timezone(&chrono::Local);
             let seg_end_datetime = segment.end_time.with_timezone(&chrono::Local);
-            let seg_date = seg_datetime.date();
+            let seg_date = (seg_datetime - conf.day_offset()).date();
             let date_str = if current_date != Some(seg_date) {
+                if current_date.is_some() {
+                    self.day_total(day_duration, &mut rows);
+                }
                 current_date = Some(seg_date);
+                day_duration = chrono::Duration::seconds(0);
                 seg_date.format("%m/%d").to_string()
             } else {
                 String::from("     ")
@@ -67,11 +358,7 @@ impl SummaryCmd {
             let start_time = seg_datetime.format("%H:%M");
             let end_time = seg_end_datetime.format("%H:%M");
 
-            let reference = if self.show_refs {
-                Some(segment.event_ref.as_str())
-            } else {
-                None
-            };
+            let reference = short_refs.get(&segment.event_ref).map(String::as_str);
 
             let tags_str = segment
                 .tags
@@ -82,11 +369,23 @@ impl SummaryCmd {
                 .join(" ");
 
             total_duration = total_duration + segment.duration;
+            day_duration = day_duration + segment.duration;
 
             let duration_str = format_duration(segment.duration);
             let total_duration_str = format_duration(total_duration);
 
-            if !self.show_ends {
+            if self.format == Format::Markdown {
+                let mut row = vec![date_str.trim().to_string(), start_time.to_string()];
+                if self.show_ends {
+                    row.push(end_time.to_string());
+                }
+                row.extend(vec![duration_str, total_duration_str, tags_str]);
+                if self.show_notes {
+                    let note = timesheet.note(&segment.event_ref).unwrap_or_default();
+                    row.push(note.lines().collect::<Vec<_>>().join("<br>"));
+                }
+                rows.push(row);
+            } else if !self.show_ends {
                 println!(
                     "{} {} {: <8} {: <8} {}",
                     date_str, start_time, duration_str, total_duration_str, tags_str
@@ -97,14 +396,474 @@ impl SummaryCmd {
                     date_str, start_time, end_time, duration_str, total_duration_str, tags_str
                 );
             }
+            if self.show_notes && self.format == Format::Text {
+                if let Some(note) = timesheet.note(&segment.event_ref) {
+                    for line in note.lines() {
+                        println!("      {}", line);
+                    }
+                }
+            }
+        }
+        if current_date.is_some() {
+            self.day_total(day_duration, &mut rows);
+        }
+        if self.format == Format::Markdown {
+            let mut headers = vec!["Date", "Start"];
+            if self.show_ends {
+                headers.push("End");
+            }
+            headers.extend(vec!["Duration", "Total", "Tags"]);
+            if self.show_notes {
+                headers.push("Note");
+            }
+            print!("{}", markdown::table(&headers, &rows));
+        }
+        self.print_days_off(&days_off, start, end);
+        if !conf.targets.is_empty() {
+            println!();
+            print!(
+                "{}",
+                budget::render(
+                    timesheet,
+                    &conf.targets,
+                    &days_off,
+                    conf.week_starts_on,
+                    end.naive_local().date()
+                )
+            );
+        }
+        Ok(())
+    }
+
+    /// A table of the time per tag on each day from `start` to `end`
+    fn matrix(
+        &self,
+        matching: &[Segment],
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        offset: chrono::Duration,
+    ) -> String {
+        let mut table = String::new();
+        match self.format {
+            Format::Text => {
+                report::write_buckets(&mut table, matching, start, end, Period::Day, None, offset)
+            }
+            Format::Markdown => {
+                let (headers, rows) =
+                    report::bucket_table(matching, start, end, Period::Day, None, offset);
+                let columns: Vec<&str> = Some("Tag")
+                    .into_iter()
+                    .chain(headers.iter().map(String::as_str))
+                    .collect();
+                let rows: Vec<Vec<String>> = rows
+                    .into_iter()
+                    .map(|(label, durations)| {
+                        let cells = durations
+                            .into_iter()
+                            .map(|duration| report::format_cell(duration, &headers));
+                        Some(label).into_iter().chain(cells).collect()
+                    })
+                    .collect();
+                table = markdown::table(&columns, &rows);
+            }
+            Format::Json => {
+                let (_, rows) =
+                    report::bucket_table(matching, start, end, Period::Day, None, offset);
+                let matrix = BucketsJson::new(
+                    &report::bucket_starts(start, end, Period::Day, offset),
+                    &rows,
+                );
+                let total = chrono::Duration::seconds(matrix.total_seconds);
+                let output = SummaryJson {
+                    matrix: Some(matrix),
+                    ..SummaryJson::new(
+                        start.with_timezone(&Local),
+                        end.with_timezone(&Local),
+                        total,
+                    )
+                };
+                table = json::to_string(&output);
+            }
+            Format::Csv => {
+                let (_, rows) =
+                    report::bucket_table(matching, start, end, Period::Day, None, offset);
+                table = report::buckets_csv(
+                    &report::bucket_starts(start, end, Period::Day, offset),
+                    Some(Period::Day),
+                    start,
+                    end,
+                    offset,
+                    &rows,
+                );
+            }
+        }
+        table
+    }
+
+    fn print_days_off(&self, days_off: &DaysOff, start: DateTime<Local>, end: DateTime<Local>) {
+        if self.format == Format::Json || self.format == Format::Csv {
+            return;
+        }
+        let mut absences: BTreeMap<_, i64> = BTreeMap::new();
+        for (_date, kind) in days_off.between(start.naive_local().date(), end.naive_local().date())
+        {
+            *absences.entry(kind).or_default() += 1;
+        }
+        if !absences.is_empty() {
+            let counts: Vec<String> = absences
+                .iter()
+                .map(|(kind, days)| format!("{} {}", days, kind))
+                .collect();
+            println!();
+            println!("Days off: {}", counts.join(", "));
+        }
+    }
+
+    /// A line under each day's events with the time tracked that day, in the
+    /// duration column. Markdown tables get a row in `rows` instead.
+    fn day_total(&self, duration: chrono::Duration, rows: &mut Vec<Vec<String>>) {
+        if self.format == Format::Markdown {
+            let mut row = vec![String::new(), String::from("**Total**")];
+            if self.show_ends {
+                row.push(String::new());
+            }
+            row.extend(vec![
+                format!("**{}**", format_duration(duration)),
+                String::new(),
+                String::new(),
+            ]);
+            if self.show_notes {
+                row.push(String::new());
+            }
+            rows.push(row);
+            return;
+        }
+        let end_column = if self.show_ends { "      " } else { "" };
+        println!("      Total {}{}", end_column, format_duration(duration));
+    }
+}
+
+/// The time in each period or tag, clipped to the range, and the total.
+/// Periods start `day_offset` after midnight.
+fn groups(
+    segments: &[Segment],
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    group_by: GroupBy,
+    day_offset: chrono::Duration,
+) -> (Vec<(String, chrono::Duration)>, chrono::Duration) {
+    match group_by {
+        GroupBy::Tag => {
+            let totals = aggregate::by_tag(segments, start, end);
+            (totals.groups.into_iter().collect(), totals.total)
+        }
+        GroupBy::Period(period) => {
+            let totals = aggregate::by_period(
+                &aggregate::shift(segments, day_offset),
+                start - day_offset,
+                end - day_offset,
+                period,
+                &Local,
+            );
+            let rows = totals
+                .groups
+                .into_iter()
+                .map(|(date, duration)| {
+                    let label = match period {
+                        Period::Day => date.format("%Y-%m-%d %a").to_string(),
+                        Period::Week(_) => date.format("Week of %Y-%m-%d").to_string(),
+                        Period::Month => date.format("%Y-%m").to_string(),
+                    };
+                    (label, duration)
+                })
+                .collect();
+            (rows, totals.total)
+        }
+    }
+}
+
+/// A CSV row for the time in each period or tag, clipped to the range.
+/// Periods start `day_offset` after midnight.
+fn groups_csv(
+    segments: &[Segment],
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    group_by: GroupBy,
+    day_offset: chrono::Duration,
+) -> String {
+    let mut out = String::from(csv::HEADER);
+    match group_by {
+        GroupBy::Tag => {
+            for (tag, duration) in aggregate::by_tag(segments, start, end).groups {
+                out.push_str(&csv::row(start, end, duration, &[tag]));
+            }
+        }
+        GroupBy::Period(period) => {
+            let totals = aggregate::by_period(
+                &aggregate::shift(segments, day_offset),
+                start - day_offset,
+                end - day_offset,
+                period,
+                &Local,
+            );
+            for (first, duration) in totals.groups {
+                let (start, end) = report::bucket_range(first, period, start, end, day_offset);
+                out.push_str(&csv::row(start, end, duration, std::iter::empty::<&str>()));
+            }
         }
     }
+    out
+}
+
+/// A table of the time in each of the `groups`, followed by the total
+fn print_groups(rows: &[(String, chrono::Duration)], total: chrono::Duration) {
+    let width = rows
+        .iter()
+        .map(|(label, _)| label.len())
+        .max()
+        .unwrap_or(0)
+        .max(5);
+    for (label, duration) in rows.iter() {
+        println!(
+            "{: <width$} {}",
+            label,
+            format_duration(*duration),
+            width = width
+        );
+    }
+    println!(
+        "{: <width$} {}",
+        "Total",
+        format_duration(total),
+        width = width
+    );
+}
+
+/// Each tag's share of all the time tracked between `start` and `end`, biggest
+/// first, and the total. Events can have several tags, so the shares can add
+/// up to more than 1.
+fn shares(
+    segments: &[Segment],
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+) -> (Vec<(Tag, f64, chrono::Duration)>, chrono::Duration) {
+    let totals = aggregate::by_tag(segments, start, end);
+    let total = totals.total.num_seconds();
+    let mut tags: Vec<(Tag, f64, chrono::Duration)> = totals
+        .groups
+        .into_iter()
+        .map(|(tag, duration)| {
+            let share = match total {
+                0 => 0.0,
+                total => duration.num_seconds() as f64 / total as f64,
+            };
+            (tag, share, duration)
+        })
+        .collect();
+    tags.sort_by_key(|(_, _, duration)| std::cmp::Reverse(*duration));
+    (tags, totals.total)
+}
+
+/// A bar for each tag, as long as its share of all the time tracked between
+/// `start` and `end`
+fn breakdown(segments: &[Segment], start: DateTime<Utc>, end: DateTime<Utc>) -> String {
+    let (tags, total) = shares(segments, start, end);
+    let width = tags
+        .iter()
+        .map(|(tag, _, _)| tag.len())
+        .max()
+        .unwrap_or(0)
+        .max(5);
+
+    let mut out = String::new();
+    for (tag, share, duration) in tags {
+        let bar = "█".repeat((share * BREAKDOWN_WIDTH as f64).round() as usize);
+        writeln!(
+            out,
+            "{: <width$} {: <bar_width$} {: >5.1}% {}",
+            tag,
+            bar,
+            share * 100.0,
+            format_duration(duration),
+            width = width,
+            bar_width = BREAKDOWN_WIDTH
+        )
+        .unwrap();
+    }
+    writeln!(
+        out,
+        "{: <width$} {}",
+        "Total",
+        format_duration(total),
+        width = width
+    )
+    .unwrap();
+    out
 }
 
-fn default_start() -> DateTime<Local> {
-    Local::today().and_hms(0, 0, 0)
+/// Each tag's share of the time, as it's written in JSON
+fn breakdown_json(segments: &[Segment], start: DateTime<Utc>, end: DateTime<Utc>) -> String {
+    let (shares, total) = shares(segments, start, end);
+    let shares = shares
+        .into_iter()
+        .map(|(tag, share, duration)| ShareJson {
+            tag,
+            share,
+            seconds: duration.num_seconds(),
+        })
+        .collect();
+    let output = SummaryJson {
+        shares: Some(shares),
+        ..SummaryJson::new(
+            start.with_timezone(&Local),
+            end.with_timezone(&Local),
+            total,
+        )
+    };
+    json::to_string(&output)
 }
 
-fn default_end() -> DateTime<Local> {
-    Local::now()
+/// Each tag's share of the time as a Markdown table
+fn breakdown_markdown(segments: &[Segment], start: DateTime<Utc>, end: DateTime<Utc>) -> String {
+    let (tags, total) = shares(segments, start, end);
+    let rows: Vec<Vec<String>> = tags
+        .into_iter()
+        .map(|(tag, share, duration)| {
+            vec![
+                tag,
+                format!("{:.1}%", share * 100.0),
+                format_duration(duration),
+            ]
+        })
+        .chain(Some(vec![
+            String::from("**Total**"),
+            String::new(),
+            format_duration(total),
+        ]))
+        .collect();
+    markdown::table(&["Tag", "Share", "Time"], &rows)
+}
+
+impl GroupBy {
+    /// The heading of the column of group names
+    fn name(self) -> &'static str {
+        match self {
+            GroupBy::Tag => "Tag",
+            GroupBy::Period(Period::Day) => "Day",
+            GroupBy::Period(Period::Week(_)) => "Week",
+            GroupBy::Period(Period::Month) => "Month",
+        }
+    }
+}
+
+impl FromStr for GroupBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tag" => Ok(GroupBy::Tag),
+            _ => s.parse().map(GroupBy::Period).map_err(|_| {
+                format!(
+                    "can't group by \"{}\"; use \"day\", \"week\", \"month\", or \"tag\"",
+                    s
+                )
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn matrix_has_a_column_per_day() {
+        let time = |d, h| Local.ymd(2019, 7, d).and_hms(h, 0, 0).with_timezone(&Utc);
+        let segment = |start, end, tags: &[&str]| Segment {
+            event_ref: "a".into(),
+            start_time: start,
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            duration: end - start,
+            end_time: end,
+        };
+        let segments = vec![
+            segment(time(1, 9), time(1, 12), &["work"]),
+            segment(time(2, 9), time(2, 11), &["work"]),
+            segment(time(2, 11), time(2, 12), &["meeting"]),
+        ];
+        let matrix = |format: &str| {
+            SummaryCmd::from_iter_safe(&["summary", "--matrix", "--format", format])
+                .unwrap()
+                .matrix(&segments, time(1, 0), time(3, 0), chrono::Duration::zero())
+        };
+        assert_eq!(
+            matrix("text").lines().collect::<Vec<_>>(),
+            vec![
+                "        Mon 07-01 Tue 07-02 Total",
+                "meeting -         1h 0m     1h 0m",
+                "work    3h 0m     2h 0m     5h 0m",
+                "Total   3h 0m     3h 0m     6h 0m",
+            ]
+        );
+        assert_eq!(
+            matrix("markdown").lines().collect::<Vec<_>>(),
+            vec![
+                "| Tag | Mon 07-01 | Tue 07-02 | Total |",
+                "| --- | --- | --- | --- |",
+                "| meeting | - | 1h 0m | 1h 0m |",
+                "| work | 3h 0m | 2h 0m | 5h 0m |",
+                "| Total | 3h 0m | 3h 0m | 6h 0m |",
+            ]
+        );
+    }
+
+    #[test]
+    fn breakdown_shares() {
+        let time = |h| Utc.ymd(2019, 7, 1).and_hms(h, 0, 0);
+        let segment = |start, end, tags: &[&str]| Segment {
+            event_ref: "a".into(),
+            start_time: start,
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            duration: end - start,
+            end_time: end,
+        };
+        let segments = vec![
+            segment(time(9), time(12), &["work"]),
+            segment(time(12), time(13), &["lunch"]),
+        ];
+        let bar = |columns| format!("{: <30}", "█".repeat(columns));
+        assert_eq!(
+            breakdown(&segments, time(0), time(23))
+                .lines()
+                .collect::<Vec<_>>(),
+            vec![
+                format!("work  {}  75.0% 3h 0m", bar(23)),
+                format!("lunch {}  25.0% 1h 0m", bar(8)),
+                String::from("Total 4h 0m"),
+            ]
+        );
+        assert_eq!(
+            breakdown_markdown(&segments, time(0), time(23))
+                .lines()
+                .collect::<Vec<_>>(),
+            vec![
+                "| Tag | Share | Time |",
+                "| --- | --- | --- |",
+                "| work | 75.0% | 3h 0m |",
+                "| lunch | 25.0% | 1h 0m |",
+                "| **Total** |  | 4h 0m |",
+            ]
+        );
+        let json: serde_json::Value =
+            serde_json::from_str(&breakdown_json(&segments, time(0), time(23))).unwrap();
+        assert_eq!(json["total_seconds"], 4 * 3600);
+        assert_eq!(
+            json["shares"],
+            serde_json::json!([
+                { "tag": "work", "share": 0.75, "seconds": 3 * 3600 },
+                { "tag": "lunch", "share": 0.25, "seconds": 3600 },
+            ])
+        );
+    }
 }
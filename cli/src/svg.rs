@@ -0,0 +1,164 @@
+//! Writing charts as SVG images, for embedding in wikis and emails
+
+use crate::escape_html;
+use snafu::{ensure, ResultExt, Snafu};
+use std::{
+    fmt::Write,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// The colour of filled cells, which fades to nothing as cells get lighter
+const FILL: &str = "#216e39";
+
+/// The colour of cells with nothing in them
+const EMPTY: &str = "#ebedf0";
+
+/// The width of a character of the labels, roughly
+const CHAR_WIDTH: u32 = 7;
+
+const FONT_SIZE: u32 = 11;
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display(
+        "Images can only be saved as SVG; use a file name ending in .svg, not {}",
+        path.display()
+    ))]
+    UnsupportedImage { path: PathBuf },
+
+    #[snafu(display("Could not write image to {}: {}", path.display(), source))]
+    WriteImage { path: PathBuf, source: io::Error },
+}
+
+/// A grid of shaded cells, with a label for each row and for some columns
+#[derive(Debug)]
+pub struct Grid {
+    pub title: String,
+    pub row_labels: Vec<String>,
+    /// The columns that have labels above them, and their labels
+    pub column_labels: Vec<(usize, String)>,
+    /// How dark each cell is, from 0.0 for empty to 1.0, or `None` for no cell
+    pub cells: Vec<Vec<Option<f64>>>,
+    pub cell_width: u32,
+    pub cell_height: u32,
+}
+
+impl Grid {
+    pub fn to_svg(&self) -> String {
+        let label_width = self
+            .row_labels
+            .iter()
+            .map(|label| label.chars().count() as u32)
+            .max()
+            .unwrap_or(0)
+            * CHAR_WIDTH
+            + CHAR_WIDTH;
+        let columns = self.cells.iter().map(Vec::len).max().unwrap_or(0) as u32;
+        let top = FONT_SIZE * 4;
+        let width = label_width + columns * self.cell_width + CHAR_WIDTH;
+        let height = top + self.cells.len() as u32 * self.cell_height + CHAR_WIDTH;
+
+        let mut out = String::new();
+        writeln!(
+            out,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" font-family=\"sans-serif\" font-size=\"{}\">",
+            width, height, FONT_SIZE
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "<text x=\"0\" y=\"{}\" font-weight=\"bold\">{}</text>",
+            FONT_SIZE + 2,
+            escape_html(&self.title)
+        )
+        .unwrap();
+        for (column, label) in self.column_labels.iter() {
+            writeln!(
+                out,
+                "<text x=\"{}\" y=\"{}\">{}</text>",
+                label_width + *column as u32 * self.cell_width,
+                top - FONT_SIZE / 2,
+                escape_html(label)
+            )
+            .unwrap();
+        }
+        for (row, (label, cells)) in self.row_labels.iter().zip(self.cells.iter()).enumerate() {
+            let y = top + row as u32 * self.cell_height;
+            writeln!(
+                out,
+                "<text x=\"0\" y=\"{}\">{}</text>",
+                // The baseline, so that the text is centred on the row
+                y + (self.cell_height + FONT_SIZE) / 2 - 1,
+                escape_html(label)
+            )
+            .unwrap();
+            for (column, shade) in cells.iter().enumerate() {
+                let shade = match shade {
+                    Some(shade) => shade,
+                    None => continue,
+                };
+                let fill = if *shade > 0.0 {
+                    format!("fill=\"{}\" fill-opacity=\"{:.2}\"", FILL, shade.min(1.0))
+                } else {
+                    format!("fill=\"{}\"", EMPTY)
+                };
+                writeln!(
+                    out,
+                    "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" {}/>",
+                    label_width + column as u32 * self.cell_width,
+                    y + 1,
+                    self.cell_width.max(2) - 1,
+                    self.cell_height - 2,
+                    fill
+                )
+                .unwrap();
+            }
+        }
+        writeln!(out, "</svg>").unwrap();
+        out
+    }
+
+    /// Write the grid to `path`, which has to be an SVG file
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let is_svg = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("svg"));
+        ensure!(is_svg, UnsupportedImage { path });
+        fs::write(path, self.to_svg()).context(WriteImage { path })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn grid_as_svg() {
+        let grid = Grid {
+            title: String::from("Work & play"),
+            row_labels: vec![String::from("Mon"), String::from("Tue")],
+            column_labels: vec![(0, String::from("00"))],
+            cells: vec![vec![Some(1.0), Some(0.0)], vec![None, Some(0.5)]],
+            cell_width: 10,
+            cell_height: 14,
+        };
+        let svg = grid.to_svg();
+        let lines: Vec<&str> = svg.lines().collect();
+        assert!(lines[0].starts_with("<svg "));
+        assert!(
+            lines.contains(&"<text x=\"0\" y=\"13\" font-weight=\"bold\">Work &amp; play</text>")
+        );
+        assert_eq!(
+            lines
+                .iter()
+                .filter(|line| line.starts_with("<rect"))
+                .count(),
+            3
+        );
+        assert!(lines.contains(
+            &"<rect x=\"38\" y=\"59\" width=\"9\" height=\"12\" fill=\"#216e39\" fill-opacity=\"0.50\"/>"
+        ));
+        assert_eq!(lines.last(), Some(&"</svg>"));
+    }
+}
@@ -1,13 +1,18 @@
-use augr_core::{store::patch::AddTag, EventRef, Patch, Timesheet};
+use crate::alias;
+use augr_core::{
+    store::patch::{AddTag, RemoveTag},
+    EventRef, Patch, Timesheet,
+};
 use snafu::Snafu;
 use structopt::StructOpt;
 
 #[derive(StructOpt, Debug)]
 pub struct Cmd {
-    /// The id of the event to modify
-    event: EventRef,
+    /// The id of the event to modify, a unique prefix of it, or
+    /// `@` for the current event and `@-1`, `@-2`, ... for earlier ones today
+    pub event: EventRef,
 
-    /// A list of tags to append to the event
+    /// The tags to change. `+tag` or `tag` adds a tag, and `-tag` removes it.
     #[structopt(required = true)]
     tags: Vec<String>,
 }
@@ -16,7 +21,37 @@ pub struct Cmd {
 pub enum Error {
     #[snafu(display("Unknown event reference: {}", event_ref))]
     UnknownEventRef { event_ref: EventRef },
+
+    #[snafu(display("Event {} doesn't have the tag \"{}\"", event_ref, tag))]
+    MissingTag { event_ref: EventRef, tag: String },
+
+    #[snafu(display("\"{}\" is not a tag", text))]
+    EmptyTag { text: String },
+}
+
+/// A change to an event's tags
+#[derive(Debug, Eq, PartialEq)]
+enum Change<'a> {
+    Add(&'a str),
+    Remove(&'a str),
+}
+
+fn parse_change(text: &str) -> Result<Change<'_>, Error> {
+    let change = if let Some(tag) = text.strip_prefix('+') {
+        Change::Add(tag)
+    } else if let Some(tag) = text.strip_prefix('-') {
+        Change::Remove(tag)
+    } else {
+        Change::Add(text)
+    };
+    match change {
+        Change::Add("") | Change::Remove("") => Err(Error::EmptyTag {
+            text: text.to_string(),
+        }),
+        change => Ok(change),
+    }
 }
+
 impl Cmd {
     pub fn exec(&self, timesheet: &Timesheet) -> Result<Vec<Patch>, Error> {
         let event = timesheet
@@ -27,14 +62,49 @@ impl Cmd {
                 event_ref: self.event.clone(),
             })?;
         let parent_patches = event.latest_patches();
+        let current_tags = event.tags();
         let mut patch = Patch::new();
-        for tag in self.tags.iter().cloned() {
-            patch.insert_add_tag(AddTag {
-                parents: parent_patches.clone(),
-                event: self.event.clone(),
-                tag,
-            });
+        for text in self.tags.iter() {
+            match parse_change(text)? {
+                Change::Add(tag) => patch.insert_add_tag(AddTag {
+                    parents: parent_patches.clone(),
+                    event: self.event.clone(),
+                    tag: tag.to_string(),
+                }),
+                Change::Remove(tag) => {
+                    let tag = &alias::tag(tag);
+                    let mut removed = false;
+                    for (patch_ref, _) in current_tags.iter().filter(|(_, t)| t == tag) {
+                        patch.insert_remove_tag(RemoveTag {
+                            parents: Some(parent_patches.clone()),
+                            patch: *patch_ref,
+                            event: self.event.clone(),
+                            tag: tag.to_string(),
+                        });
+                        removed = true;
+                    }
+                    if !removed {
+                        return Err(Error::MissingTag {
+                            event_ref: self.event.clone(),
+                            tag: tag.to_string(),
+                        });
+                    }
+                }
+            }
         }
         Ok(vec![patch])
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_changes() {
+        assert_eq!(parse_change("+work").unwrap(), Change::Add("work"));
+        assert_eq!(parse_change("work").unwrap(), Change::Add("work"));
+        assert_eq!(parse_change("-meeting").unwrap(), Change::Remove("meeting"));
+        assert!(parse_change("-").is_err());
+    }
+}
@@ -0,0 +1,116 @@
+use augr_core::{
+    store::patch::{AddTag, RemoveTag},
+    Patch, Tag, Timesheet,
+};
+use snafu::Snafu;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct Cmd {
+    /// The tag to rename
+    old: Tag,
+
+    /// What to call it from now on
+    new: Tag,
+}
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("\"{}\" is already called that", tag))]
+    SameTag { tag: Tag },
+
+    #[snafu(display("No event is tagged \"{}\"", tag))]
+    UnusedTag { tag: Tag },
+}
+
+impl Cmd {
+    pub fn exec(&self, timesheet: &Timesheet) -> Result<Vec<Patch>, Error> {
+        if self.old == self.new {
+            return SameTag {
+                tag: self.old.clone(),
+            }
+            .fail();
+        }
+        let (patch, renamed) = rename(timesheet, &self.old, &self.new);
+        if renamed == 0 {
+            return UnusedTag {
+                tag: self.old.clone(),
+            }
+            .fail();
+        }
+        println!(
+            "Renamed \"{}\" to \"{}\" on {} events",
+            self.old, self.new, renamed
+        );
+        Ok(vec![patch])
+    }
+}
+
+/// A patch replacing `old` with `new` on every event, and how many events it
+/// changes. Unlike `retag`, this isn't limited to a range.
+fn rename(timesheet: &Timesheet, old: &Tag, new: &Tag) -> (Patch, usize) {
+    let mut patch = Patch::new();
+    let mut renamed = 0;
+    for (event_ref, event) in timesheet.get_patched_timesheet().events.iter() {
+        if event.is_deleted() {
+            continue;
+        }
+        let tags = event.tags();
+        if !tags.iter().any(|(_, tag)| tag == old) {
+            continue;
+        }
+        let parents = event.latest_patches();
+        for (patch_ref, tag) in tags.iter().filter(|(_, tag)| tag == old) {
+            patch.insert_remove_tag(RemoveTag {
+                parents: Some(parents.clone()),
+                patch: *patch_ref,
+                event: event_ref.clone(),
+                tag: tag.clone(),
+            });
+        }
+        if !tags.iter().any(|(_, tag)| tag == new) {
+            patch.insert_add_tag(AddTag {
+                parents,
+                event: event_ref.clone(),
+                tag: new.clone(),
+            });
+        }
+        renamed += 1;
+    }
+    (patch, renamed)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::Tracked;
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn rename_everywhere() {
+        let time = |d| Utc.ymd(2019, 7, d).and_hms(9, 0, 0);
+        let create = Patch::new()
+            .create_event("a".into(), time(1), vec!["ws".into()])
+            .create_event("b".into(), time(2), vec!["ws".into(), "workshop".into()])
+            .create_event("c".into(), time(3), vec!["lunch".into()]);
+        let tracked = Tracked::new(&[&create]);
+        let timesheet = tracked.timesheet();
+
+        let (patch, renamed) = rename(&timesheet, &"ws".into(), &"workshop".into());
+        assert_eq!(renamed, 2);
+        let tags: Vec<Vec<Tag>> = Tracked::new(&[&create, &patch])
+            .timesheet()
+            .segments()
+            .into_iter()
+            .map(|s| s.tags.into_iter().collect())
+            .collect();
+        assert_eq!(
+            tags,
+            vec![
+                vec![String::from("workshop")],
+                vec![String::from("workshop")],
+                vec![String::from("lunch")],
+            ]
+        );
+    }
+}
@@ -1,19 +1,89 @@
-use augr_core::Timesheet;
-use std::collections::BTreeSet;
+use crate::export::json;
+use augr_core::{Tag, Timesheet};
+use clap::arg_enum;
+use serde::Serialize;
 use structopt::StructOpt;
 
+arg_enum! {
+    /// Ways of listing tags
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    enum Format {
+        Text,
+        Json,
+    }
+}
+
 #[derive(StructOpt, Debug)]
-pub struct TagsCmd {}
+pub struct TagsCmd {
+    /// "text", or "json" for each tag with the number of events that have it
+    #[structopt(
+        long = "format",
+        default_value = "text",
+        possible_values = &Format::variants(),
+        case_insensitive = true
+    )]
+    format: Format,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+struct Tags {
+    tags: Vec<TagCount>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+struct TagCount {
+    tag: Tag,
+    events: usize,
+}
 
 impl TagsCmd {
     pub fn exec(&self, timesheet: &Timesheet) {
-        let tags = timesheet
-            .events()
-            .iter()
-            .fold(BTreeSet::new(), |acc, x| acc.union(x.1).cloned().collect());
-
-        for tag in tags {
-            println!("{}", tag);
+        match self.format {
+            Format::Text => {
+                for tag in timesheet.tag_counts().keys() {
+                    println!("{}", tag);
+                }
+            }
+            Format::Json => print!("{}", json::to_string(&tags(timesheet))),
         }
     }
 }
+
+fn tags(timesheet: &Timesheet) -> Tags {
+    let tags = timesheet
+        .tag_counts()
+        .into_iter()
+        .map(|(tag, events)| TagCount { tag, events })
+        .collect();
+    Tags { tags }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::Tracked;
+    use augr_core::Patch;
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn tags_as_json() {
+        let time = |h| Utc.ymd(2019, 7, 22).and_hms(h, 0, 0);
+        let create = Patch::new()
+            .create_event("a".into(), time(9), vec!["work".into(), "coding".into()])
+            .create_event("b".into(), time(10), vec!["work".into()]);
+        let tracked = Tracked::new(&[&create]);
+        let timesheet = tracked.timesheet();
+        let value: serde_json::Value =
+            serde_json::from_str(&json::to_string(&tags(&timesheet))).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "version": 1,
+                "tags": [
+                    { "tag": "coding", "events": 1 },
+                    { "tag": "work", "events": 2 },
+                ],
+            })
+        );
+    }
+}
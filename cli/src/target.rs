@@ -0,0 +1,128 @@
+use chrono::{Datelike, Duration, NaiveDate};
+use serde::{de, Deserialize, Deserializer};
+use std::fmt;
+
+/// An amount of time that should be spent on something each period, written
+/// like `40h/week` in the config file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Target {
+    pub duration: Duration,
+    pub period: Period,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Period {
+    Day,
+    Week,
+    Month,
+}
+
+impl Target {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut parts = text.splitn(2, '/');
+        let duration_str = parts.next().unwrap_or("").trim();
+        let period = match parts.next().map(str::trim) {
+            Some("day") => Period::Day,
+            Some("week") => Period::Week,
+            Some("month") => Period::Month,
+            Some(other) => return Err(format!("unknown period \"{}\"", other)),
+            None => return Err(format!("target \"{}\" is missing a period", text)),
+        };
+        let duration = ::parse_duration::parse(duration_str)
+            .ok()
+            .and_then(|d| Duration::from_std(d).ok())
+            .ok_or_else(|| format!("invalid duration \"{}\"", duration_str))?;
+        Ok(Self { duration, period })
+    }
+
+    /// The amount of time that should be tracked on `date`. Each period's
+    /// target is spread evenly over its days that `is_workday`, so months
+    /// of different lengths each get the whole target.
+    pub fn for_date(&self, date: NaiveDate, is_workday: impl Fn(NaiveDate) -> bool) -> Duration {
+        if !is_workday(date) {
+            return Duration::zero();
+        }
+        let workdays = match self.period {
+            Period::Day => 1,
+            // Every week has each day of the week once
+            Period::Week => (0..7)
+                .map(|days| date + Duration::days(days))
+                .filter(|day| is_workday(*day))
+                .count(),
+            Period::Month => {
+                let first = date.with_day(1).expect("every month has a first day");
+                (0..31)
+                    .map(|days| first + Duration::days(days))
+                    .take_while(|day| day.month() == date.month())
+                    .filter(|day| is_workday(*day))
+                    .count()
+            }
+        };
+        self.duration / workdays as i32
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let period = match self.period {
+            Period::Day => "day",
+            Period::Week => "week",
+            Period::Month => "month",
+        };
+        write!(f, "{}/{}", crate::format_duration(self.duration), period)
+    }
+}
+
+impl<'de> Deserialize<'de> for Target {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        Target::parse(&text).map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::Weekday;
+
+    #[test]
+    fn parse_hours_per_week() {
+        assert_eq!(
+            Target::parse("40h/week"),
+            Ok(Target {
+                duration: Duration::hours(40),
+                period: Period::Week,
+            })
+        );
+    }
+
+    #[test]
+    fn spread_over_days() {
+        let every_day = |_| true;
+        let weekdays =
+            |day: NaiveDate| day.weekday() != Weekday::Sat && day.weekday() != Weekday::Sun;
+        let monday = NaiveDate::from_ymd(2019, 7, 1);
+        let saturday = NaiveDate::from_ymd(2019, 7, 6);
+
+        let week = Target::parse("35h/week").unwrap();
+        assert_eq!(week.for_date(monday, every_day), Duration::hours(5));
+        assert_eq!(week.for_date(monday, weekdays), Duration::hours(7));
+        assert_eq!(week.for_date(saturday, weekdays), Duration::zero());
+
+        // July has 31 days, 23 of them weekdays, and February 2019 has 28, 20
+        // of them weekdays
+        let month = Target::parse("155h/month").unwrap();
+        assert_eq!(month.for_date(monday, every_day), Duration::hours(5));
+        let february = NaiveDate::from_ymd(2019, 2, 1);
+        assert_eq!(
+            month.for_date(february, weekdays),
+            Duration::minutes(155 * 60 / 20)
+        );
+        assert_eq!(month.for_date(saturday, weekdays), Duration::zero());
+    }
+
+    #[test]
+    fn missing_period() {
+        assert!(Target::parse("40h").is_err());
+    }
+}
@@ -0,0 +1,132 @@
+//! Talks to Taskwarrior through its command line program, `task`.
+
+use serde::Deserialize;
+use snafu::{ensure, ResultExt, Snafu};
+use std::process::Command;
+
+/// The metadata key used to link an event to the Taskwarrior task it was
+/// started from
+pub const TASK_METADATA: &str = "taskwarrior-uuid";
+
+/// The parts of a task exported by `task export` that augr uses
+#[derive(Deserialize, Debug, Clone)]
+pub struct Task {
+    pub uuid: String,
+    pub description: String,
+    pub project: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Could not run taskwarrior: {}", source))]
+    RunTask { source: std::io::Error },
+
+    #[snafu(display("Taskwarrior exited with an error: {}", stderr))]
+    TaskFailed { stderr: String },
+
+    #[snafu(display("Could not read taskwarrior's output: {}", source))]
+    ParseExport { source: serde_json::Error },
+
+    #[snafu(display("No task matches {}", id))]
+    NoSuchTask { id: String },
+
+    #[snafu(display("{} matches several tasks:\n{}", id, matches))]
+    AmbiguousTask { id: String, matches: String },
+}
+
+impl Task {
+    /// Tags for an event spent working on this task: the project, followed
+    /// by the task's own tags
+    pub fn augr_tags(&self) -> Vec<String> {
+        self.project
+            .iter()
+            .chain(self.tags.iter())
+            .cloned()
+            .collect()
+    }
+}
+
+/// Look up a task by its id or uuid
+pub fn get_task(id: &str) -> Result<Task, Error> {
+    let output = Command::new("task")
+        .args(["rc.verbose=nothing", id, "export"])
+        .output()
+        .context(RunTask {})?;
+    ensure!(
+        output.status.success(),
+        TaskFailed {
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        }
+    );
+    let tasks: Vec<Task> = serde_json::from_slice(&output.stdout).context(ParseExport {})?;
+    only_task(id, tasks)
+}
+
+/// The one task `id` matched, or an error listing what it matched instead
+fn only_task(id: &str, mut tasks: Vec<Task>) -> Result<Task, Error> {
+    ensure!(!tasks.is_empty(), NoSuchTask { id });
+    ensure!(
+        tasks.len() == 1,
+        AmbiguousTask {
+            id,
+            matches: tasks
+                .iter()
+                .map(|task| format!(
+                    "  {} {}",
+                    &task.uuid[..8.min(task.uuid.len())],
+                    task.description
+                ))
+                .collect::<Vec<_>>()
+                .join("\n"),
+        }
+    );
+    Ok(tasks.remove(0))
+}
+
+/// Set a user defined attribute of a task
+pub fn set_attribute(uuid: &str, attribute: &str, value: &str) -> Result<(), Error> {
+    let output = Command::new("task")
+        .args(["rc.verbose=nothing", uuid, "modify"])
+        .arg(format!("{}:{}", attribute, value))
+        .output()
+        .context(RunTask {})?;
+    ensure!(
+        output.status.success(),
+        TaskFailed {
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        }
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn filters_must_match_one_task() {
+        let task = |uuid: &str, description: &str| Task {
+            uuid: uuid.into(),
+            description: description.into(),
+            project: None,
+            tags: vec![],
+        };
+        assert!(only_task("+next", vec![]).is_err());
+        let found = only_task("+next", vec![task("0123456789", "Write report")]).unwrap();
+        assert_eq!(found.description, "Write report");
+
+        let several = vec![
+            task("0123456789", "Write report"),
+            task("abcdefabcd", "Review report"),
+        ];
+        match only_task("report", several) {
+            Err(error @ Error::AmbiguousTask { .. }) => assert_eq!(
+                error.to_string(),
+                "report matches several tasks:\n  01234567 Write report\n  abcdefab Review report"
+            ),
+            other => panic!("expected an ambiguous match, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,59 @@
+//! Fixtures shared by the tests
+
+use crate::config::Conf;
+use augr_core::{
+    repository::{event::Resolution, timesheet::PatchedTimesheet},
+    FixedClock, Patch, Timesheet,
+};
+use chrono::{DateTime, Utc};
+
+/// Patches applied to an empty timesheet, to be flattened by the tests
+pub struct Tracked {
+    pub patched: PatchedTimesheet,
+    clock: Option<FixedClock>,
+}
+
+impl Tracked {
+    pub fn new(patches: &[&Patch]) -> Self {
+        let mut tracked = Self {
+            patched: PatchedTimesheet::new(),
+            clock: None,
+        };
+        for patch in patches {
+            tracked.apply(patch);
+        }
+        tracked
+    }
+
+    /// End the last event at `now`, instead of at the system clock's time
+    pub fn at(mut self, now: DateTime<Utc>) -> Self {
+        self.clock = Some(FixedClock(now));
+        self
+    }
+
+    pub fn apply(&mut self, patch: &Patch) {
+        self.patched.apply_patch(patch).unwrap();
+    }
+
+    pub fn timesheet(&self) -> Timesheet<'_> {
+        self.with_clock(self.patched.flatten().unwrap())
+    }
+
+    /// The timesheet, with conflicting start times settled by `resolution`
+    pub fn timesheet_with(&self, resolution: Resolution) -> Timesheet<'_> {
+        self.with_clock(self.patched.flatten_with(resolution).unwrap())
+    }
+
+    fn with_clock<'a>(&'a self, timesheet: Timesheet<'a>) -> Timesheet<'a> {
+        match &self.clock {
+            Some(clock) => timesheet.with_clock(clock),
+            None => timesheet,
+        }
+    }
+}
+
+/// A config with only the required settings, followed by `extra`
+pub fn conf(extra: &str) -> Conf {
+    let text = format!("sync_folder = \"/tmp\"\ndevice_id = \"a\"\n{}", extra);
+    toml::de::from_str(&text).unwrap()
+}
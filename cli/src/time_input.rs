@@ -1,5 +1,69 @@
-use chrono::{Date, DateTime, Datelike, Duration, Local, NaiveTime, TimeZone};
-use std::ffi::{OsStr, OsString};
+use augr_core::{Clock, FixedClock, SystemClock};
+use chrono::{Date, DateTime, Datelike, Duration, Local, NaiveDate, NaiveTime, TimeZone, Utc};
+use snafu::{ResultExt, Snafu};
+use std::{
+    ffi::{OsStr, OsString},
+    io,
+};
+use structopt::StructOpt;
+
+/// Date ranges longer than this are probably a typo, like 2014 instead of 2024
+pub const MAX_RANGE_YEARS: i64 = 2;
+
+/// The clock that decides what "now" is. Setting `AUGR_NOW` to an RFC 3339
+/// datetime pins the time, which is useful for testing.
+pub fn clock() -> Box<dyn Clock> {
+    std::env::var("AUGR_NOW")
+        .ok()
+        .and_then(|now| DateTime::parse_from_rfc3339(&now).ok())
+        .map(|now| Box::new(FixedClock(now.with_timezone(&Utc))) as Box<dyn Clock>)
+        .unwrap_or_else(|| Box::new(SystemClock))
+}
+
+/// Stops commands from running over a suspiciously long date range unless the
+/// user confirms it or passes `--force`
+#[derive(StructOpt, Debug, Default)]
+pub struct RangeGuard {
+    /// Allow date ranges longer than two years
+    #[structopt(long = "force")]
+    force: bool,
+}
+
+#[derive(Debug, Snafu)]
+pub enum RangeError {
+    #[snafu(display(
+        "{} to {} spans more than {} years; pass --force if that is what you meant",
+        start,
+        end,
+        MAX_RANGE_YEARS
+    ))]
+    TooLong { start: NaiveDate, end: NaiveDate },
+
+    #[snafu(display("Could not read confirmation: {}", source))]
+    ReadConfirmation { source: io::Error },
+}
+
+impl RangeGuard {
+    /// Check that `start..=end` isn't too long. When stdin is a terminal the
+    /// user is asked to confirm a long range instead of getting an error.
+    pub fn check(&self, start: NaiveDate, end: NaiveDate) -> Result<(), RangeError> {
+        if self.force || !is_too_long(start, end) {
+            return Ok(());
+        }
+        let question = format!(
+            "{} to {} spans more than {} years. Continue?",
+            start, end, MAX_RANGE_YEARS
+        );
+        match crate::prompt::confirm(&question).context(ReadConfirmation {})? {
+            Some(true) => Ok(()),
+            _ => TooLong { start, end }.fail(),
+        }
+    }
+}
+
+fn is_too_long(start: NaiveDate, end: NaiveDate) -> bool {
+    end.signed_duration_since(start) > Duration::days(MAX_RANGE_YEARS * 366)
+}
 
 pub trait Context {
     type TZ: TimeZone;
@@ -31,11 +95,24 @@ pub fn parse_default_local(text: &OsStr) -> Result<DateTime<Local>, OsString> {
         }
     }
 
-    let c = LocalContext(Local::now());
-    parse(&c, text).map_err(|_| OsString::from("No valid date, time, or duration was found"))
+    let c = LocalContext(clock().now().with_timezone(&Local));
+    parse(&c, text).map_err(|_| {
+        OsString::from(
+            "No valid date, time, or duration was found. Try something like \"09:30\", \
+             \"yesterday 9am\", or \"10 minutes ago\".",
+        )
+    })
 }
 
+/// Parse a point in time relative to `c`. Accepts full datetimes, dates
+/// ("2019-07-16", "7-16", "yesterday"), times of day ("09:30", "9am"), a date
+/// followed by a time ("yesterday 9am"), and durations into the past ("20min",
+/// "10 minutes ago").
 pub fn parse<C: Context>(c: &C, text: &str) -> Result<DateTime<C::TZ>, ()> {
+    let text = text.trim();
+    if text.eq_ignore_ascii_case("now") {
+        return Ok(c.now().clone());
+    }
     attempt!(parse_datetime(c.tz(), text));
     if let Ok(date) = parse_date(c, text) {
         return Ok(date.and_hms(0, 0, 0));
@@ -48,12 +125,40 @@ pub fn parse<C: Context>(c: &C, text: &str) -> Result<DateTime<C::TZ>, ()> {
             return Ok(yesterday.and_time(time).unwrap());
         }
     }
-    if let Ok(Ok(duration)) = ::parse_duration::parse(text).map(Duration::from_std) {
+    // Dates never have spaces in them, but times like "9:30 pm" can
+    if let Some((date_text, time_text)) = text.split_once(char::is_whitespace) {
+        if let (Ok(date), Ok(time)) = (parse_date(c, date_text), parse_time(c, time_text.trim())) {
+            return date.and_time(time).ok_or(());
+        }
+    }
+    let duration_text = match text.strip_suffix("ago") {
+        Some(rest) => rest.trim_end(),
+        None => text,
+    };
+    if let Ok(Ok(duration)) = ::parse_duration::parse(duration_text).map(Duration::from_std) {
         return Ok(c.now().clone() - duration);
     }
     Err(())
 }
 
+/// Round `datetime` to the nearest multiple of `interval`
+pub fn snap<T: TimeZone>(datetime: DateTime<T>, interval: Duration) -> DateTime<T> {
+    let interval = interval.num_seconds();
+    if interval <= 0 {
+        return datetime;
+    }
+    let timestamp = datetime.timestamp();
+    let remainder = timestamp.rem_euclid(interval);
+    let offset = if remainder * 2 >= interval {
+        interval - remainder
+    } else {
+        -remainder
+    };
+    let whole_seconds =
+        datetime.clone() - Duration::nanoseconds(datetime.timestamp_subsec_nanos().into());
+    whole_seconds + Duration::seconds(offset)
+}
+
 fn parse_datetime<T: TimeZone>(tz: &T, text: &str) -> Result<DateTime<T>, ()> {
     if let Ok(datetime) = DateTime::parse_from_rfc3339(text) {
         return Ok(datetime.with_timezone(tz));
@@ -63,6 +168,11 @@ fn parse_datetime<T: TimeZone>(tz: &T, text: &str) -> Result<DateTime<T>, ()> {
 }
 
 fn parse_date<C: Context>(c: &C, text: &str) -> Result<Date<C::TZ>, ()> {
+    match text.to_lowercase().as_str() {
+        "today" => return Ok(c.now().date()),
+        "yesterday" => return Ok(c.now().date() - Duration::days(1)),
+        _ => {}
+    }
     if let Ok(parsed) = format_parse(fmts::FULL_DATE, text) {
         return Ok(c.tz().ymd(
             parsed.year.unwrap(),
@@ -85,7 +195,30 @@ fn parse_time<C: Context>(_c: &C, text: &str) -> Result<NaiveTime, ()> {
         let _ = parsed.set_second(0);
         return parsed.to_naive_time().map_err(|_| ());
     }
-    Err(())
+    parse_twelve_hour_time(text)
+}
+
+/// Parse times like "9am", "9:30 pm", or "12am"
+fn parse_twelve_hour_time(text: &str) -> Result<NaiveTime, ()> {
+    let text = text.to_lowercase();
+    let (clock, is_pm) = if let Some(clock) = text.strip_suffix("am") {
+        (clock, false)
+    } else if let Some(clock) = text.strip_suffix("pm") {
+        (clock, true)
+    } else {
+        return Err(());
+    };
+    let mut parts = clock.trim_end().splitn(2, ':');
+    let hour: u32 = parts.next().unwrap_or("").parse().map_err(|_| ())?;
+    let minute: u32 = match parts.next() {
+        Some(minute) => minute.parse().map_err(|_| ())?,
+        None => 0,
+    };
+    if hour == 0 || hour > 12 {
+        return Err(());
+    }
+    let hour = hour % 12 + if is_pm { 12 } else { 0 };
+    NaiveTime::from_hms_opt(hour, minute, 0).ok_or(())
 }
 
 fn format_parse(fmt: &[chrono::format::Item], text: &str) -> Result<chrono::format::Parsed, ()> {
@@ -124,6 +257,7 @@ mod fmts {
 }
 
 #[cfg(test)]
+#[allow(clippy::zero_prefixed_literal)]
 mod test {
     use super::*;
     use chrono::Utc;
@@ -200,6 +334,79 @@ mod test {
         );
     }
 
+    #[test]
+    fn long_ranges() {
+        let start = NaiveDate::from_ymd(2014, 7, 1);
+        assert!(is_too_long(start, NaiveDate::from_ymd(2024, 7, 1)));
+        assert!(!is_too_long(start, NaiveDate::from_ymd(2016, 7, 1)));
+        assert!(!is_too_long(start, start));
+        assert!(RangeGuard { force: true }
+            .check(start, NaiveDate::from_ymd(2024, 7, 1))
+            .is_ok());
+    }
+
+    #[test]
+    fn snap_to_5_minutes() {
+        let interval = Duration::minutes(5);
+        assert_eq!(
+            snap(Utc.ymd(2019, 7, 16).and_hms(9, 2, 29), interval),
+            Utc.ymd(2019, 7, 16).and_hms(9, 0, 0)
+        );
+        assert_eq!(
+            snap(Utc.ymd(2019, 7, 16).and_hms(9, 2, 30), interval),
+            Utc.ymd(2019, 7, 16).and_hms(9, 5, 0)
+        );
+        assert_eq!(
+            snap(Utc.ymd(2019, 7, 16).and_hms(9, 5, 0), interval),
+            Utc.ymd(2019, 7, 16).and_hms(9, 5, 0)
+        );
+    }
+
+    #[test]
+    fn twelve_hour_times() {
+        let c = DummyContext::new();
+        assert_eq!(Ok(Utc.ymd(2019, 7, 16).and_hms(9, 0, 0)), parse(&c, "9am"));
+        assert_eq!(
+            Ok(Utc.ymd(2019, 7, 16).and_hms(12, 30, 0)),
+            parse(&c, "12:30 PM")
+        );
+        assert_eq!(Ok(Utc.ymd(2019, 7, 16).and_hms(0, 0, 0)), parse(&c, "12am"));
+        assert_eq!(Err(()), parse(&c, "13pm"));
+    }
+
+    #[test]
+    fn relative_dates() {
+        let c = DummyContext::new();
+        assert_eq!(
+            Ok(Utc.ymd(2019, 7, 16).and_hms(0, 0, 0)),
+            parse(&c, "today")
+        );
+        assert_eq!(
+            Ok(Utc.ymd(2019, 7, 15).and_hms(9, 0, 0)),
+            parse(&c, "yesterday 9am")
+        );
+        assert_eq!(
+            Ok(Utc.ymd(2019, 7, 15).and_hms(21, 30, 0)),
+            parse(&c, "yesterday 9:30 pm")
+        );
+        assert_eq!(
+            Ok(Utc.ymd(2019, 7, 14).and_hms(17, 45, 0)),
+            parse(&c, "7-14 17:45")
+        );
+        assert_eq!(
+            Ok(Utc.ymd(2019, 7, 16).and_hms(19, 25, 0)),
+            parse(&c, "now")
+        );
+    }
+
+    #[test]
+    fn duration_ago() {
+        assert_eq!(
+            Ok(Utc.ymd(2019, 7, 16).and_hms(19, 15, 0)),
+            parse(&DummyContext::new(), "10 minutes ago")
+        );
+    }
+
     #[test]
     fn duration_20minutes() {
         assert_eq!(
@@ -0,0 +1,153 @@
+use crate::{format_duration, time_input::parse_default_local};
+use augr_core::{aggregate, Timesheet};
+use chrono::{DateTime, Duration, Local, NaiveDate, Timelike, Utc};
+use std::fmt::Write;
+use structopt::StructOpt;
+
+/// The number of columns that the tracked part of the day is spread over
+const WIDTH: i64 = 48;
+
+#[derive(StructOpt, Debug)]
+pub struct Cmd {
+    /// The day to show, like "yesterday". Defaults to today.
+    #[structopt(parse(try_from_os_str = parse_default_local))]
+    day: Option<DateTime<Local>>,
+}
+
+impl Cmd {
+    pub fn exec(&self, timesheet: &Timesheet) {
+        let day = self
+            .day
+            .unwrap_or_else(|| timesheet.clock().now().with_timezone(&Local));
+        print!("{}", render(timesheet, day.naive_local().date()));
+    }
+}
+
+/// A row for each event on `date`, with a bar showing when it happened. The
+/// bars only span the hours from the first event to the end of the last one.
+fn render(timesheet: &Timesheet, date: NaiveDate) -> String {
+    let day_start = aggregate::midnight(date, &Local);
+    let day_end = aggregate::midnight(date.succ(), &Local);
+    let rows: Vec<(DateTime<Utc>, DateTime<Utc>, String)> = timesheet
+        .segments()
+        .into_iter()
+        .filter(|s| !s.tags.is_empty())
+        .map(|s| {
+            let tags = s.tags.iter().cloned().collect::<Vec<_>>().join(" ");
+            (s.start_time.max(day_start), s.end_time.min(day_end), tags)
+        })
+        .filter(|(start, end, _)| start < end)
+        .collect();
+
+    let mut out = String::new();
+    writeln!(out, "Timeline for {}", date.format("%a %Y-%m-%d")).unwrap();
+    let (first, last) = match (rows.first(), rows.last()) {
+        (Some(first), Some(last)) => (first.0, last.1),
+        _ => {
+            writeln!(out, "Nothing was tracked").unwrap();
+            return out;
+        }
+    };
+    let span_start = floor_hour(first);
+    let span_end = ceil_hour(last);
+    let seconds = (span_end - span_start).num_seconds();
+    let column = |time: DateTime<Utc>| (time - span_start).num_seconds() * WIDTH / seconds;
+
+    // Label the hours that fit without running into the previous label
+    let mut axis = String::new();
+    let mut hour = span_start;
+    while hour < span_end {
+        let position = column(hour) as usize;
+        if position >= axis.len() + usize::from(!axis.is_empty()) {
+            axis.push_str(&" ".repeat(position - axis.len()));
+            write!(axis, "{:02}", hour.with_timezone(&Local).hour()).unwrap();
+        }
+        hour = hour + Duration::hours(1);
+    }
+    writeln!(out, "            {}", axis).unwrap();
+
+    for (start, end, tags) in rows {
+        let from = column(start);
+        let to = column(end).max(from + 1).min(WIDTH);
+        let bar = format!(
+            "{}{}{}",
+            " ".repeat(from as usize),
+            "█".repeat((to - from) as usize),
+            " ".repeat((WIDTH - to) as usize)
+        );
+        writeln!(
+            out,
+            "{}-{} {} {} {}",
+            start.with_timezone(&Local).format("%H:%M"),
+            end.with_timezone(&Local).format("%H:%M"),
+            bar,
+            tags,
+            format_duration(end - start)
+        )
+        .unwrap();
+    }
+    out
+}
+
+/// The start of the local hour that `time` is in
+fn floor_hour(time: DateTime<Utc>) -> DateTime<Utc> {
+    let local = time.with_timezone(&Local);
+    time - Duration::minutes(local.minute().into())
+        - Duration::seconds(local.second().into())
+        - Duration::nanoseconds(local.nanosecond().into())
+}
+
+/// The end of the local hour that `time` is in, or `time` if it's on the hour
+fn ceil_hour(time: DateTime<Utc>) -> DateTime<Utc> {
+    let floor = floor_hour(time);
+    if floor == time {
+        time
+    } else {
+        floor + Duration::hours(1)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::Tracked;
+    use augr_core::Patch;
+    use chrono::TimeZone;
+
+    #[test]
+    fn timeline_bars() {
+        let time = |h, m| Local.ymd(2019, 7, 24).and_hms(h, m, 0).with_timezone(&Utc);
+        let create = Patch::new()
+            .create_event("a".into(), time(9, 0), vec!["work".into()])
+            .create_event("b".into(), time(12, 0), vec!["lunch".into()])
+            .create_event("c".into(), time(12, 30), vec![])
+            .create_event(
+                "d".into(),
+                time(13, 0),
+                vec!["work".into(), "review".into()],
+            )
+            .create_event("e".into(), time(14, 40), vec![]);
+        let tracked = Tracked::new(&[&create]).at(time(20, 0));
+        let timesheet = tracked.timesheet();
+
+        let timeline = render(&timesheet, NaiveDate::from_ymd(2019, 7, 24));
+        let bar = |from: usize, to: usize| {
+            format!(
+                "{}{}{}",
+                " ".repeat(from),
+                "█".repeat(to - from),
+                " ".repeat(48 - to)
+            )
+        };
+        assert_eq!(
+            timeline.lines().collect::<Vec<_>>(),
+            vec![
+                String::from("Timeline for Wed 2019-07-24"),
+                String::from("            09      10      11      12      13      14"),
+                format!("09:00-12:00 {} work 3h 0m", bar(0, 24)),
+                format!("12:00-12:30 {} lunch 30m", bar(24, 28)),
+                format!("13:00-14:40 {} review work 1h 40m", bar(32, 45)),
+            ]
+        );
+    }
+}
@@ -0,0 +1,80 @@
+use augr_core::{
+    repository::timesheet::PatchedTimesheet,
+    store::{SyncFolderStore, SyncFolderStoreError},
+    Patch, PatchRef, Repository, Store,
+};
+use snafu::{ResultExt, Snafu};
+use std::collections::BTreeSet;
+use structopt::StructOpt;
+
+/// Patch metadata that records which patch an undo patch reverts
+pub const UNDOES_METADATA: &str = "undoes";
+
+#[derive(StructOpt, Debug)]
+pub struct Cmd {}
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Nothing to undo"))]
+    NothingToUndo,
+
+    #[snafu(display("Could not read patch {}: {}", patch, source))]
+    ReadPatch {
+        patch: PatchRef,
+        source: SyncFolderStoreError,
+    },
+
+    #[snafu(display(
+        "Patch {} can't be undone, because later changes conflict with it",
+        patch
+    ))]
+    CantUndo { patch: PatchRef },
+}
+
+impl Cmd {
+    /// The patch that undoes the last change. It only says what it would undo
+    /// if the caller won't `save` it.
+    pub fn exec(
+        &self,
+        repo: &Repository<SyncFolderStore>,
+        save: bool,
+    ) -> Result<Vec<Patch>, Error> {
+        let patch = last_undoable_patch(repo)?.ok_or(Error::NothingToUndo)?;
+        let patch_ref = *patch.patch_ref();
+
+        let inverse = patch
+            .invert(repo.timesheet())
+            .with_metadata(UNDOES_METADATA, patch_ref.to_string());
+        let mut timesheet: PatchedTimesheet = repo.timesheet().clone();
+        timesheet
+            .apply_patch(&inverse)
+            .map_err(|_| Error::CantUndo { patch: patch_ref })?;
+
+        if save {
+            println!("Undid {}", patch_ref);
+        } else {
+            println!("Would undo {} (nothing was saved)", patch_ref);
+        }
+        Ok(vec![inverse])
+    }
+}
+
+/// The most recent patch added on this device that isn't an undo and hasn't
+/// been undone already. Running `undo` again keeps stepping back.
+fn last_undoable_patch(repo: &Repository<SyncFolderStore>) -> Result<Option<Patch>, Error> {
+    let mut undone = BTreeSet::new();
+    for patch_ref in repo.meta().added_patches().iter().rev() {
+        let patch = repo
+            .store()
+            .get_patch(patch_ref)
+            .context(ReadPatch { patch: *patch_ref })?;
+        if let Some(target) = patch.metadata.get(UNDOES_METADATA) {
+            undone.insert(target.clone());
+            continue;
+        }
+        if !undone.contains(&patch_ref.to_string()) {
+            return Ok(Some(patch));
+        }
+    }
+    Ok(None)
+}
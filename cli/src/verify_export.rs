@@ -0,0 +1,58 @@
+use crate::{
+    config::Conf,
+    signature::{self, Signature},
+};
+use augr_core::Timesheet;
+use chrono::Local;
+use snafu::{OptionExt, ResultExt, Snafu};
+use std::{fs, io, path::PathBuf};
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct Cmd {
+    /// The signature file written by `augr export --signature`
+    signature: PathBuf,
+}
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Could not read {}: {}", path.display(), source))]
+    ReadSignature { path: PathBuf, source: io::Error },
+
+    #[snafu(display("{}", source))]
+    ParseSignature { source: signature::ParseError },
+
+    #[snafu(display("Set `export_key` in the config to verify exports"))]
+    NoExportKey,
+
+    #[snafu(display("The export doesn't match the tracked time from {} to {}", start, end))]
+    Mismatch { start: String, end: String },
+}
+
+impl Cmd {
+    /// Check the signature against `timesheet`, which includes the events
+    /// started in private mode in case they were signed
+    pub fn exec(&self, timesheet: &Timesheet, conf: &Conf) -> Result<(), Error> {
+        let key = conf.export_key.as_ref().context(NoExportKey {})?;
+        let text = fs::read_to_string(&self.signature).context(ReadSignature {
+            path: self.signature.clone(),
+        })?;
+        let signature: Signature = text.trim().parse().context(ParseSignature {})?;
+
+        let format = |t: chrono::DateTime<chrono::Utc>| {
+            t.with_timezone(&Local).format("%Y-%m-%d %H:%M").to_string()
+        };
+        if !signature.verify(timesheet, key) {
+            return Err(Error::Mismatch {
+                start: format(signature.start),
+                end: format(signature.end),
+            });
+        }
+        println!(
+            "The export matches the tracked time from {} to {}",
+            format(signature.start),
+            format(signature.end)
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,132 @@
+use crate::{config::Conf, format_duration, time_input::parse_default_local};
+use augr_core::{
+    aggregate::{self, Period, Totals},
+    Tag, Timesheet,
+};
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate};
+use std::fmt::Write;
+use structopt::StructOpt;
+
+/// How many tags are listed for each day
+const TOP_TAGS: usize = 3;
+
+#[derive(StructOpt, Debug)]
+pub struct Cmd {
+    /// Any day in the week to show, like "7 days ago". Defaults to this week.
+    #[structopt(parse(try_from_os_str = parse_default_local))]
+    day: Option<DateTime<Local>>,
+}
+
+impl Cmd {
+    pub fn exec(&self, timesheet: &Timesheet, conf: &Conf) {
+        let day = self
+            .day
+            .unwrap_or_else(|| timesheet.clock().now().with_timezone(&Local));
+        print!(
+            "{}",
+            render(timesheet, conf.week(), day.naive_local().date())
+        );
+    }
+}
+
+/// Each day of the `week` that `day` is in, with the time tracked and the tags
+/// that got the most of it
+fn render(timesheet: &Timesheet, week: Period, day: NaiveDate) -> String {
+    let first = week.start_of(day);
+    let end = week.next(first);
+    let last = end.pred();
+    let days = aggregate::by_period_and_tag(
+        &timesheet.segments(),
+        aggregate::midnight(first, &Local),
+        aggregate::midnight(end, &Local),
+        Period::Day,
+        &Local,
+    );
+    // The ISO week that most of the days are in, even if weeks start on Sunday
+    let week = (first + Duration::days(3)).iso_week();
+
+    let mut out = String::new();
+    writeln!(
+        out,
+        "Week {} of {} ({} to {})",
+        week.week(),
+        week.year(),
+        first.format("%a %m-%d"),
+        last.format("%a %m-%d")
+    )
+    .unwrap();
+    let empty = Totals::new();
+    let mut total = Duration::zero();
+    for date in (0..(end - first).num_days()).map(|days| first + Duration::days(days)) {
+        let day = days.get(&date).unwrap_or(&empty);
+        total = total + day.total;
+        let mut tags: Vec<(&Tag, &Duration)> = day.groups.iter().collect();
+        tags.sort_by_key(|(_, duration)| std::cmp::Reverse(**duration));
+        let top: Vec<String> = tags
+            .into_iter()
+            .take(TOP_TAGS)
+            .map(|(tag, duration)| format!("{} {}", tag, format_duration(*duration)))
+            .collect();
+        let line = format!(
+            "{} {: <8} {}",
+            date.format("%a %m-%d"),
+            format_duration(day.total),
+            top.join(", ")
+        );
+        writeln!(out, "{}", line.trim_end()).unwrap();
+    }
+    writeln!(out, "Total     {}", format_duration(total)).unwrap();
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::Tracked;
+    use augr_core::Patch;
+    use chrono::{TimeZone, Utc, Weekday};
+
+    #[test]
+    fn week_lists_every_day() {
+        let time = |d, h| Local.ymd(2019, 7, d).and_hms(h, 0, 0).with_timezone(&Utc);
+        let create = Patch::new()
+            .create_event("a".into(), time(21, 9), vec!["work".into()])
+            .create_event("a2".into(), time(21, 17), vec![])
+            .create_event("b".into(), time(22, 9), vec!["work".into()])
+            .create_event("c".into(), time(22, 12), vec!["lunch".into()])
+            .create_event(
+                "d".into(),
+                time(22, 13),
+                vec!["work".into(), "review".into()],
+            )
+            .create_event("e".into(), time(22, 14), vec![])
+            .create_event("f".into(), time(25, 9), vec!["meeting".into()])
+            .create_event("g".into(), time(25, 10), vec![]);
+        let tracked = Tracked::new(&[&create]).at(time(29, 0));
+        let timesheet = tracked.timesheet();
+
+        let monday = Period::Week(Weekday::Mon);
+        let week = render(&timesheet, monday, NaiveDate::from_ymd(2019, 7, 24));
+        assert_eq!(
+            week.lines().collect::<Vec<_>>(),
+            vec![
+                "Week 30 of 2019 (Mon 07-22 to Sun 07-28)",
+                "Mon 07-22 5h 0m    work 4h 0m, lunch 1h 0m, review 1h 0m",
+                "Tue 07-23 0m",
+                "Wed 07-24 0m",
+                "Thu 07-25 1h 0m    meeting 1h 0m",
+                "Fri 07-26 0m",
+                "Sat 07-27 0m",
+                "Sun 07-28 0m",
+                "Total     6h 0m",
+            ]
+        );
+
+        let sunday = Period::Week(Weekday::Sun);
+        let week = render(&timesheet, sunday, NaiveDate::from_ymd(2019, 7, 24));
+        assert_eq!(
+            week.lines().next().unwrap(),
+            "Week 30 of 2019 (Sun 07-21 to Sat 07-27)"
+        );
+    }
+}
@@ -0,0 +1,63 @@
+use crate::history::describe;
+use augr_core::{
+    repository::Error as RepositoryError,
+    store::{SyncFolderStore, SyncFolderStoreError},
+    Repository,
+};
+use chrono::Local;
+use snafu::{ResultExt, Snafu};
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct Cmd {
+    /// Show the changes again next time
+    #[structopt(long = "peek")]
+    peek: bool,
+}
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Could not read patch arrivals: {}", source))]
+    ReadArrivals {
+        source: RepositoryError<SyncFolderStoreError>,
+    },
+
+    #[snafu(display("Could not save patch arrivals: {}", source))]
+    SaveArrivals {
+        source: RepositoryError<SyncFolderStoreError>,
+    },
+}
+
+impl Cmd {
+    /// List the changes made by patches that arrived from other devices since
+    /// the last time this command was run. They are only marked as seen when
+    /// `save` is set.
+    pub fn exec(&self, repo: &mut Repository<SyncFolderStore>, save: bool) -> Result<(), Error> {
+        let arrivals = repo.arrivals().context(ReadArrivals {})?;
+        let unchecked = arrivals.unchecked();
+        if unchecked.is_empty() {
+            println!("Nothing new");
+            return Ok(());
+        }
+
+        let events = &repo.timesheet().events;
+        for (patch_ref, arrived) in unchecked.iter() {
+            println!(
+                "{} arrived {}",
+                patch_ref,
+                arrived.with_timezone(&Local).format("%Y-%m-%d %H:%M")
+            );
+            for (event_ref, event) in events.iter() {
+                for operation in event.history().iter().filter(|o| o.patch == *patch_ref) {
+                    println!("  {} {}", event_ref, describe(&operation.kind));
+                }
+            }
+        }
+
+        if save && !self.peek {
+            let (_patch, last_arrival) = unchecked[unchecked.len() - 1];
+            repo.check_arrivals(last_arrival).context(SaveArrivals {})?;
+        }
+        Ok(())
+    }
+}
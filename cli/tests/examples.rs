@@ -0,0 +1,204 @@
+//! Runs every example from `augr examples` on the timesheet in
+//! `tests/examples`, and checks that it prints what was saved for it in
+//! `tests/examples/output`. Set `AUGR_BLESS=1` to save the output instead,
+//! after making sure the changes are the ones you meant. `week.sig` and the
+//! golden report in `sync/goldens` were made from the sample timesheet, and
+//! need to be made again if it changes.
+#![cfg(unix)]
+
+use regex::Regex;
+use std::{
+    env,
+    ffi::OsString,
+    fs, io,
+    path::{Path, PathBuf},
+    process::{self, Command, Stdio},
+};
+
+/// When the examples run: Friday afternoon, while the last event of the
+/// timesheet is still going
+const NOW: &str = "2019-07-26T16:30:00Z";
+
+/// A command line from `augr examples`
+struct Example {
+    description: String,
+    line: String,
+}
+
+fn fixture() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/examples")
+}
+
+/// Run augr in `dir`, with the example's config, a fixed time and timezone,
+/// and the stand-in for Taskwarrior in `dir/bin`. Editors exit without
+/// changing anything, and nothing can be typed in.
+fn augr(dir: &Path, args: &[String]) -> process::Output {
+    let mut path = OsString::from(dir.join("bin"));
+    path.push(":");
+    path.push(env::var_os("PATH").unwrap_or_default());
+    Command::new(env!("CARGO_BIN_EXE_augr"))
+        .arg("--config")
+        .arg("config.toml")
+        .args(args)
+        .current_dir(dir)
+        .env("AUGR_NOW", NOW)
+        .env("TZ", "UTC")
+        .env("PATH", path)
+        .env("VISUAL", "true")
+        .env_remove("EDITOR")
+        .stdin(Stdio::null())
+        .output()
+        .unwrap()
+}
+
+/// The examples that `augr examples` prints, as pairs of a "# description"
+/// line and an "augr ..." line
+fn examples(dir: &Path) -> Vec<Example> {
+    let output = augr(dir, &["examples".into()]);
+    assert!(output.status.success());
+    let text = String::from_utf8(output.stdout).unwrap();
+    text.split("\n\n")
+        .map(|block| {
+            let mut lines = block.lines();
+            let description = lines.next().unwrap().trim_start_matches("# ").into();
+            let line = lines.next().unwrap().trim_start_matches("augr ").into();
+            Example { description, line }
+        })
+        .collect()
+}
+
+/// Split an example's command line into arguments, the way a shell would for
+/// the quoting that `augr examples` uses
+fn split(line: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut arg = String::new();
+    let mut quoted = false;
+    for c in line.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            ' ' if !quoted => args.push(std::mem::take(&mut arg)),
+            c => arg.push(c),
+        }
+    }
+    args.push(arg);
+    args
+}
+
+/// The name the output of the example described by `description` is saved
+/// under, like "start-working-on-something-now"
+fn name(description: &str) -> String {
+    description
+        .to_lowercase()
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+fn copy_dir(from: &Path, to: &Path) -> io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            copy_dir(&path, &to.join(entry.file_name()))?;
+        } else {
+            fs::copy(&path, to.join(entry.file_name()))?;
+        }
+    }
+    Ok(())
+}
+
+/// The names and contents of every file in `dir`, in one string
+fn contents(dir: &Path) -> String {
+    let mut text = String::new();
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        text.push_str(&path.to_string_lossy());
+        if path.is_dir() {
+            text.push_str(&contents(&path));
+        } else {
+            text.push_str(&String::from_utf8_lossy(&fs::read(&path).unwrap()));
+        }
+    }
+    text
+}
+
+/// What running `example` printed, with the ids it made up replaced by
+/// `<new id>` so that the output is the same every time
+fn run(example: &Example, dir: &Path, known: &str) -> String {
+    let output = augr(dir, &split(&example.line));
+    let mut text = format!("$ augr {}\n", example.line);
+    text.push_str(&String::from_utf8_lossy(&output.stdout));
+    text.push_str(&String::from_utf8_lossy(&output.stderr));
+    if !output.status.success() {
+        text.push_str(&format!(
+            "(exit status {})\n",
+            output.status.code().unwrap_or(-1)
+        ));
+    }
+    let id = Regex::new("[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12}").unwrap();
+    id.replace_all(&text, |found: &regex::Captures| {
+        if known.contains(&found[0]) {
+            found[0].to_string()
+        } else {
+            String::from("<new id>")
+        }
+    })
+    .into_owned()
+}
+
+#[test]
+fn examples_print_their_saved_output() {
+    let fixture = fixture();
+    let saved = fixture.join("output");
+    let known = contents(&fixture.join("sync"));
+    let bless = env::var_os("AUGR_BLESS").is_some();
+    let root = env::temp_dir().join(format!("augr-examples-{}", process::id()));
+
+    let mut names = Vec::new();
+    let mut failures = Vec::new();
+    for example in examples(&fixture) {
+        let name = name(&example.description);
+        let dir = root.join(&name);
+        copy_dir(&fixture, &dir).unwrap();
+        fs::remove_dir_all(dir.join("output")).ok();
+        let output = run(&example, &dir, &known);
+
+        let path = saved.join(format!("{}.txt", name));
+        if bless {
+            fs::create_dir_all(&saved).unwrap();
+            fs::write(&path, &output).unwrap();
+        } else {
+            match fs::read_to_string(&path) {
+                Ok(expected) if expected == output => {}
+                Ok(expected) => failures.push(format!(
+                    "{} changed\n--- saved\n{}--- now\n{}",
+                    path.display(),
+                    expected,
+                    output
+                )),
+                Err(_) => failures.push(format!("{} is missing\n{}", path.display(), output)),
+            }
+        }
+        names.push(format!("{}.txt", name));
+    }
+    fs::remove_dir_all(&root).unwrap();
+
+    // Output saved for examples that were removed or reworded
+    for entry in fs::read_dir(&saved).unwrap() {
+        let file = entry.unwrap().file_name().to_string_lossy().into_owned();
+        if !names.contains(&file) {
+            if bless {
+                fs::remove_file(saved.join(&file)).unwrap();
+            } else {
+                failures.push(format!("{} isn't the output of any example", file));
+            }
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "{}\n\nRun with AUGR_BLESS=1 to save the new output",
+        failures.join("\n\n")
+    );
+}
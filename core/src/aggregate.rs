@@ -0,0 +1,554 @@
+//! Totals of tracked time over a range, by tag, by calendar period, or by
+//! hour of the day. Time outside the range is left out, and a segment that
+//! crosses from one period into the next counts towards both.
+
+use crate::{
+    duration::{Rounding, RoundingPer},
+    timesheet::Segment,
+    Tag,
+};
+use chrono::{DateTime, Datelike, Duration, NaiveDate, TimeZone, Timelike, Utc, Weekday};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, convert::TryFrom, fmt, str::FromStr};
+
+/// A calendar period that time can be bucketed into. Periods are written by
+/// name, so a week is "week" whichever day it starts on.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub enum Period {
+    Day,
+    /// Weeks that start on the given day
+    Week(Weekday),
+    Month,
+}
+
+/// How much time each group got, and how much time was tracked altogether.
+/// Only tagged time is counted. An event with several tags counts towards
+/// each of them, so the groups can add up to more than the total.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Totals<K: Ord> {
+    pub groups: BTreeMap<K, Duration>,
+    pub total: Duration,
+}
+
+impl<K: Ord> Totals<K> {
+    pub fn new() -> Self {
+        Self {
+            groups: BTreeMap::new(),
+            total: Duration::zero(),
+        }
+    }
+
+    fn add(&mut self, key: K, duration: Duration) {
+        let group = self.groups.entry(key).or_insert_with(Duration::zero);
+        *group = *group + duration;
+    }
+
+    /// Round each group and the total, if `rounding` is per total
+    pub fn round(&mut self, rounding: &Rounding) {
+        if rounding.per != RoundingPer::Total {
+            return;
+        }
+        for duration in self.groups.values_mut() {
+            *duration = rounding.round(*duration);
+        }
+        self.total = rounding.round(self.total);
+    }
+}
+
+impl<K: Ord> Default for Totals<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Period {
+    /// This period, with weeks starting on `weekday`
+    pub fn with_week_start(self, weekday: Weekday) -> Self {
+        match self {
+            Period::Week(_) => Period::Week(weekday),
+            period => period,
+        }
+    }
+
+    /// The first day of the period that `date` is in
+    pub fn start_of(self, date: NaiveDate) -> NaiveDate {
+        match self {
+            Period::Day => date,
+            Period::Week(weekday) => {
+                let days = (date.weekday().num_days_from_monday() + 7
+                    - weekday.num_days_from_monday())
+                    % 7;
+                date - Duration::days(days.into())
+            }
+            Period::Month => date.with_day(1).expect("every month has a first day"),
+        }
+    }
+
+    /// The first day of the period after the one starting on `start`
+    pub fn next(self, start: NaiveDate) -> NaiveDate {
+        match self {
+            Period::Day => start.succ(),
+            Period::Week(_) => start + Duration::weeks(1),
+            Period::Month if start.month() == 12 => NaiveDate::from_ymd(start.year() + 1, 1, 1),
+            Period::Month => NaiveDate::from_ymd(start.year(), start.month() + 1, 1),
+        }
+    }
+}
+
+/// When `date` starts in `timezone`. If a daylight saving change skipped
+/// midnight, the day starts at the first time the clocks showed, like 01:00.
+pub fn midnight<Tz: TimeZone>(date: NaiveDate, timezone: &Tz) -> DateTime<Utc> {
+    let naive = date.and_hms(0, 0, 0);
+    (0..24 * 60)
+        .filter_map(|minutes| {
+            timezone
+                .from_local_datetime(&(naive + Duration::minutes(minutes)))
+                .earliest()
+        })
+        .next()
+        .map(|midnight| midnight.with_timezone(&Utc))
+        .unwrap_or_else(|| DateTime::from_utc(naive, Utc))
+}
+
+/// The part of `segment` between `start` and `end`, if any
+fn clip(
+    segment: &Segment,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+    let clipped = (segment.start_time.max(start), segment.end_time.min(end));
+    Some(clipped).filter(|(start, end)| !segment.tags.is_empty() && start < end)
+}
+
+/// `segments` moved `offset` earlier, so that days split at `offset` past
+/// midnight instead of at midnight, like for someone who works until 02:00
+/// and wants that time on the day before. Ranges should be moved the same way.
+pub fn shift(segments: &[Segment], offset: Duration) -> Vec<Segment> {
+    segments
+        .iter()
+        .map(|segment| Segment {
+            start_time: segment.start_time - offset,
+            end_time: segment.end_time - offset,
+            ..segment.clone()
+        })
+        .collect()
+}
+
+/// The time spent on each tag between `start` and `end`
+pub fn by_tag(segments: &[Segment], start: DateTime<Utc>, end: DateTime<Utc>) -> Totals<Tag> {
+    let mut totals = Totals::new();
+    for segment in segments {
+        if let Some((from, to)) = clip(segment, start, end) {
+            totals.total = totals.total + (to - from);
+            for tag in segment.tags.iter() {
+                totals.add(tag.clone(), to - from);
+            }
+        }
+    }
+    totals
+}
+
+/// Like `by_tag`, with either the part of each segment in the range or the
+/// totals rounded, depending on `rounding`
+pub fn by_tag_rounded(
+    segments: &[Segment],
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    rounding: &Rounding,
+) -> Totals<Tag> {
+    let mut totals = Totals::new();
+    for segment in segments {
+        if let Some((from, to)) = clip(segment, start, end) {
+            let duration = round_event(to - from, rounding);
+            totals.total = totals.total + duration;
+            for tag in segment.tags.iter() {
+                totals.add(tag.clone(), duration);
+            }
+        }
+    }
+    totals.round(rounding);
+    totals
+}
+
+/// The time tracked in each `period` between `start` and `end`, keyed by the
+/// first day of the period. Periods follow the calendar of `timezone`.
+pub fn by_period<Tz: TimeZone>(
+    segments: &[Segment],
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    period: Period,
+    timezone: &Tz,
+) -> Totals<NaiveDate> {
+    let mut totals = Totals::new();
+    for segment in segments {
+        split_by_period(segment, start, end, period, timezone, |key, duration| {
+            totals.total = totals.total + duration;
+            totals.add(key, duration);
+        });
+    }
+    totals
+}
+
+/// The time spent in each hour of the day between `start` and `end`, keyed
+/// by the hour in `timezone` from 0 to 23, adding up all the days in the range
+pub fn by_hour<Tz: TimeZone>(
+    segments: &[Segment],
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    timezone: &Tz,
+) -> Totals<u32> {
+    let mut totals = Totals::new();
+    for (mut from, to) in segments.iter().filter_map(|s| clip(s, start, end)) {
+        totals.total = totals.total + (to - from);
+        while from < to {
+            let local = from.with_timezone(timezone);
+            let into_hour = Duration::minutes(local.minute().into())
+                + Duration::seconds(local.second().into())
+                + Duration::nanoseconds(local.nanosecond().into());
+            let next = (from - into_hour + Duration::hours(1)).min(to);
+            totals.add(local.hour(), next - from);
+            from = next;
+        }
+    }
+    totals
+}
+
+/// The time spent on each tag in each `period` between `start` and `end`,
+/// keyed by the first day of the period
+pub fn by_period_and_tag<Tz: TimeZone>(
+    segments: &[Segment],
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    period: Period,
+    timezone: &Tz,
+) -> BTreeMap<NaiveDate, Totals<Tag>> {
+    let mut buckets: BTreeMap<NaiveDate, Totals<Tag>> = BTreeMap::new();
+    for segment in segments {
+        split_by_period(segment, start, end, period, timezone, |key, duration| {
+            let bucket = buckets.entry(key).or_default();
+            bucket.total = bucket.total + duration;
+            for tag in segment.tags.iter() {
+                bucket.add(tag.clone(), duration);
+            }
+        });
+    }
+    buckets
+}
+
+/// Like `by_period_and_tag`, with either the part of each segment in each
+/// period or the totals of each period rounded, depending on `rounding`
+pub fn by_period_and_tag_rounded<Tz: TimeZone>(
+    segments: &[Segment],
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    period: Period,
+    timezone: &Tz,
+    rounding: &Rounding,
+) -> BTreeMap<NaiveDate, Totals<Tag>> {
+    let mut buckets: BTreeMap<NaiveDate, Totals<Tag>> = BTreeMap::new();
+    for segment in segments {
+        split_by_period(segment, start, end, period, timezone, |key, duration| {
+            let duration = round_event(duration, rounding);
+            let bucket = buckets.entry(key).or_default();
+            bucket.total = bucket.total + duration;
+            for tag in segment.tags.iter() {
+                bucket.add(tag.clone(), duration);
+            }
+        });
+    }
+    for bucket in buckets.values_mut() {
+        bucket.round(rounding);
+    }
+    buckets
+}
+
+fn round_event(duration: Duration, rounding: &Rounding) -> Duration {
+    match rounding.per {
+        RoundingPer::Event => rounding.round(duration),
+        RoundingPer::Total => duration,
+    }
+}
+
+/// Call `add` with each period that the part of `segment` between `start`
+/// and `end` falls in, and how much of it falls there
+fn split_by_period<Tz: TimeZone>(
+    segment: &Segment,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    period: Period,
+    timezone: &Tz,
+    mut add: impl FnMut(NaiveDate, Duration),
+) {
+    let (mut from, to) = match clip(segment, start, end) {
+        Some(clipped) => clipped,
+        None => return,
+    };
+    while from < to {
+        let key = period.start_of(from.with_timezone(timezone).naive_local().date());
+        let boundary = midnight(period.next(key), timezone).min(to);
+        add(key, boundary - from);
+        from = boundary;
+    }
+}
+
+/// The parts of the time between `start` and `end` that no tagged segment
+/// covers, like time after `stop` or before the first event, in order
+pub fn gaps(
+    segments: &[Segment],
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+) -> Vec<(DateTime<Utc>, DateTime<Utc>)> {
+    let mut covered: Vec<(DateTime<Utc>, DateTime<Utc>)> = segments
+        .iter()
+        .filter_map(|segment| clip(segment, start, end))
+        .collect();
+    covered.sort();
+
+    let mut gaps = Vec::new();
+    let mut from = start;
+    for (covered_start, covered_end) in covered {
+        if covered_start > from {
+            gaps.push((from, covered_start));
+        }
+        from = from.max(covered_end);
+    }
+    if from < end {
+        gaps.push((from, end));
+    }
+    gaps
+}
+
+impl FromStr for Period {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "day" => Ok(Period::Day),
+            "week" => Ok(Period::Week(Weekday::Mon)),
+            "month" => Ok(Period::Month),
+            _ => Err(format!(
+                "\"{}\" isn't a period; use \"day\", \"week\", or \"month\"",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Period {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Period::Day => write!(f, "day"),
+            Period::Week(_) => write!(f, "week"),
+            Period::Month => write!(f, "month"),
+        }
+    }
+}
+
+impl From<Period> for String {
+    fn from(period: Period) -> Self {
+        period.to_string()
+    }
+}
+
+impl TryFrom<String> for Period {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::duration::RoundingMode;
+    use chrono::{FixedOffset, LocalResult, NaiveDateTime};
+
+    /// São Paulo in 2018, where the clocks went from 00:00 straight to 01:00
+    /// on November 4th
+    #[derive(Copy, Clone, Debug)]
+    struct SaoPaulo;
+
+    impl SaoPaulo {
+        fn change() -> NaiveDateTime {
+            NaiveDate::from_ymd(2018, 11, 4).and_hms(0, 0, 0)
+        }
+    }
+
+    impl TimeZone for SaoPaulo {
+        type Offset = FixedOffset;
+
+        fn from_offset(_offset: &FixedOffset) -> Self {
+            SaoPaulo
+        }
+
+        fn offset_from_local_date(&self, local: &NaiveDate) -> LocalResult<FixedOffset> {
+            self.offset_from_local_datetime(&local.and_hms(12, 0, 0))
+        }
+
+        fn offset_from_local_datetime(&self, local: &NaiveDateTime) -> LocalResult<FixedOffset> {
+            if *local < Self::change() {
+                LocalResult::Single(FixedOffset::west(3 * 3600))
+            } else if *local < Self::change() + Duration::hours(1) {
+                LocalResult::None
+            } else {
+                LocalResult::Single(FixedOffset::west(2 * 3600))
+            }
+        }
+
+        fn offset_from_utc_date(&self, utc: &NaiveDate) -> FixedOffset {
+            self.offset_from_utc_datetime(&utc.and_hms(12, 0, 0))
+        }
+
+        fn offset_from_utc_datetime(&self, utc: &NaiveDateTime) -> FixedOffset {
+            if *utc < Self::change() + Duration::hours(3) {
+                FixedOffset::west(3 * 3600)
+            } else {
+                FixedOffset::west(2 * 3600)
+            }
+        }
+    }
+
+    #[test]
+    fn skipped_midnight_starts_day_at_first_valid_time() {
+        let date = |d| NaiveDate::from_ymd(2018, 11, d);
+        assert_eq!(
+            midnight(date(3), &SaoPaulo),
+            Utc.ymd(2018, 11, 3).and_hms(3, 0, 0)
+        );
+        // 01:00 at UTC-2
+        assert_eq!(
+            midnight(date(4), &SaoPaulo),
+            Utc.ymd(2018, 11, 4).and_hms(3, 0, 0)
+        );
+        assert_eq!(
+            midnight(date(5), &SaoPaulo),
+            Utc.ymd(2018, 11, 5).and_hms(2, 0, 0)
+        );
+    }
+
+    fn segment(start: DateTime<Utc>, end: DateTime<Utc>, tags: &[&str]) -> Segment {
+        Segment {
+            event_ref: "a".into(),
+            start_time: start,
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            duration: end - start,
+            end_time: end,
+        }
+    }
+
+    #[test]
+    fn periods_split_segments() {
+        let time = |m, d, h| Utc.ymd(2019, m, d).and_hms(h, 0, 0);
+        let segments = vec![
+            // Wednesday night into Thursday
+            segment(time(7, 31, 22), time(8, 1, 2), &["work"]),
+            segment(time(8, 1, 2), time(8, 1, 3), &[]),
+            segment(time(8, 5, 9), time(8, 5, 12), &["work", "coding"]),
+        ];
+        let start = time(7, 31, 23);
+        let end = time(8, 31, 0);
+
+        let days = by_period(&segments, start, end, Period::Day, &Utc);
+        assert_eq!(days.total, Duration::hours(6));
+        assert_eq!(
+            days.groups.into_iter().collect::<Vec<_>>(),
+            vec![
+                (NaiveDate::from_ymd(2019, 7, 31), Duration::hours(1)),
+                (NaiveDate::from_ymd(2019, 8, 1), Duration::hours(2)),
+                (NaiveDate::from_ymd(2019, 8, 5), Duration::hours(3)),
+            ]
+        );
+
+        let rounding = |per| Rounding {
+            increment: Duration::hours(2),
+            mode: RoundingMode::Up,
+            per,
+        };
+        let per_event = by_tag_rounded(&segments, start, end, &rounding(RoundingPer::Event));
+        assert_eq!(per_event.total, Duration::hours(8));
+        assert_eq!(per_event.groups["coding"], Duration::hours(4));
+        let per_total = by_tag_rounded(&segments, start, end, &rounding(RoundingPer::Total));
+        assert_eq!(per_total.total, Duration::hours(6));
+        assert_eq!(per_total.groups["coding"], Duration::hours(4));
+
+        // Days that start at 03:00 put the hours after midnight on July 31st
+        let shifted = by_period(
+            &shift(&segments, Duration::hours(3)),
+            start - Duration::hours(3),
+            end - Duration::hours(3),
+            Period::Day,
+            &Utc,
+        );
+        assert_eq!(
+            shifted.groups.into_iter().collect::<Vec<_>>(),
+            vec![
+                (NaiveDate::from_ymd(2019, 7, 31), Duration::hours(3)),
+                (NaiveDate::from_ymd(2019, 8, 5), Duration::hours(3)),
+            ]
+        );
+
+        let hours = by_hour(&segments, start, end, &Utc);
+        assert_eq!(hours.total, Duration::hours(6));
+        assert_eq!(
+            hours.groups.into_iter().collect::<Vec<_>>(),
+            vec![
+                (0, Duration::hours(1)),
+                (1, Duration::hours(1)),
+                (9, Duration::hours(1)),
+                (10, Duration::hours(1)),
+                (11, Duration::hours(1)),
+                (23, Duration::hours(1)),
+            ]
+        );
+
+        let weeks = by_period(&segments, start, end, Period::Week(Weekday::Mon), &Utc);
+        assert_eq!(
+            weeks.groups.into_iter().collect::<Vec<_>>(),
+            vec![
+                (NaiveDate::from_ymd(2019, 7, 29), Duration::hours(3)),
+                (NaiveDate::from_ymd(2019, 8, 5), Duration::hours(3)),
+            ]
+        );
+
+        let sunday_weeks = Period::Week(Weekday::Sun);
+        assert_eq!(
+            sunday_weeks.start_of(NaiveDate::from_ymd(2019, 8, 3)),
+            NaiveDate::from_ymd(2019, 7, 28)
+        );
+        let weeks = by_period(&segments, start, end, sunday_weeks, &Utc);
+        assert_eq!(
+            weeks.groups.into_iter().collect::<Vec<_>>(),
+            vec![
+                (NaiveDate::from_ymd(2019, 7, 28), Duration::hours(3)),
+                (NaiveDate::from_ymd(2019, 8, 4), Duration::hours(3)),
+            ]
+        );
+
+        let months = by_period(&segments, start, end, Period::Month, &Utc);
+        assert_eq!(months.groups.len(), 2);
+
+        let tags_by_week =
+            by_period_and_tag(&segments, start, end, Period::Week(Weekday::Mon), &Utc);
+        let second_week = &tags_by_week[&NaiveDate::from_ymd(2019, 8, 5)];
+        assert_eq!(second_week.total, Duration::hours(3));
+        assert_eq!(second_week.groups["coding"], Duration::hours(3));
+        assert!(!tags_by_week[&NaiveDate::from_ymd(2019, 7, 29)]
+            .groups
+            .contains_key("coding"));
+
+        assert_eq!(
+            gaps(&segments, time(7, 31, 20), time(8, 1, 4)),
+            vec![
+                (time(7, 31, 20), time(7, 31, 22)),
+                (time(8, 1, 2), time(8, 1, 4))
+            ]
+        );
+
+        let tags = by_tag(&segments, start, end);
+        assert_eq!(tags.total, Duration::hours(6));
+        assert_eq!(tags.groups["work"], Duration::hours(6));
+        assert_eq!(tags.groups["coding"], Duration::hours(3));
+    }
+}
@@ -0,0 +1,36 @@
+use chrono::{DateTime, Utc};
+use std::{fmt::Debug, rc::Rc};
+
+/// A source of the current time. Anything that needs to know what time it is
+/// should ask a `Clock`, so that tests can pin the time with `FixedClock`.
+pub trait Clock: Debug {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// Reads the time from the operating system
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// Lets one clock be shared, like between a repository and the timesheet
+/// made from it
+impl<C: Clock + ?Sized> Clock for Rc<C> {
+    fn now(&self) -> DateTime<Utc> {
+        (**self).now()
+    }
+}
+
+/// Always returns the same time
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct FixedClock(pub DateTime<Utc>);
+
+impl Clock for FixedClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.0
+    }
+}
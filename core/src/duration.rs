@@ -0,0 +1,324 @@
+//! Formatting, parsing, and rounding durations. Applications built on augr
+//! can implement `DurationFormat` to show durations the way their users
+//! expect, like decimal hours for invoicing.
+
+use chrono::Duration;
+use snafu::Snafu;
+use std::{fmt, str::FromStr};
+
+#[derive(Eq, PartialEq, Debug, Snafu)]
+pub enum ParseError {
+    #[snafu(display("\"{}\" is not a duration", text))]
+    InvalidDuration { text: String },
+}
+
+/// A way of writing durations as text
+pub trait DurationFormat {
+    fn format(&self, duration: Duration) -> String;
+
+    fn parse(&self, text: &str) -> Result<Duration, ParseError>;
+}
+
+/// Whole hours and minutes, like `2h 5m` or `45m`. This is the format the
+/// command line uses.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct HoursMinutes;
+
+/// Hours as a decimal number, like `2.25h`
+#[derive(Copy, Clone, Debug)]
+pub struct DecimalHours {
+    /// How many digits to show after the decimal point
+    pub precision: usize,
+}
+
+/// Hours and minutes like on a clock, like `07:45` or `31:05`
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Clock;
+
+impl Default for DecimalHours {
+    fn default() -> Self {
+        Self { precision: 2 }
+    }
+}
+
+impl DurationFormat for HoursMinutes {
+    fn format(&self, duration: Duration) -> String {
+        let hours = duration.num_hours();
+        let mins = duration.num_minutes() - (hours * 60);
+        if hours < 1 {
+            format!("{}m", mins)
+        } else {
+            format!("{}h {}m", hours, mins)
+        }
+    }
+
+    /// Accepts amounts of hours, minutes and seconds in any order, like
+    /// `1h 30m`, `1h30m`, `90m`, or `2 hours`
+    fn parse(&self, text: &str) -> Result<Duration, ParseError> {
+        let invalid = || ParseError::InvalidDuration {
+            text: text.to_string(),
+        };
+        let mut total = Duration::zero();
+        let mut rest = text.trim();
+        if rest.is_empty() {
+            return Err(invalid());
+        }
+        while !rest.is_empty() {
+            let digits = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            let amount: i64 = rest[..digits].parse().map_err(|_| invalid())?;
+            rest = rest[digits..].trim_start();
+            let unit_len = rest
+                .find(|c: char| !c.is_ascii_alphabetic())
+                .unwrap_or(rest.len());
+            let unit = match &rest[..unit_len] {
+                "h" | "hr" | "hrs" | "hour" | "hours" => Duration::hours(1),
+                "m" | "min" | "mins" | "minute" | "minutes" => Duration::minutes(1),
+                "s" | "sec" | "secs" | "second" | "seconds" => Duration::seconds(1),
+                _ => return Err(invalid()),
+            };
+            total = total + Duration::seconds(unit.num_seconds() * amount);
+            rest = rest[unit_len..].trim_start();
+        }
+        Ok(total)
+    }
+}
+
+impl DurationFormat for DecimalHours {
+    fn format(&self, duration: Duration) -> String {
+        let hours = duration.num_seconds() as f64 / 3600.0;
+        format!("{:.*}h", self.precision, hours)
+    }
+
+    /// Accepts a number of hours, with or without an `h` after it
+    fn parse(&self, text: &str) -> Result<Duration, ParseError> {
+        let number = text.trim().trim_end_matches('h').trim_end();
+        let hours: f64 = number.parse().map_err(|_| ParseError::InvalidDuration {
+            text: text.to_string(),
+        })?;
+        Ok(Duration::seconds((hours * 3600.0).round() as i64))
+    }
+}
+
+impl DurationFormat for Clock {
+    fn format(&self, duration: Duration) -> String {
+        let hours = duration.num_hours();
+        let mins = duration.num_minutes() - (hours * 60);
+        format!("{:02}:{:02}", hours, mins)
+    }
+
+    /// Accepts hours and minutes separated by a colon, like `7:45`
+    fn parse(&self, text: &str) -> Result<Duration, ParseError> {
+        let invalid = || ParseError::InvalidDuration {
+            text: text.to_string(),
+        };
+        let mut parts = text.trim().splitn(2, ':');
+        let hours: i64 = parts.next().unwrap_or("").parse().map_err(|_| invalid())?;
+        let mins: i64 = parts
+            .next()
+            .ok_or_else(invalid)?
+            .parse()
+            .map_err(|_| invalid())?;
+        if mins >= 60 {
+            return Err(invalid());
+        }
+        Ok(Duration::minutes(hours * 60 + mins))
+    }
+}
+
+/// Rounding durations to a billing increment, like "15m"
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Rounding {
+    pub increment: Duration,
+    pub mode: RoundingMode,
+    pub per: RoundingPer,
+}
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum RoundingMode {
+    #[default]
+    Nearest,
+    Up,
+    Down,
+}
+
+/// Whether each event is rounded before it's added up, or only the totals
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum RoundingPer {
+    Event,
+    #[default]
+    Total,
+}
+
+impl Rounding {
+    /// `duration` rounded to a multiple of the increment. Half an increment
+    /// rounds up when rounding to the nearest one.
+    pub fn round(&self, duration: Duration) -> Duration {
+        let increment = self.increment.num_seconds();
+        if increment <= 0 {
+            return duration;
+        }
+        let seconds = duration.num_seconds();
+        let whole = seconds.div_euclid(increment);
+        let remainder = seconds.rem_euclid(increment);
+        let increments = match self.mode {
+            RoundingMode::Nearest if remainder * 2 >= increment => whole + 1,
+            RoundingMode::Up if remainder > 0 => whole + 1,
+            _ => whole,
+        };
+        Duration::seconds(increments * increment)
+    }
+}
+
+/// Written like "15m up per-event"
+impl fmt::Display for Rounding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {} per-{}",
+            HoursMinutes.format(self.increment),
+            self.mode,
+            self.per
+        )
+    }
+}
+
+impl FromStr for Rounding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.split_whitespace();
+        let increment = words.next().unwrap_or("");
+        let increment = HoursMinutes.parse(increment).map_err(|e| e.to_string())?;
+        let mode = words
+            .next()
+            .map_or(Ok(RoundingMode::default()), str::parse)?;
+        let per = match words.next() {
+            Some(per) => per
+                .strip_prefix("per-")
+                .ok_or_else(|| format!("expected per-event or per-total, not \"{}\"", per))?
+                .parse()?,
+            None => RoundingPer::default(),
+        };
+        Ok(Self {
+            increment,
+            mode,
+            per,
+        })
+    }
+}
+
+impl fmt::Display for RoundingMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            RoundingMode::Nearest => "nearest",
+            RoundingMode::Up => "up",
+            RoundingMode::Down => "down",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for RoundingMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "nearest" => Ok(RoundingMode::Nearest),
+            "up" => Ok(RoundingMode::Up),
+            "down" => Ok(RoundingMode::Down),
+            _ => Err(format!(
+                "unknown rounding \"{}\"; expected nearest, up, or down",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for RoundingPer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            RoundingPer::Event => "event",
+            RoundingPer::Total => "total",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for RoundingPer {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "event" => Ok(RoundingPer::Event),
+            "total" => Ok(RoundingPer::Total),
+            _ => Err(format!("expected event or total, not \"{}\"", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn hours_minutes_round_trip() {
+        let format = HoursMinutes;
+        for minutes in &[0, 45, 60, 125, 600] {
+            let duration = Duration::minutes(*minutes);
+            assert_eq!(format.parse(&format.format(duration)), Ok(duration));
+        }
+        assert_eq!(format.format(Duration::minutes(125)), "2h 5m");
+        assert_eq!(format.parse("1h30m"), Ok(Duration::minutes(90)));
+        assert_eq!(format.parse("2 hours"), Ok(Duration::hours(2)));
+        assert!(format.parse("soon").is_err());
+        assert!(format.parse("").is_err());
+    }
+
+    #[test]
+    fn decimal_hours() {
+        let format = DecimalHours::default();
+        assert_eq!(format.format(Duration::minutes(135)), "2.25h");
+        assert_eq!(format.parse("2.25h"), Ok(Duration::minutes(135)));
+        assert_eq!(format.parse("1.5"), Ok(Duration::minutes(90)));
+        assert!(format.parse("1.5 days").is_err());
+    }
+
+    #[test]
+    fn clock() {
+        assert_eq!(Clock.format(Duration::minutes(465)), "07:45");
+        assert_eq!(Clock.format(Duration::minutes(1865)), "31:05");
+        assert_eq!(Clock.parse("7:45"), Ok(Duration::minutes(465)));
+        assert!(Clock.parse("7:75").is_err());
+        assert!(Clock.parse("7h").is_err());
+    }
+
+    #[test]
+    fn rounding_modes() {
+        let rounding = |mode| Rounding {
+            increment: Duration::minutes(15),
+            mode,
+            per: RoundingPer::Total,
+        };
+        let minutes = |m| Duration::minutes(m);
+        assert_eq!(
+            rounding(RoundingMode::Nearest).round(minutes(7)),
+            minutes(0)
+        );
+        assert_eq!(
+            rounding(RoundingMode::Nearest).round(minutes(38)),
+            minutes(45)
+        );
+        assert_eq!(rounding(RoundingMode::Up).round(minutes(31)), minutes(45));
+        assert_eq!(rounding(RoundingMode::Up).round(minutes(30)), minutes(30));
+        assert_eq!(rounding(RoundingMode::Down).round(minutes(44)), minutes(30));
+
+        let parsed: Rounding = "15m up per-event".parse().unwrap();
+        assert_eq!(parsed.mode, RoundingMode::Up);
+        assert_eq!(parsed.per, RoundingPer::Event);
+        assert_eq!(parsed.to_string(), "15m up per-event");
+        assert_eq!("15m".parse(), Ok(rounding(RoundingMode::Nearest)));
+        assert!("15m sideways".parse::<Rounding>().is_err());
+    }
+}
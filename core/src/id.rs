@@ -0,0 +1,105 @@
+//! Where the ids of new patches and events come from. They are random by
+//! default; tests and generated data can install `SeededIds` so that the same
+//! ids come out on every run.
+
+use std::{cell::RefCell, fmt::Debug};
+use uuid::{Builder, Uuid, Variant, Version};
+
+pub trait IdGenerator: Debug {
+    fn next_id(&mut self) -> Uuid;
+}
+
+/// Random version 4 UUIDs
+#[derive(Copy, Clone, Debug, Default)]
+pub struct RandomIds;
+
+impl IdGenerator for RandomIds {
+    fn next_id(&mut self) -> Uuid {
+        Uuid::new_v4()
+    }
+}
+
+/// UUIDs that look random, but only depend on the seed
+#[derive(Clone, Debug)]
+pub struct SeededIds {
+    state: u64,
+}
+
+impl SeededIds {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    /// Ids for a run that starts out knowing `known` patches. Every run that
+    /// saves adds a patch, so later runs with the same seed don't make the
+    /// ids of earlier ones again.
+    pub fn with_known_patches(seed: u64, known: u64) -> Self {
+        Self::new(seed ^ Self::new(known).next_u64())
+    }
+
+    /// splitmix64, which is plenty for ids that only have to be distinct
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+impl IdGenerator for SeededIds {
+    fn next_id(&mut self) -> Uuid {
+        let mut bytes = [0; 16];
+        bytes[..8].copy_from_slice(&self.next_u64().to_be_bytes());
+        bytes[8..].copy_from_slice(&self.next_u64().to_be_bytes());
+        Builder::from_bytes(bytes)
+            .set_variant(Variant::RFC4122)
+            .set_version(Version::Random)
+            .build()
+    }
+}
+
+thread_local! {
+    static GENERATOR: RefCell<Box<dyn IdGenerator>> = RefCell::new(Box::new(RandomIds));
+}
+
+/// Use `generator` for the ids made on this thread from now on
+pub fn set_generator(generator: impl IdGenerator + 'static) {
+    GENERATOR.with(|current| *current.borrow_mut() = Box::new(generator));
+}
+
+/// A new id from this thread's generator, for a patch or an event
+pub fn new_id() -> Uuid {
+    GENERATOR.with(|generator| generator.borrow_mut().next_id())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Patch;
+
+    #[test]
+    fn seeded_ids_repeat() {
+        set_generator(SeededIds::new(7));
+        let first = (Patch::new().patch_ref().to_owned(), new_id());
+        set_generator(SeededIds::new(7));
+        let second = (Patch::new().patch_ref().to_owned(), new_id());
+        assert_eq!(first, second);
+        assert_ne!(first.0, first.1);
+        assert_eq!(first.0.get_version(), Some(Version::Random));
+
+        set_generator(RandomIds);
+        assert_ne!(new_id(), new_id());
+    }
+
+    #[test]
+    fn seeded_ids_differ_between_runs() {
+        let ids = |known| {
+            let mut ids = SeededIds::with_known_patches(7, known);
+            (0..100).map(|_| ids.next_id()).collect::<Vec<_>>()
+        };
+        assert_eq!(ids(3), ids(3));
+        let first = ids(0);
+        assert!(ids(1).iter().all(|id| !first.contains(id)));
+    }
+}
@@ -1,18 +1,28 @@
-#[cfg(feature = "flame_it")]
-#[macro_use]
-extern crate flamer;
-
+pub mod aggregate;
+pub mod clock;
+pub mod duration;
+pub mod id;
+pub mod progress;
+pub mod redact;
 pub mod repository;
+pub mod stats;
 pub mod store;
+pub mod tag_expr;
+#[cfg(feature = "testing")]
+pub mod testing;
 pub mod timesheet;
 
+pub use crate::clock::{Clock, FixedClock, SystemClock};
+pub use crate::duration::DurationFormat;
+pub use crate::progress::{CancelToken, Progress};
 pub use crate::repository::Repository;
 pub use crate::store::{
     meta::Meta,
     patch::{Patch, PatchRef},
     Store,
 };
-pub use crate::timesheet::{Event, Timesheet};
+pub use crate::tag_expr::TagExpr;
+pub use crate::timesheet::{CurrentEvent, EndPolicy, Event, Timesheet, Zone};
 
 pub type EventRef = String;
 pub type Tag = String;
@@ -0,0 +1,70 @@
+//! Progress reporting and cancellation for operations that can take a while,
+//! like loading a large store
+
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+/// The steps of a long operation
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Stage {
+    /// Loading this device's patches
+    Load,
+    /// Loading the patches of other devices
+    Sync,
+}
+
+/// Told how far along an operation is. Operations check `is_cancelled`
+/// between steps, and stop before writing anything else when it's true.
+pub trait Progress {
+    /// `done` out of `total` steps of `stage` are finished. The total can grow
+    /// as more work is found.
+    fn update(&self, _stage: Stage, _done: usize, _total: usize) {}
+
+    fn is_cancelled(&self) -> bool {
+        false
+    }
+}
+
+/// Reports nothing and is never cancelled
+pub struct NoProgress;
+
+impl Progress for NoProgress {}
+
+/// A flag that can be set from anywhere, like a signal handler, to cancel an
+/// operation
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+impl Progress for CancelToken {
+    fn is_cancelled(&self) -> bool {
+        CancelToken::is_cancelled(self)
+    }
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Stage::Load => write!(f, "Loading patches"),
+            Stage::Sync => write!(f, "Syncing patches"),
+        }
+    }
+}
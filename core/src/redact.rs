@@ -0,0 +1,125 @@
+//! Replace the user's own words in patches with placeholders, so that a copy
+//! of the data can be shared without revealing what the time was spent on.
+
+use crate::{
+    store::patch::{AddTag, CreateEvent, RemoveTag, SetNote},
+    Patch, Tag,
+};
+use std::collections::BTreeMap;
+
+/// Hands out placeholder names for tags. The same tag always gets the same
+/// placeholder, so redacted data keeps its shape.
+#[derive(Debug, Default)]
+pub struct Redactor {
+    tags: BTreeMap<Tag, Tag>,
+}
+
+impl Redactor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The placeholder for `tag`, like `tag-3`
+    pub fn tag(&mut self, tag: &str) -> Tag {
+        let next = self.tags.len() + 1;
+        self.tags
+            .entry(tag.to_string())
+            .or_insert_with(|| format!("tag-{}", next))
+            .clone()
+    }
+
+    /// A copy of `patch` with its tags replaced by placeholders and its notes
+    /// and metadata values removed. Event and patch references and metadata
+    /// keys are kept.
+    pub fn patch(&mut self, patch: &Patch) -> Patch {
+        let mut redacted = patch.clone();
+        redacted.create_event = patch
+            .create_event
+            .iter()
+            .map(|x| CreateEvent {
+                tags: x.tags.iter().map(|t| self.tag(t)).collect(),
+                ..x.clone()
+            })
+            .collect();
+        redacted.add_tag = patch
+            .add_tag
+            .iter()
+            .map(|x| AddTag {
+                tag: self.tag(&x.tag),
+                ..x.clone()
+            })
+            .collect();
+        redacted.remove_tag = patch
+            .remove_tag
+            .iter()
+            .map(|x| RemoveTag {
+                tag: self.tag(&x.tag),
+                ..x.clone()
+            })
+            .collect();
+        redacted.set_note = patch
+            .set_note
+            .iter()
+            .map(|x| SetNote {
+                note: if x.note.is_empty() {
+                    String::new()
+                } else {
+                    String::from("redacted")
+                },
+                ..x.clone()
+            })
+            .collect();
+        redacted.metadata = patch
+            .metadata
+            .keys()
+            .map(|key| (key.clone(), String::from("redacted")))
+            .collect();
+        redacted
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn redact_tags_consistently() {
+        let create = Patch::new().create_event(
+            "a".into(),
+            Utc.ymd(2019, 7, 24).and_hms(14, 0, 0),
+            vec!["work".into(), "secret-project".into()],
+        );
+        let tag = Patch::new().add_tag(*create.patch_ref(), "a".into(), "work".into());
+
+        let mut redactor = Redactor::new();
+        let create = redactor.patch(&create);
+        let tag = redactor.patch(&tag);
+
+        let created_tags = &create.create_event.iter().next().unwrap().tags;
+        assert!(!created_tags.contains(&"secret-project".to_string()));
+        assert_eq!(
+            created_tags,
+            &vec![String::from("tag-1"), String::from("tag-2")]
+        );
+        assert_eq!(tag.add_tag.iter().next().unwrap().tag, "tag-1");
+    }
+
+    #[test]
+    fn redact_metadata_values() {
+        let patch = Patch::new()
+            .create_event(
+                "a".into(),
+                Utc.ymd(2019, 7, 24).and_hms(14, 0, 0),
+                vec!["work".into()],
+            )
+            .with_metadata("taskwarrior.description", "Call the lawyer");
+
+        let redacted = Redactor::new().patch(&patch);
+
+        assert_eq!(
+            redacted.metadata.get("taskwarrior.description"),
+            Some(&String::from("redacted"))
+        );
+    }
+}
@@ -1,10 +1,28 @@
+pub mod diff;
 pub mod event;
 pub mod timesheet;
 
-use crate::{Meta, Patch, PatchRef, Store};
+use crate::{
+    progress::{NoProgress, Progress, Stage},
+    store::meta::FORMAT_VERSION,
+    Clock, EventRef, Meta, Patch, PatchRef, Store, SystemClock,
+};
+use chrono::{DateTime, Utc};
+use event::Operation;
 use snafu::{ResultExt, Snafu};
-use std::collections::{BTreeSet, VecDeque};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
 use timesheet::{Error as TimesheetError, PatchedTimesheet};
+use tracing::{field, instrument};
+
+/// Patch metadata recording when a patch was added
+pub const CREATED_METADATA: &str = "created";
+
+/// Patch metadata recording what added a patch, like "cli" or a hook's name
+pub const SOURCE_METADATA: &str = "source";
+
+/// Patch metadata marking the events a patch creates as private, so that they
+/// are left out of exports unless asked for
+pub const PRIVATE_METADATA: &str = "private";
 
 #[derive(Eq, PartialEq, Debug, Snafu)]
 pub enum Error<IE>
@@ -17,6 +35,9 @@ where
     #[snafu(display("Unable to save metadata: {}", source))]
     SaveMeta { source: IE },
 
+    #[snafu(display("Unable to start or finish a transaction: {}", source))]
+    Transaction { source: IE },
+
     #[snafu(display("Unable to save patch {} to disk: {}", patch, source))]
     SavePatch { source: IE, patch: PatchRef },
 
@@ -40,11 +61,39 @@ where
 
     #[snafu(display("IOError: {}", source))]
     IOError { source: IE },
+
+    #[snafu(display(
+        "The sync folder was written by a newer version of augr (format version {}, this version writes {}), so it can't be changed; upgrade augr first",
+        version,
+        FORMAT_VERSION
+    ))]
+    UnsupportedFormat { version: u32 },
+
+    #[snafu(display("Cancelled"))]
+    Cancelled,
 }
 
 #[derive(Debug)]
 pub struct Repository<S: Store> {
     store: S,
+    /// This device's meta file, as it was loaded. Saving keeps everything but
+    /// the list of patches as it is.
+    meta: Meta,
+    state: State,
+    /// Recorded in the metadata of every patch this repository adds
+    source: Option<String>,
+    /// The newest format version of the meta files that have been read, from
+    /// this device and the ones it synced with
+    newest_format_version: u32,
+    /// Used for the times recorded in the meta file, patches, and arrivals
+    clock: Box<dyn Clock>,
+    /// Whether syncing writes down which patches arrived
+    records_arrivals: bool,
+}
+
+/// A set of patches and the timesheet that results from applying them
+#[derive(Debug, Default)]
+struct State {
     patches_loaded: BTreeSet<PatchRef>,
     timesheet: PatchedTimesheet,
 }
@@ -54,36 +103,257 @@ where
     S: Store,
     <S as Store>::Error: 'static,
 {
-    #[cfg_attr(feature = "flame_it", flame)]
     pub fn from_store(store: S) -> Result<Self, Vec<Error<S::Error>>> {
+        Self::from_store_with_progress(store, &NoProgress)
+    }
+
+    /// Like `from_store`, but reports how many patches have been loaded to
+    /// `progress`, and stops if it's cancelled
+    #[instrument(level = "debug", skip(store, progress))]
+    pub fn from_store_with_progress(
+        store: S,
+        progress: &dyn Progress,
+    ) -> Result<Self, Vec<Error<S::Error>>> {
         let mut repo = Self {
             store,
-            patches_loaded: BTreeSet::new(),
-            timesheet: PatchedTimesheet::new(),
+            meta: Meta::new(),
+            state: State::default(),
+            source: None,
+            newest_format_version: FORMAT_VERSION,
+            clock: Box::new(SystemClock),
+            records_arrivals: true,
         };
-        repo.load_all_patches()?;
+        repo.load_all_patches(progress)?;
         Ok(repo)
     }
 
-    #[cfg_attr(feature = "flame_it", flame)]
+    /// Load as much of the store as possible. Patches that can't be read or
+    /// applied, and the patches that build on them, are skipped; the errors
+    /// are returned alongside the repository instead.
+    pub fn from_store_lenient(store: S) -> (Self, Vec<Error<S::Error>>) {
+        Self::from_store_lenient_with_progress(store, &NoProgress)
+    }
+
+    #[instrument(level = "debug", skip(store, progress))]
+    pub fn from_store_lenient_with_progress(
+        store: S,
+        progress: &dyn Progress,
+    ) -> (Self, Vec<Error<S::Error>>) {
+        let mut repo = Self {
+            store,
+            meta: Meta::new(),
+            state: State::default(),
+            source: None,
+            newest_format_version: FORMAT_VERSION,
+            clock: Box::new(SystemClock),
+            records_arrivals: true,
+        };
+        let errors = repo.load_all_patches(progress).err().unwrap_or_default();
+        (repo, errors)
+    }
+
+    #[instrument(level = "debug", skip(self), fields(patches = self.state.patches_loaded.len()))]
     pub fn save_meta(&mut self) -> Result<(), Error<S::Error>> {
-        let mut meta = Meta::new();
-        for p in self.patches_loaded.iter() {
-            meta.add_patch(p.clone());
+        self.check_format()?;
+        self.meta.upgrade_format_version();
+        self.meta
+            .set_patches(self.state.patches_loaded.iter().copied());
+        if self.meta.created().is_none() {
+            self.meta.set_created(self.clock.now());
         }
-        self.store.save_meta(&meta).context(SaveMeta {})
+        self.store.save_meta(&self.meta).context(SaveMeta {})
+    }
+
+    /// Whether this version of augr understands the format of every meta file
+    /// that has been read. Nothing can be saved otherwise, since the store
+    /// may hold data that this version would lose.
+    pub fn is_writable(&self) -> bool {
+        self.newest_format_version <= FORMAT_VERSION
     }
 
-    pub fn add_patch(&mut self, patch: Patch) -> Result<(), Error<S::Error>> {
+    fn check_format(&self) -> Result<(), Error<S::Error>> {
+        if self.is_writable() {
+            Ok(())
+        } else {
+            Err(Error::UnsupportedFormat {
+                version: self.newest_format_version,
+            })
+        }
+    }
+
+    /// This device's meta file, including the device registry and the
+    /// capabilities of the store
+    pub fn meta(&self) -> &Meta {
+        &self.meta
+    }
+
+    /// Change this device's meta file. The changes are written by `save_meta`.
+    pub fn meta_mut(&mut self) -> &mut Meta {
+        &mut self.meta
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Whether a patch has been loaded into the timesheet
+    pub fn is_loaded(&self, patch: &PatchRef) -> bool {
+        self.state.patches_loaded.contains(patch)
+    }
+
+    /// Every patch that has been loaded into the timesheet
+    pub fn loaded_patches(&self) -> impl Iterator<Item = &PatchRef> {
+        self.state.patches_loaded.iter()
+    }
+
+    /// Set what is adding patches, so that the rate of each source can be
+    /// checked
+    pub fn with_source(mut self, source: impl Into<String>) -> Self {
+        self.source = Some(source.into());
+        self
+    }
+
+    /// Read the time from `clock` instead of the system clock
+    pub fn with_clock(mut self, clock: Box<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn clock(&self) -> &dyn Clock {
+        &*self.clock
+    }
+
+    /// Whether syncing should record the patches that arrived, which writes
+    /// to the store. Defaults to true.
+    pub fn should_record_arrivals(mut self, record: bool) -> Self {
+        self.records_arrivals = record;
+        self
+    }
+
+    pub fn add_patch(&mut self, mut patch: Patch) -> Result<(), Error<S::Error>> {
+        self.check_format()?;
+        let now = self.clock.now();
+        patch
+            .metadata
+            .entry(CREATED_METADATA.to_string())
+            .or_insert_with(|| now.to_rfc3339());
+        if let Some(source) = &self.source {
+            patch
+                .metadata
+                .entry(SOURCE_METADATA.to_string())
+                .or_insert_with(|| source.clone());
+        }
         self.load_patch(patch.clone())?;
         self.store.add_patch(&patch).context(SavePatch {
             patch: *patch.patch_ref(),
         })?;
+        self.meta.set_last_patch_added(now);
+        self.meta.record_added_patch(*patch.patch_ref());
         Ok(())
     }
 
-    #[cfg_attr(feature = "flame_it", flame)]
+    /// Start adding several patches at once. They only become part of the
+    /// store when `commit` is called, so that an interruption can't leave the
+    /// meta file pointing at patches that were never written.
+    pub fn begin(&mut self) -> Result<(), Error<S::Error>> {
+        self.check_format()?;
+        self.store.begin().context(Transaction {})
+    }
+
+    /// Save the patches added since `begin`, and then the meta file
+    pub fn commit(&mut self) -> Result<(), Error<S::Error>> {
+        self.check_format()?;
+        self.meta.upgrade_format_version();
+        self.meta
+            .set_patches(self.state.patches_loaded.iter().copied());
+        self.store.commit(&self.meta).context(Transaction {})
+    }
+
+    /// Throw away the patches added since `begin` instead of saving them. They
+    /// stay loaded in memory, so the repository shouldn't be saved afterwards.
+    pub fn rollback(&mut self) -> Result<(), Error<S::Error>> {
+        self.store.rollback().context(Transaction {})
+    }
+
+    #[instrument(level = "debug", skip(self, patch), fields(patch = %patch.patch_ref()))]
     pub fn load_patch(&mut self, patch: Patch) -> Result<(), Error<S::Error>> {
+        self.state.load_patch(patch)
+    }
+
+    pub fn timesheet(&self) -> &PatchedTimesheet {
+        &self.state.timesheet
+    }
+
+    /// Build the timesheet that results from applying only `patches` (and
+    /// their ancestors) from the store, without changing the repository.
+    pub fn timesheet_for_patches(
+        &self,
+        patches: impl Iterator<Item = PatchRef>,
+    ) -> Result<PatchedTimesheet, Vec<Error<S::Error>>> {
+        let mut state = State::default();
+        state.load_patches(&self.store, patches, Stage::Load, &NoProgress)?;
+        Ok(state.timesheet)
+    }
+
+    /// Get every operation that has been applied to an event, in the order
+    /// that the patches were loaded. `patch_metadata` has when each one was
+    /// made and by what.
+    pub fn history(&self, event_ref: &EventRef) -> Option<&[Operation]> {
+        self.state
+            .timesheet
+            .events
+            .get(event_ref)
+            .map(|e| e.history())
+    }
+
+    /// The metadata of `patch`, if it has any
+    pub fn patch_metadata(&self, patch: &PatchRef) -> Option<&BTreeMap<String, String>> {
+        self.state.timesheet.patch_metadata.get(patch)
+    }
+
+    fn load_patches(
+        &mut self,
+        patches: impl Iterator<Item = PatchRef>,
+        stage: Stage,
+        progress: &dyn Progress,
+    ) -> Result<(), Vec<Error<S::Error>>> {
+        self.state
+            .load_patches(&self.store, patches, stage, progress)
+    }
+
+    #[instrument(level = "debug", skip(self, progress))]
+    fn load_all_patches(&mut self, progress: &dyn Progress) -> Result<(), Vec<Error<S::Error>>> {
+        // A transaction that can't be recovered is reported along with any
+        // other errors, but doesn't keep the patches that were saved from
+        // loading
+        let recovered = self.store.recover().context(Transaction {});
+        let meta = self
+            .store
+            .get_meta()
+            .context(LoadMeta {})
+            .map_err(|e| vec![e])?;
+
+        let patches: Vec<PatchRef> = meta.patches().cloned().collect();
+        self.newest_format_version = self.newest_format_version.max(meta.format_version());
+        self.meta = meta;
+        let loaded = self.load_patches(patches.into_iter(), Stage::Load, progress);
+        match (recovered, loaded) {
+            (Ok(()), loaded) => loaded,
+            (Err(error), Ok(())) => Err(vec![error]),
+            (Err(error), Err(mut errors)) => {
+                errors.insert(0, error);
+                Err(errors)
+            }
+        }
+    }
+}
+
+impl State {
+    #[instrument(level = "trace", skip(self, patch), fields(patch = %patch.patch_ref()))]
+    fn load_patch<IE>(&mut self, patch: Patch) -> Result<(), Error<IE>>
+    where
+        IE: std::error::Error + 'static,
+    {
         // Don't apply patches twice
         if self.patches_loaded.contains(patch.patch_ref()) {
             return Err(Error::PatchAlreadyLoaded {
@@ -106,7 +376,7 @@ where
         }
 
         // Mark patch as loaded
-        self.patches_loaded.insert(patch.patch_ref().clone());
+        self.patches_loaded.insert(*patch.patch_ref());
 
         self.timesheet
             .apply_patch(&patch)
@@ -116,44 +386,65 @@ where
             })
     }
 
-    pub fn timesheet(&self) -> &PatchedTimesheet {
-        &self.timesheet
-    }
-
-    #[cfg_attr(feature = "flame_it", flame)]
-    fn load_patches(
+    #[instrument(level = "debug", skip(self, store, patches, progress), fields(loaded = field::Empty, errors = field::Empty))]
+    fn load_patches<S>(
         &mut self,
+        store: &S,
         patches: impl Iterator<Item = PatchRef>,
-    ) -> Result<(), Vec<Error<S::Error>>> {
+        stage: Stage,
+        progress: &dyn Progress,
+    ) -> Result<(), Vec<Error<S::Error>>>
+    where
+        S: Store,
+        <S as Store>::Error: 'static,
+    {
         let mut errors = Vec::new();
+        let patches_before = self.patches_loaded.len();
 
         let mut error_on_loading: BTreeSet<PatchRef> = BTreeSet::new();
 
         let mut patches_to_load: VecDeque<PatchRef> = patches.collect();
+        let mut done = 0;
         while let Some(patch_ref) = patches_to_load.pop_front() {
-            // Don't load patches that have already been loaded
-            if self.patches_loaded.contains(&patch_ref) {
+            // Loading only changes memory, so it can stop anywhere
+            if progress.is_cancelled() {
+                errors.push(Error::Cancelled);
+                break;
+            }
+            progress.update(stage, done, done + patches_to_load.len() + 1);
+            done += 1;
+
+            // Don't load patches that have already been loaded, or try again
+            // with patches that failed
+            if self.patches_loaded.contains(&patch_ref) || error_on_loading.contains(&patch_ref) {
                 continue;
             }
 
-            let patch = match self.store.get_patch(&patch_ref) {
+            let patch = match store.get_patch(&patch_ref) {
                 Ok(p) => p,
                 Err(source) => {
                     errors.push(Error::PatchNotFound {
                         source,
                         patch: patch_ref,
                     });
+                    error_on_loading.insert(patch_ref);
                     continue;
                 }
             };
 
             match self.load_patch(patch) {
                 Ok(()) => {}
+                // Patches that build on a patch that couldn't be loaded can't
+                // be loaded either
+                Err(error @ Error::MissingParentPatches { .. })
+                    if missing_parents(&error).any(|p| error_on_loading.contains(p)) =>
+                {
+                    errors.push(error);
+                    error_on_loading.insert(patch_ref);
+                }
                 Err(Error::MissingParentPatches { parents, .. }) => {
                     for parent in parents {
-                        if !error_on_loading.contains(&parent) {
-                            patches_to_load.push_back(parent);
-                        }
+                        patches_to_load.push_back(parent);
                     }
                     patches_to_load.push_back(patch_ref);
                 }
@@ -165,41 +456,106 @@ where
             }
         }
 
+        let span = tracing::Span::current();
+        span.record("loaded", self.patches_loaded.len() - patches_before);
+        span.record("errors", errors.len());
+
         if !errors.is_empty() {
             Err(errors)
         } else {
             Ok(())
         }
     }
+}
 
-    #[cfg_attr(feature = "flame_it", flame)]
-    fn load_all_patches(&mut self) -> Result<(), Vec<Error<S::Error>>> {
-        let meta = self
-            .store
-            .get_meta()
-            .context(LoadMeta {})
-            .map_err(|e| vec![e])?;
-
-        self.load_patches(meta.patches().cloned())
-    }
+fn missing_parents<IE>(error: &Error<IE>) -> impl Iterator<Item = &PatchRef>
+where
+    IE: std::error::Error + 'static,
+{
+    let parents = match error {
+        Error::MissingParentPatches { parents, .. } => &parents[..],
+        _ => &[],
+    };
+    parents.iter()
 }
 
-use crate::store::sync_folder_store::{SyncFolderStore, SyncFolderStoreError};
+use crate::store::sync_folder_store::{Arrivals, SyncFolderStore, SyncFolderStoreError};
 
 impl Repository<SyncFolderStore> {
-    #[cfg_attr(feature = "flame_it", flame)]
     pub fn try_sync_data(&mut self) -> Result<(), Vec<Error<SyncFolderStoreError>>> {
+        self.try_sync_data_with_progress(&NoProgress)
+    }
+
+    /// Like `try_sync_data`, but reports how many patches have been loaded to
+    /// `progress`. When it's cancelled, or arrivals aren't recorded, the store
+    /// is left as it was.
+    #[instrument(level = "debug", skip(self, progress))]
+    pub fn try_sync_data_with_progress(
+        &mut self,
+        progress: &dyn Progress,
+    ) -> Result<(), Vec<Error<SyncFolderStoreError>>> {
         let metas = self
             .store
             .get_other_metas()
             .context(IOError {})
             .map_err(|e| vec![e])?;
 
-        let patches_to_load: Vec<PatchRef> = metas
-            .filter_map(|x| x.ok())
-            .flat_map(|meta| meta.patches().copied().collect::<Vec<_>>().into_iter())
-            .collect();
+        let mut patches_to_load: Vec<PatchRef> = Vec::new();
+        for meta in metas.filter_map(|x| x.ok()) {
+            self.newest_format_version = self.newest_format_version.max(meta.format_version());
+            self.meta.merge_devices(&meta);
+            patches_to_load.extend(meta.patches().copied());
+        }
+
+        let patches_before = self.state.patches_loaded.clone();
+        let result = self.load_patches(patches_to_load.into_iter(), Stage::Sync, progress);
+        if progress.is_cancelled() || !self.records_arrivals {
+            return result;
+        }
+        let arrived = self.state.patches_loaded.difference(&patches_before);
+        self.record_arrivals(arrived.copied().collect())
+            .map_err(|e| vec![e])?;
+        result
+    }
+
+    /// Note that `patches` arrived on this device just now. The first time
+    /// that arrivals are recorded, they are all marked as already checked.
+    fn record_arrivals(
+        &mut self,
+        patches: Vec<PatchRef>,
+    ) -> Result<(), Error<SyncFolderStoreError>> {
+        let now = self.clock.now();
+        let mut arrivals = match self.store.get_arrivals().context(IOError {})? {
+            Some(_) if patches.is_empty() => return Ok(()),
+            Some(arrivals) => arrivals,
+            None => Arrivals {
+                last_checked: Some(now),
+                patches: Default::default(),
+            },
+        };
+        for patch in patches {
+            arrivals.patches.insert(patch, now);
+        }
+        self.store.save_arrivals(&arrivals).context(IOError {})
+    }
 
-        self.load_patches(patches_to_load.into_iter())
+    /// When patches from other devices arrived on this device
+    pub fn arrivals(&self) -> Result<Arrivals, Error<SyncFolderStoreError>> {
+        Ok(self
+            .store
+            .get_arrivals()
+            .context(IOError {})?
+            .unwrap_or_default())
+    }
+
+    /// Mark every patch that has arrived up to `time` as seen by
+    /// `augr whatsnew`
+    pub fn check_arrivals(
+        &mut self,
+        time: DateTime<Utc>,
+    ) -> Result<(), Error<SyncFolderStoreError>> {
+        let mut arrivals = self.arrivals()?;
+        arrivals.last_checked = Some(time);
+        self.store.save_arrivals(&arrivals).context(IOError {})
     }
 }
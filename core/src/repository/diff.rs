@@ -0,0 +1,212 @@
+use crate::{
+    repository::{event::PatchedEvent, timesheet::PatchedTimesheet},
+    EventRef, Tag,
+};
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// How a single event differs between two timesheets
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum EventDiff {
+    /// The event only exists in the new timesheet
+    Added { event: EventRef },
+
+    /// The event only exists in the old timesheet
+    Removed { event: EventRef },
+
+    /// The event exists in both timesheets, but its starts, tags, note or
+    /// metadata differ
+    Changed {
+        event: EventRef,
+        starts_added: BTreeSet<DateTime<Utc>>,
+        starts_removed: BTreeSet<DateTime<Utc>>,
+        tags_added: BTreeSet<Tag>,
+        tags_removed: BTreeSet<Tag>,
+        /// The note before and after, if it changed
+        note: Option<NoteChange>,
+        /// The metadata keys whose values changed, with their new values.
+        /// Removed keys have no value.
+        metadata: BTreeMap<String, Option<String>>,
+    },
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct NoteChange {
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+impl EventDiff {
+    pub fn event(&self) -> &EventRef {
+        match self {
+            EventDiff::Added { event } => event,
+            EventDiff::Removed { event } => event,
+            EventDiff::Changed { event, .. } => event,
+        }
+    }
+}
+
+/// Compare the events of two timesheets, such as the timesheets built from the
+/// patches that two devices know about. Deleted events are treated as if they
+/// don't exist.
+pub fn diff(old: &PatchedTimesheet, new: &PatchedTimesheet) -> Vec<EventDiff> {
+    let event_refs: BTreeSet<&EventRef> = old.events.keys().chain(new.events.keys()).collect();
+
+    let mut diffs = Vec::new();
+    for event_ref in event_refs {
+        match (live_event(old, event_ref), live_event(new, event_ref)) {
+            (None, None) => {}
+            (None, Some(_)) => diffs.push(EventDiff::Added {
+                event: event_ref.clone(),
+            }),
+            (Some(_), None) => diffs.push(EventDiff::Removed {
+                event: event_ref.clone(),
+            }),
+            (Some(old_event), Some(new_event)) => {
+                let (old_starts, new_starts) = (start_times(old_event), start_times(new_event));
+                let (old_tags, new_tags) = (tag_names(old_event), tag_names(new_event));
+                let (old_note, new_note) = (old_event.note(), new_event.note());
+                let metadata =
+                    metadata_changes(&metadata(old, old_event), &metadata(new, new_event));
+                if old_starts != new_starts
+                    || old_tags != new_tags
+                    || old_note != new_note
+                    || !metadata.is_empty()
+                {
+                    let note = if old_note != new_note {
+                        Some(NoteChange {
+                            old: old_note,
+                            new: new_note,
+                        })
+                    } else {
+                        None
+                    };
+                    diffs.push(EventDiff::Changed {
+                        event: event_ref.clone(),
+                        starts_added: new_starts.difference(&old_starts).cloned().collect(),
+                        starts_removed: old_starts.difference(&new_starts).cloned().collect(),
+                        tags_added: new_tags.difference(&old_tags).cloned().collect(),
+                        tags_removed: old_tags.difference(&new_tags).cloned().collect(),
+                        note,
+                        metadata,
+                    });
+                }
+            }
+        }
+    }
+    diffs
+}
+
+fn live_event<'t>(timesheet: &'t PatchedTimesheet, event: &EventRef) -> Option<&'t PatchedEvent> {
+    timesheet.events.get(event).filter(|e| !e.is_deleted())
+}
+
+fn start_times(event: &PatchedEvent) -> BTreeSet<DateTime<Utc>> {
+    event
+        .starts()
+        .into_iter()
+        .map(|(_patch, time)| time)
+        .collect()
+}
+
+fn tag_names(event: &PatchedEvent) -> BTreeSet<Tag> {
+    event.tags().into_iter().map(|(_patch, tag)| tag).collect()
+}
+
+/// The metadata of the patch that created the event
+pub fn metadata(timesheet: &PatchedTimesheet, event: &PatchedEvent) -> BTreeMap<String, String> {
+    event
+        .created_by()
+        .and_then(|patch| timesheet.patch_metadata.get(&patch))
+        .cloned()
+        .unwrap_or_default()
+}
+
+fn metadata_changes(
+    old: &BTreeMap<String, String>,
+    new: &BTreeMap<String, String>,
+) -> BTreeMap<String, Option<String>> {
+    let keys: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+    keys.into_iter()
+        .filter(|key| old.get(*key) != new.get(*key))
+        .map(|key| (key.clone(), new.get(key).cloned()))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Patch;
+    use chrono::TimeZone;
+
+    #[test]
+    fn diff_timesheets() {
+        let dt0 = Utc.ymd(2019, 7, 23).and_hms(12, 0, 0);
+        let dt1 = Utc.ymd(2019, 7, 23).and_hms(12, 30, 0);
+        let patch0 = Patch::new()
+            .create_event("a".into(), dt0, vec!["work".into()])
+            .create_event("b".into(), dt1, vec!["lunch".into()]);
+        let patch1 = Patch::new()
+            .remove_start(*patch0.patch_ref(), "a".into(), dt0)
+            .add_start(*patch0.patch_ref(), "a".into(), dt1)
+            .add_tag(*patch0.patch_ref(), "a".into(), "coding".into())
+            .delete_event(*patch0.patch_ref(), "b".into())
+            .create_event("c".into(), dt0, vec![]);
+
+        let mut old = PatchedTimesheet::new();
+        old.apply_patch(&patch0).unwrap();
+        let mut new = old.clone();
+        new.apply_patch(&patch1).unwrap();
+
+        assert_eq!(
+            diff(&old, &new),
+            vec![
+                EventDiff::Changed {
+                    event: "a".into(),
+                    starts_added: [dt1].iter().cloned().collect(),
+                    starts_removed: [dt0].iter().cloned().collect(),
+                    tags_added: ["coding".to_string()].iter().cloned().collect(),
+                    tags_removed: BTreeSet::new(),
+                    note: None,
+                    metadata: BTreeMap::new(),
+                },
+                EventDiff::Removed { event: "b".into() },
+                EventDiff::Added { event: "c".into() },
+            ]
+        );
+        assert!(diff(&new, &new).is_empty());
+    }
+
+    #[test]
+    fn diff_notes() {
+        let dt0 = Utc.ymd(2019, 7, 23).and_hms(12, 0, 0);
+        let create = Patch::new().create_event("a".into(), dt0, vec!["work".into()]);
+        let annotate = Patch::new().set_note(
+            *create.patch_ref(),
+            BTreeSet::new(),
+            "a".into(),
+            "hello".into(),
+        );
+
+        let mut old = PatchedTimesheet::new();
+        old.apply_patch(&create).unwrap();
+        let mut new = old.clone();
+        new.apply_patch(&annotate).unwrap();
+
+        assert_eq!(
+            diff(&old, &new),
+            vec![EventDiff::Changed {
+                event: "a".into(),
+                starts_added: BTreeSet::new(),
+                starts_removed: BTreeSet::new(),
+                tags_added: BTreeSet::new(),
+                tags_removed: BTreeSet::new(),
+                note: Some(NoteChange {
+                    old: None,
+                    new: Some("hello".into()),
+                }),
+                metadata: BTreeMap::new(),
+            }]
+        );
+    }
+}
@@ -1,7 +1,11 @@
 use crate::{Event, PatchRef, Tag};
 use chrono::{DateTime, Utc};
 use snafu::{ensure, Snafu};
-use std::collections::BTreeSet;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    str::FromStr,
+};
 
 #[derive(Default, Clone, Debug)]
 pub struct PatchedEvent {
@@ -9,6 +13,16 @@ pub struct PatchedEvent {
     starts_removed: BTreeSet<(PatchRef, DateTime<Utc>)>,
     tags_added: BTreeSet<(PatchRef, String)>,
     tags_removed: BTreeSet<(PatchRef, String)>,
+    deletes_added: BTreeSet<PatchRef>,
+    deletes_removed: BTreeSet<PatchRef>,
+
+    /// The notes that haven't been replaced, by the patch that set them. Notes
+    /// set concurrently are all kept.
+    notes: BTreeMap<PatchRef, String>,
+
+    /// Every operation that has been applied to this event, in the order the
+    /// patches were applied
+    history: Vec<Operation>,
 
     /// Stores the latest patches that have been applied. Will generally be a
     /// single patch, but if multiple patches were created asynchronously, there
@@ -17,6 +31,28 @@ pub struct PatchedEvent {
     latest_patches: BTreeSet<PatchRef>,
 }
 
+/// A single change that a patch made to an event
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Operation {
+    pub patch: PatchRef,
+    pub kind: OperationKind,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum OperationKind {
+    Create {
+        start: DateTime<Utc>,
+        tags: Vec<Tag>,
+    },
+    AddStart(DateTime<Utc>),
+    RemoveStart(DateTime<Utc>),
+    AddTag(Tag),
+    RemoveTag(Tag),
+    Delete,
+    Undelete,
+    SetNote(String),
+}
+
 #[derive(Eq, PartialEq, Debug, Snafu)]
 pub enum Error {
     #[snafu(display("Event has multiple start times"))]
@@ -26,6 +62,59 @@ pub enum Error {
     NoStartTimes,
 }
 
+/// When each patch was created, for the patches that recorded it
+pub type CreatedTimes = BTreeMap<PatchRef, DateTime<Utc>>;
+
+/// How to pick a start time for an event that concurrent patches have left
+/// with more than one
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum Resolution {
+    /// Leave the event as a conflict for the user to fix
+    #[default]
+    Manual,
+
+    /// Keep the earliest of the start times
+    EarliestWins,
+
+    /// Keep the start time added by the patch that was created last, going by
+    /// the time in its metadata. Patches that don't record when they were
+    /// created count as older, and ties go to the greater patch id, so that
+    /// every device picks the same start whatever order it loaded them in.
+    LatestPatchWins,
+}
+
+#[derive(Eq, PartialEq, Debug, Snafu)]
+pub enum ParseResolutionError {
+    #[snafu(display(
+        "Unknown resolution \"{}\"; expected manual, earliest-wins, or latest-patch-wins",
+        text
+    ))]
+    UnknownResolution { text: String },
+}
+
+impl FromStr for Resolution {
+    type Err = ParseResolutionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "manual" => Ok(Resolution::Manual),
+            "earliest-wins" => Ok(Resolution::EarliestWins),
+            "latest-patch-wins" => Ok(Resolution::LatestPatchWins),
+            _ => UnknownResolution { text: s }.fail(),
+        }
+    }
+}
+
+impl fmt::Display for Resolution {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Resolution::Manual => write!(f, "manual"),
+            Resolution::EarliestWins => write!(f, "earliest-wins"),
+            Resolution::LatestPatchWins => write!(f, "latest-patch-wins"),
+        }
+    }
+}
+
 impl PatchedEvent {
     pub fn new() -> Self {
         Self {
@@ -33,6 +122,10 @@ impl PatchedEvent {
             starts_removed: BTreeSet::new(),
             tags_added: BTreeSet::new(),
             tags_removed: BTreeSet::new(),
+            deletes_added: BTreeSet::new(),
+            deletes_removed: BTreeSet::new(),
+            notes: BTreeMap::new(),
+            history: Vec::new(),
             latest_patches: BTreeSet::new(),
         }
     }
@@ -78,19 +171,127 @@ impl PatchedEvent {
             .collect()
     }
 
+    pub fn add_delete(&mut self, patch: PatchRef) {
+        self.deletes_added.insert(patch);
+    }
+
+    pub fn remove_delete(&mut self, patch: PatchRef) {
+        self.deletes_removed.insert(patch);
+    }
+
+    /// The patches that have deleted this event, and have not been undone
+    pub fn deletes(&self) -> BTreeSet<PatchRef> {
+        self.deletes_added
+            .difference(&self.deletes_removed)
+            .cloned()
+            .collect()
+    }
+
+    /// Whether `patch` deleted this event, even if that has been undone
+    pub fn was_deleted_by(&self, patch: &PatchRef) -> bool {
+        self.deletes_added.contains(patch)
+    }
+
+    pub fn is_deleted(&self) -> bool {
+        !self.deletes().is_empty()
+    }
+
+    /// Set the note of the event, replacing the notes set by `replaces`
+    pub fn set_note(&mut self, patch: PatchRef, replaces: &BTreeSet<PatchRef>, note: String) {
+        for replaced in replaces {
+            self.notes.remove(replaced);
+        }
+        self.notes.insert(patch, note);
+    }
+
+    /// The patches that set the event's current notes
+    pub fn note_patches(&self) -> BTreeSet<PatchRef> {
+        self.notes.keys().copied().collect()
+    }
+
+    /// The event's note. Notes that were set concurrently are joined by
+    /// newlines.
+    pub fn note(&self) -> Option<String> {
+        let notes: Vec<&str> = self
+            .notes
+            .values()
+            .map(String::as_str)
+            .filter(|note| !note.is_empty())
+            .collect();
+        if notes.is_empty() {
+            None
+        } else {
+            Some(notes.join("\n"))
+        }
+    }
+
+    /// The text of the notes that `patches` set, found in the event's history
+    pub fn notes_set_by(&self, patches: &BTreeSet<PatchRef>) -> Vec<String> {
+        self.history
+            .iter()
+            .filter(|op| patches.contains(&op.patch))
+            .filter_map(|op| match &op.kind {
+                OperationKind::SetNote(note) if !note.is_empty() => Some(note.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Record that `patch` changed this event
+    pub fn record(&mut self, patch: PatchRef, kind: OperationKind) {
+        self.history.push(Operation { patch, kind });
+    }
+
+    /// Every operation applied to this event, oldest first
+    pub fn history(&self) -> &[Operation] {
+        &self.history
+    }
+
+    /// The patch that created this event
+    pub fn created_by(&self) -> Option<PatchRef> {
+        self.history
+            .iter()
+            .find(|op| matches!(op.kind, OperationKind::Create { .. }))
+            .map(|op| op.patch)
+    }
+
     pub fn latest_patches(&self) -> BTreeSet<PatchRef> {
         self.latest_patches.clone()
     }
 
-    pub fn flatten(&self) -> Result<Event, Error> {
+    /// Pick the start time of this event, using `resolution` if there is more
+    /// than one. `created` has the time each patch was created at, for
+    /// `LatestPatchWins`.
+    pub fn resolve_start(
+        &self,
+        resolution: Resolution,
+        created: &CreatedTimes,
+    ) -> Result<(PatchRef, DateTime<Utc>), Error> {
         let starts = self.starts();
-        ensure!(starts.len() < 2, MultipleStartTimes);
         ensure!(!starts.is_empty(), NoStartTimes);
-        let start = starts
-            .iter()
-            .map(|patch_and_dt| patch_and_dt.1)
-            .next()
-            .expect("should be exactly one start");
+        let start = match resolution {
+            Resolution::Manual => {
+                ensure!(starts.len() < 2, MultipleStartTimes);
+                starts.into_iter().next()
+            }
+            Resolution::EarliestWins => starts.into_iter().min_by_key(|(_patch, time)| *time),
+            Resolution::LatestPatchWins => starts
+                .into_iter()
+                .max_by_key(|(patch, time)| (created.get(patch), *patch, *time)),
+        };
+        Ok(start.expect("there is at least one start"))
+    }
+
+    pub fn flatten(&self) -> Result<Event, Error> {
+        self.flatten_with(Resolution::Manual, &CreatedTimes::new())
+    }
+
+    pub fn flatten_with(
+        &self,
+        resolution: Resolution,
+        created: &CreatedTimes,
+    ) -> Result<Event, Error> {
+        let (_patch, start) = self.resolve_start(resolution, created)?;
         let tags = self
             .tags_added
             .difference(&self.tags_removed)
@@ -102,6 +303,12 @@ impl PatchedEvent {
 }
 
 #[cfg(test)]
+#[allow(
+    array_into_iter,
+    clippy::clone_on_copy,
+    clippy::into_iter_on_ref,
+    clippy::zero_prefixed_literal
+)]
 mod test {
     use super::*;
     use chrono::TimeZone;
@@ -141,4 +348,58 @@ mod test {
                 .collect()
         );
     }
+
+    #[test]
+    fn set_and_replace_notes() {
+        let patch_ref_a = Uuid::parse_str("81790c38-96dd-4577-8b85-9f7c8bd6802b").unwrap();
+        let patch_ref_b = Uuid::parse_str("e39076fe-6b5a-4a7f-b927-7fc1df5ba275").unwrap();
+        let patch_ref_c = Uuid::parse_str("fa5de1d9-aa11-49fa-b064-8128281a7d91").unwrap();
+
+        let mut event = PatchedEvent::new();
+        assert_eq!(event.note(), None);
+
+        // Notes set concurrently are both kept
+        event.set_note(patch_ref_a, &BTreeSet::new(), "first".into());
+        event.set_note(patch_ref_b, &BTreeSet::new(), "second".into());
+        assert_eq!(event.note(), Some("first\nsecond".into()));
+
+        let replaces = event.note_patches();
+        event.set_note(patch_ref_c, &replaces, "".into());
+        assert_eq!(event.note(), None);
+    }
+
+    #[test]
+    fn resolve_multiple_starts() {
+        let dt0 = Utc.ymd(2019, 7, 23).and_hms(12, 0, 0);
+        let dt1 = Utc.ymd(2019, 7, 23).and_hms(11, 30, 0);
+        let patch_ref_a = Uuid::parse_str("81790c38-96dd-4577-8b85-9f7c8bd6802b").unwrap();
+        let patch_ref_b = Uuid::parse_str("0bd19d2e-3c55-4d5e-9cfa-a3b1d25e8f44").unwrap();
+
+        let mut event = PatchedEvent::new();
+        event.add_start(patch_ref_a, dt0);
+        event.add_start(patch_ref_b, dt1);
+
+        let none = CreatedTimes::new();
+        assert_eq!(
+            event.resolve_start(Resolution::Manual, &none),
+            Err(Error::MultipleStartTimes)
+        );
+        assert_eq!(
+            event.resolve_start(Resolution::EarliestWins, &none),
+            Ok((patch_ref_b, dt1))
+        );
+        // Without creation times, the greater patch id wins
+        assert_eq!(
+            event.resolve_start(Resolution::LatestPatchWins, &none),
+            Ok((patch_ref_a, dt0))
+        );
+
+        let mut created = CreatedTimes::new();
+        created.insert(patch_ref_a, dt0);
+        created.insert(patch_ref_b, dt0 + chrono::Duration::minutes(1));
+        assert_eq!(
+            event.resolve_start(Resolution::LatestPatchWins, &created),
+            Ok((patch_ref_b, dt1))
+        );
+    }
 }
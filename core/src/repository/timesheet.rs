@@ -1,16 +1,30 @@
 use crate::{
-    repository::event::{Error as EventError, PatchedEvent},
+    repository::{
+        event::{CreatedTimes, Error as EventError, OperationKind, PatchedEvent, Resolution},
+        CREATED_METADATA, PRIVATE_METADATA, SOURCE_METADATA,
+    },
+    store::patch::{AddAbsence, RemoveStart},
     EventRef, Patch, PatchRef, Timesheet,
 };
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, Utc};
 use snafu::Snafu;
-use std::collections::BTreeMap;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+use tracing::instrument;
 
 /// This representation of a timesheet is an intermediate form that allows
 /// an event to have multiple starts
 #[derive(Default, Clone, Debug)]
 pub struct PatchedTimesheet {
     pub events: BTreeMap<EventRef, PatchedEvent>,
+
+    /// The metadata of each applied patch that has any
+    pub patch_metadata: BTreeMap<PatchRef, BTreeMap<String, String>>,
+
+    /// The absences that haven't been removed, with the patch that added each
+    pub absences: BTreeSet<(PatchRef, AddAbsence)>,
 }
 
 #[derive(Eq, PartialEq, Debug, Snafu)]
@@ -33,21 +47,121 @@ pub enum Error {
 
     #[snafu(display("Two events were created with the same id {}", id))]
     DuplicateEventId { id: EventRef },
+
+    #[snafu(display(
+        "Patch {} undeletes event {}, but patch {} didn't delete it",
+        patch,
+        event,
+        deleted_by
+    ))]
+    UnknownDelete {
+        patch: PatchRef,
+        event: EventRef,
+        deleted_by: PatchRef,
+    },
+
+    #[snafu(display(
+        "Patch {} removes an absence from {} to {} that patch {} didn't add",
+        patch,
+        start,
+        end,
+        added_by
+    ))]
+    UnknownAbsence {
+        patch: PatchRef,
+        added_by: PatchRef,
+        start: NaiveDate,
+        end: NaiveDate,
+    },
+
+    #[snafu(display("Patch {} adds an absence that ends before it starts", patch))]
+    BackwardsAbsence { patch: PatchRef },
+}
+
+/// The shortest prefix of an event reference that is printed, even when a
+/// shorter one would be unique
+pub const MIN_SHORT_REF_LEN: usize = 8;
+
+#[derive(Eq, PartialEq, Debug, Snafu)]
+pub enum RefError {
+    #[snafu(display("Unknown event reference: {}", prefix))]
+    UnknownRef { prefix: String },
+
+    #[snafu(display(
+        "Event reference {} is ambiguous; it could be any of: {}",
+        prefix,
+        candidates.join(", ")
+    ))]
+    AmbiguousRef {
+        prefix: String,
+        candidates: Vec<EventRef>,
+    },
+
+    #[snafu(display(
+        "Invalid relative event reference {}; use @ for the current event or @-1, @-2, ... for earlier ones",
+        reference
+    ))]
+    InvalidRelativeRef { reference: String },
+
+    #[snafu(display("There is no event {} today", reference))]
+    NoRelativeEvent { reference: String },
+}
+
+/// A place where the merged patches disagree about the shape of the timesheet.
+/// Unlike `Error`, conflicts are reported as data so that they can be shown to
+/// the user and resolved, instead of aborting the flatten.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Conflict {
+    /// More than one event starts at the same moment
+    SharedStartTime {
+        time: DateTime<Utc>,
+        events: Vec<EventRef>,
+    },
+
+    /// An event has several surviving start times, so it overlaps itself
+    MultipleStartTimes {
+        event: EventRef,
+        starts: Vec<(PatchRef, DateTime<Utc>)>,
+    },
+
+    /// Every start time of an event has been removed
+    NoStartTimes { event: EventRef },
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Conflict::SharedStartTime { time, events } => write!(
+                f,
+                "events {} all start at {}",
+                events.join(", "),
+                time.to_rfc3339()
+            ),
+            Conflict::MultipleStartTimes { event, starts } => {
+                let times: Vec<String> = starts
+                    .iter()
+                    .map(|(patch, time)| format!("{} (patch {})", time.to_rfc3339(), patch))
+                    .collect();
+                write!(f, "event {} starts at {}", event, times.join(" and "))
+            }
+            Conflict::NoStartTimes { event } => write!(f, "event {} has no start time", event),
+        }
+    }
 }
 
 impl PatchedTimesheet {
     pub fn new() -> Self {
         Self {
             events: BTreeMap::new(),
+            patch_metadata: BTreeMap::new(),
+            absences: BTreeSet::new(),
         }
     }
 
-    #[cfg_attr(feature = "flame_it", flame)]
+    #[instrument(level = "trace", skip(self, patch), fields(patch = %patch.patch_ref()))]
     pub fn apply_patch(&mut self, patch: &Patch) -> Result<(), Vec<Error>> {
         // Verify patch. From this point on, we should have no errors, and `expect("valid patch")` indicates that
-        if let Err(errors) = self.verify_patch(patch) {
-            return Err(errors);
-        }
+        self.verify_patch(patch)?;
         let patch_ref = patch.patch_ref();
 
         for start_added in patch.add_start.iter() {
@@ -56,12 +170,13 @@ impl PatchedTimesheet {
                 .get_mut(&start_added.event)
                 .expect("valid patch");
             event.add_start(*patch_ref, start_added.time);
+            event.record(*patch_ref, OperationKind::AddStart(start_added.time));
 
             // Update metadata
             for parent in start_added.parents() {
-                event.remove_patch_from_latest(&parent);
+                event.remove_patch_from_latest(parent);
             }
-            event.add_patch_to_latest(patch_ref.clone());
+            event.add_patch_to_latest(*patch_ref);
         }
         for start_removed in patch.remove_start.iter() {
             let event = self
@@ -69,24 +184,26 @@ impl PatchedTimesheet {
                 .get_mut(&start_removed.event)
                 .expect("valid patch");
             event.remove_start(start_removed.patch, start_removed.time);
+            event.record(*patch_ref, OperationKind::RemoveStart(start_removed.time));
 
             // Update metadata
             event.remove_patch_from_latest(&start_removed.patch);
             for parent in start_removed.parents() {
-                event.remove_patch_from_latest(&parent);
+                event.remove_patch_from_latest(parent);
             }
-            event.add_patch_to_latest(patch_ref.clone());
+            event.add_patch_to_latest(*patch_ref);
         }
 
         for tag_added in patch.add_tag.iter() {
             let event = self.events.get_mut(&tag_added.event).expect("valid patch");
-            event.add_tag(patch_ref.clone(), tag_added.tag.clone());
+            event.add_tag(*patch_ref, tag_added.tag.clone());
+            event.record(*patch_ref, OperationKind::AddTag(tag_added.tag.clone()));
 
             // Update metadata
             for parent in tag_added.parents() {
-                event.remove_patch_from_latest(&parent);
+                event.remove_patch_from_latest(parent);
             }
-            event.add_patch_to_latest(patch_ref.clone());
+            event.add_patch_to_latest(*patch_ref);
         }
         for tag_removed in patch.remove_tag.iter() {
             let event = self
@@ -94,33 +211,188 @@ impl PatchedTimesheet {
                 .get_mut(&tag_removed.event)
                 .expect("valid patch");
             event.remove_tag(tag_removed.patch, tag_removed.tag.clone());
+            event.record(
+                *patch_ref,
+                OperationKind::RemoveTag(tag_removed.tag.clone()),
+            );
 
             // Update metadata
             event.remove_patch_from_latest(&tag_removed.patch);
             for parent in tag_removed.parents() {
-                event.remove_patch_from_latest(&parent);
+                event.remove_patch_from_latest(parent);
             }
-            event.add_patch_to_latest(patch_ref.clone());
+            event.add_patch_to_latest(*patch_ref);
         }
 
         for new_event in patch.create_event.iter() {
             let mut event = PatchedEvent::new();
-            event.add_start(patch_ref.clone(), new_event.start);
+            event.add_start(*patch_ref, new_event.start);
             for tag in new_event.tags.iter().cloned() {
-                event.add_tag(patch_ref.clone(), tag);
+                event.add_tag(*patch_ref, tag);
             }
+            event.record(
+                *patch_ref,
+                OperationKind::Create {
+                    start: new_event.start,
+                    tags: new_event.tags.clone(),
+                },
+            );
 
             // Update metadata
-            event.add_patch_to_latest(patch_ref.clone());
+            event.add_patch_to_latest(*patch_ref);
 
             let prev_entry = self.events.insert(new_event.event.clone(), event);
             assert!(prev_entry.is_none());
         }
 
+        for delete_event in patch.delete_event.iter() {
+            let event = self
+                .events
+                .get_mut(&delete_event.event)
+                .expect("valid patch");
+            event.add_delete(*patch_ref);
+            event.record(*patch_ref, OperationKind::Delete);
+
+            // Update metadata
+            for parent in delete_event.parents() {
+                event.remove_patch_from_latest(parent);
+            }
+            event.add_patch_to_latest(*patch_ref);
+        }
+        for undelete_event in patch.undelete_event.iter() {
+            let event = self
+                .events
+                .get_mut(&undelete_event.event)
+                .expect("valid patch");
+            event.remove_delete(undelete_event.patch);
+            event.record(*patch_ref, OperationKind::Undelete);
+
+            // Update metadata
+            event.remove_patch_from_latest(&undelete_event.patch);
+            for parent in undelete_event.parents() {
+                event.remove_patch_from_latest(parent);
+            }
+            event.add_patch_to_latest(*patch_ref);
+        }
+
+        for set_note in patch.set_note.iter() {
+            let event = self.events.get_mut(&set_note.event).expect("valid patch");
+            event.set_note(*patch_ref, &set_note.replaces, set_note.note.clone());
+            event.record(*patch_ref, OperationKind::SetNote(set_note.note.clone()));
+
+            // Update metadata
+            for parent in set_note.parents() {
+                event.remove_patch_from_latest(parent);
+            }
+            event.add_patch_to_latest(*patch_ref);
+        }
+
+        for add_absence in patch.add_absence.iter() {
+            self.absences.insert((*patch_ref, add_absence.clone()));
+        }
+        for remove_absence in patch.remove_absence.iter() {
+            self.absences
+                .remove(&(remove_absence.patch, remove_absence.absence()));
+        }
+
+        if !patch.metadata.is_empty() {
+            self.patch_metadata
+                .insert(*patch_ref, patch.metadata.clone());
+        }
+
         Ok(())
     }
 
-    #[cfg_attr(feature = "flame_it", flame)]
+    /// Find the event that `prefix` refers to. A full reference always
+    /// matches its event; otherwise the prefix has to match exactly one event.
+    pub fn resolve_event_ref(&self, prefix: &str) -> Result<EventRef, RefError> {
+        if self.events.contains_key(prefix) {
+            return Ok(prefix.to_string());
+        }
+        let candidates: Vec<EventRef> = self
+            .events
+            .range(prefix.to_string()..)
+            .map(|(event_ref, _)| event_ref)
+            .take_while(|event_ref| event_ref.starts_with(prefix))
+            .cloned()
+            .collect();
+        match candidates.len() {
+            0 => Err(RefError::UnknownRef {
+                prefix: prefix.to_string(),
+            }),
+            1 => Ok(candidates.into_iter().next().unwrap()),
+            _ => Err(RefError::AmbiguousRef {
+                prefix: prefix.to_string(),
+                candidates,
+            }),
+        }
+    }
+
+    /// The shortest prefix of each event reference that no other event shares,
+    /// and at least `MIN_SHORT_REF_LEN` long
+    pub fn short_refs(&self) -> BTreeMap<EventRef, String> {
+        let refs: Vec<&EventRef> = self.events.keys().collect();
+        let shared =
+            |a: &str, b: &str| a.chars().zip(b.chars()).take_while(|(x, y)| x == y).count();
+        refs.iter()
+            .enumerate()
+            .map(|(i, event_ref)| {
+                let before = i.checked_sub(1).map_or(0, |j| shared(refs[j], event_ref));
+                let after = refs.get(i + 1).map_or(0, |next| shared(next, event_ref));
+                let len = (before.max(after) + 1).max(MIN_SHORT_REF_LEN);
+                let short: String = event_ref.chars().take(len).collect();
+                ((*event_ref).clone(), short)
+            })
+            .collect()
+    }
+
+    /// How many patches each source added since `since`, going by their
+    /// metadata. Patches without a source are counted as "unknown"; patches
+    /// that don't record when they were added aren't counted.
+    pub fn patch_rates(&self, since: DateTime<Utc>) -> BTreeMap<String, usize> {
+        let mut rates = BTreeMap::new();
+        for metadata in self.patch_metadata.values() {
+            let created = metadata
+                .get(CREATED_METADATA)
+                .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+                .map(|t| t.with_timezone(&Utc));
+            if created.is_none_or(|t| t < since) {
+                continue;
+            }
+            let source = metadata
+                .get(SOURCE_METADATA)
+                .map(String::as_str)
+                .unwrap_or("unknown");
+            *rates.entry(source.to_string()).or_insert(0) += 1;
+        }
+        rates
+    }
+
+    /// When each patch was created, going by its metadata
+    pub fn created_times(&self) -> CreatedTimes {
+        self.patch_metadata
+            .iter()
+            .filter_map(|(patch, metadata)| {
+                let created = DateTime::parse_from_rfc3339(metadata.get(CREATED_METADATA)?).ok()?;
+                Some((*patch, created.with_timezone(&Utc)))
+            })
+            .collect()
+    }
+
+    /// Whether the patch that created `event` marked it private
+    pub fn is_private(&self, event: &EventRef) -> bool {
+        self.event_metadata(event, PRIVATE_METADATA) == Some("true")
+    }
+
+    /// Look up `key` in the metadata of the patch that created `event`
+    pub fn event_metadata(&self, event: &EventRef, key: &str) -> Option<&str> {
+        let created_by = self.events.get(event)?.created_by()?;
+        self.patch_metadata
+            .get(&created_by)?
+            .get(key)
+            .map(String::as_str)
+    }
+
     fn verify_patch(&self, patch: &Patch) -> Result<(), Vec<Error>> {
         let mut errors = Vec::new();
         let patch_ref = patch.patch_ref();
@@ -150,25 +422,78 @@ impl PatchedTimesheet {
             };
         }
 
-        for tag_added in patch.add_tag.iter() {
-            self.events
-                .get(&tag_added.event)
-                .expect("no event for add-tag");
+        let tagged_events = patch
+            .add_tag
+            .iter()
+            .map(|x| &x.event)
+            .chain(patch.remove_tag.iter().map(|x| &x.event));
+        for event in tagged_events {
+            if !self.events.contains_key(event) {
+                errors.push(Error::UnknownEvent {
+                    patch: *patch_ref,
+                    event: event.clone(),
+                });
+            }
         }
-        for tag_removed in patch.remove_tag.iter() {
-            self.events
-                .get(&tag_removed.event)
-                .expect("no event for remove-tag");
+
+        let deleted_events = patch
+            .delete_event
+            .iter()
+            .map(|x| &x.event)
+            .chain(patch.undelete_event.iter().map(|x| &x.event))
+            .chain(patch.set_note.iter().map(|x| &x.event));
+        for event in deleted_events {
+            let created_by_patch = patch.create_event.iter().any(|x| &x.event == event);
+            if !self.events.contains_key(event) && !created_by_patch {
+                errors.push(Error::UnknownEvent {
+                    patch: *patch_ref,
+                    event: event.clone(),
+                });
+            }
         }
 
+        // Two devices may undo the same delete, so an undelete only has to
+        // point at a delete that happened
+        for undelete in patch.undelete_event.iter() {
+            let deleted = self
+                .events
+                .get(&undelete.event)
+                .is_some_and(|event| event.was_deleted_by(&undelete.patch));
+            if self.events.contains_key(&undelete.event) && !deleted {
+                errors.push(Error::UnknownDelete {
+                    patch: *patch_ref,
+                    event: undelete.event.clone(),
+                    deleted_by: undelete.patch,
+                });
+            }
+        }
+
+        let mut created = BTreeSet::new();
         for new_event in patch.create_event.iter() {
-            if self.events.get(&new_event.event).is_some() {
+            if self.events.contains_key(&new_event.event) || !created.insert(&new_event.event) {
                 errors.push(Error::DuplicateEventId {
                     id: new_event.event.clone(),
                 });
             }
         }
 
+        for add_absence in patch.add_absence.iter() {
+            if add_absence.end < add_absence.start {
+                errors.push(Error::BackwardsAbsence { patch: *patch_ref });
+            }
+        }
+        for remove_absence in patch.remove_absence.iter() {
+            let key = (remove_absence.patch, remove_absence.absence());
+            if !self.absences.contains(&key) {
+                errors.push(Error::UnknownAbsence {
+                    patch: *patch_ref,
+                    added_by: remove_absence.patch,
+                    start: remove_absence.start,
+                    end: remove_absence.end,
+                });
+            }
+        }
+
         if !errors.is_empty() {
             Err(errors)
         } else {
@@ -176,22 +501,82 @@ impl PatchedTimesheet {
         }
     }
 
+    /// Find every conflict in the timesheet. An empty list means that
+    /// `flatten` will succeed.
+    pub fn conflicts(&self) -> Vec<Conflict> {
+        let mut conflicts = Vec::new();
+        let mut events_by_start: BTreeMap<DateTime<Utc>, Vec<EventRef>> = BTreeMap::new();
+        for (event_ref, patched_event) in self.events.iter() {
+            if patched_event.is_deleted() {
+                continue;
+            }
+            let starts = patched_event.starts();
+            match starts.len() {
+                0 => conflicts.push(Conflict::NoStartTimes {
+                    event: event_ref.clone(),
+                }),
+                1 => {
+                    let (_patch, time) = starts.into_iter().next().expect("one start");
+                    events_by_start
+                        .entry(time)
+                        .or_default()
+                        .push(event_ref.clone());
+                }
+                _ => conflicts.push(Conflict::MultipleStartTimes {
+                    event: event_ref.clone(),
+                    starts: starts.into_iter().collect(),
+                }),
+            }
+        }
+        for (time, events) in events_by_start {
+            if events.len() > 1 {
+                conflicts.push(Conflict::SharedStartTime { time, events });
+            }
+        }
+        conflicts
+    }
+
     pub fn flatten(&self) -> Result<Timesheet<'_>, Vec<Error>> {
-        let mut timesheet = Timesheet::new(&self);
+        self.flatten_with(Resolution::Manual)
+    }
+
+    /// Flatten the timesheet, using `resolution` to pick between the start
+    /// times of events that have more than one.
+    #[instrument(level = "debug", skip(self), fields(events = self.events.len()))]
+    pub fn flatten_with(&self, resolution: Resolution) -> Result<Timesheet<'_>, Vec<Error>> {
+        let (timesheet, errors) = self.flatten_lossy(resolution);
+        if !errors.is_empty() {
+            Err(errors)
+        } else {
+            Ok(timesheet)
+        }
+    }
+
+    /// Flatten the timesheet, leaving out the events that can't be
+    /// flattened. When two events start at the same time, the first one is
+    /// kept. The errors are returned alongside the timesheet.
+    pub fn flatten_lossy(&self, resolution: Resolution) -> (Timesheet<'_>, Vec<Error>) {
+        let mut timesheet = Timesheet::new(self);
         let mut errors = Vec::new();
         let mut event_datetimes_to_refs: BTreeMap<DateTime<Utc>, EventRef> = BTreeMap::new();
+        let created = self.created_times();
         for (event_ref, patched_event) in self.events.iter() {
-            match patched_event.flatten() {
+            if patched_event.is_deleted() {
+                continue;
+            }
+            match patched_event.flatten_with(resolution, &created) {
                 Ok(event) => {
-                    if let Some(_event_a_tags) =
-                        timesheet.event_at_time(event.start().clone(), event_ref.clone())
+                    if let Some(event_a) =
+                        timesheet.event_at_time(*event.start(), event_ref.clone())
                     {
                         errors.push(Error::DuplicateEventTime {
                             event_a: event_datetimes_to_refs[event.start()].clone(),
                             event_b: event_ref.clone(),
                         });
+                        timesheet.event_at_time(*event.start(), event_a);
+                        continue;
                     }
-                    event_datetimes_to_refs.insert(event.start().clone(), event_ref.clone());
+                    event_datetimes_to_refs.insert(*event.start(), event_ref.clone());
                 }
                 Err(source) => {
                     errors.push(Error::FlattenEventError {
@@ -202,10 +587,188 @@ impl PatchedTimesheet {
             }
         }
 
-        if !errors.is_empty() {
-            Err(errors)
+        (timesheet, errors)
+    }
+
+    /// Create a patch that removes the start times `resolution` would discard,
+    /// so that the choice is recorded and shared with other devices. Returns
+    /// `None` if there is nothing to resolve.
+    pub fn resolution_patch(&self, resolution: Resolution) -> Option<Patch> {
+        let mut patch = Patch::new();
+        let mut changed = false;
+        let created = self.created_times();
+        for (event_ref, patched_event) in self.events.iter() {
+            if patched_event.is_deleted() || patched_event.starts().len() < 2 {
+                continue;
+            }
+            let kept = match patched_event.resolve_start(resolution, &created) {
+                Ok(kept) => kept,
+                Err(_) => continue,
+            };
+            for (start_patch, time) in patched_event.starts() {
+                if (start_patch, time) == kept {
+                    continue;
+                }
+                patch.insert_remove_start(RemoveStart {
+                    parents: Some(patched_event.latest_patches()),
+                    patch: start_patch,
+                    event: event_ref.clone(),
+                    time,
+                });
+                changed = true;
+            }
+        }
+        if changed {
+            Some(patch)
         } else {
-            Ok(timesheet)
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::store::patch::{DeleteEvent, UndeleteEvent};
+    use chrono::TimeZone;
+    use uuid::Uuid;
+
+    #[test]
+    fn count_patches_by_source() {
+        let time = |m| Utc.ymd(2019, 7, 24).and_hms(14, m, 0);
+        let mut timesheet = PatchedTimesheet::new();
+        for (i, source) in ["hook", "hook", "cli"].iter().enumerate() {
+            let patch = Patch::new()
+                .create_event(format!("e{}", i), time(i as u32), vec![])
+                .with_metadata(CREATED_METADATA, time(i as u32 * 20).to_rfc3339())
+                .with_metadata(SOURCE_METADATA, *source);
+            timesheet.apply_patch(&patch).unwrap();
         }
+        let untracked = Patch::new().create_event("old".into(), time(50), vec![]);
+        timesheet.apply_patch(&untracked).unwrap();
+
+        let rates = timesheet.patch_rates(time(10));
+        assert_eq!(rates.get("hook"), Some(&1));
+        assert_eq!(rates.get("cli"), Some(&1));
+        assert_eq!(rates.len(), 2);
+    }
+
+    #[test]
+    fn resolve_short_event_refs() {
+        let time = Utc.ymd(2019, 7, 24).and_hms(14, 0, 0);
+        let mut timesheet = PatchedTimesheet::new();
+        for event in ["abcdef0123", "abcdef4567", "bbbbbbbbbbbb"].iter() {
+            let patch = Patch::new().create_event(event.to_string(), time, vec![]);
+            timesheet.apply_patch(&patch).unwrap();
+        }
+
+        let short = timesheet.short_refs();
+        assert_eq!(short["abcdef0123"], "abcdef01");
+        assert_eq!(short["bbbbbbbbbbbb"], "bbbbbbbb");
+
+        assert_eq!(
+            timesheet.resolve_event_ref("abcdef01"),
+            Ok("abcdef0123".into())
+        );
+        assert_eq!(timesheet.resolve_event_ref("b"), Ok("bbbbbbbbbbbb".into()));
+        assert_eq!(
+            timesheet.resolve_event_ref("abc"),
+            Err(RefError::AmbiguousRef {
+                prefix: "abc".into(),
+                candidates: vec!["abcdef0123".into(), "abcdef4567".into()],
+            })
+        );
+        assert!(timesheet.resolve_event_ref("c").is_err());
+    }
+
+    #[test]
+    fn latest_patch_wins_whatever_the_load_order() {
+        let time = |m| Utc.ymd(2019, 7, 24).and_hms(14, m, 0);
+        let create = Patch::new().create_event("a".into(), time(0), vec![]);
+        // Two devices move the start at once; the second one does it later
+        let first = Patch::new()
+            .add_start(create.id, "a".into(), time(10))
+            .with_metadata(CREATED_METADATA, time(30).to_rfc3339());
+        let second = Patch::new()
+            .add_start(create.id, "a".into(), time(20))
+            .with_metadata(CREATED_METADATA, time(40).to_rfc3339());
+
+        for order in [[&first, &second], [&second, &first]].iter() {
+            let mut timesheet = PatchedTimesheet::new();
+            timesheet.apply_patch(&create).unwrap();
+            for patch in order.iter() {
+                timesheet.apply_patch(patch).unwrap();
+            }
+            let resolution = timesheet
+                .resolution_patch(Resolution::LatestPatchWins)
+                .unwrap();
+            let removed: BTreeSet<_> = resolution.remove_start.iter().map(|r| r.time).collect();
+            assert_eq!(removed, [time(0), time(10)].iter().copied().collect());
+        }
+    }
+
+    #[test]
+    fn only_deleted_events_are_undeleted() {
+        let time = Utc.ymd(2019, 7, 24).and_hms(14, 0, 0);
+        let create = Patch::new().create_event("a".into(), time, vec![]);
+        let mut timesheet = PatchedTimesheet::new();
+        timesheet.apply_patch(&create).unwrap();
+
+        let mut undelete = Patch::new();
+        undelete.insert_undelete_event(UndeleteEvent {
+            parents: None,
+            patch: create.id,
+            event: "a".into(),
+        });
+        assert_eq!(
+            timesheet.clone().apply_patch(&undelete),
+            Err(vec![Error::UnknownDelete {
+                patch: undelete.id,
+                event: "a".into(),
+                deleted_by: create.id,
+            }])
+        );
+
+        let mut delete = Patch::new();
+        delete.insert_delete_event(DeleteEvent {
+            parents: BTreeSet::new(),
+            event: "a".into(),
+        });
+        timesheet.apply_patch(&delete).unwrap();
+        let undelete = delete.invert(&timesheet);
+        timesheet.apply_patch(&undelete).unwrap();
+        assert!(!timesheet.events["a"].is_deleted());
+    }
+
+    #[test]
+    fn absences_are_added_and_removed_by_patches() {
+        let absence = AddAbsence {
+            kind: "sick".into(),
+            start: NaiveDate::from_ymd(2019, 7, 23),
+            end: NaiveDate::from_ymd(2019, 7, 24),
+        };
+        let mut add = Patch::new();
+        add.insert_add_absence(absence.clone());
+        let mut timesheet = PatchedTimesheet::new();
+        timesheet.apply_patch(&add).unwrap();
+        assert!(timesheet.absences.contains(&(add.id, absence.clone())));
+
+        let remove = add.invert(&timesheet);
+        let mut removed = timesheet.clone();
+        removed.apply_patch(&remove).unwrap();
+        assert!(removed.absences.is_empty());
+
+        // Only an absence that is there can be removed
+        let mut unknown = remove.clone();
+        unknown.id = Uuid::nil();
+        assert_eq!(
+            PatchedTimesheet::new().apply_patch(&unknown),
+            Err(vec![Error::UnknownAbsence {
+                patch: Uuid::nil(),
+                added_by: add.id,
+                start: absence.start,
+                end: absence.end,
+            }])
+        );
     }
 }
@@ -0,0 +1,280 @@
+//! Statistics about the time tracked over a range, like the longest event and
+//! the busiest day of the week
+
+use crate::{
+    aggregate::{self, Period},
+    timesheet::Segment,
+    EventRef, Tag,
+};
+use chrono::{DateTime, Datelike, Duration, NaiveDate, TimeZone, Utc, Weekday};
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Stats {
+    /// All the tagged time in the range
+    pub total: Duration,
+    /// How many days in the range had some tagged time
+    pub days_tracked: usize,
+    /// The total divided by the days that had time tracked
+    pub average_per_day: Duration,
+    pub longest_event: Option<LongestEvent>,
+    /// The day of the week that got the most time, over the whole range
+    pub busiest_weekday: Option<(Weekday, Duration)>,
+    pub longest_streak: Option<Streak>,
+    /// How many different tags were used
+    pub distinct_tags: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LongestEvent {
+    pub event_ref: EventRef,
+    pub start: DateTime<Utc>,
+    pub tags: BTreeSet<Tag>,
+    /// Only the part of the event inside the range
+    pub duration: Duration,
+}
+
+/// Days in a row that had time tracked
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Streak {
+    pub first: NaiveDate,
+    pub days: usize,
+}
+
+/// Statistics for the time between `start` and `end`, with days following the
+/// calendar of `timezone`. The streak only counts days with time on
+/// `streak_tag`, or days with any tagged time if it's `None`.
+///
+/// If there are `workdays`, time on the other days of the week is left out,
+/// and streaks carry on over them.
+pub fn stats<Tz: TimeZone>(
+    segments: &[Segment],
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    streak_tag: Option<&Tag>,
+    workdays: Option<&[Weekday]>,
+    timezone: &Tz,
+) -> Stats {
+    let is_workday = |date: &NaiveDate| workdays.is_none_or(|w| w.contains(&date.weekday()));
+    let mut days = aggregate::by_period(segments, start, end, Period::Day, timezone);
+    if workdays.is_some() {
+        days.groups.retain(|date, _| is_workday(date));
+        days.total = days.groups.values().fold(Duration::zero(), |a, b| a + *b);
+    }
+    let days_tracked = days.groups.len();
+    let average_per_day = match days_tracked {
+        0 => Duration::zero(),
+        days_tracked => days.total / days_tracked as i32,
+    };
+
+    let longest_event = segments
+        .iter()
+        .filter(|s| !s.tags.is_empty())
+        .filter(|s| is_workday(&s.start_time.with_timezone(timezone).date().naive_local()))
+        .map(|s| (s, s.end_time.min(end) - s.start_time.max(start)))
+        .filter(|(_, duration)| *duration > Duration::zero())
+        .max_by_key(|(s, duration)| (*duration, std::cmp::Reverse(s.start_time)))
+        .map(|(s, duration)| LongestEvent {
+            event_ref: s.event_ref.clone(),
+            start: s.start_time,
+            tags: s.tags.clone(),
+            duration,
+        });
+
+    let mut weekdays: BTreeMap<u32, Duration> = BTreeMap::new();
+    for (date, duration) in days.groups.iter() {
+        let weekday = weekdays
+            .entry(date.weekday().num_days_from_monday())
+            .or_insert_with(Duration::zero);
+        *weekday = *weekday + *duration;
+    }
+    let busiest_weekday = weekdays
+        .iter()
+        .max_by_key(|(day, duration)| (**duration, std::cmp::Reverse(**day)))
+        .map(|(day, duration)| (weekday_from_monday(*day), *duration));
+
+    let streak_days: BTreeSet<NaiveDate> = match streak_tag {
+        Some(tag) => {
+            let tagged: Vec<Segment> = segments
+                .iter()
+                .filter(|s| s.tags.contains(tag))
+                .cloned()
+                .collect();
+            aggregate::by_period(&tagged, start, end, Period::Day, timezone)
+                .groups
+                .into_keys()
+                .filter(is_workday)
+                .collect()
+        }
+        None => days.groups.keys().copied().collect(),
+    };
+
+    let distinct_tags = segments
+        .iter()
+        .filter(|s| s.end_time > start && s.start_time < end)
+        .flat_map(|s| s.tags.iter())
+        .collect::<BTreeSet<_>>()
+        .len();
+
+    Stats {
+        total: days.total,
+        days_tracked,
+        average_per_day,
+        longest_event,
+        busiest_weekday,
+        longest_streak: longest_streak(&streak_days, is_workday),
+        distinct_tags,
+    }
+}
+
+/// The longest run of consecutive days in `days`, skipping over the days that
+/// aren't `counted`. Ties go to the earliest.
+fn longest_streak(
+    days: &BTreeSet<NaiveDate>,
+    counted: impl Fn(&NaiveDate) -> bool,
+) -> Option<Streak> {
+    let mut longest: Option<Streak> = None;
+    let mut current: Option<(Streak, NaiveDate)> = None;
+    for date in days.iter() {
+        let mut next = *date;
+        loop {
+            next = next.succ();
+            if counted(&next) || next - *date >= Duration::weeks(1) {
+                break;
+            }
+        }
+        current = match current {
+            Some((streak, expected)) if expected == *date => Some((
+                Streak {
+                    first: streak.first,
+                    days: streak.days + 1,
+                },
+                next,
+            )),
+            _ => Some((
+                Streak {
+                    first: *date,
+                    days: 1,
+                },
+                next,
+            )),
+        };
+        let streak = current.as_ref().map(|(streak, _)| streak);
+        if longest.as_ref().map(|l| l.days) < streak.map(|s| s.days) {
+            longest = streak.cloned();
+        }
+    }
+    longest
+}
+
+fn weekday_from_monday(days: u32) -> Weekday {
+    let mut weekday = Weekday::Mon;
+    for _ in 0..days {
+        weekday = weekday.succ();
+    }
+    weekday
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn segment(start: DateTime<Utc>, end: DateTime<Utc>, tags: &[&str]) -> Segment {
+        Segment {
+            event_ref: format!("{}", start.timestamp()),
+            start_time: start,
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            duration: end - start,
+            end_time: end,
+        }
+    }
+
+    #[test]
+    fn stats_for_a_week() {
+        // 2019-07-01 is a Monday
+        let time = |d, h| Utc.ymd(2019, 7, d).and_hms(h, 0, 0);
+        let segments = vec![
+            segment(time(1, 9), time(1, 12), &["work", "coding"]),
+            segment(time(2, 9), time(2, 10), &["gym"]),
+            segment(time(3, 9), time(3, 11), &["gym"]),
+            segment(time(3, 11), time(3, 12), &[]),
+            segment(time(4, 9), time(4, 10), &["gym"]),
+            segment(time(8, 9), time(8, 18), &["work"]),
+        ];
+        let stats = stats(&segments, time(1, 0), time(8, 12), None, None, &Utc);
+        assert_eq!(stats.total, Duration::hours(10));
+        assert_eq!(stats.days_tracked, 5);
+        assert_eq!(stats.average_per_day, Duration::hours(2));
+        let longest = stats.longest_event.unwrap();
+        assert_eq!(longest.start, time(1, 9));
+        assert_eq!(longest.duration, Duration::hours(3));
+        assert_eq!(
+            stats.busiest_weekday,
+            Some((Weekday::Mon, Duration::hours(6)))
+        );
+        assert_eq!(
+            stats.longest_streak,
+            Some(Streak {
+                first: NaiveDate::from_ymd(2019, 7, 1),
+                days: 4,
+            })
+        );
+        assert_eq!(stats.distinct_tags, 3);
+
+        let gym = stats_for_tag(&segments, time(1, 0), time(8, 12), "gym");
+        assert_eq!(
+            gym,
+            Some(Streak {
+                first: NaiveDate::from_ymd(2019, 7, 2),
+                days: 3,
+            })
+        );
+    }
+
+    fn stats_for_tag(
+        segments: &[Segment],
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        tag: &str,
+    ) -> Option<Streak> {
+        stats(segments, start, end, Some(&tag.to_string()), None, &Utc).longest_streak
+    }
+
+    #[test]
+    fn stats_for_workdays() {
+        // 2019-07-05 is a Friday
+        let time = |d, h| Utc.ymd(2019, 7, d).and_hms(h, 0, 0);
+        let segments = vec![
+            segment(time(4, 9), time(4, 13), &["work"]),
+            segment(time(5, 9), time(5, 11), &["work"]),
+            segment(time(6, 9), time(6, 18), &["hike"]),
+            segment(time(8, 9), time(8, 12), &["work"]),
+        ];
+        let workdays = [
+            Weekday::Mon,
+            Weekday::Tue,
+            Weekday::Wed,
+            Weekday::Thu,
+            Weekday::Fri,
+        ];
+        let stats = stats(
+            &segments,
+            time(1, 0),
+            time(9, 0),
+            None,
+            Some(&workdays),
+            &Utc,
+        );
+        assert_eq!(stats.total, Duration::hours(9));
+        assert_eq!(stats.days_tracked, 3);
+        assert_eq!(stats.average_per_day, Duration::hours(3));
+        assert_eq!(stats.longest_event.unwrap().start, time(4, 9));
+        assert_eq!(
+            stats.longest_streak,
+            Some(Streak {
+                first: NaiveDate::from_ymd(2019, 7, 4),
+                days: 3,
+            })
+        );
+    }
+}
@@ -2,7 +2,7 @@ pub mod meta;
 pub mod patch;
 pub mod sync_folder_store;
 
-pub use sync_folder_store::{SyncFolderStore, SyncFolderStoreError};
+pub use sync_folder_store::{Arrivals, StoreStats, SyncFolderStore, SyncFolderStoreError};
 
 use self::meta::Meta;
 use self::patch::Patch;
@@ -16,4 +16,28 @@ pub trait Store {
     fn save_meta(&mut self, meta: &Meta) -> Result<(), Self::Error>;
     fn get_patch(&self, patch_ref: &PatchRef) -> Result<Patch, Self::Error>;
     fn add_patch(&mut self, patch: &Patch) -> Result<(), Self::Error>;
+
+    /// Finish or undo a transaction that was interrupted, like by a crash.
+    /// Called before the meta file is loaded.
+    fn recover(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Start a transaction. Patches added until `commit` is called only
+    /// become part of the store together, along with the meta file. Stores
+    /// that can't do that write each patch right away.
+    fn begin(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Finish the current transaction by saving its patches, and `meta` last
+    fn commit(&mut self, meta: &Meta) -> Result<(), Self::Error> {
+        self.save_meta(meta)
+    }
+
+    /// Throw away the patches added since `begin`. Stores that write each
+    /// patch right away keep them.
+    fn rollback(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
 }
@@ -1,20 +1,130 @@
 use crate::PatchRef;
+use chrono::{DateTime, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt};
 
 type Set<T> = std::collections::HashSet<T>;
 
-#[derive(Default, Eq, PartialEq, Debug, Clone, Serialize, Deserialize)]
+/// The version of the store format written by this version of augr. Stores
+/// with a newer version may contain data that this version doesn't understand.
+///
+/// - 1: events are created, and their starts and tags added and removed
+/// - 2: events can be deleted and undeleted, and notes set on them
+/// - 3: days can be marked as absences
+pub const FORMAT_VERSION: u32 = 3;
+
+#[derive(Eq, PartialEq, Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct Meta {
+    /// The version of the format that this device's data is stored in
+    #[serde(default = "original_format_version")]
+    format_version: u32,
+
+    /// When this device first saved its meta file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    created: Option<DateTime<Utc>>,
+
+    /// When this device last added a patch to the store
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_patch_added: Option<DateTime<Utc>>,
+
+    /// The patches that this device has added, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    added_patches: Vec<PatchRef>,
+
+    /// Events started on this device are marked private
+    #[serde(default, skip_serializing_if = "is_false")]
+    private_mode: bool,
+
+    /// Optional features that the data in this store relies on
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    capabilities: Vec<Capability>,
+
     /// The patches that this Meta file depends on, which may exclude patches
     /// that are referenced as ancestors of some patch that is included.
     patches: Set<PatchRef>,
+
+    /// Checksums of the events that this device sees on each recent day, so
+    /// that devices can tell when they disagree about the same patches
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    day_checksums: Option<DayChecksums>,
+
+    /// Information about the devices that share this store, by device id
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    devices: BTreeMap<String, Device>,
+}
+
+/// Meta files written before the format was versioned
+fn original_format_version() -> u32 {
+    1
+}
+
+#[derive(Default, Eq, PartialEq, Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Device {
+    /// A human readable name, like "laptop"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
+    /// When the name was set, so that the newest name wins when devices
+    /// share what they know
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub renamed: Option<DateTime<Utc>>,
+
+    /// The device is no longer used, so it won't add any more patches
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub retired: bool,
+}
+
+/// Checksums of the flattened events that start on each day, by UTC date
+#[derive(Eq, PartialEq, Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct DayChecksums {
+    /// A digest of the patches that were loaded when the checksums were made
+    pub patches: String,
+
+    /// The first day that has been checked. Days after it that have no
+    /// checksum have no events.
+    pub since: NaiveDate,
+
+    pub days: BTreeMap<NaiveDate, String>,
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+/// An optional feature of the store. Capabilities that this version of augr
+/// doesn't know about are kept as `Other`, so that they survive being saved
+/// again.
+#[derive(Eq, PartialEq, Ord, PartialOrd, Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum Capability {
+    /// Patches are encrypted
+    Encryption,
+    /// Events may have explicit end times
+    EndTimes,
+    Other(String),
+}
+
+impl Default for Meta {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Meta {
     pub fn new() -> Self {
         Self {
+            format_version: FORMAT_VERSION,
+            created: None,
+            last_patch_added: None,
+            added_patches: Vec::new(),
+            private_mode: false,
+            capabilities: Vec::new(),
             patches: Set::new(),
+            day_checksums: None,
+            devices: BTreeMap::new(),
         }
     }
 
@@ -25,28 +135,259 @@ impl Meta {
     pub fn patches(&self) -> impl Iterator<Item = &PatchRef> {
         self.patches.iter()
     }
+
+    /// Replace the patches that this meta file depends on
+    pub fn set_patches(&mut self, patches: impl IntoIterator<Item = PatchRef>) {
+        self.patches = patches.into_iter().collect();
+    }
+
+    pub fn format_version(&self) -> u32 {
+        self.format_version
+    }
+
+    /// Whether this version of augr understands the format of the store
+    pub fn is_supported(&self) -> bool {
+        self.format_version <= FORMAT_VERSION
+    }
+
+    /// Mark the data as written in this version's format, since the patches
+    /// that this version adds may rely on it
+    pub fn upgrade_format_version(&mut self) {
+        self.format_version = self.format_version.max(FORMAT_VERSION);
+    }
+
+    pub fn created(&self) -> Option<DateTime<Utc>> {
+        self.created
+    }
+
+    pub fn set_created(&mut self, created: DateTime<Utc>) {
+        self.created = Some(created);
+    }
+
+    pub fn last_patch_added(&self) -> Option<DateTime<Utc>> {
+        self.last_patch_added
+    }
+
+    pub fn set_last_patch_added(&mut self, time: DateTime<Utc>) {
+        self.last_patch_added = Some(time);
+    }
+
+    /// The patches that this device has added, oldest first
+    pub fn added_patches(&self) -> &[PatchRef] {
+        &self.added_patches
+    }
+
+    pub fn record_added_patch(&mut self, patch_ref: PatchRef) {
+        self.added_patches.push(patch_ref);
+    }
+
+    /// Whether events started on this device should be marked private
+    pub fn private_mode(&self) -> bool {
+        self.private_mode
+    }
+
+    pub fn set_private_mode(&mut self, enabled: bool) {
+        self.private_mode = enabled;
+    }
+
+    pub fn day_checksums(&self) -> Option<&DayChecksums> {
+        self.day_checksums.as_ref()
+    }
+
+    pub fn set_day_checksums(&mut self, checksums: DayChecksums) {
+        self.day_checksums = Some(checksums);
+    }
+
+    pub fn capabilities(&self) -> impl Iterator<Item = &Capability> {
+        self.capabilities.iter()
+    }
+
+    pub fn has_capability(&self, capability: &Capability) -> bool {
+        self.capabilities.contains(capability)
+    }
+
+    pub fn enable_capability(&mut self, capability: Capability) {
+        if !self.has_capability(&capability) {
+            self.capabilities.push(capability);
+            self.capabilities.sort();
+        }
+    }
+
+    pub fn devices(&self) -> impl Iterator<Item = (&String, &Device)> {
+        self.devices.iter()
+    }
+
+    pub fn device(&self, device_id: &str) -> Option<&Device> {
+        self.devices.get(device_id)
+    }
+
+    /// The human readable name of a device, if it has been given one
+    pub fn device_name(&self, device_id: &str) -> Option<&str> {
+        self.device(device_id).and_then(|d| d.name.as_deref())
+    }
+
+    /// Name a device, as of `at`
+    pub fn set_device_name(
+        &mut self,
+        device_id: impl Into<String>,
+        name: impl Into<String>,
+        at: DateTime<Utc>,
+    ) {
+        let device = self.devices.entry(device_id.into()).or_default();
+        device.name = Some(name.into());
+        device.renamed = Some(at);
+    }
+
+    pub fn retire_device(&mut self, device_id: impl Into<String>) {
+        self.devices.entry(device_id.into()).or_default().retired = true;
+    }
+
+    pub fn is_retired(&self, device_id: &str) -> bool {
+        self.device(device_id).is_some_and(|d| d.retired)
+    }
+
+    /// Combine what `other` knows about devices with what this meta knows.
+    /// The name that was set last wins, and retiring a device can't be undone.
+    /// Names without a time lose to ones with a time, and ties go to the name
+    /// that sorts last, so that every device settles on the same name.
+    pub fn merge_devices(&mut self, other: &Meta) {
+        for (id, theirs) in other.devices.iter() {
+            let ours = self.devices.entry(id.clone()).or_default();
+            if (&theirs.renamed, &theirs.name) > (&ours.renamed, &ours.name) {
+                ours.name = theirs.name.clone();
+                ours.renamed = theirs.renamed;
+            }
+            ours.retired |= theirs.retired;
+        }
+    }
+}
+
+impl Capability {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Capability::Encryption => "encryption",
+            Capability::EndTimes => "end-times",
+            Capability::Other(name) => name,
+        }
+    }
+}
+
+impl From<String> for Capability {
+    fn from(name: String) -> Self {
+        match name.as_str() {
+            "encryption" => Capability::Encryption,
+            "end-times" => Capability::EndTimes,
+            _ => Capability::Other(name),
+        }
+    }
+}
+
+impl From<Capability> for String {
+    fn from(capability: Capability) -> Self {
+        capability.as_str().to_string()
+    }
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
 }
 
 #[cfg(test)]
+#[allow(array_into_iter, clippy::into_iter_on_ref)]
 mod test {
     use super::*;
+    use chrono::TimeZone;
     use uuid::Uuid;
 
     #[test]
     fn read_from_toml() {
-        let expected = Meta {
-            patches: [
+        let mut expected = Meta::new();
+        // The meta file is from before the format was versioned
+        expected.format_version = 1;
+        expected.set_patches(
+            [
                 "c10350e8-3f30-4d27-b120-8ee079e256d9",
                 "7a826905-7a3e-430d-9d54-5af08ecb482c",
             ]
             .into_iter()
-            .map(|s| Uuid::parse_str(s).unwrap())
-            .collect(),
-        };
+            .map(|s| Uuid::parse_str(s).unwrap()),
+        );
         let toml_str = r#"
             patches = ["c10350e8-3f30-4d27-b120-8ee079e256d9", "7a826905-7a3e-430d-9d54-5af08ecb482c"]
         "#;
         assert_eq!(toml::de::from_str(toml_str), Ok(expected));
     }
 
+    #[test]
+    fn round_trip_extended_meta() {
+        let mut meta = Meta::new();
+        meta.add_patch(Uuid::parse_str("c10350e8-3f30-4d27-b120-8ee079e256d9").unwrap());
+        meta.set_created(chrono::TimeZone::ymd(&Utc, 2019, 7, 16).and_hms(9, 0, 0));
+        meta.set_last_patch_added(chrono::TimeZone::ymd(&Utc, 2019, 8, 1).and_hms(17, 0, 0));
+        meta.record_added_patch(Uuid::parse_str("c10350e8-3f30-4d27-b120-8ee079e256d9").unwrap());
+        meta.enable_capability(Capability::EndTimes);
+        meta.enable_capability(Capability::Other("from-the-future".into()));
+        meta.set_device_name("7a826905", "laptop", Utc.ymd(2019, 7, 24).and_hms(9, 0, 0));
+        meta.set_private_mode(true);
+        let day = NaiveDate::from_ymd(2019, 8, 1);
+        meta.set_day_checksums(DayChecksums {
+            patches: "0a1b".into(),
+            since: day,
+            days: vec![(day, String::from("2c3d"))].into_iter().collect(),
+        });
+
+        let toml_str = toml::ser::to_string(&meta).unwrap();
+        let read: Meta = toml::de::from_str(&toml_str).unwrap();
+        assert_eq!(read, meta);
+        assert_eq!(read.device_name("7a826905"), Some("laptop"));
+        assert_eq!(read.added_patches().len(), 1);
+        assert!(read.has_capability(&Capability::EndTimes));
+        assert!(!read.has_capability(&Capability::Encryption));
+        assert!(read.is_supported());
+        assert!(read.private_mode());
+        assert_eq!(read.day_checksums().unwrap().days[&day], "2c3d");
+    }
+
+    #[test]
+    fn merge_device_registries() {
+        let time = |h| Utc.ymd(2019, 7, 24).and_hms(h, 0, 0);
+        let mut ours = Meta::new();
+        ours.set_device_name("a", "laptop", time(10));
+        ours.set_device_name("c", "desktop", time(9));
+        let mut theirs = Meta::new();
+        theirs.set_device_name("a", "old laptop", time(9));
+        theirs.set_device_name("b", "phone", time(9));
+        theirs.set_device_name("c", "work desktop", time(10));
+        theirs.retire_device("a");
+
+        ours.merge_devices(&theirs);
+        assert_eq!(ours.device_name("a"), Some("laptop"));
+        assert_eq!(ours.device_name("b"), Some("phone"));
+        assert_eq!(ours.device_name("c"), Some("work desktop"));
+        assert!(ours.is_retired("a"));
+        assert!(!ours.is_retired("b"));
+
+        // Merging the other way around settles on the same names
+        theirs.merge_devices(&ours);
+        assert_eq!(theirs.device_name("a"), Some("laptop"));
+        assert_eq!(theirs.device_name("c"), Some("work desktop"));
+    }
+
+    #[test]
+    fn names_without_a_time_lose() {
+        let mut ours: Meta =
+            toml::de::from_str("patches = []\n[devices.a]\nname = \"phone\"").unwrap();
+        let mut theirs = Meta::new();
+        theirs.set_device_name("a", "old phone", Utc.ymd(2019, 7, 24).and_hms(9, 0, 0));
+        ours.merge_devices(&theirs);
+        assert_eq!(ours.device_name("a"), Some("old phone"));
+    }
+
+    #[test]
+    fn newer_format_is_unsupported() {
+        let meta: Meta = toml::de::from_str("format-version = 4\npatches = []").unwrap();
+        assert!(!meta.is_supported());
+    }
 }
@@ -1,15 +1,18 @@
-use crate::Tag;
-use chrono::{DateTime, Utc};
+use crate::{repository::timesheet::PatchedTimesheet, Tag};
+use chrono::{DateTime, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
 use uuid::Uuid;
 
 pub type PatchRef = Uuid;
 type EventRef = String;
 type Set<T> = std::collections::HashSet<T>;
 
+/// Fields that this version doesn't know are rejected rather than ignored,
+/// since they would be operations from a newer version that would otherwise
+/// be left out without a word.
 #[derive(Eq, PartialEq, Debug, Clone, Serialize, Deserialize)]
-#[serde(rename_all = "kebab-case")]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
 pub struct Patch {
     pub id: Uuid,
 
@@ -27,10 +30,30 @@ pub struct Patch {
 
     #[serde(default, skip_serializing_if = "Set::is_empty")]
     pub create_event: Set<CreateEvent>,
+
+    #[serde(default, skip_serializing_if = "Set::is_empty")]
+    pub delete_event: Set<DeleteEvent>,
+
+    #[serde(default, skip_serializing_if = "Set::is_empty")]
+    pub undelete_event: Set<UndeleteEvent>,
+
+    #[serde(default, skip_serializing_if = "Set::is_empty")]
+    pub set_note: Set<SetNote>,
+
+    #[serde(default, skip_serializing_if = "Set::is_empty")]
+    pub add_absence: Set<AddAbsence>,
+
+    #[serde(default, skip_serializing_if = "Set::is_empty")]
+    pub remove_absence: Set<RemoveAbsence>,
+
+    /// Extra information about the patch that doesn't affect the timesheet,
+    /// such as the exact time an event was entered before being rounded
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: BTreeMap<String, String>,
 }
 
 #[derive(Hash, Eq, PartialEq, Debug, Clone, Serialize, Deserialize)]
-#[serde(rename_all = "kebab-case")]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
 pub struct AddStart {
     #[serde(default)]
     pub parents: BTreeSet<PatchRef>,
@@ -39,7 +62,7 @@ pub struct AddStart {
 }
 
 #[derive(Hash, Eq, PartialEq, Debug, Clone, Serialize, Deserialize)]
-#[serde(rename_all = "kebab-case")]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
 pub struct RemoveStart {
     #[serde(default)]
     pub parents: Option<BTreeSet<PatchRef>>,
@@ -49,7 +72,7 @@ pub struct RemoveStart {
 }
 
 #[derive(Hash, Eq, PartialEq, Debug, Clone, Serialize, Deserialize)]
-#[serde(rename_all = "kebab-case")]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
 pub struct AddTag {
     #[serde(default)]
     pub parents: BTreeSet<PatchRef>,
@@ -58,7 +81,7 @@ pub struct AddTag {
 }
 
 #[derive(Hash, Eq, PartialEq, Debug, Clone, Serialize, Deserialize)]
-#[serde(rename_all = "kebab-case")]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
 pub struct RemoveTag {
     #[serde(default)]
     pub parents: Option<BTreeSet<PatchRef>>,
@@ -68,22 +91,81 @@ pub struct RemoveTag {
 }
 
 #[derive(Hash, Eq, PartialEq, Debug, Clone, Serialize, Deserialize)]
-#[serde(rename_all = "kebab-case")]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
 pub struct CreateEvent {
     pub event: EventRef,
     pub start: DateTime<Utc>,
     pub tags: Vec<Tag>,
 }
 
+#[derive(Hash, Eq, PartialEq, Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct DeleteEvent {
+    #[serde(default)]
+    pub parents: BTreeSet<PatchRef>,
+    pub event: EventRef,
+}
+
+/// Reverts the `delete-event` made by `patch`
+#[derive(Hash, Eq, PartialEq, Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct UndeleteEvent {
+    #[serde(default)]
+    pub parents: Option<BTreeSet<PatchRef>>,
+    pub patch: PatchRef,
+    pub event: EventRef,
+}
+
+/// Sets the free-text note of an event. The notes set by the patches in
+/// `replaces` are removed; an empty note clears the event's note.
+#[derive(Hash, Eq, PartialEq, Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct SetNote {
+    #[serde(default)]
+    pub parents: BTreeSet<PatchRef>,
+    #[serde(default)]
+    pub replaces: BTreeSet<PatchRef>,
+    pub event: EventRef,
+    pub note: String,
+}
+
+/// Marks the days from `start` to `end`, inclusive, as days someone was
+/// absent, like "vacation" or "sick". Absences aren't events, so they don't
+/// take up any time in the timesheet.
+#[derive(Hash, Eq, PartialEq, Ord, PartialOrd, Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct AddAbsence {
+    pub kind: String,
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+}
+
+/// Removes the absence that `patch` added
+#[derive(Hash, Eq, PartialEq, Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct RemoveAbsence {
+    pub patch: PatchRef,
+    pub kind: String,
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+}
+
 impl Patch {
+    /// A patch with a new id from `id::new_id`
     pub fn new() -> Self {
         Self {
-            id: Uuid::new_v4(),
+            id: crate::id::new_id(),
             add_start: Set::new(),
             remove_start: Set::new(),
             add_tag: Set::new(),
             remove_tag: Set::new(),
             create_event: Set::new(),
+            delete_event: Set::new(),
+            undelete_event: Set::new(),
+            set_note: Set::new(),
+            add_absence: Set::new(),
+            remove_absence: Set::new(),
+            metadata: BTreeMap::new(),
         }
     }
 
@@ -95,6 +177,12 @@ impl Patch {
             add_tag: Set::new(),
             remove_tag: Set::new(),
             create_event: Set::new(),
+            delete_event: Set::new(),
+            undelete_event: Set::new(),
+            set_note: Set::new(),
+            add_absence: Set::new(),
+            remove_absence: Set::new(),
+            metadata: BTreeMap::new(),
         }
     }
 
@@ -115,10 +203,25 @@ impl Patch {
                 .flat_map(|x| x.parents.iter().flat_map(|s| s.iter())),
         );
         let add_tag_parents = self.add_tag.iter().flat_map(|x| x.parents.iter());
+        let delete_event_parents = self.delete_event.iter().flat_map(|x| x.parents.iter());
+        let undelete_event_parents = self.undelete_event.iter().map(|x| &x.patch).chain(
+            self.undelete_event
+                .iter()
+                .flat_map(|x| x.parents.iter().flat_map(|s| s.iter())),
+        );
+        let set_note_parents = self
+            .set_note
+            .iter()
+            .flat_map(|x| x.parents.iter().chain(x.replaces.iter()));
+        let remove_absence_parents = self.remove_absence.iter().map(|x| &x.patch);
         add_start_parents
             .chain(remove_start_parents)
             .chain(remove_tag_parents)
             .chain(add_tag_parents)
+            .chain(delete_event_parents)
+            .chain(undelete_event_parents)
+            .chain(set_note_parents)
+            .chain(remove_absence_parents)
             .cloned()
             .collect()
     }
@@ -179,6 +282,145 @@ impl Patch {
         self
     }
 
+    pub fn delete_event(mut self, parent: PatchRef, event: EventRef) -> Self {
+        self.delete_event.insert(DeleteEvent {
+            parents: {
+                let mut s = BTreeSet::new();
+                s.insert(parent);
+                s
+            },
+            event,
+        });
+        self
+    }
+
+    pub fn undelete_event(mut self, patch: PatchRef, event: EventRef) -> Self {
+        self.undelete_event.insert(UndeleteEvent {
+            parents: None,
+            patch,
+            event,
+        });
+        self
+    }
+
+    pub fn set_note(
+        mut self,
+        parent: PatchRef,
+        replaces: BTreeSet<PatchRef>,
+        event: EventRef,
+        note: String,
+    ) -> Self {
+        self.set_note.insert(SetNote {
+            parents: {
+                let mut s = BTreeSet::new();
+                s.insert(parent);
+                s
+            },
+            replaces,
+            event,
+            note,
+        });
+        self
+    }
+
+    /// Create a patch that undoes the effects of this one. `timesheet` should
+    /// be the timesheet this patch has been applied to; it is used to find the
+    /// parents of the new patch.
+    pub fn invert(&self, timesheet: &PatchedTimesheet) -> Patch {
+        let parents = |event: &EventRef| {
+            let mut parents = timesheet
+                .events
+                .get(event)
+                .map(|e| e.latest_patches())
+                .unwrap_or_default();
+            parents.insert(self.id);
+            parents
+        };
+
+        let mut inverse = Patch::new();
+        for add_start in self.add_start.iter() {
+            inverse.insert_remove_start(RemoveStart {
+                parents: Some(parents(&add_start.event)),
+                patch: self.id,
+                event: add_start.event.clone(),
+                time: add_start.time,
+            });
+        }
+        for remove_start in self.remove_start.iter() {
+            inverse.insert_add_start(AddStart {
+                parents: parents(&remove_start.event),
+                event: remove_start.event.clone(),
+                time: remove_start.time,
+            });
+        }
+        for add_tag in self.add_tag.iter() {
+            inverse.insert_remove_tag(RemoveTag {
+                parents: Some(parents(&add_tag.event)),
+                patch: self.id,
+                event: add_tag.event.clone(),
+                tag: add_tag.tag.clone(),
+            });
+        }
+        for remove_tag in self.remove_tag.iter() {
+            inverse.insert_add_tag(AddTag {
+                parents: parents(&remove_tag.event),
+                event: remove_tag.event.clone(),
+                tag: remove_tag.tag.clone(),
+            });
+        }
+        for create_event in self.create_event.iter() {
+            inverse.insert_delete_event(DeleteEvent {
+                parents: parents(&create_event.event),
+                event: create_event.event.clone(),
+            });
+        }
+        for delete_event in self.delete_event.iter() {
+            inverse.insert_undelete_event(UndeleteEvent {
+                parents: Some(parents(&delete_event.event)),
+                patch: self.id,
+                event: delete_event.event.clone(),
+            });
+        }
+        for undelete_event in self.undelete_event.iter() {
+            inverse.insert_delete_event(DeleteEvent {
+                parents: parents(&undelete_event.event),
+                event: undelete_event.event.clone(),
+            });
+        }
+        for set_note in self.set_note.iter() {
+            let previous = timesheet
+                .events
+                .get(&set_note.event)
+                .map(|e| e.notes_set_by(&set_note.replaces))
+                .unwrap_or_default();
+            let mut replaces = BTreeSet::new();
+            replaces.insert(self.id);
+            inverse.insert_set_note(SetNote {
+                parents: parents(&set_note.event),
+                replaces,
+                event: set_note.event.clone(),
+                note: previous.join("\n"),
+            });
+        }
+        for add_absence in self.add_absence.iter() {
+            inverse.insert_remove_absence(RemoveAbsence {
+                patch: self.id,
+                kind: add_absence.kind.clone(),
+                start: add_absence.start,
+                end: add_absence.end,
+            });
+        }
+        for remove_absence in self.remove_absence.iter() {
+            inverse.insert_add_absence(remove_absence.absence());
+        }
+        inverse
+    }
+
+    pub fn with_metadata(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.metadata.insert(key.into(), value.into());
+        self
+    }
+
     pub fn insert_add_start(&mut self, add_start: AddStart) {
         self.add_start.insert(add_start);
     }
@@ -198,6 +440,26 @@ impl Patch {
     pub fn insert_create_event(&mut self, create_event: CreateEvent) {
         self.create_event.insert(create_event);
     }
+
+    pub fn insert_delete_event(&mut self, delete_event: DeleteEvent) {
+        self.delete_event.insert(delete_event);
+    }
+
+    pub fn insert_undelete_event(&mut self, undelete_event: UndeleteEvent) {
+        self.undelete_event.insert(undelete_event);
+    }
+
+    pub fn insert_set_note(&mut self, set_note: SetNote) {
+        self.set_note.insert(set_note);
+    }
+
+    pub fn insert_add_absence(&mut self, add_absence: AddAbsence) {
+        self.add_absence.insert(add_absence);
+    }
+
+    pub fn insert_remove_absence(&mut self, remove_absence: RemoveAbsence) {
+        self.remove_absence.insert(remove_absence);
+    }
 }
 
 impl Default for Patch {
@@ -226,8 +488,34 @@ impl RemoveTag {
         self.parents.iter().flat_map(|s| s.iter())
     }
 }
+impl DeleteEvent {
+    pub fn parents(&self) -> impl Iterator<Item = &PatchRef> {
+        self.parents.iter()
+    }
+}
+impl UndeleteEvent {
+    pub fn parents(&self) -> impl Iterator<Item = &PatchRef> {
+        self.parents.iter().flat_map(|s| s.iter())
+    }
+}
+impl SetNote {
+    pub fn parents(&self) -> impl Iterator<Item = &PatchRef> {
+        self.parents.iter()
+    }
+}
+impl RemoveAbsence {
+    /// The absence that is removed
+    pub fn absence(&self) -> AddAbsence {
+        AddAbsence {
+            kind: self.kind.clone(),
+            start: self.start,
+            end: self.end,
+        }
+    }
+}
 
 #[cfg(test)]
+#[allow(clippy::clone_on_copy, clippy::zero_prefixed_literal)]
 mod test {
     use super::*;
     use chrono::offset::{TimeZone, Utc};
@@ -260,6 +548,28 @@ mod test {
         assert_eq!(toml::de::from_str(toml_str), Ok(expected));
     }
 
+    #[test]
+    fn read_patch_with_set_note_toml() {
+        let id = Uuid::parse_str("e39076fe-6b5a-4a7f-b927-7fc1df5ba275").unwrap();
+        let patch0 = Uuid::parse_str("fa5de1d9-aa11-49fa-b064-8128281a7d91").unwrap();
+        let expected = Patch::with_id(id).set_note(
+            patch0,
+            BTreeSet::new(),
+            s!("a"),
+            s!("Planning for the release"),
+        );
+
+        let toml_str = r#"
+            id = "e39076fe-6b5a-4a7f-b927-7fc1df5ba275"
+
+            [[set-note]]
+            parents = ["fa5de1d9-aa11-49fa-b064-8128281a7d91"]
+            event = "a"
+            note = "Planning for the release"
+        "#;
+        assert_eq!(toml::de::from_str(toml_str), Ok(expected));
+    }
+
     #[test]
     fn serialize_patch_with_add_tag_toml() {
         let id = Uuid::parse_str("e39076fe-6b5a-4a7f-b927-7fc1df5ba275").unwrap();
@@ -366,4 +676,105 @@ mod test {
         assert_eq!(toml::de::from_str(toml_str), Ok(expected));
     }
 
+    #[test]
+    fn invert_undoes_patch() {
+        let patch0 = Patch::new().create_event(
+            s!("a"),
+            Utc.ymd(2019, 7, 24).and_hms(14, 0, 0),
+            vec![s!("work"), s!("coding")],
+        );
+        let patch1 = Patch::new()
+            .remove_start(
+                *patch0.patch_ref(),
+                s!("a"),
+                Utc.ymd(2019, 7, 24).and_hms(14, 0, 0),
+            )
+            .add_start(
+                *patch0.patch_ref(),
+                s!("a"),
+                Utc.ymd(2019, 7, 24).and_hms(15, 0, 0),
+            )
+            .remove_tag(*patch0.patch_ref(), s!("a"), s!("coding"))
+            .add_tag(*patch0.patch_ref(), s!("a"), s!("meeting"));
+
+        let mut timesheet = PatchedTimesheet::new();
+        timesheet.apply_patch(&patch0).unwrap();
+        let before = timesheet.flatten().unwrap().events();
+
+        timesheet.apply_patch(&patch1).unwrap();
+        let inverse = patch1.invert(&timesheet);
+        assert!(inverse.parents().contains(patch1.patch_ref()));
+        timesheet.apply_patch(&inverse).unwrap();
+
+        assert_eq!(timesheet.flatten().unwrap().events(), before);
+    }
+
+    #[test]
+    fn invert_create_event_deletes_it() {
+        let patch0 = Patch::new().create_event(
+            s!("a"),
+            Utc.ymd(2019, 7, 24).and_hms(14, 0, 0),
+            vec![s!("work")],
+        );
+
+        let mut timesheet = PatchedTimesheet::new();
+        timesheet.apply_patch(&patch0).unwrap();
+        let delete = patch0.invert(&timesheet);
+        timesheet.apply_patch(&delete).unwrap();
+        assert!(timesheet.flatten().unwrap().events().is_empty());
+        assert!(timesheet.conflicts().is_empty());
+
+        let undelete = delete.invert(&timesheet);
+        timesheet.apply_patch(&undelete).unwrap();
+        assert_eq!(timesheet.flatten().unwrap().events().len(), 1);
+    }
+
+    #[test]
+    fn invert_set_note_restores_previous_note() {
+        let patch0 = Patch::new().create_event(
+            s!("a"),
+            Utc.ymd(2019, 7, 24).and_hms(14, 0, 0),
+            vec![s!("work")],
+        );
+        let patch1 =
+            Patch::new().set_note(*patch0.patch_ref(), BTreeSet::new(), s!("a"), s!("one"));
+        let mut replaces = BTreeSet::new();
+        replaces.insert(*patch1.patch_ref());
+        let patch2 = Patch::new().set_note(*patch1.patch_ref(), replaces, s!("a"), s!("two"));
+
+        let mut timesheet = PatchedTimesheet::new();
+        for patch in [&patch0, &patch1, &patch2].iter() {
+            timesheet.apply_patch(patch).unwrap();
+        }
+        let flat = timesheet.flatten().unwrap();
+        assert_eq!(flat.note(&s!("a")), Some(s!("two")));
+
+        let inverse = patch2.invert(&timesheet);
+        timesheet.apply_patch(&inverse).unwrap();
+        assert_eq!(timesheet.flatten().unwrap().note(&s!("a")), Some(s!("one")));
+    }
+
+    #[test]
+    fn metadata_roundtrip_toml() {
+        let id = Uuid::parse_str("e39076fe-6b5a-4a7f-b927-7fc1df5ba275").unwrap();
+        let patch = Patch::with_id(id)
+            .create_event(
+                s!("a"),
+                Utc.ymd(2019, 7, 24).and_hms(14, 0, 0),
+                vec![s!("work")],
+            )
+            .with_metadata("exact-time", "2019-07-24T14:01:37Z");
+
+        let serialized = toml::ser::to_string(&patch).unwrap();
+        assert!(serialized.contains("[metadata]"));
+        assert_eq!(toml::de::from_str(&serialized), Ok(patch));
+    }
+
+    #[test]
+    fn unknown_operations_are_rejected() {
+        let patch = "id = \"e39076fe-6b5a-4a7f-b927-7fc1df5ba275\"\n\
+                     [[move-event]]\n\
+                     event = \"a\"\n";
+        assert!(toml::de::from_str::<Patch>(patch).is_err());
+    }
 }
@@ -1,19 +1,38 @@
 use crate::{Meta, Patch, PatchRef, Store};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 use snafu::{ResultExt, Snafu};
 use std::{
-    fs::{create_dir_all, read_to_string, OpenOptions},
+    collections::BTreeMap,
+    fs::{self, create_dir_all, read_to_string, File, OpenOptions, TryLockError},
     io::Write,
-    path::PathBuf,
+    path::{Path, PathBuf},
 };
 use toml;
+use tracing::instrument;
 
 #[derive(Debug)]
 pub struct SyncFolderStore {
     /// Whether the repository should create a new file if one is not found
     init: bool,
+    /// Whether interrupted transactions should be finished or thrown away
+    /// when the store is loaded
+    recover: bool,
     root_folder: PathBuf,
     patch_folder: PathBuf,
     device_id: String,
+    /// The patches staged by the current transaction, if there is one
+    transaction: Option<Vec<PatchRef>>,
+    /// Held while this store uses its staging folder, so that another process
+    /// doesn't recover a transaction that is still going on
+    staging_lock: Option<File>,
+}
+
+/// Written when a transaction starts being committed. From then on, an
+/// interrupted transaction is finished rather than thrown away.
+#[derive(Serialize, Deserialize)]
+struct CommitFile {
+    patches: Vec<PatchRef>,
 }
 
 #[derive(Debug, Snafu)]
@@ -30,6 +49,30 @@ pub enum SyncFolderStoreError {
         device_id: String,
     },
 
+    #[snafu(display("Unable to deserialize arrivals {}: {}", path.display(), source))]
+    DeserializeArrivals {
+        source: toml::de::Error,
+        path: PathBuf,
+    },
+
+    #[snafu(display("Unable to serialize arrivals {}: {}", path.display(), source))]
+    SerializeArrivals {
+        source: toml::ser::Error,
+        path: PathBuf,
+    },
+
+    #[snafu(display("Unable to read transaction {}: {}", path.display(), source))]
+    DeserializeCommit {
+        source: toml::de::Error,
+        path: PathBuf,
+    },
+
+    #[snafu(display("Unable to write transaction {}: {}", path.display(), source))]
+    SerializeCommit {
+        source: toml::ser::Error,
+        path: PathBuf,
+    },
+
     #[snafu(display("Unable to deserialize meta {}: {}", patch_ref, source))]
     DeserializePatch {
         source: toml::de::Error,
@@ -52,13 +95,75 @@ pub enum SyncFolderStoreError {
     IOError { source: std::io::Error },
 }
 
+/// The size of the files in a sync folder
+#[derive(Default, Eq, PartialEq, Debug, Clone)]
+pub struct StoreStats {
+    pub patch_files: usize,
+    /// The combined length of the patch files
+    pub patch_bytes: u64,
+    /// The space the patch files take up on disk, counting whole blocks
+    pub patch_disk_bytes: u64,
+}
+
+/// The block size assumed when estimating how much space files take up
+pub const BLOCK_SIZE: u64 = 4096;
+
+impl StoreStats {
+    fn add_patch_file(&mut self, len: u64) {
+        self.patch_files += 1;
+        self.patch_bytes += len;
+        self.patch_disk_bytes += round_up_to_block(len);
+    }
+
+    /// Roughly how much disk space would be saved by storing every patch in a
+    /// single file, since most patches are much smaller than a block
+    pub fn pack_savings(&self) -> u64 {
+        self.patch_disk_bytes
+            .saturating_sub(round_up_to_block(self.patch_bytes))
+    }
+}
+
+fn round_up_to_block(len: u64) -> u64 {
+    len.div_ceil(BLOCK_SIZE) * BLOCK_SIZE
+}
+
+/// When patches from other devices first arrived on this device. Unlike the
+/// patches themselves, this is different for every device.
+#[derive(Default, Eq, PartialEq, Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Arrivals {
+    /// The latest arrival time that has been shown by `augr whatsnew`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_checked: Option<DateTime<Utc>>,
+
+    #[serde(default)]
+    pub patches: BTreeMap<PatchRef, DateTime<Utc>>,
+}
+
+impl Arrivals {
+    /// The patches that arrived after `last_checked`, oldest first
+    pub fn unchecked(&self) -> Vec<(PatchRef, DateTime<Utc>)> {
+        let mut unchecked: Vec<_> = self
+            .patches
+            .iter()
+            .filter(|(_patch, arrived)| self.last_checked.is_none_or(|c| **arrived > c))
+            .map(|(patch, arrived)| (*patch, *arrived))
+            .collect();
+        unchecked.sort_by_key(|(patch, arrived)| (*arrived, *patch));
+        unchecked
+    }
+}
+
 impl SyncFolderStore {
     pub fn new(root_folder: PathBuf, device_id: String) -> Self {
         Self {
             init: false,
+            recover: true,
             device_id,
             patch_folder: root_folder.join("patches"),
             root_folder,
+            transaction: None,
+            staging_lock: None,
         }
     }
 
@@ -67,6 +172,14 @@ impl SyncFolderStore {
         self
     }
 
+    /// Leave interrupted transactions alone when loading, for runs that must
+    /// not write anything. Their patches stay hidden until the next run that
+    /// recovers them.
+    pub fn should_recover(mut self, should_recover: bool) -> Self {
+        self.recover = should_recover;
+        self
+    }
+
     fn meta_file_path(&self) -> PathBuf {
         self.root_folder
             .join("meta")
@@ -74,26 +187,167 @@ impl SyncFolderStore {
             .with_extension("toml")
     }
 
+    fn patch_file_path(&self, patch_ref: &PatchRef) -> PathBuf {
+        self.patch_folder
+            .join(patch_ref.to_string())
+            .with_extension("toml")
+    }
+
+    /// Where the patches of a transaction wait until it is committed. Every
+    /// device has its own, and it only exists during a transaction.
+    fn staging_folder(&self) -> PathBuf {
+        self.root_folder.join("staging").join(&self.device_id)
+    }
+
+    fn commit_file_path(&self) -> PathBuf {
+        self.staging_folder().join("commit.toml")
+    }
+
+    /// Lock this device's staging folder. The lock file is next to the folder
+    /// and is never removed, so that every process locks the same file. The
+    /// lock goes away with the process, even if it crashes. Returns `false`
+    /// if `wait` isn't set and another process holds the lock.
+    fn lock_staging(&mut self, wait: bool) -> Result<bool, SyncFolderStoreError> {
+        if self.staging_lock.is_some() {
+            return Ok(true);
+        }
+        let path = self
+            .root_folder
+            .join("staging")
+            .join(format!("{}.lock", self.device_id));
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent).context(WriteFile { path: parent })?;
+        }
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .context(WriteFile { path: path.clone() })?;
+        if wait {
+            file.lock().context(WriteFile { path })?;
+        } else {
+            match file.try_lock() {
+                Ok(()) => {}
+                Err(TryLockError::WouldBlock) => return Ok(false),
+                Err(TryLockError::Error(source)) => return Err(source).context(WriteFile { path }),
+            }
+        }
+        self.staging_lock = Some(file);
+        Ok(true)
+    }
+
+    /// Move the staged `patches` into the patch folder, then save `meta`
+    fn finish_commit(
+        &mut self,
+        patches: &[PatchRef],
+        meta: &Meta,
+    ) -> Result<(), SyncFolderStoreError> {
+        let staging = self.staging_folder();
+        create_dir_all(&self.patch_folder).context(WriteFile {
+            path: self.patch_folder.clone(),
+        })?;
+        for patch_ref in patches {
+            let staged = staging.join(patch_ref.to_string()).with_extension("toml");
+            // Patches that are missing were moved before an interruption
+            if staged.exists() {
+                let path = self.patch_file_path(patch_ref);
+                fs::rename(&staged, &path).context(WriteFile { path })?;
+            }
+        }
+        sync_folder(&self.patch_folder);
+        self.save_meta(meta)?;
+        remove_staging_folder(&staging)?;
+        self.staging_lock = None;
+        Ok(())
+    }
+
+    pub fn device_id(&self) -> &str {
+        &self.device_id
+    }
+
+    /// Count the patch files in the sync folder and how much space they use
+    #[instrument(level = "debug", skip(self))]
+    pub fn stats(&self) -> Result<StoreStats, SyncFolderStoreError> {
+        let mut stats = StoreStats::default();
+        if !self.patch_folder.exists() {
+            return Ok(stats);
+        }
+        for entry in self.patch_folder.read_dir().context(IOError {})? {
+            let metadata = entry.and_then(|e| e.metadata()).context(IOError {})?;
+            if metadata.is_file() {
+                stats.add_patch_file(metadata.len());
+            }
+        }
+        Ok(stats)
+    }
+
+    fn arrivals_file_path(&self) -> PathBuf {
+        self.root_folder
+            .join("arrivals")
+            .join(self.device_id.clone())
+            .with_extension("toml")
+    }
+
+    /// Get the arrival times of patches on this device. `None` means that no
+    /// arrivals have been recorded yet.
+    #[instrument(level = "trace", skip(self), fields(device = %self.device_id))]
+    pub fn get_arrivals(&self) -> Result<Option<Arrivals>, SyncFolderStoreError> {
+        let path = self.arrivals_file_path();
+        if !path.exists() {
+            return Ok(None);
+        }
+        let contents = read_to_string(&path).context(ReadFile { path: path.clone() })?;
+        let arrivals = toml::de::from_str(&contents).context(DeserializeArrivals { path })?;
+        Ok(Some(arrivals))
+    }
+
+    pub fn save_arrivals(&mut self, arrivals: &Arrivals) -> Result<(), SyncFolderStoreError> {
+        let path = self.arrivals_file_path();
+        let contents =
+            toml::ser::to_vec(arrivals).context(SerializeArrivals { path: path.clone() })?;
+        write_file(&path, &contents)
+    }
+
     pub fn get_other_metas(
         &self,
     ) -> Result<impl Iterator<Item = Result<Meta, SyncFolderStoreError>>, SyncFolderStoreError>
     {
+        Ok(self
+            .get_other_device_metas()?
+            .map(|result| result.map(|(_device_id, meta)| meta)))
+    }
+
+    /// Get the meta file of every other device that shares the sync folder,
+    /// along with the id of the device
+    pub fn get_other_device_metas(
+        &self,
+    ) -> Result<
+        impl Iterator<Item = Result<(String, Meta), SyncFolderStoreError>>,
+        SyncFolderStoreError,
+    > {
         let meta_folder = self.root_folder.join("meta");
         let meta_file = self.meta_file_path();
 
         let sync_folder_items = meta_folder.read_dir().context(IOError {})?;
         let iter = sync_folder_items
             .filter_map(|d| d.ok())
+            // Meta files that are being written end in .tmp
+            .filter(|dir_entry| dir_entry.path().extension() == Some("toml".as_ref()))
             .filter(move |dir_entry| dir_entry.path() != meta_file)
             .map(|dir_entry| {
                 let path = dir_entry.path();
+                let device_id = path
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+                    .unwrap_or_default();
                 let contents = read_to_string(&path).context(ReadFile { path: path.clone() })?;
 
                 let meta = toml::de::from_str(&contents).context(DeserializeMeta {
                     device_id: path.display().to_string(),
                 })?;
 
-                Ok(meta)
+                Ok((device_id, meta))
             });
         Ok(iter)
     }
@@ -102,7 +356,7 @@ impl SyncFolderStore {
 impl Store for SyncFolderStore {
     type Error = SyncFolderStoreError;
 
-    #[cfg_attr(feature = "flame_it", flame)]
+    #[instrument(level = "trace", skip(self), fields(device = %self.device_id))]
     fn get_meta(&self) -> Result<Meta, Self::Error> {
         let path = self.meta_file_path();
 
@@ -132,19 +386,19 @@ impl Store for SyncFolderStore {
             }
         }
 
-        let mut file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .open(path.clone())
-            .context(WriteFile { path: path.clone() })?;
-
-        file.write_all(contents.as_slice())
-            .context(WriteFile { path: path.clone() })?;
+        // Write a new file and move it over the old one, so that the meta
+        // file is never half written
+        let temp_path = path.with_extension("tmp");
+        write_synced(&temp_path, &contents, false)?;
+        fs::rename(&temp_path, &path).context(WriteFile { path: path.clone() })?;
+        if let Some(parent) = path.parent() {
+            sync_folder(parent);
+        }
 
         Ok(())
     }
 
-    #[cfg_attr(feature = "flame_it", flame)]
+    #[instrument(level = "trace", skip(self), fields(patch = %patch_ref))]
     fn get_patch(&self, patch_ref: &PatchRef) -> Result<Patch, Self::Error> {
         let path = self
             .patch_folder
@@ -161,8 +415,22 @@ impl Store for SyncFolderStore {
     }
 
     fn add_patch(&mut self, patch: &Patch) -> Result<(), Self::Error> {
-        let patch_ref = patch.patch_ref().to_string();
-        let path = self.patch_folder.join(&patch_ref).with_extension("toml");
+        let contents = toml::ser::to_vec(patch).context(SerializeMeta {
+            device_id: self.device_id.clone(),
+        })?;
+        if self.transaction.is_some() {
+            let path = self
+                .staging_folder()
+                .join(patch.patch_ref().to_string())
+                .with_extension("toml");
+            write_synced(&path, &contents, true)?;
+            if let Some(staged) = self.transaction.as_mut() {
+                staged.push(*patch.patch_ref());
+            }
+            return Ok(());
+        }
+
+        let path = self.patch_file_path(patch.patch_ref());
 
         if let Some(parent) = path.parent() {
             if !parent.exists() {
@@ -170,10 +438,6 @@ impl Store for SyncFolderStore {
             }
         }
 
-        let contents = toml::ser::to_vec(patch).context(SerializeMeta {
-            device_id: self.device_id.clone(),
-        })?;
-
         let mut file = OpenOptions::new()
             .write(true)
             .create_new(true)
@@ -185,9 +449,251 @@ impl Store for SyncFolderStore {
 
         Ok(())
     }
+
+    /// A transaction that was interrupted before it was committed is thrown
+    /// away, since the meta file doesn't refer to any of its patches yet. One
+    /// that was interrupted while being committed is finished.
+    /// Nothing is done if recovery is turned off, or while another process
+    /// is in the middle of a transaction.
+    fn recover(&mut self) -> Result<(), Self::Error> {
+        let staging = self.staging_folder();
+        if !self.recover || !staging.exists() || !self.lock_staging(false)? {
+            return Ok(());
+        }
+        let commit_path = self.commit_file_path();
+        if !commit_path.exists() {
+            remove_staging_folder(&staging)?;
+            self.staging_lock = None;
+            return Ok(());
+        }
+        let contents = read_to_string(&commit_path).context(ReadFile {
+            path: commit_path.clone(),
+        })?;
+        let commit: CommitFile =
+            toml::de::from_str(&contents).context(DeserializeCommit { path: commit_path })?;
+        let mut meta = self.get_meta()?;
+        for patch_ref in commit.patches.iter() {
+            meta.add_patch(*patch_ref);
+        }
+        self.finish_commit(&commit.patches, &meta)
+    }
+
+    fn begin(&mut self) -> Result<(), Self::Error> {
+        // Wait for another process to finish its transaction first
+        self.lock_staging(true)?;
+        let staging = self.staging_folder();
+        create_dir_all(&staging).context(WriteFile { path: staging })?;
+        self.transaction = Some(Vec::new());
+        Ok(())
+    }
+
+    fn commit(&mut self, meta: &Meta) -> Result<(), Self::Error> {
+        let patches = match self.transaction.take() {
+            Some(patches) => patches,
+            None => return self.save_meta(meta),
+        };
+        let commit_path = self.commit_file_path();
+        let contents = toml::ser::to_vec(&CommitFile {
+            patches: patches.clone(),
+        })
+        .context(SerializeCommit {
+            path: commit_path.clone(),
+        })?;
+        write_synced(&commit_path, &contents, false)?;
+        self.finish_commit(&patches, meta)
+    }
+
+    fn rollback(&mut self) -> Result<(), Self::Error> {
+        if self.transaction.take().is_some() {
+            remove_staging_folder(&self.staging_folder())?;
+            self.staging_lock = None;
+        }
+        Ok(())
+    }
+}
+
+/// Write `contents` to `path` and wait until they are on disk
+fn write_synced(
+    path: &Path,
+    contents: &[u8],
+    create_new: bool,
+) -> Result<(), SyncFolderStoreError> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .create_new(create_new)
+        .truncate(true)
+        .open(path)
+        .context(WriteFile { path })?;
+    file.write_all(contents).context(WriteFile { path })?;
+    file.sync_all().context(WriteFile { path })
+}
+
+/// Remove a device's staging folder, and the folder holding it once no device
+/// has one
+fn remove_staging_folder(staging: &Path) -> Result<(), SyncFolderStoreError> {
+    fs::remove_dir_all(staging).context(WriteFile { path: staging })?;
+    if let Some(parent) = staging.parent() {
+        // Fails when other devices' staging folders are still there
+        let _ = fs::remove_dir(parent);
+    }
+    Ok(())
+}
+
+/// Make sure that files moved into `folder` stay there after a crash. Not
+/// every platform can sync a folder, so this is best effort.
+fn sync_folder(folder: &Path) {
+    if let Ok(folder) = File::open(folder) {
+        let _ = folder.sync_all();
+    }
+}
+
+fn write_file(path: &Path, contents: &[u8]) -> Result<(), SyncFolderStoreError> {
+    if let Some(parent) = path.parent() {
+        if !parent.exists() {
+            create_dir_all(parent).context(WriteFile { path: parent })?;
+        }
+    }
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)
+        .context(WriteFile { path })?;
+    file.write_all(contents).context(WriteFile { path })
 }
 
-#[cfg_attr(feature = "flame_it", flame)]
+#[instrument(level = "trace")]
 fn load_file_contents(path: &std::path::Path) -> Result<String, std::io::Error> {
-    read_to_string(&path)
+    read_to_string(path)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::TimeZone;
+    use uuid::Uuid;
+
+    #[test]
+    fn pack_savings() {
+        let mut stats = StoreStats::default();
+        for _ in 0..4 {
+            stats.add_patch_file(1000);
+        }
+        assert_eq!(stats.patch_disk_bytes, 4 * BLOCK_SIZE);
+        assert_eq!(stats.pack_savings(), 3 * BLOCK_SIZE);
+    }
+
+    #[test]
+    fn interrupted_transactions_are_recovered() {
+        let root = std::env::temp_dir().join(format!("augr-transaction-{}", Uuid::new_v4()));
+        let store = || SyncFolderStore::new(root.clone(), "laptop".into()).should_init(true);
+        let start = Utc.ymd(2019, 7, 16).and_hms(9, 0, 0);
+        let patch = || Patch::new().create_event("a".into(), start, vec!["work".into()]);
+
+        // Patches only show up once the transaction is committed
+        let mut committed = store();
+        let first = patch();
+        committed.begin().unwrap();
+        committed.add_patch(&first).unwrap();
+        assert!(!committed.patch_file_path(first.patch_ref()).exists());
+        let mut meta = Meta::new();
+        meta.add_patch(*first.patch_ref());
+        committed.commit(&meta).unwrap();
+        assert!(committed.get_patch(first.patch_ref()).is_ok());
+        assert!(!committed.staging_folder().exists());
+
+        // A transaction that never started committing is thrown away
+        let second = patch();
+        let mut crashed = store();
+        crashed.begin().unwrap();
+        crashed.add_patch(&second).unwrap();
+        drop(crashed);
+        let mut recovered = store();
+        recovered.recover().unwrap();
+        assert!(!recovered.staging_folder().exists());
+        assert!(recovered.get_patch(second.patch_ref()).is_err());
+
+        // One that crashed while being committed is finished
+        let third = patch();
+        let mut crashed = store();
+        crashed.begin().unwrap();
+        crashed.add_patch(&third).unwrap();
+        let contents = toml::ser::to_vec(&CommitFile {
+            patches: vec![*third.patch_ref()],
+        })
+        .unwrap();
+        write_synced(&crashed.commit_file_path(), &contents, false).unwrap();
+        drop(crashed);
+        let mut recovered = store();
+        recovered.recover().unwrap();
+        assert!(recovered.get_patch(third.patch_ref()).is_ok());
+        assert_eq!(recovered.get_meta().unwrap().patches().count(), 2);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn rolled_back_transactions_are_thrown_away() {
+        let root = std::env::temp_dir().join(format!("augr-transaction-{}", Uuid::new_v4()));
+        let mut store = SyncFolderStore::new(root.clone(), "laptop".into()).should_init(true);
+        let start = Utc.ymd(2019, 7, 16).and_hms(9, 0, 0);
+        let patch = Patch::new().create_event("a".into(), start, vec!["work".into()]);
+
+        store.begin().unwrap();
+        store.add_patch(&patch).unwrap();
+        store.rollback().unwrap();
+        assert!(!store.staging_folder().exists());
+        assert!(store.get_patch(patch.patch_ref()).is_err());
+
+        // The store can start another transaction afterwards
+        store.begin().unwrap();
+        store.add_patch(&patch).unwrap();
+        let mut meta = Meta::new();
+        meta.add_patch(*patch.patch_ref());
+        store.commit(&meta).unwrap();
+        assert!(store.get_patch(patch.patch_ref()).is_ok());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn transactions_in_progress_are_left_alone() {
+        let root = std::env::temp_dir().join(format!("augr-transaction-{}", Uuid::new_v4()));
+        let store = || SyncFolderStore::new(root.clone(), "laptop".into()).should_init(true);
+        let start = Utc.ymd(2019, 7, 16).and_hms(9, 0, 0);
+        let patch = Patch::new().create_event("a".into(), start, vec!["work".into()]);
+
+        let mut running = store();
+        running.begin().unwrap();
+        running.add_patch(&patch).unwrap();
+
+        // Another process loading the store doesn't throw the transaction away
+        store().recover().unwrap();
+        assert!(running.staging_folder().exists());
+
+        // Neither does one that isn't allowed to write
+        drop(running);
+        store().should_recover(false).recover().unwrap();
+        assert!(store().staging_folder().exists());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn unchecked_arrivals() {
+        let time = |h| Utc.ymd(2019, 7, 16).and_hms(h, 0, 0);
+        let patch = |n: u8| Uuid::from_bytes([n; 16]);
+        let mut arrivals = Arrivals {
+            last_checked: Some(time(10)),
+            patches: BTreeMap::new(),
+        };
+        arrivals.patches.insert(patch(1), time(9));
+        arrivals.patches.insert(patch(2), time(12));
+        arrivals.patches.insert(patch(3), time(11));
+        assert_eq!(
+            arrivals.unchecked(),
+            vec![(patch(3), time(11)), (patch(2), time(12))]
+        );
+    }
 }
@@ -0,0 +1,330 @@
+//! Boolean expressions over the tags of an event, such as
+//! `work and not meeting` or `(coding or review) and augr`.
+
+use crate::Tag;
+use snafu::Snafu;
+use std::{collections::BTreeSet, fmt, str::FromStr};
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum TagExpr {
+    /// Matches events that have the tag
+    Tag(Tag),
+    Not(Box<TagExpr>),
+    And(Box<TagExpr>, Box<TagExpr>),
+    Or(Box<TagExpr>, Box<TagExpr>),
+}
+
+#[derive(Eq, PartialEq, Debug, Snafu)]
+pub enum ParseError {
+    #[snafu(display("Tag expression is empty"))]
+    Empty,
+
+    #[snafu(display("Unexpected \"{}\" in tag expression", token))]
+    UnexpectedToken { token: String },
+
+    #[snafu(display("Tag expression ended unexpectedly"))]
+    UnexpectedEnd,
+
+    #[snafu(display("Unmatched parenthesis in tag expression"))]
+    UnmatchedParen,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Token {
+    Word(String),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+impl TagExpr {
+    pub fn tag(tag: impl Into<Tag>) -> Self {
+        TagExpr::Tag(tag.into())
+    }
+
+    /// An expression that matches events having every one of `tags`
+    pub fn all<I: IntoIterator<Item = Tag>>(tags: I) -> Option<Self> {
+        tags.into_iter()
+            .map(TagExpr::Tag)
+            .fold(None, |acc, expr| match acc {
+                None => Some(expr),
+                Some(acc) => Some(acc.and(expr)),
+            })
+    }
+
+    pub fn and(self, other: TagExpr) -> Self {
+        TagExpr::And(Box::new(self), Box::new(other))
+    }
+
+    pub fn or(self, other: TagExpr) -> Self {
+        TagExpr::Or(Box::new(self), Box::new(other))
+    }
+
+    pub fn matches(&self, tags: &BTreeSet<Tag>) -> bool {
+        match self {
+            TagExpr::Tag(tag) => tags.contains(tag),
+            TagExpr::Not(expr) => !expr.matches(tags),
+            TagExpr::And(a, b) => a.matches(tags) && b.matches(tags),
+            TagExpr::Or(a, b) => a.matches(tags) || b.matches(tags),
+        }
+    }
+
+    /// Every tag mentioned in the expression
+    pub fn tags(&self) -> BTreeSet<&Tag> {
+        let mut tags = BTreeSet::new();
+        self.collect_tags(&mut tags);
+        tags
+    }
+
+    /// The tags that every set of tags matching the expression has
+    pub fn required_tags(&self) -> BTreeSet<&Tag> {
+        match self {
+            TagExpr::Tag(tag) => Some(tag).into_iter().collect(),
+            TagExpr::Not(_) => BTreeSet::new(),
+            TagExpr::And(a, b) => &a.required_tags() | &b.required_tags(),
+            TagExpr::Or(a, b) => &a.required_tags() & &b.required_tags(),
+        }
+    }
+
+    /// The same expression with `f` applied to each of its tags
+    pub fn map_tags(self, f: &impl Fn(Tag) -> Tag) -> Self {
+        match self {
+            TagExpr::Tag(tag) => TagExpr::Tag(f(tag)),
+            TagExpr::Not(expr) => TagExpr::Not(Box::new(expr.map_tags(f))),
+            TagExpr::And(a, b) => a.map_tags(f).and(b.map_tags(f)),
+            TagExpr::Or(a, b) => a.map_tags(f).or(b.map_tags(f)),
+        }
+    }
+
+    fn collect_tags<'a>(&'a self, tags: &mut BTreeSet<&'a Tag>) {
+        match self {
+            TagExpr::Tag(tag) => {
+                tags.insert(tag);
+            }
+            TagExpr::Not(expr) => expr.collect_tags(tags),
+            TagExpr::And(a, b) | TagExpr::Or(a, b) => {
+                a.collect_tags(tags);
+                b.collect_tags(tags);
+            }
+        }
+    }
+}
+
+fn tokenize(text: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let spaced = text.replace('(', " ( ").replace(')', " ) ");
+    for word in spaced.split_whitespace() {
+        tokens.push(match word {
+            "and" | "&" => Token::And,
+            "or" | "|" => Token::Or,
+            "not" | "!" => Token::Not,
+            "(" => Token::Open,
+            ")" => Token::Close,
+            _ => Token::Word(word.to_string()),
+        });
+    }
+    tokens
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn or(&mut self) -> Result<TagExpr, ParseError> {
+        let mut expr = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            expr = TagExpr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<TagExpr, ParseError> {
+        let mut expr = self.not()?;
+        while self.peek() == Some(&Token::And) {
+            self.next();
+            expr = TagExpr::And(Box::new(expr), Box::new(self.not()?));
+        }
+        Ok(expr)
+    }
+
+    fn not(&mut self) -> Result<TagExpr, ParseError> {
+        match self.next() {
+            Some(Token::Not) => Ok(TagExpr::Not(Box::new(self.not()?))),
+            Some(Token::Word(tag)) => Ok(TagExpr::Tag(tag)),
+            Some(Token::Open) => {
+                let expr = self.or()?;
+                match self.next() {
+                    Some(Token::Close) => Ok(expr),
+                    _ => Err(ParseError::UnmatchedParen),
+                }
+            }
+            Some(token) => Err(ParseError::UnexpectedToken {
+                token: token.to_string(),
+            }),
+            None => Err(ParseError::UnexpectedEnd),
+        }
+    }
+}
+
+impl FromStr for TagExpr {
+    type Err = ParseError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let tokens = tokenize(text);
+        if tokens.is_empty() {
+            return Err(ParseError::Empty);
+        }
+        let mut parser = Parser { tokens, pos: 0 };
+        let expr = parser.or()?;
+        match parser.next() {
+            None => Ok(expr),
+            Some(Token::Close) => Err(ParseError::UnmatchedParen),
+            Some(token) => Err(ParseError::UnexpectedToken {
+                token: token.to_string(),
+            }),
+        }
+    }
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Word(word) => write!(f, "{}", word),
+            Token::And => write!(f, "and"),
+            Token::Or => write!(f, "or"),
+            Token::Not => write!(f, "not"),
+            Token::Open => write!(f, "("),
+            Token::Close => write!(f, ")"),
+        }
+    }
+}
+
+impl fmt::Display for TagExpr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TagExpr::Tag(tag) => write!(f, "{}", tag),
+            TagExpr::Not(expr) => match **expr {
+                TagExpr::Tag(_) | TagExpr::Not(_) => write!(f, "not {}", expr),
+                _ => write!(f, "not ({})", expr),
+            },
+            TagExpr::And(a, b) => {
+                for (i, expr) in [a, b].iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " and ")?;
+                    }
+                    match ***expr {
+                        TagExpr::Or(_, _) => write!(f, "({})", expr)?,
+                        _ => write!(f, "{}", expr)?,
+                    }
+                }
+                Ok(())
+            }
+            TagExpr::Or(a, b) => write!(f, "{} or {}", a, b),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn tags(list: &[&str]) -> BTreeSet<Tag> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn and_not() {
+        let expr: TagExpr = "work and not meeting".parse().unwrap();
+        assert!(expr.matches(&tags(&["work", "coding"])));
+        assert!(!expr.matches(&tags(&["work", "meeting"])));
+        assert!(!expr.matches(&tags(&["coding"])));
+    }
+
+    #[test]
+    fn or_binds_looser_than_and() {
+        let expr: TagExpr = "coding or review and work".parse().unwrap();
+        assert_eq!(
+            expr,
+            TagExpr::Or(
+                Box::new(TagExpr::tag("coding")),
+                Box::new(TagExpr::And(
+                    Box::new(TagExpr::tag("review")),
+                    Box::new(TagExpr::tag("work"))
+                ))
+            )
+        );
+    }
+
+    #[test]
+    fn parentheses() {
+        let expr: TagExpr = "(coding or review) and augr".parse().unwrap();
+        assert!(expr.matches(&tags(&["review", "augr"])));
+        assert!(!expr.matches(&tags(&["review"])));
+        assert_eq!(expr.to_string(), "(coding or review) and augr");
+        assert_eq!(
+            TagExpr::tag("coding")
+                .or(TagExpr::tag("review"))
+                .and(TagExpr::tag("augr")),
+            expr
+        );
+    }
+
+    #[test]
+    fn required_tags() {
+        let required = |text: &str| {
+            let expr: TagExpr = text.parse().unwrap();
+            expr.required_tags()
+                .into_iter()
+                .cloned()
+                .collect::<BTreeSet<_>>()
+        };
+        assert_eq!(required("work and not meeting"), tags(&["work"]));
+        assert_eq!(
+            required("(work and coding) or (work and review)"),
+            tags(&["work"])
+        );
+        assert_eq!(required("work or home"), tags(&[]));
+    }
+
+    #[test]
+    fn map_tags() {
+        let expr: TagExpr = "ws and not (mtg or lunch)".parse().unwrap();
+        let expanded = expr.map_tags(&|tag| match tag.as_str() {
+            "ws" => String::from("workshop"),
+            "mtg" => String::from("meeting"),
+            _ => tag,
+        });
+        assert_eq!(expanded.to_string(), "workshop and not (meeting or lunch)");
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!("".parse::<TagExpr>(), Err(ParseError::Empty));
+        assert_eq!(
+            "work and".parse::<TagExpr>(),
+            Err(ParseError::UnexpectedEnd)
+        );
+        assert_eq!("(work".parse::<TagExpr>(), Err(ParseError::UnmatchedParen));
+        assert_eq!(
+            "work coding".parse::<TagExpr>(),
+            Err(ParseError::UnexpectedToken {
+                token: "coding".into()
+            })
+        );
+    }
+}
@@ -0,0 +1,283 @@
+//! Proptest strategies for generating patches, for fuzzing `apply_patch` and
+//! `flatten`. Only available with the `testing` feature.
+
+use crate::{
+    repository::timesheet::PatchedTimesheet,
+    store::patch::{
+        AddStart, AddTag, CreateEvent, DeleteEvent, RemoveStart, RemoveTag, SetNote, UndeleteEvent,
+    },
+    EventRef, Patch, PatchRef, Tag,
+};
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use proptest::{collection, prelude::*, sample::Index};
+use std::collections::BTreeSet;
+use uuid::Uuid;
+
+/// Datetimes on a 15 minute grid over a few days, so that generated events
+/// often share start times
+pub fn arb_datetime() -> impl Strategy<Value = DateTime<Utc>> {
+    (0i64..4 * 24 * 3).prop_map(|quarters| {
+        Utc.ymd(2019, 7, 23).and_hms(0, 0, 0) + Duration::minutes(quarters * 15)
+    })
+}
+
+pub fn arb_tag() -> impl Strategy<Value = Tag> {
+    "[a-e]"
+}
+
+pub fn arb_event_ref() -> impl Strategy<Value = EventRef> {
+    "[a-h]"
+}
+
+pub fn arb_patch_ref() -> impl Strategy<Value = PatchRef> {
+    any::<[u8; 16]>().prop_map(Uuid::from_bytes)
+}
+
+fn arb_parents() -> impl Strategy<Value = BTreeSet<PatchRef>> {
+    collection::btree_set(arb_patch_ref(), 0..3)
+}
+
+pub fn arb_add_start() -> impl Strategy<Value = AddStart> {
+    (arb_parents(), arb_event_ref(), arb_datetime()).prop_map(|(parents, event, time)| AddStart {
+        parents,
+        event,
+        time,
+    })
+}
+
+pub fn arb_remove_start() -> impl Strategy<Value = RemoveStart> {
+    (
+        proptest::option::of(arb_parents()),
+        arb_patch_ref(),
+        arb_event_ref(),
+        arb_datetime(),
+    )
+        .prop_map(|(parents, patch, event, time)| RemoveStart {
+            parents,
+            patch,
+            event,
+            time,
+        })
+}
+
+pub fn arb_add_tag() -> impl Strategy<Value = AddTag> {
+    (arb_parents(), arb_event_ref(), arb_tag()).prop_map(|(parents, event, tag)| AddTag {
+        parents,
+        event,
+        tag,
+    })
+}
+
+pub fn arb_remove_tag() -> impl Strategy<Value = RemoveTag> {
+    (
+        proptest::option::of(arb_parents()),
+        arb_patch_ref(),
+        arb_event_ref(),
+        arb_tag(),
+    )
+        .prop_map(|(parents, patch, event, tag)| RemoveTag {
+            parents,
+            patch,
+            event,
+            tag,
+        })
+}
+
+pub fn arb_create_event() -> impl Strategy<Value = CreateEvent> {
+    (
+        arb_event_ref(),
+        arb_datetime(),
+        collection::vec(arb_tag(), 0..3),
+    )
+        .prop_map(|(event, start, tags)| CreateEvent { event, start, tags })
+}
+
+pub fn arb_delete_event() -> impl Strategy<Value = DeleteEvent> {
+    (arb_parents(), arb_event_ref()).prop_map(|(parents, event)| DeleteEvent { parents, event })
+}
+
+pub fn arb_undelete_event() -> impl Strategy<Value = UndeleteEvent> {
+    (
+        proptest::option::of(arb_parents()),
+        arb_patch_ref(),
+        arb_event_ref(),
+    )
+        .prop_map(|(parents, patch, event)| UndeleteEvent {
+            parents,
+            patch,
+            event,
+        })
+}
+
+pub fn arb_set_note() -> impl Strategy<Value = SetNote> {
+    (
+        arb_parents(),
+        arb_parents(),
+        arb_event_ref(),
+        "[a-z ]{0,10}",
+    )
+        .prop_map(|(parents, replaces, event, note)| SetNote {
+            parents,
+            replaces,
+            event,
+            note,
+        })
+}
+
+/// A patch with random contents. It will often refer to events and patches
+/// that don't exist.
+pub fn arb_patch() -> impl Strategy<Value = Patch> {
+    (
+        arb_patch_ref(),
+        collection::vec(arb_add_start(), 0..2),
+        collection::vec(arb_remove_start(), 0..2),
+        collection::vec(arb_add_tag(), 0..2),
+        collection::vec(arb_remove_tag(), 0..2),
+        collection::vec(arb_create_event(), 0..3),
+        collection::vec(arb_delete_event(), 0..2),
+        collection::vec(arb_undelete_event(), 0..2),
+        collection::vec(arb_set_note(), 0..2),
+    )
+        .prop_map(
+            |(id, add_start, remove_start, add_tag, remove_tag, create, delete, undelete, note)| {
+                let mut patch = Patch::with_id(id);
+                add_start
+                    .into_iter()
+                    .for_each(|x| patch.insert_add_start(x));
+                remove_start
+                    .into_iter()
+                    .for_each(|x| patch.insert_remove_start(x));
+                add_tag.into_iter().for_each(|x| patch.insert_add_tag(x));
+                remove_tag
+                    .into_iter()
+                    .for_each(|x| patch.insert_remove_tag(x));
+                create
+                    .into_iter()
+                    .for_each(|x| patch.insert_create_event(x));
+                delete
+                    .into_iter()
+                    .for_each(|x| patch.insert_delete_event(x));
+                undelete
+                    .into_iter()
+                    .for_each(|x| patch.insert_undelete_event(x));
+                note.into_iter().for_each(|x| patch.insert_set_note(x));
+                patch
+            },
+        )
+}
+
+/// One step in building a history of patches. Indexes pick from whatever
+/// exists when the step is applied.
+#[derive(Clone, Debug)]
+enum Step {
+    Create(DateTime<Utc>, Vec<Tag>),
+    AddStart(Index, Index, DateTime<Utc>),
+    RemoveStart(Index, Index, Index),
+    AddTag(Index, Index, Tag),
+    RemoveTag(Index, Index, Index),
+    Delete(Index, Index),
+    Undelete(Index, Index),
+    SetNote(Index, Index, String),
+}
+
+fn arb_step() -> impl Strategy<Value = Step> {
+    prop_oneof![
+        3 => (arb_datetime(), collection::vec(arb_tag(), 0..3))
+            .prop_map(|(start, tags)| Step::Create(start, tags)),
+        2 => (any::<Index>(), any::<Index>(), arb_datetime())
+            .prop_map(|(e, p, t)| Step::AddStart(e, p, t)),
+        1 => (any::<Index>(), any::<Index>(), any::<Index>())
+            .prop_map(|(e, p, s)| Step::RemoveStart(e, p, s)),
+        2 => (any::<Index>(), any::<Index>(), arb_tag())
+            .prop_map(|(e, p, t)| Step::AddTag(e, p, t)),
+        1 => (any::<Index>(), any::<Index>(), any::<Index>())
+            .prop_map(|(e, p, t)| Step::RemoveTag(e, p, t)),
+        1 => (any::<Index>(), any::<Index>()).prop_map(|(e, p)| Step::Delete(e, p)),
+        1 => (any::<Index>(), any::<Index>()).prop_map(|(e, p)| Step::Undelete(e, p)),
+        1 => (any::<Index>(), any::<Index>(), "[a-z ]{0,10}")
+            .prop_map(|(e, p, n)| Step::SetNote(e, p, n)),
+    ]
+}
+
+/// A list of patches that form a valid DAG when applied in order: every patch
+/// only refers to events and patches that come before it. Parents are picked
+/// from each event's history, so concurrent edits (and conflicts) are common.
+pub fn arb_patch_dag() -> impl Strategy<Value = Vec<Patch>> {
+    collection::vec(arb_step(), 1..30).prop_map(|steps| {
+        let mut timesheet = PatchedTimesheet::new();
+        let mut patches = Vec::new();
+        for (i, step) in steps.into_iter().enumerate() {
+            if let Some(patch) = build_patch(&timesheet, i, step) {
+                timesheet
+                    .apply_patch(&patch)
+                    .expect("generated patch to be valid");
+                patches.push(patch);
+            }
+        }
+        patches
+    })
+}
+
+fn build_patch(timesheet: &PatchedTimesheet, i: usize, step: Step) -> Option<Patch> {
+    let patch = Patch::with_id(Uuid::from_bytes((i as u128 + 1).to_be_bytes()));
+    let events: Vec<&EventRef> = timesheet.events.keys().collect();
+    let pick_event = |index: &Index| {
+        if events.is_empty() {
+            None
+        } else {
+            Some(events[index.index(events.len())].clone())
+        }
+    };
+    let pick_parent = |event: &EventRef, index: &Index| {
+        let history = timesheet.events[event].history();
+        history[index.index(history.len())].patch
+    };
+
+    let patch = match step {
+        Step::Create(start, tags) => patch.create_event(format!("event-{}", i), start, tags),
+        Step::AddStart(e, p, time) => {
+            let event = pick_event(&e)?;
+            patch.add_start(pick_parent(&event, &p), event, time)
+        }
+        Step::RemoveStart(e, _p, s) => {
+            let event = pick_event(&e)?;
+            let starts: Vec<_> = timesheet.events[&event].starts().into_iter().collect();
+            if starts.is_empty() {
+                return None;
+            }
+            let (start_patch, time) = starts[s.index(starts.len())];
+            patch.remove_start(start_patch, event, time)
+        }
+        Step::AddTag(e, p, tag) => {
+            let event = pick_event(&e)?;
+            patch.add_tag(pick_parent(&event, &p), event, tag)
+        }
+        Step::RemoveTag(e, _p, t) => {
+            let event = pick_event(&e)?;
+            let tags: Vec<_> = timesheet.events[&event].tags().into_iter().collect();
+            if tags.is_empty() {
+                return None;
+            }
+            let (tag_patch, tag) = tags[t.index(tags.len())].clone();
+            patch.remove_tag(tag_patch, event, tag)
+        }
+        Step::Delete(e, p) => {
+            let event = pick_event(&e)?;
+            patch.delete_event(pick_parent(&event, &p), event)
+        }
+        Step::Undelete(e, d) => {
+            let event = pick_event(&e)?;
+            let deletes: Vec<_> = timesheet.events[&event].deletes().into_iter().collect();
+            if deletes.is_empty() {
+                return None;
+            }
+            patch.undelete_event(deletes[d.index(deletes.len())], event)
+        }
+        Step::SetNote(e, p, note) => {
+            let event = pick_event(&e)?;
+            let replaces = timesheet.events[&event].note_patches();
+            patch.set_note(pick_parent(&event, &p), replaces, event, note)
+        }
+    };
+    Some(patch)
+}
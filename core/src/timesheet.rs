@@ -1,8 +1,14 @@
-use crate::{repository::timesheet::PatchedTimesheet, EventRef, Tag};
-use chrono::{DateTime, Duration, Utc};
+use crate::{
+    aggregate,
+    clock::{Clock, SystemClock},
+    repository::timesheet::{PatchedTimesheet, RefError},
+    EventRef, Tag,
+};
+use chrono::{DateTime, Duration, FixedOffset, Local, TimeZone, Utc};
+use serde::{ser::SerializeStruct, Serialize, Serializer};
 use std::collections::{BTreeMap, BTreeSet};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct Event {
     start: DateTime<Utc>,
     tags: BTreeSet<Tag>,
@@ -12,17 +18,93 @@ pub struct Event {
 pub struct Timesheet<'cl> {
     patched_timesheet: &'cl PatchedTimesheet,
     event_starts: BTreeMap<DateTime<Utc>, EventRef>,
+    end_policy: EndPolicy,
+    clock: &'cl dyn Clock,
+    hide_private: bool,
 }
 
-#[derive(Clone, Debug)]
+/// Decides when an event ends. Every event ends when the next one starts, but
+/// a policy may end it earlier, leaving the time in between untracked.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum EndPolicy {
+    /// Events last until the next event starts
+    #[default]
+    NextEvent,
+
+    /// Events last until the next event starts, but no longer than the given
+    /// duration
+    MaxDuration(Duration),
+
+    /// Events last until the next event starts or the day ends, whichever
+    /// comes first. Days start `day_start` after midnight in `zone`.
+    EndOfDay { zone: Zone, day_start: Duration },
+}
+
+/// The time zone that days are measured in
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Zone {
+    /// The system's time zone, with its daylight saving changes
+    Local,
+    /// A zone that is always the same offset from UTC
+    Fixed(FixedOffset),
+}
+
+impl EndPolicy {
+    /// Find when an event that starts at `start` ends, given that the next
+    /// event starts at `next_start`.
+    pub fn end_time(&self, start: DateTime<Utc>, next_start: DateTime<Utc>) -> DateTime<Utc> {
+        match self {
+            EndPolicy::NextEvent => next_start,
+            EndPolicy::MaxDuration(max) => next_start.min(start + *max),
+            EndPolicy::EndOfDay { zone, day_start } => {
+                let day_end = match zone {
+                    Zone::Local => day_end(&Local, start, *day_start),
+                    Zone::Fixed(offset) => day_end(offset, start, *day_start),
+                };
+                next_start.min(day_end)
+            }
+        }
+    }
+}
+
+/// When the day that `time` is in ends, if days start `day_start` after
+/// midnight by the clock on the wall in `zone`
+fn day_end<Tz: TimeZone>(zone: &Tz, time: DateTime<Utc>, day_start: Duration) -> DateTime<Utc> {
+    let date = (time.with_timezone(zone).naive_local() - day_start).date();
+    let next = date.succ();
+    zone.from_local_datetime(&(next.and_hms(0, 0, 0) + day_start))
+        .earliest()
+        .map(|end| end.with_timezone(&Utc))
+        // The start of the day was skipped by a daylight saving change
+        .unwrap_or_else(|| aggregate::midnight(next, zone) + day_start)
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
 pub struct Segment {
     pub event_ref: EventRef,
     pub start_time: DateTime<Utc>,
     pub tags: BTreeSet<Tag>,
+    /// Serialized as a whole number of seconds
+    #[serde(serialize_with = "serialize_seconds")]
     pub duration: Duration,
     pub end_time: DateTime<Utc>,
 }
 
+fn serialize_seconds<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_i64(duration.num_seconds())
+}
+
+/// The event that is ongoing at some moment, as returned by
+/// `Timesheet::current_event`
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CurrentEvent {
+    pub event_ref: EventRef,
+    pub start_time: DateTime<Utc>,
+    pub tags: BTreeSet<Tag>,
+    pub elapsed: Duration,
+}
+
 impl Event {
     pub fn new(start: DateTime<Utc>, tags: BTreeSet<Tag>) -> Self {
         Self { start, tags }
@@ -37,6 +119,37 @@ impl Event {
     }
 }
 
+/// A timesheet is serialized as a list of its events, in order of their start
+/// times, each with its id:
+///
+/// ```json
+/// { "events": [ { "id": "...", "start": "2019-07-23T12:00:00Z", "tags": ["lunch"] } ] }
+/// ```
+impl Serialize for Timesheet<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct EventWithId<'a> {
+            id: &'a EventRef,
+            start: &'a DateTime<Utc>,
+            tags: BTreeSet<Tag>,
+        }
+
+        let events: Vec<EventWithId> = self
+            .event_starts
+            .iter()
+            .map(|(start, event_ref)| EventWithId {
+                id: event_ref,
+                start,
+                tags: self.event_tags(event_ref),
+            })
+            .collect();
+
+        let mut state = serializer.serialize_struct("Timesheet", 1)?;
+        state.serialize_field("events", &events)?;
+        state.end()
+    }
+}
+
 impl<'a, 'b> PartialEq<Timesheet<'b>> for Timesheet<'a> {
     fn eq(&self, other: &Timesheet) -> bool {
         self.events().eq(&other.events())
@@ -56,64 +169,180 @@ impl<'cl> Timesheet<'cl> {
         Self {
             patched_timesheet,
             event_starts: BTreeMap::new(),
+            end_policy: EndPolicy::default(),
+            clock: &SystemClock,
+            hide_private: false,
         }
     }
 
+    /// Use `clock` to find out when the last event ends. Defaults to the
+    /// system clock.
+    pub fn with_clock(mut self, clock: &'cl dyn Clock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn clock(&self) -> &'cl dyn Clock {
+        self.clock
+    }
+
+    /// Use `end_policy` to decide when events end in `segments` and other
+    /// queries
+    pub fn with_end_policy(mut self, end_policy: EndPolicy) -> Self {
+        self.end_policy = end_policy;
+        self
+    }
+
+    pub fn end_policy(&self) -> EndPolicy {
+        self.end_policy
+    }
+
+    /// Leave out the events that were started in private mode. Their time
+    /// counts as untracked, and they have no tags or note.
+    pub fn without_private(mut self) -> Self {
+        self.hide_private = true;
+        self
+    }
+
+    /// Whether `event_ref` is left out by `without_private`
+    fn is_hidden(&self, event_ref: &EventRef) -> bool {
+        self.hide_private && self.patched_timesheet.is_private(event_ref)
+    }
+
+    /// The tags of `event_ref`, or none if it is hidden
+    fn event_tags(&self, event_ref: &EventRef) -> BTreeSet<Tag> {
+        if self.is_hidden(event_ref) {
+            return BTreeSet::new();
+        }
+        self.patched_timesheet.events[event_ref]
+            .tags()
+            .into_iter()
+            .map(|(_patch_ref, tag)| tag)
+            .collect()
+    }
+
     pub fn get_patched_timesheet(&'cl self) -> &'cl PatchedTimesheet {
-        &self.patched_timesheet
+        self.patched_timesheet
     }
 
     pub fn event_at_time(&mut self, start: DateTime<Utc>, event_ref: EventRef) -> Option<EventRef> {
-        match self.event_starts.insert(start, event_ref) {
-            None => None,
-            Some(previous_event_ref) => Some(previous_event_ref),
-        }
+        self.event_starts.insert(start, event_ref)
     }
 
     pub fn events(&self) -> BTreeMap<DateTime<Utc>, BTreeSet<Tag>> {
         self.event_starts
             .iter()
-            .map(|(start, event_ref)| {
-                let tags = self.patched_timesheet.events[event_ref]
-                    .tags()
-                    .into_iter()
-                    .map(|(_patch_ref, tag)| tag)
-                    .collect();
-                (*start, tags)
-            })
+            .map(|(start, event_ref)| (*start, self.event_tags(event_ref)))
             .collect()
     }
 
+    /// The free-text note attached to an event, if it has one
+    pub fn note(&self, event_ref: &EventRef) -> Option<String> {
+        if self.is_hidden(event_ref) {
+            return None;
+        }
+        self.patched_timesheet
+            .events
+            .get(event_ref)
+            .and_then(|event| event.note())
+    }
+
+    /// How many events use each tag
+    pub fn tag_counts(&self) -> BTreeMap<Tag, usize> {
+        let mut counts = BTreeMap::new();
+        for tags in self.events().values() {
+            for tag in tags {
+                *counts.entry(tag.clone()).or_insert(0) += 1;
+            }
+        }
+        counts
+    }
+
     pub fn segments(&self) -> Vec<Segment> {
-        let now = Utc::now();
+        let now = self.clock.now();
         let end_cap_arr = [now];
         self.event_starts
             .iter()
             .zip(self.event_starts.keys().skip(1).chain(end_cap_arr.iter()))
-            .map(|((start_time, event_ref), end_time)| {
-                let event = &self.patched_timesheet.events[event_ref];
+            .map(|((start_time, event_ref), next_start)| {
+                let end_time = self
+                    .end_policy
+                    .end_time(*start_time, *next_start)
+                    .max(*start_time);
                 let duration = end_time.signed_duration_since(*start_time);
                 Segment {
                     event_ref: event_ref.clone(),
                     start_time: *start_time,
-                    tags: event.tags().into_iter().map(|(_ref, tag)| tag).collect(),
+                    tags: self.event_tags(event_ref),
                     duration,
-                    end_time: *end_time,
+                    end_time,
                 }
             })
             .collect()
     }
 
-    pub fn tags_at_time<'ts>(&'ts self, datetime: &DateTime<Utc>) -> Option<BTreeSet<Tag>> {
+    pub fn tags_at_time(&self, datetime: &DateTime<Utc>) -> Option<BTreeSet<Tag>> {
         self.event_starts
             .range::<DateTime<_>, _>(..datetime)
             .last()
-            .map(|(_time, event_ref)| {
-                self.patched_timesheet.events[event_ref]
-                    .tags()
-                    .into_iter()
-                    .map(|(_patch_ref, tag)| tag)
-                    .collect()
+            .filter(|(start, _event_ref)| self.is_ongoing(**start, *datetime))
+            .map(|(_time, event_ref)| self.event_tags(event_ref))
+    }
+
+    /// Get the event that was being tracked at `now`, along with how long it
+    /// has been going on for.
+    pub fn current_event(&self, now: DateTime<Utc>) -> Option<CurrentEvent> {
+        self.event_starts
+            .range(..=now)
+            .next_back()
+            .filter(|(start, _event_ref)| self.is_ongoing(**start, now))
+            .map(|(start_time, event_ref)| CurrentEvent {
+                event_ref: event_ref.clone(),
+                start_time: *start_time,
+                tags: self.event_tags(event_ref),
+                elapsed: now.signed_duration_since(*start_time),
             })
     }
+
+    /// Find the event that `reference` refers to. Besides full references and
+    /// unique prefixes, `@` refers to the latest event that has started, and
+    /// `@-1`, `@-2`, ... to the events of today before it. Events without
+    /// tags, which only mark where tracking stopped, are skipped. Days are
+    /// measured in the given time zone.
+    pub fn resolve_event_ref(
+        &self,
+        reference: &str,
+        offset: FixedOffset,
+    ) -> Result<EventRef, RefError> {
+        if !reference.starts_with('@') {
+            return self.patched_timesheet.resolve_event_ref(reference);
+        }
+        let invalid = || RefError::InvalidRelativeRef {
+            reference: reference.to_string(),
+        };
+        let back: usize = match &reference[1..] {
+            "" => 0,
+            rest if rest.starts_with('-') => rest[1..].parse().map_err(|_| invalid())?,
+            _ => return Err(invalid()),
+        };
+
+        let now = self.clock.now();
+        let today = now.with_timezone(&offset).date();
+        self.event_starts
+            .range(..=now)
+            .rev()
+            .filter(|(_, event_ref)| !self.event_tags(event_ref).is_empty())
+            .nth(back)
+            .filter(|(start, _)| back == 0 || start.with_timezone(&offset).date() == today)
+            .map(|(_, event_ref)| event_ref.clone())
+            .ok_or_else(|| RefError::NoRelativeEvent {
+                reference: reference.to_string(),
+            })
+    }
+
+    /// Check whether an event starting at `start` is still going on at `at`,
+    /// assuming that no other event starts in between.
+    fn is_ongoing(&self, start: DateTime<Utc>, at: DateTime<Utc>) -> bool {
+        at <= self.end_policy.end_time(start, at)
+    }
 }
@@ -1,10 +1,14 @@
+#![allow(array_into_iter, clippy::clone_on_copy, clippy::map_clone)]
+
 use augr_core::{
     repository::{
-        event::Error as EventError, timesheet::Error as TimesheetError, Error as RepositoryError,
+        event::{Error as EventError, Resolution},
+        timesheet::{Conflict, Error as TimesheetError},
+        Error as RepositoryError, CREATED_METADATA,
     },
-    Meta, Patch, PatchRef, Repository, Store,
+    FixedClock, Meta, Patch, PatchRef, Repository, Store,
 };
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, TimeZone, Utc};
 use snafu::Snafu;
 use std::collections::BTreeMap;
 use uuid::Uuid;
@@ -45,12 +49,15 @@ impl Store for MemStore {
         Ok(self.meta.clone())
     }
 
-    fn save_meta(&mut self, _meta: &Meta) -> Result<(), Self::Error> {
-        unimplemented!()
+    fn save_meta(&mut self, meta: &Meta) -> Result<(), Self::Error> {
+        self.meta = meta.clone();
+        Ok(())
     }
 
-    fn add_patch(&mut self, _patch: &Patch) -> Result<(), Self::Error> {
-        unimplemented!()
+    fn add_patch(&mut self, patch: &Patch) -> Result<(), Self::Error> {
+        self.patches
+            .insert(patch.patch_ref().clone(), patch.clone());
+        Ok(())
     }
 
     fn get_patch(&self, patch_ref: &PatchRef) -> Result<Patch, Self::Error> {
@@ -119,6 +126,26 @@ fn unknown_event_ref_reported() {
     }));
 }
 
+#[test]
+fn unknown_tagged_event_reported() {
+    let patch1 = &Uuid::parse_str("2a226f4d-60f2-493d-9e9a-d6c71d98b515").unwrap();
+    let patch2 = &Uuid::parse_str("dad9051e-2e83-446e-b9aa-299bd4a34b37").unwrap();
+
+    let store = MemStore::new(meta![patch2])
+        .patch(p!(patch1).create_event(s!("a"), dt!("2019-07-23T12:00:00Z"), sl!["lunch"]))
+        .patch(p!(patch2).add_tag(*patch1, s!("b"), s!("food")));
+
+    let errors = Repository::from_store(store).expect_err("patches to produce error");
+
+    assert!(errors.contains(&RepositoryError::PatchingTimesheet {
+        patch: *patch2,
+        conflicts: vec![TimesheetError::UnknownEvent {
+            patch: *patch2,
+            event: s!("b")
+        }]
+    }));
+}
+
 #[test]
 fn unknown_patch_reported() {
     let patch1 = &Uuid::new_v4();
@@ -166,3 +193,192 @@ fn invalid_number_of_start_times() {
         event: s!("b")
     }));
 }
+
+#[test]
+fn conflicts_reported_as_data() {
+    let patch1 = &Uuid::parse_str("1b1b3b3a-39c4-4e3c-9a0f-6a3b3f1d1f2e").unwrap();
+    let patch2 = &Uuid::parse_str("2c6e1d0b-5f7e-4c39-8d4b-0e5e4d0c9a11").unwrap();
+    let patch3 = &Uuid::new_v4();
+
+    let store = MemStore::new(meta![patch2, patch3])
+        .patch(
+            p!(patch1)
+                .create_event(s!("a"), dt!("2019-07-23T12:00:00Z"), sl!["lunch", "food"])
+                .create_event(s!("b"), dt!("2019-07-23T13:00:00Z"), sl!["work"])
+                .create_event(s!("c"), dt!("2019-07-23T14:00:00Z"), sl!["break"]),
+        )
+        .patch(p!(patch2).add_start(*patch1, s!("a"), dt!("2019-07-23T12:30:00Z")))
+        .patch(
+            p!(patch3)
+                .remove_start(*patch1, s!("c"), dt!("2019-07-23T14:00:00Z"))
+                .add_start(*patch1, s!("c"), dt!("2019-07-23T13:00:00Z")),
+        );
+
+    let repo = Repository::from_store(store).unwrap();
+    let conflicts = repo.timesheet().conflicts();
+
+    assert_eq!(conflicts.len(), 2);
+    assert!(conflicts.contains(&Conflict::MultipleStartTimes {
+        event: s!("a"),
+        starts: vec![
+            (*patch1, dt!("2019-07-23T12:00:00Z")),
+            (*patch2, dt!("2019-07-23T12:30:00Z")),
+        ],
+    }));
+    assert!(conflicts.contains(&Conflict::SharedStartTime {
+        time: dt!("2019-07-23T13:00:00Z"),
+        events: vec![s!("b"), s!("c")],
+    }));
+}
+
+#[test]
+fn multiple_start_times_resolved() {
+    let patch1 = &Uuid::new_v4();
+    let patch2 = &Uuid::new_v4();
+
+    let store = MemStore::new(meta![patch2])
+        .patch(
+            p!(patch1)
+                .create_event(s!("a"), dt!("2019-07-23T12:00:00Z"), sl!["lunch"])
+                .with_metadata(CREATED_METADATA, "2019-07-23T12:00:00Z"),
+        )
+        .patch(
+            p!(patch2)
+                .add_start(*patch1, s!("a"), dt!("2019-07-23T12:30:00Z"))
+                .with_metadata(CREATED_METADATA, "2019-07-23T12:35:00Z"),
+        );
+
+    let repo = Repository::from_store(store).unwrap();
+    let patched = repo.timesheet();
+    assert!(patched.resolution_patch(Resolution::Manual).is_none());
+
+    let earliest = patched.flatten_with(Resolution::EarliestWins).unwrap();
+    assert!(earliest.events().contains_key(&dt!("2019-07-23T12:00:00Z")));
+
+    let latest = patched.flatten_with(Resolution::LatestPatchWins).unwrap();
+    assert!(latest.events().contains_key(&dt!("2019-07-23T12:30:00Z")));
+
+    let resolution = patched
+        .resolution_patch(Resolution::EarliestWins)
+        .expect("a patch to resolve the conflict");
+    let mut resolved = patched.clone();
+    resolved.apply_patch(&resolution).unwrap();
+    let timesheet = resolved.flatten().unwrap();
+    assert!(timesheet
+        .events()
+        .contains_key(&dt!("2019-07-23T12:00:00Z")));
+}
+
+#[test]
+fn lenient_load_skips_descendants_of_missing_patches() {
+    let patch1 = &Uuid::new_v4();
+    let patch2 = &Uuid::new_v4();
+    let patch3 = &Uuid::new_v4();
+    let missing = &Uuid::new_v4();
+
+    let store = MemStore::new(meta![patch1, patch2, patch3])
+        .patch(p!(patch1).create_event(s!("a"), dt!("2019-07-23T12:00:00Z"), sl!["lunch"]))
+        .patch(p!(patch2).add_tag(*missing, s!("a"), s!("food")))
+        .patch(p!(patch3).add_tag(*patch2, s!("a"), s!("break")));
+
+    let (repo, errors) = Repository::from_store_lenient(store);
+
+    assert!(errors.contains(&RepositoryError::PatchNotFound {
+        source: MemStoreError::PatchNotFound {
+            patch_ref: *missing
+        },
+        patch: *missing,
+    }));
+    assert!(repo.is_loaded(patch1));
+    assert!(!repo.is_loaded(patch2));
+    assert!(!repo.is_loaded(patch3));
+    let events = repo.timesheet().flatten().unwrap().events();
+    assert_eq!(events[&dt!("2019-07-23T12:00:00Z")], sl!["lunch"]);
+}
+
+#[test]
+fn lossy_flatten_keeps_the_events_it_can() {
+    let patch1 = &Uuid::new_v4();
+    let patch2 = &Uuid::new_v4();
+
+    let store = MemStore::new(meta![patch2])
+        .patch(
+            p!(patch1)
+                .create_event(s!("a"), dt!("2019-07-23T12:00:00Z"), sl!["lunch"])
+                .create_event(s!("b"), dt!("2019-07-23T13:00:00Z"), sl!["work"])
+                .create_event(s!("c"), dt!("2019-07-23T13:00:00Z"), sl!["break"]),
+        )
+        .patch(p!(patch2).add_start(*patch1, s!("a"), dt!("2019-07-23T12:30:00Z")));
+
+    let repo = Repository::from_store(store).unwrap();
+    let (timesheet, errors) = repo.timesheet().flatten_lossy(Resolution::Manual);
+
+    assert_eq!(errors.len(), 2);
+    let events = timesheet.events();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[&dt!("2019-07-23T13:00:00Z")], sl!["work"]);
+}
+
+#[test]
+fn cancelled_load_stops() {
+    use augr_core::{progress::Stage, CancelToken, Progress};
+    use std::cell::RefCell;
+
+    let patch1 = &Uuid::parse_str("2a226f4d-60f2-493d-9e9a-d6c71d98b515").unwrap();
+    let patch2 = &Uuid::parse_str("dad9051e-2e83-446e-b9aa-299bd4a34b37").unwrap();
+    let store = || {
+        MemStore::new(meta![patch2])
+            .patch(p!(patch1).create_event(s!("a"), dt!("2019-07-23T12:00:00Z"), sl!["lunch"]))
+            .patch(p!(patch2).add_tag(*patch1, s!("a"), s!("food")))
+    };
+
+    struct Recorder(RefCell<Vec<(usize, usize)>>);
+    impl Progress for Recorder {
+        fn update(&self, stage: Stage, done: usize, total: usize) {
+            assert_eq!(stage, Stage::Load);
+            self.0.borrow_mut().push((done, total));
+        }
+    }
+    let recorder = Recorder(RefCell::new(Vec::new()));
+    Repository::from_store_with_progress(store(), &recorder).unwrap();
+    // The parent is only found after the patch that needs it, which is then
+    // tried again
+    assert_eq!(recorder.0.into_inner(), vec![(0, 1), (1, 3), (2, 3)]);
+
+    let token = CancelToken::new();
+    token.cancel();
+    let errors = Repository::from_store_with_progress(store(), &token).unwrap_err();
+    assert_eq!(errors, vec![RepositoryError::Cancelled]);
+}
+
+#[test]
+fn newer_format_is_read_only() {
+    let meta: Meta = toml::de::from_str("format-version = 99\npatches = []").unwrap();
+    let mut repository = Repository::from_store(MemStore::new(meta)).unwrap();
+
+    assert!(!repository.is_writable());
+    assert_eq!(
+        repository.save_meta(),
+        Err(RepositoryError::UnsupportedFormat { version: 99 })
+    );
+    assert_eq!(
+        repository.add_patch(Patch::new()),
+        Err(RepositoryError::UnsupportedFormat { version: 99 })
+    );
+}
+
+#[test]
+fn times_come_from_the_clock() {
+    let now = Utc.ymd(2019, 7, 24).and_hms(9, 0, 0);
+    let mut repository = Repository::from_store(MemStore::new(Meta::new()))
+        .unwrap()
+        .with_clock(Box::new(FixedClock(now)));
+
+    let patch = Patch::new();
+    repository.add_patch(patch.clone()).unwrap();
+    repository.save_meta().unwrap();
+    let saved = repository.store().get_patch(patch.patch_ref()).unwrap();
+    assert_eq!(saved.metadata["created"], now.to_rfc3339());
+    assert_eq!(repository.meta().last_patch_added(), Some(now));
+    assert_eq!(repository.meta().created(), Some(now));
+}
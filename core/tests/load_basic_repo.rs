@@ -1,5 +1,16 @@
-use augr_core::{store::SyncFolderStore, Meta, Patch, Repository, Store, Tag};
-use chrono::{DateTime, Utc};
+#![allow(array_into_iter, clippy::clone_on_copy)]
+
+use augr_core::{
+    repository::{
+        diff::{diff, EventDiff},
+        event::{Operation, OperationKind},
+        timesheet::{PatchedTimesheet, RefError},
+        PRIVATE_METADATA,
+    },
+    store::SyncFolderStore,
+    CurrentEvent, EndPolicy, FixedClock, Meta, Patch, Repository, Store, Tag, Zone,
+};
+use chrono::{DateTime, Duration, FixedOffset, Utc};
 use std::collections::{BTreeMap, BTreeSet};
 use uuid::Uuid;
 
@@ -21,18 +32,6 @@ macro_rules! s {
     };
 }
 
-macro_rules! meta {
-    ( $( $x:expr ),* ) => {
-        {
-            let mut temp_meta = Meta::new();
-            $(
-                temp_meta.add_patch($x.clone());
-            )*
-            temp_meta
-        }
-    };
-}
-
 fn simple_store() -> SyncFolderStore {
     SyncFolderStore::new("tests/basic_repo".into(), "laptop".into())
 }
@@ -42,7 +41,9 @@ fn load_patches_into_store() {
     let patch1 = &Uuid::parse_str("d83f2984-8f59-4a32-9492-f910717b683c").unwrap();
     let patch2 = &Uuid::parse_str("386d2d62-7c3f-4518-9709-d2145261b853").unwrap();
 
-    let expected_meta = meta![patch2.clone()];
+    // The repo's meta file is from before the format was versioned
+    let mut expected_meta: Meta = toml::de::from_str("format-version = 1\npatches = []").unwrap();
+    expected_meta.add_patch(patch2.clone());
     let expected_patches = vec![
         Patch::with_id(patch1.clone())
             .create_event(s!("a"), dt!("2019-07-23T12:00:00Z"), sl!["lunch", "food"])
@@ -78,3 +79,229 @@ fn check_repository_state() {
     assert!(timesheet.is_ok());
     assert!(timesheet.unwrap().eq(&expected_timesheet));
 }
+
+#[test]
+fn current_event() {
+    let repository = Repository::from_store(simple_store()).unwrap();
+    let timesheet = repository.timesheet().flatten().unwrap();
+
+    assert_eq!(timesheet.current_event(dt!("2019-07-23T12:00:00Z")), None);
+    assert_eq!(
+        timesheet.current_event(dt!("2019-07-23T12:45:00Z")),
+        Some(CurrentEvent {
+            event_ref: s!("a"),
+            start_time: dt!("2019-07-23T12:30:00Z"),
+            tags: sl!["lunch"],
+            elapsed: Duration::minutes(15),
+        })
+    );
+    assert_eq!(
+        timesheet
+            .current_event(dt!("2019-07-23T15:00:00Z"))
+            .map(|current| current.event_ref),
+        Some(s!("b"))
+    );
+}
+
+#[test]
+fn relative_event_refs() {
+    let repository = Repository::from_store(simple_store()).unwrap();
+    let clock = FixedClock(dt!("2019-07-23T15:00:00Z"));
+    let timesheet = repository.timesheet().flatten().unwrap().with_clock(&clock);
+    let utc = FixedOffset::east(0);
+
+    assert_eq!(timesheet.resolve_event_ref("@", utc), Ok(s!("b")));
+    assert_eq!(timesheet.resolve_event_ref("@-1", utc), Ok(s!("a")));
+    assert_eq!(
+        timesheet.resolve_event_ref("@-2", utc),
+        Err(RefError::NoRelativeEvent {
+            reference: s!("@-2")
+        })
+    );
+    assert!(timesheet.resolve_event_ref("@+1", utc).is_err());
+    assert_eq!(timesheet.resolve_event_ref("a", utc), Ok(s!("a")));
+
+    // Yesterday's events can only be reached through `@`
+    let clock = FixedClock(dt!("2019-07-24T09:00:00Z"));
+    let timesheet = timesheet.with_clock(&clock);
+    assert_eq!(timesheet.resolve_event_ref("@", utc), Ok(s!("b")));
+    assert!(timesheet.resolve_event_ref("@-1", utc).is_err());
+}
+
+#[test]
+fn relative_event_refs_skip_stops() {
+    let mut patched = PatchedTimesheet::new();
+    let create = Patch::new()
+        .create_event(s!("a"), dt!("2019-07-23T09:00:00Z"), sl!["work"])
+        .create_event(s!("b"), dt!("2019-07-23T12:00:00Z"), Vec::new());
+    patched.apply_patch(&create).unwrap();
+    let clock = FixedClock(dt!("2019-07-23T15:00:00Z"));
+    let timesheet = patched.flatten().unwrap().with_clock(&clock);
+    let utc = FixedOffset::east(0);
+
+    assert_eq!(timesheet.resolve_event_ref("@", utc), Ok(s!("a")));
+    assert!(timesheet.resolve_event_ref("@-1", utc).is_err());
+}
+
+#[test]
+fn end_policy_caps_segments() {
+    let repository = Repository::from_store(simple_store()).unwrap();
+    let timesheet = repository
+        .timesheet()
+        .flatten()
+        .unwrap()
+        .with_end_policy(EndPolicy::MaxDuration(Duration::minutes(20)));
+
+    let segments = timesheet.segments();
+    assert_eq!(segments[0].end_time, dt!("2019-07-23T12:50:00Z"));
+    assert_eq!(segments[0].duration, Duration::minutes(20));
+    assert_eq!(segments[1].end_time, dt!("2019-07-23T13:20:00Z"));
+
+    assert_eq!(
+        timesheet.tags_at_time(&dt!("2019-07-23T12:40:00Z")),
+        Some(sl!["lunch"])
+    );
+    assert_eq!(timesheet.tags_at_time(&dt!("2019-07-23T12:55:00Z")), None);
+    assert_eq!(timesheet.current_event(dt!("2019-07-23T14:00:00Z")), None);
+}
+
+#[test]
+fn end_of_day_follows_each_events_day() {
+    let mut patched = PatchedTimesheet::new();
+    let create = Patch::new()
+        .create_event(s!("a"), dt!("2019-07-23T20:00:00Z"), sl!["work"])
+        .create_event(s!("b"), dt!("2019-07-24T01:00:00Z"), sl!["late"])
+        .create_event(s!("c"), dt!("2019-07-25T09:00:00Z"), sl!["work"]);
+    patched.apply_patch(&create).unwrap();
+    let clock = FixedClock(dt!("2019-07-25T10:00:00Z"));
+    let timesheet = patched
+        .flatten()
+        .unwrap()
+        .with_clock(&clock)
+        .with_end_policy(EndPolicy::EndOfDay {
+            zone: Zone::Fixed(FixedOffset::east(2 * 3600)),
+            day_start: Duration::hours(4),
+        });
+
+    // Days start at 04:00 in UTC+2, which is 02:00 UTC
+    let segments = timesheet.segments();
+    assert_eq!(segments[0].end_time, dt!("2019-07-24T01:00:00Z"));
+    assert_eq!(segments[1].end_time, dt!("2019-07-24T02:00:00Z"));
+    assert_eq!(segments[2].end_time, dt!("2019-07-25T10:00:00Z"));
+}
+
+#[test]
+fn clock_ends_last_segment() {
+    let repository = Repository::from_store(simple_store()).unwrap();
+    let clock = FixedClock(dt!("2019-07-23T15:00:00Z"));
+    let timesheet = repository.timesheet().flatten().unwrap().with_clock(&clock);
+
+    let segments = timesheet.segments();
+    assert_eq!(segments[1].end_time, dt!("2019-07-23T15:00:00Z"));
+    assert_eq!(segments[1].duration, Duration::hours(2));
+}
+
+#[test]
+fn event_history() {
+    let patch1 = Uuid::parse_str("d83f2984-8f59-4a32-9492-f910717b683c").unwrap();
+    let patch2 = Uuid::parse_str("386d2d62-7c3f-4518-9709-d2145261b853").unwrap();
+    let repository = Repository::from_store(simple_store()).unwrap();
+
+    let history = repository.history(&s!("a")).unwrap();
+    assert_eq!(
+        history[0],
+        Operation {
+            patch: patch1,
+            kind: OperationKind::Create {
+                start: dt!("2019-07-23T12:00:00Z"),
+                tags: sl!["lunch", "food"],
+            },
+        }
+    );
+    assert_eq!(history.len(), 4);
+    assert!(history[1..].iter().all(|op| op.patch == patch2));
+    assert!(history.contains(&Operation {
+        patch: patch2,
+        kind: OperationKind::RemoveTag(s!("food")),
+    }));
+
+    assert!(repository.history(&s!("unknown")).is_none());
+}
+
+#[test]
+fn diff_against_older_state() {
+    let patch1 = Uuid::parse_str("d83f2984-8f59-4a32-9492-f910717b683c").unwrap();
+    let repository = Repository::from_store(simple_store()).unwrap();
+
+    let old = repository
+        .timesheet_for_patches(vec![patch1].into_iter())
+        .unwrap();
+    let diffs = diff(&old, repository.timesheet());
+
+    assert_eq!(diffs.len(), 2);
+    assert_eq!(
+        diffs[1],
+        EventDiff::Changed {
+            event: s!("b"),
+            starts_added: BTreeSet::new(),
+            starts_removed: BTreeSet::new(),
+            tags_added: sl!["awesome-project"],
+            tags_removed: BTreeSet::new(),
+            note: None,
+            metadata: BTreeMap::new(),
+        }
+    );
+}
+
+#[test]
+fn serialize_timesheet() {
+    let repository = Repository::from_store(simple_store()).unwrap();
+    let timesheet = repository.timesheet().flatten().unwrap();
+
+    assert_eq!(
+        serde_json::to_value(&timesheet).unwrap(),
+        serde_json::json!({
+            "events": [
+                { "id": "a", "start": "2019-07-23T12:30:00Z", "tags": ["lunch"] },
+                { "id": "b", "start": "2019-07-23T13:00:00Z", "tags": ["awesome-project", "work"] },
+            ]
+        })
+    );
+
+    let segment = &timesheet.segments()[0];
+    assert_eq!(
+        serde_json::to_value(segment).unwrap(),
+        serde_json::json!({
+            "event-ref": "a",
+            "start-time": "2019-07-23T12:30:00Z",
+            "tags": ["lunch"],
+            "duration": 1800,
+            "end-time": "2019-07-23T13:00:00Z",
+        })
+    );
+}
+
+#[test]
+fn without_private_leaves_private_events_untracked() {
+    let work = Patch::new().create_event(s!("a"), dt!("2019-07-23T12:00:00Z"), vec![s!("work")]);
+    let private = Patch::new()
+        .create_event(s!("b"), dt!("2019-07-23T13:00:00Z"), vec![s!("doctor")])
+        .with_metadata(PRIVATE_METADATA, "true");
+    let mut patched = PatchedTimesheet::new();
+    patched.apply_patch(&work).unwrap();
+    patched.apply_patch(&private).unwrap();
+    let clock = FixedClock(dt!("2019-07-23T14:00:00Z"));
+    let timesheet = patched.flatten().unwrap().with_clock(&clock);
+
+    assert_eq!(timesheet.segments()[1].tags, sl!["doctor"]);
+
+    let timesheet = timesheet.without_private();
+    let segments = timesheet.segments();
+    assert_eq!(segments[0].tags, sl!["work"]);
+    assert_eq!(segments[0].duration, Duration::hours(1));
+    assert_eq!(segments[1].tags, BTreeSet::new());
+    assert_eq!(
+        timesheet.tags_at_time(&dt!("2019-07-23T13:30:00Z")),
+        Some(BTreeSet::new())
+    );
+}
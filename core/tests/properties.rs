@@ -0,0 +1,42 @@
+#![cfg(feature = "testing")]
+
+use augr_core::{
+    repository::timesheet::PatchedTimesheet,
+    testing::{arb_patch, arb_patch_dag},
+};
+use proptest::{collection, prelude::*};
+
+proptest! {
+    #[test]
+    fn flatten_never_panics_on_valid_dags(patches in arb_patch_dag()) {
+        let mut timesheet = PatchedTimesheet::new();
+        for patch in patches.iter() {
+            prop_assert!(timesheet.apply_patch(patch).is_ok());
+        }
+
+        let conflicts = timesheet.conflicts();
+        match timesheet.flatten() {
+            Ok(flat) => {
+                prop_assert!(conflicts.is_empty());
+                let live = timesheet.events.values().filter(|e| !e.is_deleted()).count();
+                prop_assert_eq!(flat.events().len(), live);
+                for segment in flat.segments() {
+                    prop_assert!(segment.end_time >= segment.start_time);
+                }
+            }
+            Err(_) => prop_assert!(!conflicts.is_empty()),
+        }
+    }
+
+    #[test]
+    fn apply_patch_never_panics(patches in collection::vec(arb_patch(), 0..10)) {
+        let mut timesheet = PatchedTimesheet::new();
+        for patch in patches.iter() {
+            let _ = timesheet.apply_patch(patch);
+        }
+        let _ = timesheet.conflicts();
+        if let Ok(flat) = timesheet.flatten() {
+            flat.segments();
+        }
+    }
+}